@@ -0,0 +1,999 @@
+//! A stub for the GDB remote serial protocol, so that gdb
+//! (usually `gdb-multiarch`) can debug a program while mipsy runs it.
+//!
+//! Breakpoints and watchpoints from gdb are kept in the binary, just like
+//! the ones set in interactive mode, and reverse execution walks back
+//! along the runtime's timeline.
+
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    mem::take,
+    net::{TcpListener, TcpStream},
+    rc::Rc,
+    time::{Duration, SystemTime},
+};
+
+use mipsy_lib::{
+    compile::breakpoints::{
+        find_watchpoint_hits, get_affected_registers, Breakpoint, TargetAction, Watchpoint,
+        WatchpointTarget,
+    },
+    error::runtime::{Error, ErrorContext, InvalidSyscallReason, RuntimeError},
+    runtime::{
+        io::{self as sys_io, Input, Output},
+        RuntimeSyscallGuard, SPECIAL, SYS13_OPEN, SYS14_READ, SYS15_WRITE, SYS16_CLOSE,
+    },
+    Binary, InstSet, MipsyError, Runtime,
+};
+
+use crate::{get_input, read_confirm, report_bad_input};
+
+const SYSCALL: u32 = 0b001100;
+
+// gdb's own signal numbers, which are the same for every target
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGFPE: u8 = 8;
+const SIGBUS: u8 = 10;
+const SIGSEGV: u8 = 11;
+const SIGSYS: u8 = 12;
+
+// gdb numbers the MIPS registers as the 32 general purpose registers,
+// then these, then the 32 floating point registers, fcsr and fir
+const REG_SR: usize = 32;
+const REG_LO: usize = 33;
+const REG_HI: usize = 34;
+const REG_BAD: usize = 35;
+const REG_CAUSE: usize = 36;
+const REG_PC: usize = 37;
+const NUM_REGS: usize = 72;
+
+/// How many instructions are run between checks for an interrupt from gdb.
+const BATCH_SIZE: usize = 10_000;
+
+const TARGET_XML: &str = concat!(
+    r#"<?xml version="1.0"?><!DOCTYPE target SYSTEM "gdb-target.dtd">"#,
+    "<target><architecture>mips:isa32</architecture></target>",
+);
+
+/// How the debugging session finished.
+pub enum Ending {
+    Exited(i32),
+    Killed,
+    /// gdb let go of the program, which should keep running without it.
+    Detached(Box<Runtime>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Packet {
+    Command(Vec<u8>),
+    /// gdb wants a running program to stop (from a ctrl-c).
+    Interrupt,
+}
+
+/// Reads the next packet, acknowledging it, or `None` once gdb has gone away.
+fn read_packet(reader: &mut impl BufRead, writer: &mut impl Write) -> io::Result<Option<Packet>> {
+    loop {
+        let mut byte = [0];
+        if reader.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+
+        match byte[0] {
+            0x03 => return Ok(Some(Packet::Interrupt)),
+            b'$' => {}
+            // acknowledgements of our own packets, and line noise
+            _ => continue,
+        }
+
+        let mut data = vec![];
+        if reader.read_until(b'#', &mut data)? == 0 || data.pop() != Some(b'#') {
+            return Ok(None);
+        }
+
+        let mut checksum = [0; 2];
+        reader.read_exact(&mut checksum)?;
+
+        let sum = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        if parse_hex(&checksum) != Some(sum as u32) {
+            writer.write_all(b"-")?;
+            writer.flush()?;
+            continue;
+        }
+
+        writer.write_all(b"+")?;
+        writer.flush()?;
+
+        let mut command = Vec::with_capacity(data.len());
+        let mut bytes = data.into_iter();
+
+        while let Some(byte) = bytes.next() {
+            if byte == b'}' {
+                command.extend(bytes.next().map(|byte| byte ^ 0x20));
+            } else {
+                command.push(byte);
+            }
+        }
+
+        return Ok(Some(Packet::Command(command)));
+    }
+}
+
+fn write_packet(writer: &mut impl Write, data: &[u8]) -> io::Result<()> {
+    let mut packet = vec![b'$'];
+
+    for &byte in data {
+        if matches!(byte, b'$' | b'#' | b'}' | b'*') {
+            packet.extend([b'}', byte ^ 0x20]);
+        } else {
+            packet.push(byte);
+        }
+    }
+
+    let sum = packet[1..]
+        .iter()
+        .fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    packet.extend(format!("#{sum:02x}").bytes());
+
+    writer.write_all(&packet)?;
+    writer.flush()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn unhex(text: &[u8]) -> Option<Vec<u8>> {
+    text.chunks(2)
+        .map(|pair| match pair {
+            [_, _] => parse_hex(pair).map(|byte| byte as u8),
+            _ => None,
+        })
+        .collect()
+}
+
+fn parse_hex(text: &[u8]) -> Option<u32> {
+    u32::from_str_radix(std::str::from_utf8(text).ok()?, 16).ok()
+}
+
+/// Splits `text` at the first `separator`, for packets like `m addr,length`.
+fn split(text: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let index = text.iter().position(|&byte| byte == separator)?;
+    Some((&text[..index], &text[index + 1..]))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resume {
+    Step,
+    Continue,
+    ReverseStep,
+    ReverseContinue,
+}
+
+enum Action {
+    Reply(String),
+    Resume(Resume),
+    Kill,
+    Detach,
+}
+
+/// Why the program stopped, as told to gdb.
+#[derive(Debug, Clone, PartialEq)]
+enum Stop {
+    Signal(u8),
+    Breakpoint,
+    Watchpoint {
+        action: TargetAction,
+        addr: u32,
+    },
+    /// Went back as far as the timeline goes.
+    HistoryStart,
+    Exited(i32),
+}
+
+impl Stop {
+    fn reply(&self) -> String {
+        match *self {
+            Stop::Signal(signal) => format!("S{signal:02x}"),
+            Stop::Breakpoint => format!("T{SIGTRAP:02x}swbreak:;"),
+            Stop::Watchpoint { action, addr } => {
+                let kind = match action {
+                    TargetAction::WriteOnly => "watch",
+                    TargetAction::ReadOnly => "rwatch",
+                    TargetAction::ReadWrite => "awatch",
+                };
+
+                format!("T{SIGTRAP:02x}{kind}:{addr:x};")
+            }
+            Stop::HistoryStart => format!("T{SIGTRAP:02x}replaylog:begin;"),
+            Stop::Exited(code) => format!("W{:02x}", code as u8),
+        }
+    }
+}
+
+struct Stub<'a> {
+    iset: &'a InstSet,
+    binary: &'a mut Binary,
+    runtime: Runtime,
+    files: Vec<(Rc<str>, Rc<str>)>,
+    input: &'a mut dyn Input,
+    output: &'a mut dyn Output,
+    next_id: u32,
+    /// Where we last stopped for a watchpoint, so that
+    /// the access isn't reported again when resuming.
+    watch_stop: Option<u32>,
+}
+
+/// Waits for gdb to connect to `addr`, then lets it control the program until it's done.
+pub fn serve(
+    addr: &str,
+    iset: &InstSet,
+    binary: &mut Binary,
+    runtime: Runtime,
+    files: &[(String, String)],
+    input: &mut dyn Input,
+    output: &mut dyn Output,
+) -> io::Result<Ending> {
+    let listener = TcpListener::bind(addr)?;
+    eprintln!("[mipsy] waiting for gdb to connect to {addr}");
+
+    let (stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+
+    let mut stub = Stub::new(iset, binary, runtime, files, input, output);
+
+    loop {
+        let command = match read_packet(&mut reader, &mut writer)? {
+            Some(Packet::Command(command)) => command,
+            // the program isn't running, so there's nothing to interrupt
+            Some(Packet::Interrupt) => continue,
+            None => return Ok(Ending::Killed),
+        };
+
+        match stub.handle(&command) {
+            Action::Reply(reply) => write_packet(&mut writer, reply.as_bytes())?,
+            Action::Resume(resume) => {
+                let stop = stub.resume(resume, &mut || interrupted(&mut reader).unwrap_or(true));
+                write_packet(&mut writer, stop.reply().as_bytes())?;
+
+                if let Stop::Exited(code) = stop {
+                    return Ok(Ending::Exited(code));
+                }
+            }
+            Action::Kill => return Ok(Ending::Killed),
+            Action::Detach => {
+                write_packet(&mut writer, b"OK")?;
+                return Ok(Ending::Detached(Box::new(stub.runtime)));
+            }
+        }
+    }
+}
+
+/// Whether gdb has sent an interrupt (or hung up) while the program was running.
+fn interrupted(reader: &mut BufReader<TcpStream>) -> io::Result<bool> {
+    if reader.buffer().is_empty() {
+        reader.get_ref().set_nonblocking(true)?;
+        let filled = reader.fill_buf().map(|buf| buf.len());
+        reader.get_ref().set_nonblocking(false)?;
+
+        match filled {
+            Ok(0) => return Ok(true),
+            Ok(_) => {}
+            Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(false),
+            Err(err) => return Err(err),
+        }
+    }
+
+    while let Some(&byte) = reader.buffer().first() {
+        match byte {
+            0x03 => {
+                reader.consume(1);
+                return Ok(true);
+            }
+            b'+' | b'-' => reader.consume(1),
+            _ => break,
+        }
+    }
+
+    Ok(false)
+}
+
+impl<'a> Stub<'a> {
+    fn new(
+        iset: &'a InstSet,
+        binary: &'a mut Binary,
+        runtime: Runtime,
+        files: &[(String, String)],
+        input: &'a mut dyn Input,
+        output: &'a mut dyn Output,
+    ) -> Self {
+        let next_id = binary
+            .breakpoints
+            .values()
+            .map(|bp| bp.id)
+            .chain(binary.watchpoints.values().map(|wp| wp.id))
+            .max()
+            .map_or(1, |id| id + 1);
+
+        Self {
+            iset,
+            binary,
+            runtime,
+            files: files
+                .iter()
+                .map(|(tag, content)| (Rc::from(&**tag), Rc::from(&**content)))
+                .collect(),
+            input,
+            output,
+            next_id,
+            watch_stop: None,
+        }
+    }
+
+    fn handle(&mut self, command: &[u8]) -> Action {
+        let reply = match command {
+            b"?" => Stop::Signal(SIGTRAP).reply(),
+            b"g" => (0..NUM_REGS).map(|index| self.register(index)).collect(),
+            [b'G', values @ ..] => match unhex(values) {
+                Some(values) => {
+                    let values = values
+                        .chunks(4)
+                        .map_while(|value| value.try_into().ok().map(u32::from_le_bytes));
+
+                    for (index, value) in values.enumerate() {
+                        // leave uninitialized registers alone unless they really change
+                        if self.register_value(index).unwrap_or(0) != value {
+                            self.write_register(index, value);
+                        }
+                    }
+
+                    String::from("OK")
+                }
+                None => String::from("E01"),
+            },
+            [b'p', index @ ..] => match parse_hex(index) {
+                Some(index) => self.register(index as usize),
+                None => String::from("E01"),
+            },
+            [b'P', assignment @ ..] => {
+                let parsed = split(assignment, b'=').and_then(|(index, value)| {
+                    let value = unhex(value)?;
+                    let value = u32::from_le_bytes(value.try_into().ok()?);
+
+                    Some((parse_hex(index)?, value))
+                });
+
+                match parsed {
+                    Some((index, value)) => {
+                        self.write_register(index as usize, value);
+                        String::from("OK")
+                    }
+                    None => String::from("E01"),
+                }
+            }
+            [b'm', range @ ..] => match parse_range(range) {
+                Some((addr, len)) => self.read_memory(addr, len),
+                None => String::from("E01"),
+            },
+            [b'M', write @ ..] => {
+                let parsed = split(write, b':')
+                    .and_then(|(range, data)| Some((parse_range(range)?, unhex(data)?)));
+
+                match parsed {
+                    Some(((addr, _), data)) => self.write_memory(addr, &data),
+                    None => String::from("E01"),
+                }
+            }
+            [b'Z', point @ ..] => self.insert_point(point),
+            [b'z', point @ ..] => self.remove_point(point),
+            [b's', addr @ ..] => return self.resume_at(addr, Resume::Step),
+            [b'c', addr @ ..] => return self.resume_at(addr, Resume::Continue),
+            b"bs" => return Action::Resume(Resume::ReverseStep),
+            b"bc" => return Action::Resume(Resume::ReverseContinue),
+            b"k" => return Action::Kill,
+            [b'D', ..] => return Action::Detach,
+            _ if command.starts_with(b"qSupported") => String::from(
+                "PacketSize=4000;qXfer:features:read+;swbreak+;ReverseStep+;ReverseContinue+",
+            ),
+            _ if command.starts_with(b"qXfer:features:read:") => {
+                read_target_xml(&command[b"qXfer:features:read:".len()..])
+            }
+            b"qAttached" => String::from("1"),
+            b"qC" => String::from("QC1"),
+            b"qfThreadInfo" => String::from("m1"),
+            b"qsThreadInfo" => String::from("l"),
+            // there is only ever the one thread
+            [b'H' | b'T', ..] => String::from("OK"),
+            _ => String::new(),
+        };
+
+        Action::Reply(reply)
+    }
+
+    /// `s` and `c` can say where to resume from, which moves the pc first.
+    fn resume_at(&mut self, addr: &[u8], resume: Resume) -> Action {
+        if !addr.is_empty() {
+            match parse_hex(addr) {
+                Some(addr) => self.runtime.timeline_mut().state_mut().set_pc(addr),
+                None => return Action::Reply(String::from("E01")),
+            }
+        }
+
+        Action::Resume(resume)
+    }
+
+    fn register_value(&self, index: usize) -> Option<u32> {
+        let state = self.runtime.timeline().state();
+
+        match index {
+            0..=31 => state
+                .read_register(index as u32)
+                .ok()
+                .map(|value| value as u32),
+            // mipsy doesn't have a coprocessor 0 for these to come from
+            REG_SR | REG_BAD | REG_CAUSE => Some(0),
+            REG_LO => state.read_lo().ok().map(|value| value as u32),
+            REG_HI => state.read_hi().ok().map(|value| value as u32),
+            REG_PC => Some(state.pc()),
+            // or a floating point unit
+            _ => None,
+        }
+    }
+
+    /// A register as it's sent to gdb, which is `x`s when it has no value.
+    fn register(&self, index: usize) -> String {
+        match self.register_value(index) {
+            Some(value) => hex(&value.to_le_bytes()),
+            None => String::from("xxxxxxxx"),
+        }
+    }
+
+    /// Writes to registers that mipsy doesn't have are ignored.
+    fn write_register(&mut self, index: usize, value: u32) {
+        let state = self.runtime.timeline_mut().state_mut();
+
+        match index {
+            0..=31 => state.write_register(index as u32, value as i32),
+            REG_LO => state.write_lo(value as i32),
+            REG_HI => state.write_hi(value as i32),
+            REG_PC => state.set_pc(value),
+            _ => {}
+        }
+    }
+
+    /// Reads up to `len` bytes, stopping early at memory that can't be read at all.
+    /// Uninitialized bytes are read as zero.
+    fn read_memory(&self, addr: u32, len: u32) -> String {
+        let state = self.runtime.timeline().state();
+
+        let bytes = (0..len)
+            .map_while(|offset| {
+                let byte = state.read_mem_byte_uninit(addr.checked_add(offset)?).ok()?;
+                Some(byte.into_option().unwrap_or(0))
+            })
+            .collect::<Vec<_>>();
+
+        if bytes.is_empty() && len > 0 {
+            String::from("E14")
+        } else {
+            hex(&bytes)
+        }
+    }
+
+    fn write_memory(&mut self, addr: u32, data: &[u8]) -> String {
+        let state = self.runtime.timeline_mut().state_mut();
+
+        for (offset, &byte) in data.iter().enumerate() {
+            let written = addr
+                .checked_add(offset as u32)
+                .map(|addr| state.write_mem_byte(addr, byte));
+
+            if !matches!(written, Some(Ok(()))) {
+                return String::from("E14");
+            }
+        }
+
+        String::from("OK")
+    }
+
+    fn next_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        id
+    }
+
+    /// `Z type,addr,kind`: software and hardware breakpoints are the same thing in mipsy,
+    /// and watchpoints can be on writes, reads, or both.
+    fn insert_point(&mut self, point: &[u8]) -> String {
+        let (kind, addr, len) = match parse_point(point) {
+            Some(point) => point,
+            None => return String::from("E01"),
+        };
+
+        match kind {
+            0 | 1 => {
+                if !self.binary.breakpoints.contains_key(&addr) {
+                    let id = self.next_id();
+                    self.binary.breakpoints.insert(addr, Breakpoint::new(id));
+                }
+            }
+            2..=4 => {
+                let action = watch_action(kind);
+                let target = WatchpointTarget::MemRange { start: addr, len };
+
+                match self.binary.watchpoints.get_mut(&target) {
+                    Some(wp) if wp.action != action => wp.action = TargetAction::ReadWrite,
+                    Some(_) => {}
+                    None => {
+                        let id = self.next_id();
+                        self.binary
+                            .watchpoints
+                            .insert(target, Watchpoint::new(id, action));
+                    }
+                }
+            }
+            _ => return String::new(),
+        }
+
+        String::from("OK")
+    }
+
+    fn remove_point(&mut self, point: &[u8]) -> String {
+        let (kind, addr, len) = match parse_point(point) {
+            Some(point) => point,
+            None => return String::from("E01"),
+        };
+
+        match kind {
+            0 | 1 => {
+                self.binary.breakpoints.remove(&addr);
+            }
+            2..=4 => {
+                let target = WatchpointTarget::MemRange { start: addr, len };
+
+                if let Some(wp) = self.binary.watchpoints.get_mut(&target) {
+                    match wp.action - watch_action(kind) {
+                        Some(action) => wp.action = action,
+                        None => {
+                            self.binary.watchpoints.remove(&target);
+                        }
+                    }
+                }
+            }
+            _ => return String::new(),
+        }
+
+        String::from("OK")
+    }
+
+    /// Runs (or undoes) instructions until there's a reason to stop.
+    fn resume(&mut self, resume: Resume, interrupted: &mut dyn FnMut() -> bool) -> Stop {
+        let stop = match resume {
+            Resume::Step => self.step().unwrap_or(Stop::Signal(SIGTRAP)),
+            Resume::ReverseStep => self.step_back().unwrap_or(Stop::Signal(SIGTRAP)),
+            Resume::Continue | Resume::ReverseContinue => 'run: loop {
+                for _ in 0..BATCH_SIZE {
+                    let stop = if resume == Resume::Continue {
+                        self.step()
+                    } else {
+                        self.step_back()
+                    };
+
+                    if let Some(stop) = stop {
+                        break 'run stop;
+                    }
+                }
+
+                if interrupted() {
+                    break Stop::Signal(SIGINT);
+                }
+            },
+        };
+
+        self.output.flush();
+        stop
+    }
+
+    fn step(&mut self) -> Option<Stop> {
+        let skip_watchpoints = self.watch_stop.take() == Some(self.pc());
+
+        let runtime = take(&mut self.runtime);
+        let inst = runtime.current_inst();
+
+        let stop = match runtime.step() {
+            Ok(Ok(runtime)) => {
+                self.runtime = runtime;
+                None
+            }
+            Ok(Err(guard)) => self.syscall(guard),
+            Err((runtime, MipsyError::Runtime(err))) => {
+                self.runtime = runtime;
+                return Some(self.runtime_error(err));
+            }
+            Err((_, MipsyError::Parser(_) | MipsyError::Compiler(_))) => {
+                unreachable!("the only possible error at runtime is a MipsyError::Runtime");
+            }
+        };
+
+        if stop.is_some() {
+            return stop;
+        }
+
+        if !skip_watchpoints {
+            if let Some(stop) = self.check_watchpoints(inst) {
+                // gdb expects MIPS watchpoints to stop before the access happens,
+                // but syscalls can't be undone without repeating their I/O
+                if inst >> 26 != SPECIAL || inst & 0x3F != SYSCALL {
+                    self.runtime.timeline_mut().pop_last_state();
+                    self.watch_stop = Some(self.pc());
+                }
+
+                return Some(stop);
+            }
+        }
+
+        self.breakpoint_at_pc()
+    }
+
+    /// Undoes the last instruction.
+    fn step_back(&mut self) -> Option<Stop> {
+        let timeline = self.runtime.timeline();
+        let inst = match timeline.prev_state() {
+            Some(state) => state.read_mem_word(state.pc()).unwrap_or(0),
+            None => return Some(Stop::HistoryStart),
+        };
+
+        let stop = self.check_watchpoints(inst);
+        self.runtime.timeline_mut().pop_last_state();
+        self.watch_stop = None;
+
+        stop.or_else(|| self.breakpoint_at_pc())
+    }
+
+    fn pc(&self) -> u32 {
+        self.runtime.timeline().state().pc()
+    }
+
+    fn breakpoint_at_pc(&self) -> Option<Stop> {
+        self.binary
+            .breakpoints
+            .get(&self.pc())
+            .is_some_and(|bp| bp.enabled)
+            .then_some(Stop::Breakpoint)
+    }
+
+    /// The first watchpoint on the memory that `inst` (which was just run) touched.
+    fn check_watchpoints(&self, inst: u32) -> Option<Stop> {
+        let affected = get_affected_registers(&self.runtime, inst);
+        let state = self.runtime.timeline().state();
+
+        find_watchpoint_hits(&self.binary.watchpoints, &affected, state)
+            .into_iter()
+            .find_map(|hit| match hit.target {
+                WatchpointTarget::MemRange { start, .. } => Some(Stop::Watchpoint {
+                    action: self.binary.watchpoints[&hit.target].action,
+                    addr: start,
+                }),
+                WatchpointTarget::Register(_) => None,
+            })
+    }
+
+    fn syscall(&mut self, guard: RuntimeSyscallGuard) -> Option<Stop> {
+        use RuntimeSyscallGuard::*;
+
+        let input = &mut *self.input;
+        let output = &mut *self.output;
+
+        let guard = match guard.perform_io(input, output, &mut report_bad_input) {
+            Ok(runtime) => {
+                self.runtime = runtime;
+                return None;
+            }
+            Err(guard) => guard,
+        };
+
+        let (runtime, stop) = match guard {
+            PrintInt(..) | PrintFloat(..) | PrintDouble(..) | PrintString(..) | PrintChar(..) => {
+                unreachable!("printed by perform_io")
+            }
+            ReadInt(_) | ReadFloat(_) | ReadDouble(_) | ReadString(..) | ReadChar(_) => {
+                unreachable!("read by perform_io")
+            }
+            Sbrk(_args, runtime) => (runtime, None),
+            Exit(runtime) => (runtime, Some(Stop::Exited(0))),
+            ExitStatus(args, runtime) => (runtime, Some(Stop::Exited(args.exit_code))),
+            Open(_args, guard) => return self.unimplemented(guard(-1), SYS13_OPEN),
+            Read(_args, guard) => return self.unimplemented(guard((-1, Vec::new())), SYS14_READ),
+            Write(_args, guard) => return self.unimplemented(guard(-1), SYS15_WRITE),
+            Close(_args, guard) => return self.unimplemented(guard(-1), SYS16_CLOSE),
+            Time(guard) => {
+                let millis = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map(|time| time.as_millis() as u64)
+                    .unwrap_or(0);
+                (guard(millis), None)
+            }
+            Sleep(args, runtime) => {
+                std::thread::sleep(Duration::from_millis(args.millis as u64));
+                (runtime, None)
+            }
+            ConfirmDialog(args, guard) => {
+                output.write_bytes(&args.message);
+                output.write_bytes(b" [y/n] ");
+                output.flush();
+                (guard(read_confirm(input)), None)
+            }
+            InputDialogInt(args, guard) => {
+                output.write_bytes(&args.message);
+                output.flush();
+                (guard(get_input(input, "int", sys_io::read_int)), None)
+            }
+            InputDialogString(args, guard) => {
+                output.write_bytes(&args.message);
+                output.flush();
+
+                let string = get_input(input, "string", sys_io::read_line).map(|mut line| {
+                    if line.last() == Some(&b'\n') {
+                        line.pop();
+                    }
+                    line
+                });
+                (guard(string), None)
+            }
+            Breakpoint(runtime) => (runtime, Some(Stop::Breakpoint)),
+            Trap(runtime) => (runtime, Some(Stop::Signal(SIGTRAP))),
+        };
+
+        self.runtime = runtime;
+        stop
+    }
+
+    /// The file syscalls aren't supported, so they're undone and reported as an error.
+    fn unimplemented(&mut self, mut runtime: Runtime, syscall: i32) -> Option<Stop> {
+        runtime.timeline_mut().pop_last_state();
+        self.runtime = runtime;
+
+        Some(self.runtime_error(RuntimeError::new(Error::InvalidSyscall {
+            syscall,
+            reason: InvalidSyscallReason::Unimplemented,
+        })))
+    }
+
+    /// Shows the error in mipsy's terminal, and tells gdb about it as the closest signal.
+    fn runtime_error(&mut self, err: RuntimeError) -> Stop {
+        self.output.flush();
+        println!();
+        err.show_error(
+            ErrorContext::Binary,
+            self.files.clone(),
+            self.iset,
+            self.binary,
+            &self.runtime,
+        );
+
+        Stop::Signal(match err.error() {
+            Error::UnknownInstruction { .. } => SIGILL,
+            Error::UnalignedAccess { .. } => SIGBUS,
+            Error::IntegerOverflow | Error::DivisionByZero => SIGFPE,
+            Error::Uninitialised { .. } | Error::SegmentationFault { .. } => SIGSEGV,
+            Error::InvalidSyscall { .. } => SIGSYS,
+        })
+    }
+}
+
+/// Parses `addr,length`.
+fn parse_range(range: &[u8]) -> Option<(u32, u32)> {
+    let (addr, len) = split(range, b',')?;
+    Some((parse_hex(addr)?, parse_hex(len)?))
+}
+
+/// Parses the `type,addr,kind` of a `Z` or `z` packet,
+/// where the kind of a watchpoint is its length.
+fn parse_point(point: &[u8]) -> Option<(u32, u32, u32)> {
+    let (kind, range) = split(point, b',')?;
+    let (addr, len) = parse_range(range.split(|&byte| byte == b';').next()?)?;
+
+    Some((parse_hex(kind)?, addr, len))
+}
+
+fn watch_action(kind: u32) -> TargetAction {
+    match kind {
+        2 => TargetAction::WriteOnly,
+        3 => TargetAction::ReadOnly,
+        _ => TargetAction::ReadWrite,
+    }
+}
+
+/// Replies to `qXfer:features:read:annex:offset,length` with part of the target description.
+fn read_target_xml(request: &[u8]) -> String {
+    let range = match split(request, b':') {
+        Some((b"target.xml", range)) => parse_range(range),
+        _ => None,
+    };
+
+    let (offset, len) = match range {
+        Some((offset, len)) => (offset as usize, len as usize),
+        None => return String::from("E00"),
+    };
+
+    let rest = TARGET_XML.get(offset.min(TARGET_XML.len())..).unwrap_or("");
+
+    if rest.len() <= len {
+        format!("l{rest}")
+    } else {
+        format!("m{}", &rest[..len])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mipsy_lib::{
+        compile::CompilerOptions,
+        runtime::io::{MemoryInput, MemoryOutput},
+    };
+    use mipsy_utils::MipsyConfig;
+
+    use super::*;
+
+    const PROGRAM: &str = "\
+main:
+    li   $t0, 5
+    sw   $t0, value
+    addi $t0, $t0, 1
+    li   $v0, 10
+    syscall
+
+    .data
+value:
+    .word 0
+";
+
+    fn with_stub(test: impl FnOnce(&mut Stub<'_>)) {
+        let files = [(String::from("test.s"), String::from(PROGRAM))];
+        let (iset, mut binary, runtime, _) = crate::compile(
+            mipsy_instructions::inst_set(),
+            &CompilerOptions::new(vec![]),
+            &MipsyConfig::default(),
+            &files,
+            &[],
+        )
+        .unwrap();
+
+        let mut input = MemoryInput::new("");
+        let mut output = MemoryOutput::new();
+        let mut stub = Stub::new(&iset, &mut binary, runtime, &files, &mut input, &mut output);
+
+        test(&mut stub);
+    }
+
+    fn reply(stub: &mut Stub<'_>, command: &str) -> String {
+        match stub.handle(command.as_bytes()) {
+            Action::Reply(reply) => reply,
+            _ => panic!("expected `{command}` to be replied to"),
+        }
+    }
+
+    fn resume(stub: &mut Stub<'_>, command: &str) -> Stop {
+        match stub.handle(command.as_bytes()) {
+            Action::Resume(resume) => stub.resume(resume, &mut || false),
+            _ => panic!("expected `{command}` to resume"),
+        }
+    }
+
+    fn label(stub: &Stub<'_>, label: &str) -> u32 {
+        stub.binary.labels[label]
+    }
+
+    #[test]
+    fn test_packets() {
+        let mut written = vec![];
+        write_packet(&mut written, b"a}b#").unwrap();
+        assert_eq!(written, b"$a}]b}\x03#1d");
+
+        let mut acks = vec![];
+        let mut incoming = &b"+$bad#00$m0,4#fd\x03"[..];
+        assert_eq!(
+            read_packet(&mut incoming, &mut acks).unwrap(),
+            Some(Packet::Command(b"m0,4".to_vec()))
+        );
+        assert_eq!(
+            read_packet(&mut incoming, &mut acks).unwrap(),
+            Some(Packet::Interrupt)
+        );
+        assert_eq!(read_packet(&mut incoming, &mut acks).unwrap(), None);
+        assert_eq!(acks, b"-+");
+
+        assert_eq!(
+            read_target_xml(b"target.xml:0,5"),
+            format!("m{}", &TARGET_XML[..5])
+        );
+        assert_eq!(
+            read_target_xml(b"target.xml:5,1000"),
+            format!("l{}", &TARGET_XML[5..])
+        );
+    }
+
+    #[test]
+    fn test_registers_and_memory() {
+        with_stub(|stub| {
+            let pc = stub.pc();
+            let registers = reply(stub, "g");
+
+            assert_eq!(registers.len(), NUM_REGS * 8);
+            assert_eq!(&registers[..8], "00000000");
+            assert_eq!(&registers[8..16], "xxxxxxxx");
+            assert_eq!(&registers[REG_PC * 8..][..8], hex(&pc.to_le_bytes()));
+
+            assert_eq!(reply(stub, "P8=2a000000"), "OK");
+            assert_eq!(reply(stub, "p8"), "2a000000");
+
+            let value = label(stub, "value");
+            assert_eq!(reply(stub, &format!("M{value:x},2:3412")), "OK");
+            assert_eq!(reply(stub, &format!("m{value:x},4")), "34120000");
+            assert_eq!(reply(stub, "m0,4"), "E14");
+
+            assert_eq!(reply(stub, "vMustReplyEmpty"), "");
+        });
+    }
+
+    #[test]
+    fn test_breakpoints_and_reverse() {
+        with_stub(|stub| {
+            let second = label(stub, "main") + 4;
+
+            assert_eq!(reply(stub, &format!("Z0,{second:x},4")), "OK");
+            assert_eq!(resume(stub, "c"), Stop::Breakpoint);
+            assert_eq!(stub.pc(), second);
+
+            assert_eq!(reply(stub, &format!("z0,{second:x},4")), "OK");
+            assert!(stub.binary.breakpoints.is_empty());
+
+            assert_eq!(resume(stub, "s"), Stop::Signal(SIGTRAP));
+            assert_eq!(stub.pc(), second + 4);
+
+            assert_eq!(resume(stub, "bs"), Stop::Signal(SIGTRAP));
+            assert_eq!(stub.pc(), second);
+            assert_eq!(resume(stub, "bc"), Stop::HistoryStart);
+
+            assert_eq!(resume(stub, "c"), Stop::Exited(0));
+        });
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        with_stub(|stub| {
+            // `sw $t0, value` is `lui`, `ori`, then the store itself
+            let store = label(stub, "main") + 12;
+            let value = label(stub, "value");
+
+            assert_eq!(reply(stub, &format!("Z2,{value:x},4")), "OK");
+
+            // stops before the store, like gdb expects
+            let stop = resume(stub, "c");
+            assert_eq!(
+                stop,
+                Stop::Watchpoint {
+                    action: TargetAction::WriteOnly,
+                    addr: value
+                }
+            );
+            assert_eq!(stop.reply(), format!("T05watch:{value:x};"));
+            assert_eq!(stub.pc(), store);
+
+            assert_eq!(resume(stub, "s"), Stop::Signal(SIGTRAP));
+            assert_eq!(reply(stub, &format!("m{value:x},4")), "05000000");
+
+            // and is found again going backwards
+            assert!(matches!(resume(stub, "bc"), Stop::Watchpoint { .. }));
+            assert_eq!(stub.pc(), store);
+
+            assert_eq!(reply(stub, &format!("z2,{value:x},4")), "OK");
+            assert!(stub.binary.watchpoints.is_empty());
+        });
+    }
+}
@@ -1,24 +1,28 @@
 use std::{
-    fmt::{Debug, Display},
     fs,
     io::Write,
     process,
     rc::Rc,
-    str::FromStr,
+    time::{Duration, SystemTime},
 };
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 use colored::Colorize;
+use mipsy_instructions::validate::InstSetError;
 use mipsy_interactive::prompt;
 use mipsy_lib::error::runtime::{Error, ErrorContext, InvalidSyscallReason, RuntimeError};
+use mipsy_lib::runtime::io::{
+    self, Input, Output, ReadError, ReaderInput, StdinInput, StdoutOutput, WriterOutput,
+};
 use mipsy_lib::runtime::{SYS13_OPEN, SYS14_READ, SYS15_WRITE, SYS16_CLOSE};
 use mipsy_lib::{
     compile::{get_kernel, CompilerOptions},
-    Binary, InstSet, MipsyError, MipsyResult, MpProgram, Runtime, Safe,
+    Binary, CompileResult, CompilerWarning, InstSet, MipsyError, MpProgram, Runtime, Safe,
 };
 use mipsy_parser::TaggedFile;
-use mipsy_utils::{config_path, read_config, MipsyConfig, MipsyConfigError};
-use text_io::try_read;
+use mipsy_utils::{config_path, expand_tilde, read_config, MipsyConfig, MipsyConfigError};
+
+mod gdb;
 
 #[derive(Parser, Debug)]
 #[command(version = VERSION, author = "Zac K. <zac.kologlu@gmail.com>")]
@@ -31,6 +35,10 @@ struct Opts {
     #[arg(long)]
     check_no_main: bool,
 
+    /// Treat compiler warnings as errors
+    #[arg(long)]
+    deny_warnings: bool,
+
     /// Just compile program instead of executing
     #[arg(long)]
     compile: bool,
@@ -43,112 +51,117 @@ struct Opts {
     #[arg(long)]
     hex_pad_zero: bool,
 
+    /// Implies --compile: write the compiled program to a `.mipsy` file instead
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<String>,
+
+    /// Just compile program and write it to an ELF executable
+    #[arg(long, value_name = "FILE")]
+    elf: Option<String>,
+
     /// Enable some SPIM compatibility options
     #[arg(long)]
     spim: bool,
 
+    /// Enable the MARS-compatible extended syscalls
+    #[arg(long)]
+    mars: bool,
+
+    /// Use the instructions from a YAML file (in the format of mips.yaml) instead of the usual ones
+    #[arg(long, value_name = "FILE")]
+    iset: Option<String>,
+
     /// Move a label to point to a different label
     #[arg(long)]
     move_label: Vec<String>,
 
-    /// File(s) to be loaded and executed
+    /// Define a symbol for `.if`/`.ifdef`, as if by `.set NAME value`
+    #[arg(short = 'D', value_name = "NAME[=VALUE]")]
+    define: Vec<String>,
+
+    /// Read the program's input from a file instead of stdin
+    #[arg(long)]
+    stdin_file: Option<String>,
+
+    /// Write the program's output to a file instead of stdout
+    #[arg(long)]
+    stdout_file: Option<String>,
+
+    /// Let gdb debug the program over the remote protocol, listening on ADDR (e.g. 127.0.0.1:1234)
+    #[arg(long, value_name = "ADDR")]
+    gdb: Option<String>,
+
+    /// File(s) to be loaded and executed, or a single `.mipsy` or ELF binary
+    /// (a file named like a subcommand, e.g. `fmt`, has to be given as `./fmt`)
     #[arg()]
     files: Vec<String>,
 
     /// Command line argument(s) to be passed to the program
     #[arg(last = true, requires = "files")]
     args: Vec<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-fn get_input<T>(name: &str, line: bool) -> T
-where
-    T: FromStr + Display,
-    <T as FromStr>::Err: Debug,
-{
-    loop {
-        let result: Result<T, _> = if line {
-            let mut input = String::new();
-            std::io::stdin().read_line(&mut input).unwrap();
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Format source files in the style from your mipsy config
+    Fmt(FmtOpts),
 
-            input.parse().map_err(|_| ())
-        } else {
-            try_read!().map_err(|_| ())
-        };
-
-        match result {
-            Ok(n) => return n,
-            Err(_) => {
-                print!("[mipsy] bad input (expected {}), try again: ", name);
-                std::io::stdout().flush().unwrap();
+    /// Work with instruction sets
+    #[command(subcommand)]
+    Isa(IsaCommand),
+}
 
-                continue;
-            }
-        };
-    }
+#[derive(Subcommand, Debug)]
+enum IsaCommand {
+    /// Check an instruction set for mistakes, like ambiguous encodings or shadowed pseudo-instructions
+    Check {
+        /// YAML file to be checked (by default, the one from --iset or the usual one)
+        file: Option<String>,
+    },
 }
 
-fn get_input_eof<T>(name: &str) -> Option<T>
-where
-    T: FromStr + Display,
-    <T as FromStr>::Err: Debug,
-{
-    loop {
-        let result: Result<T, _> = try_read!();
+#[derive(Args, Debug)]
+struct FmtOpts {
+    /// Don't change anything, but fail if any file isn't already formatted
+    #[arg(long, conflicts_with = "write")]
+    check: bool,
 
-        match result {
-            Ok(n) => return Some(n),
-            Err(text_io::Error::Parse(leftover, _)) => {
-                if leftover.is_empty() {
-                    return None;
-                }
+    /// Overwrite the files with their formatted contents, instead of printing them
+    #[arg(long)]
+    write: bool,
 
-                print!("[mipsy] bad input (expected {}), try again: ", name);
-                std::io::stdout().flush().unwrap();
-                continue;
-            }
-            Err(_) => {
-                print!("[mipsy] bad input (expected {}), try again: ", name);
-                std::io::stdout().flush().unwrap();
-                continue;
-            }
-        };
-    }
+    /// File(s) to be formatted
+    #[arg(required = true)]
+    files: Vec<String>,
 }
 
-fn get_input_int(name: &str) -> Option<i32> {
-    loop {
-        let result: Result<i128, _> = try_read!();
-
-        match result {
-            Ok(n) => match i32::try_from(n) {
-                Ok(n) => return Some(n),
-                Err(_) => {
-                    println!("[mipsy] bad input (too big to fit in 32 bits)");
-                    println!(
-                        "[mipsy] if you want the value to be truncated to 32 bits, try {}",
-                        n as i32
-                    );
-                    print!("[mipsy] try again: ");
-                    std::io::stdout().flush().unwrap();
-                    continue;
-                }
-            },
-            Err(text_io::Error::Parse(leftover, _)) => {
-                if leftover.is_empty() {
-                    return None;
-                }
+fn get_input<T>(
+    input: &mut dyn Input,
+    name: &str,
+    read: fn(&mut dyn Input) -> Result<T, ReadError>,
+) -> Option<T> {
+    io::read_retrying(input, name, read, &mut report_bad_input)
+}
 
-                print!("[mipsy] bad input (expected {}), try again: ", name);
-                std::io::stdout().flush().unwrap();
-                continue;
-            }
-            Err(_) => {
-                print!("[mipsy] bad input (expected {}), try again: ", name);
-                std::io::stdout().flush().unwrap();
-                continue;
-            }
-        };
+fn report_bad_input(name: &str, err: &ReadError) {
+    match err {
+        ReadError::TooBig(n) => {
+            println!("[mipsy] bad input (too big to fit in 32 bits)");
+            println!(
+                "[mipsy] if you want the value to be truncated to 32 bits, try {}",
+                *n as i32
+            );
+            print!("[mipsy] try again: ");
+        }
+        ReadError::Invalid(_) | ReadError::Eof => {
+            print!("[mipsy] bad input (expected {}), try again: ", name);
+        }
     }
+
+    std::io::stdout().flush().unwrap();
 }
 
 fn main() {
@@ -174,6 +187,16 @@ fn main() {
             .collect::<Vec<_>>()
     };
 
+    // `-D NAME` on its own defines NAME as 1
+    let defines = opts
+        .define
+        .into_iter()
+        .map(|define| match define.split_once('=') {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None => (define, String::from("1")),
+        })
+        .collect::<Vec<_>>();
+
     let mut config = match read_config() {
         Ok(config) => config,
         Err(MipsyConfigError::InvalidConfig(to_path, config)) => {
@@ -198,12 +221,27 @@ fn main() {
         config.spim = true;
     }
 
+    if opts.mars {
+        config.mars = true;
+    }
+
+    match opts.command {
+        Some(Command::Fmt(fmt_opts)) => format_files(fmt_opts, &config),
+        Some(Command::Isa(IsaCommand::Check { file })) => check_iset(file.or(opts.iset)),
+        None => {}
+    }
+
+    let iset = match &opts.iset {
+        Some(path) => load_iset(path),
+        None => mipsy_instructions::inst_set(),
+    };
+
     if opts.files.is_empty() {
         // launch() returns !
-        mipsy_interactive::launch(config);
+        mipsy_interactive::launch(config, iset);
     }
 
-    let files = opts
+    let (binaries, files): (Vec<_>, Vec<_>) = opts
         .files
         .into_iter()
         .map(|mut name| {
@@ -212,7 +250,7 @@ fn main() {
                 name = String::from("/dev/stdin");
             }
 
-            let file_contents = match fs::read_to_string(&name) {
+            let file_contents = match fs::read(&name) {
                 Ok(contents) => contents,
                 Err(err) => {
                     prompt::error_nl(format!(
@@ -227,14 +265,91 @@ fn main() {
 
             (name, file_contents)
         })
+        .partition(|(_, contents)| {
+            mipsy_lib::cache::is_cache(contents) || mipsy_lib::elf::is_elf(contents)
+        });
+
+    let binary_file = match (binaries.into_iter().next(), files.first()) {
+        (Some((name, _)), Some(_)) => {
+            prompt::error_nl(format!(
+                "`{}` is already compiled, so it has to be the only file",
+                name.bold(),
+            ));
+
+            process::exit(1);
+        }
+        (binary_file, _) => binary_file,
+    };
+
+    let mut files = files
+        .into_iter()
+        .map(|(name, contents)| match String::from_utf8(contents) {
+            Ok(contents) => (name, contents),
+            Err(_) => {
+                prompt::error_nl(format!(
+                    "failed to read file `{}`: {}",
+                    name.bold(),
+                    "stream did not contain valid UTF-8".bright_red()
+                ));
+
+                process::exit(1);
+            }
+        })
         .collect::<Vec<_>>();
 
+    if let Err((name, err)) =
+        mipsy_parser::load_includes(&mut files, |path| fs::read_to_string(path))
+    {
+        prompt::error_nl(format!(
+            "failed to read file `{}`: {}",
+            name.bold(),
+            err.to_string().bright_red()
+        ));
+
+        process::exit(1);
+    }
+
     let args = opts.args.iter().map(|arg| &**arg).collect::<Vec<_>>();
 
-    let compiler_options = CompilerOptions::new(moves);
+    let mut input: Box<dyn Input> = match &opts.stdin_file {
+        Some(path) => match ReaderInput::from_file(expand_tilde(path)) {
+            Ok(input) => Box::new(input),
+            Err(err) => {
+                prompt::error_nl(format!(
+                    "failed to read file `{}`: {}",
+                    path.bold(),
+                    err.to_string().bright_red()
+                ));
+
+                process::exit(1);
+            }
+        },
+        None => Box::new(StdinInput),
+    };
+
+    let mut output: Box<dyn Output> = match &opts.stdout_file {
+        Some(path) => match WriterOutput::from_file(expand_tilde(path)) {
+            Ok(output) => Box::new(output),
+            Err(err) => {
+                prompt::error_nl(format!(
+                    "failed to write file `{}`: {}",
+                    path.bold(),
+                    err.to_string().bright_red()
+                ));
+
+                process::exit(1);
+            }
+        },
+        None => Box::new(StdoutOutput),
+    };
+
+    let compiler_options = CompilerOptions::new(moves).with_defines(defines);
 
-    let compiled = if opts.check_no_main {
+    let compiled = if let Some((name, contents)) = &binary_file {
+        load_binary(iset, &config, name, contents, &args, !opts.check_no_main)
+    } else if opts.check_no_main {
         compile_with_kernel(
+            iset,
             &compiler_options,
             &config,
             &files,
@@ -242,55 +357,80 @@ fn main() {
             &mut MpProgram::new(vec![], vec![]),
         )
     } else {
-        compile(&compiler_options, &config, &files, &args)
+        compile(iset, &compiler_options, &config, &files, &args)
     };
 
-    let (iset, binary, mut runtime) = match compiled {
-        Ok((iset, binary, runtime)) => (iset, binary, runtime),
+    let (iset, mut binary, mut runtime) = match compiled {
+        Ok((iset, binary, runtime, warnings)) => {
+            for warning in warnings.iter() {
+                let file = files
+                    .iter()
+                    .find(|(tag, _)| **tag == *warning.file_tag())
+                    .map(|(_, str)| Rc::from(&**str))
+                    .unwrap_or_else(|| Rc::from(""));
+
+                prompt::warning(format!(
+                    "`{}` in `{}`",
+                    warning.warning().code(),
+                    warning.file_tag()
+                ));
+                warning.show_warning(&config, file);
+            }
 
-        Err(MipsyError::Parser(error)) => {
-            prompt::error(format!("failed to parse `{}`", error.file_tag()));
+            if opts.deny_warnings && !warnings.is_empty() {
+                let plural = if warnings.len() == 1 { "" } else { "s" };
 
-            let file_tag = error.file_tag();
+                prompt::error(format!(
+                    "aborting due to {} warning{} (`{}` is enabled)",
+                    warnings.len(),
+                    plural,
+                    "--deny-warnings".bold(),
+                ));
 
-            let file = files
-                .iter()
-                .find(|(tag, _)| &**tag == &*file_tag)
-                .map(|(_, str)| Rc::from(&**str))
-                .expect("for file to throw a parser error, it should probably exist");
+                process::exit(1);
+            }
+
+            (iset, binary, runtime)
+        }
 
-            error.show_error(&config, file);
+        Err(errors) => {
+            show_errors(errors, &files, &config);
 
             process::exit(1);
         }
+    };
 
-        Err(MipsyError::Compiler(error)) => {
-            let compile_tag = if error.file_tag().is_empty() {
-                String::new()
-            } else {
-                format!(" `{}`", error.file_tag())
-            };
+    if opts.check || opts.check_no_main {
+        return;
+    }
+
+    if let Some(path) = &opts.output {
+        let cache = mipsy_lib::cache::write_cache(&binary, &iset);
 
-            prompt::error(format!("failed to compile{}", compile_tag));
+        if let Err(err) = fs::write(path, cache) {
+            prompt::error_nl(format!(
+                "failed to write file `{}`: {}",
+                path.bold(),
+                err.to_string().bright_red()
+            ));
 
-            let file_tag = error.file_tag();
+            process::exit(1);
+        }
 
-            let file = files
-                .iter()
-                .find(|(tag, _)| &**tag == &*file_tag)
-                .map(|(_, str)| Rc::from(&**str))
-                .unwrap_or_else(|| Rc::from(""));
+        return;
+    }
 
-            error.show_error(&config, file);
+    if let Some(path) = &opts.elf {
+        if let Err(err) = fs::write(path, mipsy_lib::elf::write_elf(&binary)) {
+            prompt::error_nl(format!(
+                "failed to write file `{}`: {}",
+                path.bold(),
+                err.to_string().bright_red()
+            ));
 
             process::exit(1);
         }
 
-        // unreachable: a bit tricky to get a runtime error at compile-time
-        Err(MipsyError::Runtime(_)) => unreachable!(),
-    };
-
-    if opts.check || opts.check_no_main {
         return;
     }
 
@@ -317,6 +457,33 @@ fn main() {
         return;
     }
 
+    if let Some(addr) = &opts.gdb {
+        let ending = gdb::serve(
+            addr,
+            &iset,
+            &mut binary,
+            runtime,
+            &files,
+            &mut *input,
+            &mut *output,
+        );
+
+        runtime = match ending {
+            Ok(gdb::Ending::Detached(runtime)) => *runtime,
+            Ok(gdb::Ending::Exited(code)) => process::exit(code),
+            Ok(gdb::Ending::Killed) => process::exit(1),
+            Err(err) => {
+                prompt::error_nl(format!(
+                    "failed to debug with gdb on `{}`: {}",
+                    addr.bold(),
+                    err.to_string().bright_red()
+                ));
+
+                process::exit(1);
+            }
+        };
+    }
+
     loop {
         match runtime.step() {
             Ok(stepped_runtime) => {
@@ -327,67 +494,35 @@ fn main() {
                     Err(runtime_guard) => {
                         use mipsy_lib::runtime::RuntimeSyscallGuard::*;
 
-                        match runtime_guard {
-                            PrintInt(args, new_runtime) => {
-                                print!("{}", args.value);
-                                std::io::stdout().flush().unwrap();
-
-                                runtime = new_runtime;
-                            }
-                            PrintFloat(args, new_runtime) => {
-                                print!("{}", args.value);
-                                std::io::stdout().flush().unwrap();
-
-                                runtime = new_runtime;
-                            }
-                            PrintDouble(args, new_runtime) => {
-                                print!("{}", args.value);
-                                std::io::stdout().flush().unwrap();
-
+                        let runtime_guard = match runtime_guard.perform_io(
+                            &mut *input,
+                            &mut *output,
+                            &mut report_bad_input,
+                        ) {
+                            Ok(new_runtime) => {
                                 runtime = new_runtime;
+                                continue;
                             }
-                            PrintString(args, new_runtime) => {
-                                print!("{}", String::from_utf8_lossy(&args.value));
-                                std::io::stdout().flush().unwrap();
+                            Err(runtime_guard) => runtime_guard,
+                        };
 
-                                runtime = new_runtime;
-                            }
-                            ReadInt(guard) => {
-                                let number = get_input_int("int").unwrap_or(0);
-                                runtime = guard(number);
-                            }
-                            ReadFloat(guard) => {
-                                let number = get_input_eof("float").unwrap_or(0.0);
-                                runtime = guard(number);
-                            }
-                            ReadDouble(guard) => {
-                                let number = get_input_eof("double").unwrap_or(0.0);
-                                runtime = guard(number);
-                            }
-                            ReadString(args, guard) => {
-                                let string = read_string(args.max_len);
-                                runtime = guard(string.into_bytes());
-                            }
+                        match runtime_guard {
+                            PrintInt(..) | PrintFloat(..) | PrintDouble(..) | PrintString(..)
+                            | PrintChar(..) => unreachable!("printed by perform_io"),
+                            ReadInt(_) | ReadFloat(_) | ReadDouble(_) | ReadString(..)
+                            | ReadChar(_) => unreachable!("read by perform_io"),
                             Sbrk(_args, new_runtime) => {
                                 runtime = new_runtime;
                             }
                             Exit(_new_runtime) => {
+                                output.flush();
                                 std::process::exit(0);
                             }
-                            PrintChar(args, new_runtime) => {
-                                print!("{}", args.value as char);
-                                std::io::stdout().flush().unwrap();
-
-                                runtime = new_runtime;
-                            }
-                            ReadChar(guard) => {
-                                let character: char = get_input_eof("character").unwrap_or('\0');
-                                runtime = guard(character as u8);
-                            }
                             Open(_args, guard) => {
                                 // TODO: implement file open for mipsy cli frontend
                                 runtime = guard(-1);
                                 runtime.timeline_mut().pop_last_state();
+                                output.flush();
                                 println!();
                                 RuntimeError::new(Error::InvalidSyscall {
                                     syscall: SYS13_OPEN,
@@ -411,6 +546,7 @@ fn main() {
                                 // TODO: implement file read for mipsy cli frontend
                                 runtime = guard((-1, Vec::new()));
                                 runtime.timeline_mut().pop_last_state();
+                                output.flush();
                                 println!();
                                 RuntimeError::new(Error::InvalidSyscall {
                                     syscall: SYS14_READ,
@@ -434,6 +570,7 @@ fn main() {
                                 // TODO: implement file write for mipsy cli frontend
                                 runtime = guard(-1);
                                 runtime.timeline_mut().pop_last_state();
+                                output.flush();
                                 println!();
                                 RuntimeError::new(Error::InvalidSyscall {
                                     syscall: SYS15_WRITE,
@@ -457,6 +594,7 @@ fn main() {
                                 // TODO: implement file close for mipsy cli frontend
                                 runtime = guard(-1);
                                 runtime.timeline_mut().pop_last_state();
+                                output.flush();
                                 println!();
                                 RuntimeError::new(Error::InvalidSyscall {
                                     syscall: SYS16_CLOSE,
@@ -477,8 +615,46 @@ fn main() {
                                 process::exit(1);
                             }
                             ExitStatus(args, _new_runtime) => {
+                                output.flush();
                                 std::process::exit(args.exit_code);
                             }
+                            Time(guard) => {
+                                let millis = SystemTime::now()
+                                    .duration_since(SystemTime::UNIX_EPOCH)
+                                    .map(|time| time.as_millis() as u64)
+                                    .unwrap_or(0);
+                                runtime = guard(millis);
+                            }
+                            Sleep(args, new_runtime) => {
+                                std::thread::sleep(Duration::from_millis(args.millis as u64));
+                                runtime = new_runtime;
+                            }
+                            ConfirmDialog(args, guard) => {
+                                output.write_bytes(&args.message);
+                                output.write_bytes(b" [y/n] ");
+
+                                let choice = read_confirm(&mut *input);
+                                runtime = guard(choice);
+                            }
+                            InputDialogInt(args, guard) => {
+                                output.write_bytes(&args.message);
+
+                                let number = get_input(&mut *input, "int", io::read_int);
+                                runtime = guard(number);
+                            }
+                            InputDialogString(args, guard) => {
+                                output.write_bytes(&args.message);
+
+                                let string = get_input(&mut *input, "string", io::read_line).map(
+                                    |mut line| {
+                                        if line.last() == Some(&b'\n') {
+                                            line.pop();
+                                        }
+                                        line
+                                    },
+                                );
+                                runtime = guard(string);
+                            }
                             Breakpoint(new_runtime) => {
                                 runtime = new_runtime;
                             }
@@ -493,6 +669,7 @@ fn main() {
             Err((old_runtime, MipsyError::Runtime(err))) => {
                 runtime = old_runtime;
 
+                output.flush();
                 println!();
                 err.show_error(
                     ErrorContext::Binary,
@@ -514,56 +691,250 @@ fn main() {
     }
 }
 
-fn read_string(_max_len: u32) -> String {
-    loop {
-        let input: String = get_input("string", true);
+fn show_errors(errors: Vec<MipsyError>, files: &[(String, String)], config: &MipsyConfig) {
+    let count = errors.len();
+
+    for error in errors {
+        match error {
+            MipsyError::Parser(error) => {
+                prompt::error(format!("failed to parse `{}`", error.file_tag()));
+
+                let file_tag = error.file_tag();
+
+                let file = files
+                    .iter()
+                    .find(|(tag, _)| &**tag == &*file_tag)
+                    .map(|(_, str)| Rc::from(&**str))
+                    .expect("for file to throw a parser error, it should probably exist");
+
+                error.show_error(config, file);
+            }
+
+            MipsyError::Compiler(error) => {
+                let compile_tag = if error.file_tag().is_empty() {
+                    String::new()
+                } else {
+                    format!(" `{}`", error.file_tag())
+                };
+
+                prompt::error(format!("failed to compile{}", compile_tag));
+
+                let file_tag = error.file_tag();
+
+                let file = files
+                    .iter()
+                    .find(|(tag, _)| &**tag == &*file_tag)
+                    .map(|(_, str)| Rc::from(&**str))
+                    .unwrap_or_else(|| Rc::from(""));
+
+                error.show_error(config, file);
+            }
+
+            // unreachable: a bit tricky to get a runtime error at compile-time
+            MipsyError::Runtime(_) => unreachable!(),
+        }
+    }
+
+    if count > 1 {
+        prompt::error(format!("aborting due to {count} errors"));
+    }
+}
+
+fn format_files(opts: FmtOpts, config: &MipsyConfig) -> ! {
+    let mut failed = false;
+
+    for name in opts.files {
+        let contents = match fs::read_to_string(&name) {
+            Ok(contents) => contents,
+            Err(err) => {
+                prompt::error_nl(format!(
+                    "failed to read file `{}`: {}",
+                    name.bold(),
+                    err.to_string().bright_red()
+                ));
+
+                process::exit(1);
+            }
+        };
+
+        let formatted = match mipsy_lib::format(TaggedFile::new(Some(&name), &contents), config) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                show_errors(errors, &[(name, contents)], config);
+                failed = true;
+                continue;
+            }
+        };
+
+        if opts.check {
+            if formatted != contents {
+                prompt::error(format!("`{}` is not formatted", name.bold()));
+                failed = true;
+            }
+        } else if opts.write {
+            if formatted != contents {
+                if let Err(err) = fs::write(&name, formatted) {
+                    prompt::error_nl(format!(
+                        "failed to write file `{}`: {}",
+                        name.bold(),
+                        err.to_string().bright_red()
+                    ));
+
+                    process::exit(1);
+                }
+            }
+        } else {
+            print!("{formatted}");
+        }
+    }
+
+    process::exit(if failed { 1 } else { 0 });
+}
+
+fn load_iset(path: &str) -> InstSet {
+    let yaml = read_iset(path);
+
+    match mipsy_instructions::load_inst_set(&yaml) {
+        Ok(iset) => iset,
+        Err(errors) => {
+            show_iset_errors(path, &errors);
+            process::exit(1);
+        }
+    }
+}
+
+fn check_iset(path: Option<String>) -> ! {
+    let (name, errors) = match &path {
+        Some(path) => (
+            path.as_str(),
+            mipsy_instructions::check_inst_set(&read_iset(path)),
+        ),
+        None => (
+            "mips.yaml",
+            mipsy_instructions::check_inst_set(mipsy_instructions::MIPS_YAML),
+        ),
+    };
+
+    if !errors.is_empty() {
+        show_iset_errors(name, &errors);
+        process::exit(1);
+    }
 
-        // if input.len() > max_len as usize {
-        //     println!("[mipsy] bad input (max string length specified as {}, given string is {} bytes)", max_len, input.len());
-        //     print!  ("[mipsy] please try again: ");
-        //     std::io::stdout().flush().unwrap();
+    prompt::success(format!("`{}` is a valid instruction set", name.bold()));
+    process::exit(0);
+}
+
+fn read_iset(path: &str) -> String {
+    match fs::read_to_string(expand_tilde(path)) {
+        Ok(yaml) => yaml,
+        Err(err) => {
+            prompt::error_nl(format!(
+                "failed to read file `{}`: {}",
+                path.bold(),
+                err.to_string().bright_red()
+            ));
+
+            process::exit(1);
+        }
+    }
+}
 
-        //     continue;
-        // }
+fn show_iset_errors(path: &str, errors: &[InstSetError]) {
+    for error in errors {
+        prompt::error(format!(
+            "invalid instruction set `{}`: {}",
+            path.bold(),
+            error
+        ));
+    }
 
-        // if input.len() == max_len as usize {
-        //     println!("[mipsy] bad input (max string length specified as {}, given string is {} bytes -- must be at least one byte fewer, for NULL character), try again: ", max_len, input.len());
-        //     print!  ("[mipsy] please try again: ");
-        //     std::io::stdout().flush().unwrap();
+    if errors.len() > 1 {
+        prompt::error(format!("aborting due to {} errors", errors.len()));
+    }
+}
 
-        //     continue;
-        // }
+fn read_confirm(input: &mut dyn Input) -> i32 {
+    use mipsy_lib::runtime::{CONFIRM_DIALOG_CANCEL, CONFIRM_DIALOG_NO, CONFIRM_DIALOG_YES};
 
-        return input;
+    match io::read_line(input)
+        .map(|line| String::from_utf8_lossy(&line).trim().to_ascii_lowercase())
+        .as_deref()
+    {
+        Ok("y" | "yes") => CONFIRM_DIALOG_YES,
+        Ok("n" | "no") => CONFIRM_DIALOG_NO,
+        _ => CONFIRM_DIALOG_CANCEL,
     }
 }
 
 fn compile(
+    iset: InstSet,
     options: &CompilerOptions,
     config: &MipsyConfig,
     files: &[(String, String)],
     args: &[&str],
-) -> MipsyResult<(InstSet, Binary, Runtime)> {
-    compile_with_kernel(options, config, files, args, &mut get_kernel())
+) -> CompileResult<(InstSet, Binary, Runtime, Vec<CompilerWarning>)> {
+    compile_with_kernel(iset, options, config, files, args, &mut get_kernel())
 }
 
 fn compile_with_kernel(
+    iset: InstSet,
     options: &CompilerOptions,
     config: &MipsyConfig,
     files: &[(String, String)],
     args: &[&str],
     kernel: &mut MpProgram,
-) -> MipsyResult<(InstSet, Binary, Runtime)> {
+) -> CompileResult<(InstSet, Binary, Runtime, Vec<CompilerWarning>)> {
     let files = files
         .iter()
         .map(|(k, v)| TaggedFile::new(Some(k), v))
         .collect::<Vec<_>>();
 
-    let iset = mipsy_instructions::inst_set();
-    let binary = mipsy_lib::compile_with_kernel(&iset, files, kernel, options, config)?;
-    let runtime = mipsy_lib::runtime(&binary, args);
+    let (binary, warnings) = mipsy_lib::compile_with_kernel(&iset, files, kernel, options, config)?;
+    let runtime = mipsy_lib::runtime(&binary, args, config);
+
+    Ok((iset, binary, runtime, warnings))
+}
+
+fn load_binary(
+    iset: InstSet,
+    config: &MipsyConfig,
+    name: &str,
+    contents: &[u8],
+    args: &[&str],
+    with_kernel: bool,
+) -> CompileResult<(InstSet, Binary, Runtime, Vec<CompilerWarning>)> {
+    // only executables have an entry point of their own
+    let binary = if mipsy_lib::cache::is_cache(contents) {
+        mipsy_lib::cache::read_cache(contents, &iset)
+            .map(|binary| (binary, None))
+            .map_err(|err| err.to_string())
+    } else {
+        mipsy_lib::elf::read_elf(contents)
+            .map(|(binary, entry)| (binary, Some(entry)))
+            .map_err(|err| err.to_string())
+    };
+
+    let (mut binary, entry) = match binary {
+        Ok(binary) => binary,
+        Err(err) => {
+            prompt::error_nl(format!(
+                "failed to load `{}`: {}",
+                name.bold(),
+                err.bright_red()
+            ));
+
+            process::exit(1);
+        }
+    };
+
+    // binaries compiled by mipsy already have the kernel linked in
+    if with_kernel && binary.ktext.is_empty() {
+        mipsy_lib::link_kernel(&iset, &mut binary, &mut get_kernel(), entry, config)?;
+    }
+
+    let runtime = mipsy_lib::runtime(&binary, args, config);
 
-    Ok((iset, binary, runtime))
+    Ok((iset, binary, runtime, vec![]))
 }
 
 pub const VERSION: &str = concat!(
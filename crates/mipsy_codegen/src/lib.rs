@@ -3,6 +3,7 @@
 use mipsy_instructions::{
     base::{self, InstructionYaml, PseudoInstructionYaml},
     load_instructions, meta,
+    validate::validate,
 };
 use proc_macro::{TokenStream, TokenTree};
 use quote::quote;
@@ -32,6 +33,20 @@ pub fn instruction_set(input: TokenStream) -> TokenStream {
 
     let base_yaml = load_instructions(meta_yaml);
 
+    let errors = validate(&base_yaml);
+    if !errors.is_empty() {
+        let errors = errors
+            .iter()
+            .map(|error| format!("  - {error}"))
+            .collect::<Vec<_>>();
+
+        panic!(
+            "Invalid instruction set {}:\n{}",
+            path.to_string_lossy(),
+            errors.join("\n")
+        );
+    }
+
     let mut native_instructions: Vec<proc_macro2::TokenStream> = vec![];
     let mut pseudo_instructions: Vec<proc_macro2::TokenStream> = vec![];
 
@@ -247,6 +262,24 @@ fn quote_pseudo_instruction(instruction: PseudoInstructionYaml) -> proc_macro2::
         }
     };
 
+    let desc_short = {
+        match instruction.desc_short {
+            Some(desc) => {
+                quote! { ::std::option::Option::Some(::std::string::String::from(#desc)) }
+            }
+            None => quote! { ::std::option::Option::None },
+        }
+    };
+
+    let desc_long = {
+        match instruction.desc_long {
+            Some(desc) => {
+                quote! { ::std::option::Option::Some(::std::string::String::from(#desc)) }
+            }
+            None => quote! { ::std::option::Option::None },
+        }
+    };
+
     quote! {
         ::mipsy_lib::inst::PseudoSignature::new(
             ::std::string::String::from(#name),
@@ -257,6 +290,10 @@ fn quote_pseudo_instruction(instruction: PseudoInstructionYaml) -> proc_macro2::
                 #relative_label,
             ),
             #expand,
+            ::mipsy_lib::inst::InstMetadata::new(
+                #desc_short,
+                #desc_long,
+            ),
         )
     }
 }
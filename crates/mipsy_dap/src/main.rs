@@ -0,0 +1,616 @@
+//! `mipsy-dap`, a debug adapter for MIPS assembly.
+//!
+//! Speaks the Debug Adapter Protocol over stdio, so that editors can set
+//! breakpoints and watchpoints in `.s` files, step and continue both forwards
+//! and backwards through a program, and look at its registers and memory.
+//! The program's input and output go through the debug console.
+
+mod protocol;
+mod session;
+
+use std::{
+    io::{self, Stdout},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use mipsy_utils::{read_config, MipsyConfig};
+use protocol::{Request, Sender};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use session::{DataBreakpoint, Placed, Run, Session, SourceBreakpoint, Stop, Variable};
+
+/// mipsy programs only ever have the one thread.
+const THREAD_ID: i64 = 1;
+
+/// How many instructions to run between checking for requests, like `pause`.
+const BATCH_SIZE: usize = 10_000;
+
+/// The variable references of each scope.
+const REGISTERS: i64 = 1;
+const DATA: i64 = 2;
+const STACK: i64 = 3;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LaunchArguments {
+    program: String,
+    #[serde(default)]
+    args: Vec<String>,
+    /// A file to read the program's input from, instead of the debug console.
+    stdin: Option<String>,
+    /// Stop at `main`, rather than running to the first breakpoint.
+    #[serde(default)]
+    stop_on_entry: bool,
+    /// Stop when the program exits, so that it can still be stepped back through.
+    #[serde(default)]
+    stop_at_exit: bool,
+}
+
+#[derive(Deserialize)]
+struct Source {
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct SetBreakpointsArguments {
+    source: Source,
+    #[serde(default)]
+    breakpoints: Vec<SourceBreakpoint>,
+}
+
+#[derive(Deserialize)]
+struct SetDataBreakpointsArguments {
+    breakpoints: Vec<DataBreakpoint>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DataBreakpointInfoArguments {
+    variables_reference: Option<i64>,
+    name: String,
+}
+
+#[derive(Deserialize, Default)]
+struct ValueFormat {
+    #[serde(default)]
+    hex: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VariablesArguments {
+    variables_reference: i64,
+    #[serde(default)]
+    format: Option<ValueFormat>,
+}
+
+#[derive(Deserialize)]
+struct EvaluateArguments {
+    expression: String,
+    context: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReadMemoryArguments {
+    memory_reference: String,
+    #[serde(default)]
+    offset: i64,
+    count: u32,
+}
+
+fn main() -> io::Result<()> {
+    // the messages are shown as plain text, so the ansi colours would just be noise
+    colored::control::set_override(false);
+
+    // requests are read on their own thread, so that they can be checked for while running
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+
+        while let Ok(Some(message)) = protocol::read_message(&mut stdin) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut server = Server {
+        sender: Sender::new(io::stdout()),
+        // a broken config has already been replaced by the default one
+        config: read_config().unwrap_or_default(),
+        session: None,
+        running: None,
+        waiting: None,
+        stop_on_entry: false,
+        stop_at_exit: false,
+    };
+
+    server.run(receiver)
+}
+
+struct Server {
+    sender: Sender<Stdout>,
+    config: MipsyConfig,
+    session: Option<Session>,
+    /// How the program is being run, if it is.
+    running: Option<Run>,
+    /// How the program was being run when it stopped for input.
+    waiting: Option<Run>,
+    stop_on_entry: bool,
+    stop_at_exit: bool,
+}
+
+impl Server {
+    fn run(&mut self, receiver: Receiver<Value>) -> io::Result<()> {
+        loop {
+            let message = match self.running {
+                Some(run) => {
+                    self.run_batch(run)?;
+
+                    match receiver.try_recv() {
+                        Ok(message) => message,
+                        Err(TryRecvError::Empty) => continue,
+                        Err(TryRecvError::Disconnected) => return Ok(()),
+                    }
+                }
+                None => match receiver.recv() {
+                    Ok(message) => message,
+                    Err(_) => return Ok(()),
+                },
+            };
+
+            // responses to reverse requests aren't needed
+            if message["type"] != "request" {
+                continue;
+            }
+
+            let request = match serde_json::from_value::<Request>(message) {
+                Ok(request) => request,
+                Err(_) => continue,
+            };
+
+            if request.command == "disconnect" {
+                return self.sender.respond(&request, json!({}));
+            }
+
+            self.handle_request(&request)?;
+        }
+    }
+
+    fn run_batch(&mut self, run: Run) -> io::Result<()> {
+        let session = self
+            .session
+            .as_mut()
+            .expect("can only run a launched program");
+
+        for _ in 0..BATCH_SIZE {
+            if let Some(stop) = session.advance(run) {
+                return self.stopped(stop, run);
+            }
+        }
+
+        self.flush_output()
+    }
+
+    fn handle_request(&mut self, request: &Request) -> io::Result<()> {
+        let result = match request.command.as_str() {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsStepBack": true,
+                "supportsDataBreakpoints": true,
+                "supportsReadMemoryRequest": true,
+                "supportsConditionalBreakpoints": true,
+                "supportsHitConditionalBreakpoints": true,
+                "supportsEvaluateForHovers": true,
+                "supportsRestartRequest": true,
+                "supportsTerminateRequest": true,
+            })),
+            "launch" => return self.launch(request),
+            "configurationDone" | "restart" => {
+                if request.command == "restart" {
+                    if let Some(session) = &mut self.session {
+                        session.restart();
+                    }
+                }
+
+                self.sender.respond(request, json!({}))?;
+                return self.start();
+            }
+            "setBreakpoints" => arguments(request).map(|args| self.set_breakpoints(args)),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "dataBreakpointInfo" => arguments(request).map(|args| self.data_breakpoint_info(args)),
+            "setDataBreakpoints" => arguments(request).map(|args| self.set_data_breakpoints(args)),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+            "stackTrace" => self.with_session(Self::stack_trace),
+            "scopes" => Ok(json!({
+                "scopes": [
+                    { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                    { "name": "Data", "variablesReference": DATA, "expensive": false },
+                    { "name": "Stack", "variablesReference": STACK, "expensive": false },
+                ]
+            })),
+            "variables" => arguments(request).and_then(|args| self.variables(args)),
+            "evaluate" => return self.evaluate(request),
+            "readMemory" => arguments(request).and_then(|args| self.read_memory(args)),
+            "continue" | "next" | "stepIn" | "stepOut" | "stepBack" | "reverseContinue" => {
+                return self.resume(request)
+            }
+            "pause" => {
+                self.sender.respond(request, json!({}))?;
+
+                if self.running.take().is_some() {
+                    self.flush_output()?;
+                    self.send_stopped("pause", None, None, vec![])?;
+                }
+
+                return Ok(());
+            }
+            "terminate" => {
+                self.running = None;
+                self.sender.respond(request, json!({}))?;
+                return self.sender.event("terminated", json!({}));
+            }
+            command => Err(format!("unsupported request `{command}`")),
+        };
+
+        match result {
+            Ok(body) => self.sender.respond(request, body),
+            Err(message) => self.sender.fail(request, message),
+        }
+    }
+
+    fn with_session(&self, handler: fn(&Session) -> Value) -> Result<Value, String> {
+        match &self.session {
+            Some(session) => Ok(handler(session)),
+            None => Err(String::from("no program has been launched")),
+        }
+    }
+
+    fn launch(&mut self, request: &Request) -> io::Result<()> {
+        let args = match arguments::<LaunchArguments>(request) {
+            Ok(args) => args,
+            Err(message) => return self.sender.fail(request, message),
+        };
+
+        match Session::launch(
+            &args.program,
+            &args.args,
+            args.stdin.as_deref(),
+            &self.config,
+        ) {
+            Ok(session) => {
+                self.session = Some(session);
+                self.stop_on_entry = args.stop_on_entry;
+                self.stop_at_exit = args.stop_at_exit;
+
+                self.sender.respond(request, json!({}))?;
+
+                // now that there's a program, breakpoints can be placed in it
+                self.sender.event("initialized", json!({}))
+            }
+            Err(message) => {
+                self.sender.output("stderr", format!("{message}\n"))?;
+                self.sender.fail(request, "failed to compile the program")
+            }
+        }
+    }
+
+    /// Runs the program from wherever it is, once everything has been configured.
+    fn start(&mut self) -> io::Result<()> {
+        let session = match &self.session {
+            Some(session) => session,
+            None => return Ok(()),
+        };
+
+        self.running = Some(Run::Forward {
+            until: if self.stop_on_entry {
+                session.entry()
+            } else {
+                None
+            },
+        });
+
+        Ok(())
+    }
+
+    fn resume(&mut self, request: &Request) -> io::Result<()> {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => return self.sender.fail(request, "no program has been launched"),
+        };
+
+        self.waiting = None;
+
+        let run = match request.command.as_str() {
+            "continue" => Some(Run::Forward { until: None }),
+            "next" => session
+                .step_over_target()
+                .map(|until| Run::Forward { until: Some(until) }),
+            "stepOut" => session
+                .return_address()
+                .map(|until| Run::Forward { until: Some(until) }),
+            "reverseContinue" => Some(Run::Backward),
+            _ => None,
+        };
+
+        self.sender
+            .respond(request, json!({ "allThreadsContinued": true }))?;
+
+        match run {
+            Some(run) => {
+                self.running = Some(run);
+                Ok(())
+            }
+            None if request.command == "stepBack" => {
+                let stop = if session.step_back() {
+                    Stop::Step
+                } else {
+                    Stop::Start
+                };
+
+                self.stopped(stop, Run::Backward)
+            }
+            None => {
+                let stop = session.step().unwrap_or(Stop::Step);
+                self.stopped(stop, Run::Forward { until: None })
+            }
+        }
+    }
+
+    fn flush_output(&mut self) -> io::Result<()> {
+        let output = match &mut self.session {
+            Some(session) => session.take_output(),
+            None => return Ok(()),
+        };
+
+        if output.is_empty() {
+            return Ok(());
+        }
+
+        self.sender
+            .output("stdout", String::from_utf8_lossy(&output))
+    }
+
+    /// Tells the client why the program stopped, having been run by `run`.
+    fn stopped(&mut self, stop: Stop, run: Run) -> io::Result<()> {
+        self.running = None;
+        self.flush_output()?;
+
+        match stop {
+            Stop::Step if self.stop_on_entry && run != Run::Backward => {
+                self.stop_on_entry = false;
+                self.send_stopped("entry", None, None, vec![])
+            }
+            Stop::Step => self.send_stopped("step", None, None, vec![]),
+            Stop::Breakpoint(id) => {
+                self.send_stopped("breakpoint", None, None, id.into_iter().collect())
+            }
+            Stop::Watchpoint { ids, description } => {
+                self.sender
+                    .output("console", format!("[mipsy] {description}\n"))?;
+                self.send_stopped("data breakpoint", None, Some(description), ids)
+            }
+            Stop::Input => {
+                self.waiting = Some(run);
+                self.sender.output(
+                    "console",
+                    "[mipsy] waiting for input -- type it into the debug console\n",
+                )?;
+                self.send_stopped("pause", Some("waiting for input"), None, vec![])
+            }
+            Stop::Exception(message) => {
+                self.sender.output("stderr", format!("{message}\n"))?;
+                self.send_stopped("exception", Some("runtime error"), Some(message), vec![])
+            }
+            Stop::Start => {
+                self.sender.output(
+                    "console",
+                    "[mipsy] reached the start of the program's history\n",
+                )?;
+                self.send_stopped("step", None, None, vec![])
+            }
+            Stop::Exited(code) if self.stop_at_exit => {
+                self.sender.output(
+                    "console",
+                    format!(
+                        "[mipsy] program exited with status {code} -- step back to look around\n"
+                    ),
+                )?;
+                self.send_stopped("exit", Some("exited"), None, vec![])
+            }
+            Stop::Exited(code) => {
+                self.sender.event("exited", json!({ "exitCode": code }))?;
+                self.sender.event("terminated", json!({}))
+            }
+        }
+    }
+
+    fn send_stopped(
+        &mut self,
+        reason: &str,
+        description: Option<&str>,
+        text: Option<String>,
+        hit_breakpoint_ids: Vec<u32>,
+    ) -> io::Result<()> {
+        self.sender.event(
+            "stopped",
+            json!({
+                "reason": reason,
+                "description": description,
+                "text": text,
+                "threadId": THREAD_ID,
+                "allThreadsStopped": true,
+                "hitBreakpointIds": hit_breakpoint_ids,
+            }),
+        )
+    }
+
+    fn set_breakpoints(&mut self, args: SetBreakpointsArguments) -> Value {
+        let placed = match &mut self.session {
+            Some(session) => session.set_breakpoints(&args.source.path, &args.breakpoints),
+            None => vec![Placed::default(); args.breakpoints.len()],
+        };
+
+        json!({ "breakpoints": placed.iter().map(breakpoint).collect::<Vec<_>>() })
+    }
+
+    fn data_breakpoint_info(&self, args: DataBreakpointInfoArguments) -> Value {
+        // of the registers, only the general purpose ones can be watched
+        let target = match &self.session {
+            Some(_)
+                if args.variables_reference == Some(REGISTERS) && !args.name.starts_with('$') =>
+            {
+                Err(format!("`{}` can't be watched", args.name))
+            }
+            Some(session) => session.watch_target(&args.name),
+            None => Err(String::from("no program has been launched")),
+        };
+
+        match target {
+            Ok((data_id, description)) => json!({
+                "dataId": data_id,
+                "description": description,
+                "accessTypes": ["read", "write", "readWrite"],
+            }),
+            Err(message) => json!({ "dataId": null, "description": message }),
+        }
+    }
+
+    fn set_data_breakpoints(&mut self, args: SetDataBreakpointsArguments) -> Value {
+        let placed = match &mut self.session {
+            Some(session) => session.set_watchpoints(&args.breakpoints),
+            None => vec![Placed::default(); args.breakpoints.len()],
+        };
+
+        json!({ "breakpoints": placed.iter().map(breakpoint).collect::<Vec<_>>() })
+    }
+
+    fn stack_trace(session: &Session) -> Value {
+        let pc = session.pc();
+
+        let mut frame = json!({
+            "id": 1,
+            "name": session.function().map(String::from).unwrap_or_else(|| format!("0x{pc:08x}")),
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": format!("0x{pc:08x}"),
+        });
+
+        if let Some((path, line)) = session.location() {
+            frame["line"] = json!(line);
+            frame["column"] = json!(1);
+            frame["source"] = json!({
+                "name": path.file_name().map(|name| name.to_string_lossy()),
+                "path": path.to_string_lossy(),
+            });
+        }
+
+        json!({ "stackFrames": [frame], "totalFrames": 1 })
+    }
+
+    fn variables(&self, args: VariablesArguments) -> Result<Value, String> {
+        let session = self
+            .session
+            .as_ref()
+            .ok_or("no program has been launched")?;
+
+        let hex = args.format.unwrap_or_default().hex;
+
+        let variables = match args.variables_reference {
+            REGISTERS => session.registers(hex),
+            DATA => session.data(hex),
+            STACK => session.stack(hex),
+            _ => vec![],
+        };
+
+        Ok(json!({ "variables": variables.iter().map(variable).collect::<Vec<_>>() }))
+    }
+
+    fn evaluate(&mut self, request: &Request) -> io::Result<()> {
+        let args = match arguments::<EvaluateArguments>(request) {
+            Ok(args) => args,
+            Err(message) => return self.sender.fail(request, message),
+        };
+
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => return self.sender.fail(request, "no program has been launched"),
+        };
+
+        // while the program is waiting for input, anything typed into the console is that input
+        if args.context.as_deref() == Some("repl") {
+            if let Some(run) = self.waiting.take() {
+                session.input().push_line(&args.expression);
+
+                self.sender
+                    .respond(request, json!({ "result": "", "variablesReference": 0 }))?;
+                self.sender.event(
+                    "continued",
+                    json!({ "threadId": THREAD_ID, "allThreadsContinued": true }),
+                )?;
+
+                self.running = Some(run);
+                return Ok(());
+            }
+        }
+
+        match session.evaluate(&args.expression) {
+            Ok(value) => self.sender.respond(
+                request,
+                json!({
+                    "result": format!("{value} (0x{:08x})", value as u32),
+                    "variablesReference": 0,
+                    "memoryReference": format!("0x{:08x}", value as u32),
+                }),
+            ),
+            Err(message) => self.sender.fail(request, message),
+        }
+    }
+
+    fn read_memory(&self, args: ReadMemoryArguments) -> Result<Value, String> {
+        let session = self
+            .session
+            .as_ref()
+            .ok_or("no program has been launched")?;
+
+        let addr = args
+            .memory_reference
+            .strip_prefix("0x")
+            .and_then(|addr| u32::from_str_radix(addr, 16).ok())
+            .ok_or_else(|| format!("bad memory reference `{}`", args.memory_reference))?
+            .wrapping_add(args.offset as u32);
+
+        let bytes = session.read_memory(addr, args.count);
+
+        Ok(json!({
+            "address": format!("0x{addr:08x}"),
+            "data": protocol::base64(&bytes),
+            "unreadableBytes": args.count - bytes.len() as u32,
+        }))
+    }
+}
+
+fn arguments<T: for<'de> Deserialize<'de>>(request: &Request) -> Result<T, String> {
+    serde_json::from_value(request.arguments.clone())
+        .map_err(|err| format!("bad arguments for `{}`: {err}", request.command))
+}
+
+fn breakpoint(placed: &Placed) -> Value {
+    json!({
+        "id": placed.id,
+        "verified": placed.id.is_some(),
+        "line": placed.line,
+        "message": placed.message,
+    })
+}
+
+fn variable(variable: &Variable) -> Value {
+    json!({
+        "name": variable.name,
+        "value": variable.value,
+        "variablesReference": 0,
+        "memoryReference": variable.memory_reference,
+    })
+}
@@ -0,0 +1,179 @@
+//! The wire format of the Debug Adapter Protocol: json messages,
+//! each preceded by a `Content-Length` header.
+
+use std::io::{self, BufRead, Write};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Request {
+    pub seq: i64,
+    pub command: String,
+    #[serde(default)]
+    pub arguments: Value,
+}
+
+/// Reads the next message, or `None` once the client has gone away.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = match length {
+        Some(length) => length,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "message is missing its Content-Length",
+            ))
+        }
+    };
+
+    let mut content = vec![0; length];
+    reader.read_exact(&mut content)?;
+
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let content = message.to_string();
+
+    write!(
+        writer,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )?;
+    writer.flush()
+}
+
+/// Numbers each outgoing message, as the protocol requires.
+pub struct Sender<W> {
+    writer: W,
+    seq: i64,
+}
+
+impl<W: Write> Sender<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, seq: 0 }
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+
+        write_message(&mut self.writer, &message)
+    }
+
+    pub fn respond(&mut self, request: &Request, body: Value) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "success": true,
+            "command": request.command,
+            "body": body,
+        }))
+    }
+
+    pub fn fail(&mut self, request: &Request, message: impl Into<String>) -> io::Result<()> {
+        let message = message.into();
+
+        self.send(json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "success": false,
+            "command": request.command,
+            "message": message,
+            "body": { "error": { "id": 1, "format": message, "showUser": true } },
+        }))
+    }
+
+    pub fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }))
+    }
+
+    /// Shows some text in the debug console.
+    pub fn output(&mut self, category: &str, output: impl Into<String>) -> io::Result<()> {
+        self.event(
+            "output",
+            json!({ "category": category, "output": output.into() }),
+        )
+    }
+}
+
+/// Encodes bytes as base64, which is how memory is sent to the client.
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let word = chunk.iter().enumerate().fold(0u32, |word, (index, &byte)| {
+            word | (byte as u32) << (16 - 8 * index)
+        });
+
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(word >> (18 - 6 * index) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_framing() {
+        let mut sender = Sender::new(vec![]);
+        sender.event("initialized", json!({})).unwrap();
+        sender.output("stdout", "héllo\n").unwrap();
+
+        let mut written = &sender.writer[..];
+        let first = read_message(&mut written).unwrap().unwrap();
+        let second = read_message(&mut written).unwrap().unwrap();
+
+        assert_eq!(first["event"], "initialized");
+        assert_eq!(first["seq"], 1);
+        assert_eq!(second["body"]["output"], "héllo\n");
+        assert_eq!(second["seq"], 2);
+        assert!(read_message(&mut written).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(&[0xFF, 0x00, 0x10, 0x7F]), "/wAQfw==");
+    }
+}
@@ -0,0 +1,1120 @@
+//! A program being debugged: its binary, the runtime whose timeline lets it
+//! run backwards, and the input and output of its syscalls.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    mem::take,
+    path::{Path, PathBuf},
+    rc::Rc,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use mipsy_lib::{
+    compile::{
+        breakpoints::{
+            find_watchpoint_hits, get_affected_registers, Breakpoint, Condition, TargetAction,
+            Watchpoint, WatchpointTarget,
+        },
+        expr::Expr,
+        source_label_name, CompilerOptions,
+    },
+    error::runtime::{Error, ErrorContext, InvalidSyscallReason, RuntimeError},
+    runtime::{
+        io::{self, Input, ReadError},
+        RuntimeSyscallGuard, JAL, SPECIAL, SYS12_READ_CHAR, SYS13_OPEN, SYS14_READ, SYS15_WRITE,
+        SYS16_CLOSE, SYS50_CONFIRM_DIALOG, SYS51_INPUT_DIALOG_INT, SYS54_INPUT_DIALOG_STRING,
+        SYS5_READ_INT, SYS6_READ_FLOAT, SYS7_READ_DOUBLE, SYS8_READ_STRING,
+    },
+    util::{get_segment, Segment},
+    Binary, InstSet, MipsyError, Register, Runtime, Safe, STACK_PTR,
+};
+use mipsy_parser::TaggedFile;
+use mipsy_utils::MipsyConfig;
+use serde::Deserialize;
+
+const SYSCALL: u32 = 0b001100;
+const JALR: u32 = 0b001001;
+
+/// The most words of the stack that are shown at once.
+const MAX_STACK_WORDS: u32 = 256;
+
+/// Why the program stopped running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stop {
+    /// A step finished.
+    Step,
+    /// Hit one of our breakpoints, or a `break` instruction.
+    Breakpoint(Option<u32>),
+    Watchpoint {
+        ids: Vec<u32>,
+        description: String,
+    },
+    /// A read syscall is waiting for input from the debug console.
+    Input,
+    Exception(String),
+    Exited(i32),
+    /// Went back as far as the timeline goes.
+    Start,
+}
+
+/// How far to run the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Run {
+    /// Forwards until something stops it, or until the pc reaches `until`.
+    Forward { until: Option<u32> },
+    /// Backwards until a breakpoint, or the start of the timeline.
+    Backward,
+}
+
+/// A breakpoint on a line of source, as the client sends it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceBreakpoint {
+    pub line: u32,
+    pub condition: Option<String>,
+    pub hit_condition: Option<String>,
+}
+
+/// A watchpoint, as the client sends it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataBreakpoint {
+    pub data_id: String,
+    pub access_type: Option<String>,
+    pub condition: Option<String>,
+    pub hit_condition: Option<String>,
+}
+
+/// Where a breakpoint or watchpoint ended up, or why it couldn't be set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Placed {
+    pub id: Option<u32>,
+    pub line: Option<u32>,
+    pub message: Option<String>,
+}
+
+impl Placed {
+    fn failed(message: impl Into<String>) -> Self {
+        Self {
+            id: None,
+            line: None,
+            message: Some(message.into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    pub value: String,
+    /// The address the value points to, so the client can open a memory view there.
+    pub memory_reference: Option<String>,
+}
+
+/// Input typed into the debug console, a line at a time.
+#[derive(Debug, Clone, Default)]
+pub struct ConsoleInput {
+    bytes: VecDeque<u8>,
+    /// Once closed, reads past the end see EOF rather than waiting.
+    closed: bool,
+}
+
+impl ConsoleInput {
+    pub fn push_line(&mut self, line: &str) {
+        self.bytes.extend(line.bytes());
+        self.bytes.push_back(b'\n');
+    }
+
+    pub fn close(&mut self) {
+        self.closed = true;
+    }
+}
+
+impl Input for ConsoleInput {
+    fn peek_byte(&mut self) -> Option<u8> {
+        self.bytes.front().copied()
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        self.bytes.pop_front()
+    }
+}
+
+pub struct Session {
+    iset: InstSet,
+    binary: Binary,
+    runtime: Runtime,
+    files: Vec<(String, String)>,
+    /// The canonical path of each file, by its tag.
+    paths: HashMap<String, PathBuf>,
+    /// The addresses of the breakpoints set in each file.
+    source_breakpoints: HashMap<PathBuf, Vec<u32>>,
+    next_id: u32,
+    input: ConsoleInput,
+    output: Vec<u8>,
+    exit_code: Option<i32>,
+}
+
+impl Session {
+    /// Reads and compiles `program`, along with any files it includes.
+    pub fn launch(
+        program: &str,
+        args: &[String],
+        stdin: Option<&str>,
+        config: &MipsyConfig,
+    ) -> Result<Self, String> {
+        let source = fs::read_to_string(program)
+            .map_err(|err| format!("failed to read file `{program}`: {err}"))?;
+
+        let mut files = vec![(program.to_string(), source)];
+        mipsy_parser::load_includes(&mut files, |path| fs::read_to_string(path))
+            .map_err(|(name, err)| format!("failed to read file `{name}`: {err}"))?;
+
+        let mut session = Self::new(files, args, config)?;
+
+        if let Some(path) = stdin {
+            let contents = fs::read_to_string(path)
+                .map_err(|err| format!("failed to read file `{path}`: {err}"))?;
+
+            session.input.bytes.extend(contents.bytes());
+            session.input.close();
+        }
+
+        Ok(session)
+    }
+
+    pub fn new(
+        files: Vec<(String, String)>,
+        args: &[String],
+        config: &MipsyConfig,
+    ) -> Result<Self, String> {
+        let iset = mipsy_instructions::inst_set();
+
+        let tagged_files = files
+            .iter()
+            .map(|(tag, source)| TaggedFile::new(Some(tag), source))
+            .collect();
+
+        let binary = mipsy_lib::compile(&iset, tagged_files, &CompilerOptions::new(vec![]), config)
+            .map_err(|errors| {
+                errors
+                    .into_iter()
+                    .map(compile_error)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })?;
+
+        let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+        let runtime = mipsy_lib::runtime(&binary, &args, config);
+
+        let paths = files
+            .iter()
+            .map(|(tag, _)| (tag.clone(), canonical(tag)))
+            .collect();
+
+        Ok(Self {
+            iset,
+            binary,
+            runtime,
+            files,
+            paths,
+            source_breakpoints: HashMap::new(),
+            next_id: 1,
+            input: ConsoleInput::default(),
+            output: vec![],
+            exit_code: None,
+        })
+    }
+
+    /// Everything the program has printed since this was last called.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        take(&mut self.output)
+    }
+
+    pub fn input(&mut self) -> &mut ConsoleInput {
+        &mut self.input
+    }
+
+    pub fn pc(&self) -> u32 {
+        self.runtime.timeline().state().pc()
+    }
+
+    /// Where user code starts, once the kernel has set things up.
+    pub fn entry(&self) -> Option<u32> {
+        self.binary.labels.get("main").copied()
+    }
+
+    pub fn restart(&mut self) {
+        self.runtime.timeline_mut().reset();
+        self.exit_code = None;
+    }
+
+    /// Runs (or undoes) one instruction, returning why the program should stop, if it should.
+    pub fn advance(&mut self, run: Run) -> Option<Stop> {
+        match run {
+            Run::Forward { until } => {
+                let stop = self.step();
+
+                if stop.is_none() && until == Some(self.pc()) {
+                    Some(Stop::Step)
+                } else {
+                    stop
+                }
+            }
+            Run::Backward => {
+                if !self.step_back() {
+                    Some(Stop::Start)
+                } else {
+                    self.breakpoint_at_pc().map(|id| Stop::Breakpoint(Some(id)))
+                }
+            }
+        }
+    }
+
+    /// How far a step over the current instruction should run -- to just after
+    /// a function call, or `None` for any other instruction.
+    pub fn step_over_target(&self) -> Option<u32> {
+        let inst = self.runtime.current_inst();
+        let opcode = inst >> 26;
+
+        if opcode == JAL || (opcode == SPECIAL && inst & 0x3F == JALR) {
+            Some(self.pc().wrapping_add(4))
+        } else {
+            None
+        }
+    }
+
+    /// Where the current function will return to.
+    pub fn return_address(&self) -> Option<u32> {
+        self.runtime
+            .timeline()
+            .state()
+            .read_register(Register::Ra.to_u32())
+            .ok()
+            .map(|ra| ra as u32)
+    }
+
+    pub fn step(&mut self) -> Option<Stop> {
+        if let Some(code) = self.exit_code {
+            return Some(Stop::Exited(code));
+        }
+
+        if self.waiting_for_input() {
+            return Some(Stop::Input);
+        }
+
+        let runtime = take(&mut self.runtime);
+        let inst = runtime.current_inst();
+
+        let stop = match runtime.step() {
+            Ok(Ok(runtime)) => {
+                self.runtime = runtime;
+                None
+            }
+            Ok(Err(guard)) => self.syscall(guard),
+            Err((runtime, err)) => {
+                self.runtime = runtime;
+                return Some(Stop::Exception(self.runtime_error(err)));
+            }
+        };
+
+        match stop {
+            Some(Stop::Breakpoint(None)) | None => self.check_points(inst).or(stop),
+            stop => stop,
+        }
+    }
+
+    /// Undoes the last instruction, or returns `false` if there's no history left.
+    pub fn step_back(&mut self) -> bool {
+        if self.runtime.timeline_mut().pop_last_state() {
+            self.exit_code = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn syscall(&mut self, guard: RuntimeSyscallGuard) -> Option<Stop> {
+        use RuntimeSyscallGuard::*;
+
+        let input = &mut self.input;
+        let output = &mut self.output;
+
+        // the input was checked before the syscall ran, so there are no bad tokens to report
+        let guard = match guard.perform_io(input, output, &mut |_, _| {}) {
+            Ok(runtime) => {
+                self.runtime = runtime;
+                return None;
+            }
+            Err(guard) => guard,
+        };
+
+        let (runtime, stop) = match guard {
+            PrintInt(..) | PrintFloat(..) | PrintDouble(..) | PrintString(..) | PrintChar(..) => {
+                unreachable!("printed by perform_io")
+            }
+            ReadInt(_) | ReadFloat(_) | ReadDouble(_) | ReadString(..) | ReadChar(_) => {
+                unreachable!("read by perform_io")
+            }
+            Sbrk(_args, runtime) => (runtime, None),
+            Exit(runtime) => {
+                self.exit_code = Some(0);
+                (runtime, Some(Stop::Exited(0)))
+            }
+            ExitStatus(args, runtime) => {
+                self.exit_code = Some(args.exit_code);
+                (runtime, Some(Stop::Exited(args.exit_code)))
+            }
+            Open(_args, guard) => return self.unimplemented(guard(-1), SYS13_OPEN),
+            Read(_args, guard) => return self.unimplemented(guard((-1, Vec::new())), SYS14_READ),
+            Write(_args, guard) => return self.unimplemented(guard(-1), SYS15_WRITE),
+            Close(_args, guard) => return self.unimplemented(guard(-1), SYS16_CLOSE),
+            Time(guard) => {
+                let millis = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map(|time| time.as_millis() as u64)
+                    .unwrap_or(0);
+
+                (guard(millis), None)
+            }
+            Sleep(args, runtime) => {
+                std::thread::sleep(Duration::from_millis(args.millis as u64));
+                (runtime, None)
+            }
+            ConfirmDialog(args, guard) => {
+                use mipsy_lib::runtime::{
+                    CONFIRM_DIALOG_CANCEL, CONFIRM_DIALOG_NO, CONFIRM_DIALOG_YES,
+                };
+
+                output.extend(args.message);
+                output.extend(b" [y/n]\n");
+
+                let choice = match io::read_line(input)
+                    .map(|line| String::from_utf8_lossy(&line).trim().to_ascii_lowercase())
+                    .as_deref()
+                {
+                    Ok("y" | "yes") => CONFIRM_DIALOG_YES,
+                    Ok("n" | "no") => CONFIRM_DIALOG_NO,
+                    _ => CONFIRM_DIALOG_CANCEL,
+                };
+
+                (guard(choice), None)
+            }
+            InputDialogInt(args, guard) => {
+                output.extend(args.message);
+                output.push(b'\n');
+
+                (guard(io::read_int(input).ok()), None)
+            }
+            InputDialogString(args, guard) => {
+                output.extend(args.message);
+                output.push(b'\n');
+
+                let string = io::read_line(input).ok().map(|mut line| {
+                    if line.last() == Some(&b'\n') {
+                        line.pop();
+                    }
+                    line
+                });
+
+                (guard(string), None)
+            }
+            Breakpoint(runtime) => (runtime, Some(Stop::Breakpoint(None))),
+            Trap(runtime) => (runtime, Some(Stop::Exception(String::from("trap")))),
+        };
+
+        self.runtime = runtime;
+        stop
+    }
+
+    /// The file syscalls aren't supported, so they're undone and reported as an error.
+    fn unimplemented(&mut self, mut runtime: Runtime, syscall: i32) -> Option<Stop> {
+        runtime.timeline_mut().pop_last_state();
+        self.runtime = runtime;
+
+        let error = MipsyError::Runtime(RuntimeError::new(Error::InvalidSyscall {
+            syscall,
+            reason: InvalidSyscallReason::Unimplemented,
+        }));
+
+        Some(Stop::Exception(self.runtime_error(error)))
+    }
+
+    /// Whether the next instruction is a read syscall that doesn't have its input yet.
+    fn waiting_for_input(&mut self) -> bool {
+        let inst = self.runtime.current_inst();
+        if inst >> 26 != SPECIAL || inst & 0x3F != SYSCALL {
+            return false;
+        }
+
+        let syscall = self
+            .runtime
+            .timeline()
+            .state()
+            .read_register(Register::V0.to_u32());
+
+        let ready = match syscall {
+            Ok(SYS5_READ_INT) => self.input_ready("int", io::read_int),
+            Ok(SYS6_READ_FLOAT) => self.input_ready("float", io::read_float),
+            Ok(SYS7_READ_DOUBLE) => self.input_ready("double", io::read_double),
+            Ok(SYS8_READ_STRING) => self.input_ready("string", io::read_line),
+            Ok(SYS12_READ_CHAR) => self.input_ready("character", io::read_char),
+            Ok(SYS51_INPUT_DIALOG_INT) if self.runtime.mars() => {
+                self.input_ready("int", io::read_int)
+            }
+            Ok(SYS50_CONFIRM_DIALOG | SYS54_INPUT_DIALOG_STRING) if self.runtime.mars() => {
+                self.input_ready("string", io::read_line)
+            }
+            _ => true,
+        };
+
+        !ready
+    }
+
+    /// Whether there's enough input for `read` without waiting for more,
+    /// throwing away (and complaining about) anything it can't use.
+    fn input_ready<T>(
+        &mut self,
+        name: &str,
+        read: fn(&mut dyn Input) -> Result<T, ReadError>,
+    ) -> bool {
+        loop {
+            let mut lookahead = self.input.clone();
+
+            match read(&mut lookahead) {
+                Ok(_) => return true,
+                Err(ReadError::Eof) => return self.input.closed,
+                Err(ReadError::TooBig(n)) => {
+                    self.output.extend(
+                        format!(
+                            "[mipsy] bad input (too big to fit in 32 bits)\n\
+                             [mipsy] if you want the value to be truncated to 32 bits, try {}\n",
+                            n as i32
+                        )
+                        .bytes(),
+                    );
+                }
+                Err(ReadError::Invalid(_)) => {
+                    self.output.extend(
+                        format!("[mipsy] bad input (expected {name}), try again\n").bytes(),
+                    );
+                }
+            }
+
+            // the bad token has been consumed from the lookahead
+            self.input = lookahead;
+        }
+    }
+
+    /// Checks the breakpoints at the new pc, and the watchpoints on what `inst` touched.
+    fn check_points(&mut self, inst: u32) -> Option<Stop> {
+        let affected = get_affected_registers(&self.runtime, inst);
+        let state = self.runtime.timeline().state();
+        let hits = find_watchpoint_hits(&self.binary.watchpoints, &affected, state);
+
+        if let Some(bp) = self.binary.breakpoints.get_mut(&state.pc()) {
+            let condition = bp.condition.as_ref().map(|cond| cond.holds(state));
+
+            if bp.enabled && !matches!(condition, Some(Ok(false))) {
+                if bp.ignore_count > 0 {
+                    bp.ignore_count -= 1;
+                } else if let Some(Err(err)) = condition {
+                    return Some(Stop::Exception(format!(
+                        "failed to evaluate breakpoint condition: {err}"
+                    )));
+                } else {
+                    return Some(Stop::Breakpoint(Some(bp.id)));
+                }
+            }
+        }
+
+        let mut ids = vec![];
+        let mut descriptions = vec![];
+
+        for hit in hits {
+            let wp = self
+                .binary
+                .watchpoints
+                .get_mut(&hit.target)
+                .expect("hits are only found for existing watchpoints");
+
+            if wp.ignore_count > 0 {
+                wp.ignore_count -= 1;
+                continue;
+            }
+
+            let format_value = |value: Option<u32>| match value {
+                Some(value) => (value as i32).to_string(),
+                None => String::from("uninitialized"),
+            };
+
+            let target = match hit.access.target {
+                WatchpointTarget::Register(reg) => format!("${}", reg.to_lower_str()),
+                WatchpointTarget::MemRange { start, .. } => {
+                    let label = self.binary.labels.iter().find(|&(_, &addr)| addr == start);
+
+                    match label {
+                        Some((label, _)) => {
+                            format!("{} (0x{start:08x})", source_label_name(label))
+                        }
+                        None => format!("0x{start:08x}"),
+                    }
+                }
+            };
+
+            let (old, new) = hit.values(&self.runtime);
+            descriptions.push(match hit.access.action {
+                TargetAction::ReadOnly => format!("{target} was read from: {}", format_value(new)),
+                TargetAction::WriteOnly | TargetAction::ReadWrite => format!(
+                    "{target} was written to: {} -> {}",
+                    format_value(old),
+                    format_value(new)
+                ),
+            });
+            ids.push(wp.id);
+        }
+
+        if ids.is_empty() {
+            None
+        } else {
+            Some(Stop::Watchpoint {
+                ids,
+                description: descriptions.join("\n"),
+            })
+        }
+    }
+
+    /// The enabled breakpoint at the pc whose condition holds, if there is one.
+    /// Used when running backwards, so ignore counts are left alone.
+    fn breakpoint_at_pc(&self) -> Option<u32> {
+        let state = self.runtime.timeline().state();
+        let bp = self.binary.breakpoints.get(&state.pc())?;
+
+        let condition_failed = bp
+            .condition
+            .as_ref()
+            .is_some_and(|cond| !cond.holds(state).unwrap_or(true));
+
+        (bp.enabled && !condition_failed).then_some(bp.id)
+    }
+
+    fn runtime_error(&self, error: MipsyError) -> String {
+        let error = match error {
+            MipsyError::Runtime(error) => error,
+            error => return compile_error(error),
+        };
+
+        let source_code = self
+            .files
+            .iter()
+            .map(|(tag, content)| (Rc::from(&**tag), Rc::from(&**content)))
+            .collect::<Vec<_>>();
+
+        let mut message = error.error().message(
+            ErrorContext::Binary,
+            &source_code,
+            &self.iset,
+            &self.binary,
+            &self.runtime,
+        );
+
+        for tip in error
+            .error()
+            .tips(&source_code, &self.iset, &self.binary, &self.runtime)
+        {
+            message.push_str("\ntip: ");
+            message.push_str(tip.trim_end());
+        }
+
+        message
+    }
+
+    fn next_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        id
+    }
+
+    /// Replaces the breakpoints in the file at `path`,
+    /// moving any on lines without code down to the next instruction.
+    pub fn set_breakpoints(&mut self, path: &str, breakpoints: &[SourceBreakpoint]) -> Vec<Placed> {
+        let path = canonical(path);
+
+        for addr in self.source_breakpoints.remove(&path).unwrap_or_default() {
+            self.binary.breakpoints.remove(&addr);
+        }
+
+        let mut lines = self
+            .binary
+            .line_numbers
+            .iter()
+            .filter(|(_, (tag, _))| self.paths.get(&**tag) == Some(&path))
+            .map(|(&addr, &(_, line))| (line, addr))
+            .collect::<Vec<_>>();
+        lines.sort_unstable();
+
+        let mut addrs = vec![];
+        let mut placed = vec![];
+
+        for source_bp in breakpoints {
+            let (line, addr) = match lines.iter().find(|&&(line, _)| line >= source_bp.line) {
+                Some(&found) => found,
+                None => {
+                    placed.push(Placed::failed("no instructions on or after this line"));
+                    continue;
+                }
+            };
+
+            let mut bp = Breakpoint::new(0);
+
+            if let Some(condition) = non_empty(&source_bp.condition) {
+                match Condition::parse(condition, &self.binary) {
+                    Ok(condition) => bp.condition = Some(condition),
+                    Err(err) => {
+                        placed.push(Placed::failed(format!("bad condition: {err}")));
+                        continue;
+                    }
+                }
+            }
+
+            match hit_count(&source_bp.hit_condition) {
+                Ok(ignore_count) => bp.ignore_count = ignore_count,
+                Err(message) => {
+                    placed.push(Placed::failed(message));
+                    continue;
+                }
+            }
+
+            bp.id = self.next_id();
+            placed.push(Placed {
+                id: Some(bp.id),
+                line: Some(line),
+                message: None,
+            });
+
+            self.binary.breakpoints.insert(addr, bp);
+            addrs.push(addr);
+        }
+
+        self.source_breakpoints.insert(path, addrs);
+
+        placed
+    }
+
+    /// What a watchpoint on `name` would watch, as an id for `set_watchpoints`,
+    /// with a description of it.
+    ///
+    /// `name` is a register, or an expression for the address of a word in memory.
+    pub fn watch_target(&self, name: &str) -> Result<(String, String), String> {
+        if let Ok(register) = Register::from_str(name.strip_prefix('$').unwrap_or(name)) {
+            let name = format!("${}", register.to_lower_str());
+            return Ok((name.clone(), name));
+        }
+
+        let addr = Expr::parse(name, &self.binary)
+            .and_then(|expr| expr.evaluate(self.runtime.timeline().state()))
+            .map_err(|err| err.to_string())? as u32;
+
+        Ok((format!("0x{addr:08x}"), format!("{name} (0x{addr:08x})")))
+    }
+
+    /// Replaces all of the watchpoints.
+    pub fn set_watchpoints(&mut self, watchpoints: &[DataBreakpoint]) -> Vec<Placed> {
+        self.binary.watchpoints.clear();
+
+        watchpoints
+            .iter()
+            .map(|data_bp| {
+                let target = match parse_data_id(&data_bp.data_id) {
+                    Some(target) => target,
+                    None => return Placed::failed(format!("can't watch `{}`", data_bp.data_id)),
+                };
+
+                let action = match data_bp.access_type.as_deref() {
+                    Some("read") => TargetAction::ReadOnly,
+                    Some("readWrite") => TargetAction::ReadWrite,
+                    _ => TargetAction::WriteOnly,
+                };
+
+                let mut wp = Watchpoint::new(0, action);
+
+                if let Some(condition) = non_empty(&data_bp.condition) {
+                    match Condition::parse(condition, &self.binary) {
+                        Ok(condition) => wp.condition = Some(condition),
+                        Err(err) => return Placed::failed(format!("bad condition: {err}")),
+                    }
+                }
+
+                match hit_count(&data_bp.hit_condition) {
+                    Ok(ignore_count) => wp.ignore_count = ignore_count,
+                    Err(message) => return Placed::failed(message),
+                }
+
+                wp.id = self.next_id();
+                let id = wp.id;
+                self.binary.watchpoints.insert(target, wp);
+
+                Placed {
+                    id: Some(id),
+                    line: None,
+                    message: None,
+                }
+            })
+            .collect()
+    }
+
+    /// The source file and line of the pc, if it's in one of the program's files.
+    pub fn location(&self) -> Option<(&Path, u32)> {
+        let (tag, line) = self.binary.line_numbers.get(&self.pc())?;
+        let path = self.paths.get(&**tag)?;
+
+        Some((path, *line))
+    }
+
+    /// The name of the function (or other label) that the pc is in.
+    pub fn function(&self) -> Option<&str> {
+        let pc = self.pc();
+        let segment = get_segment(pc);
+
+        self.binary
+            .labels
+            .iter()
+            .filter(|&(_, &addr)| addr <= pc && get_segment(addr) == segment)
+            .max_by_key(|&(_, &addr)| addr)
+            .map(|(name, _)| source_label_name(name))
+    }
+
+    /// The 32 general purpose registers, then hi, lo and the pc.
+    pub fn registers(&self, hex: bool) -> Vec<Variable> {
+        let state = self.runtime.timeline().state();
+
+        let register = |name: String, value: Safe<i32>| Variable {
+            name,
+            value: format_value(value.into_option(), hex),
+            memory_reference: value.into_option().map(|value| format!("0x{value:08x}")),
+        };
+
+        let mut registers = Register::all()
+            .into_iter()
+            .map(|reg| {
+                register(
+                    format!("${}", reg.to_lower_str()),
+                    state.read_register_uninit(reg.to_u32()),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        registers.push(register(String::from("hi"), to_safe(state.read_hi().ok())));
+        registers.push(register(String::from("lo"), to_safe(state.read_lo().ok())));
+        registers.push(register(String::from("pc"), Safe::Valid(state.pc() as i32)));
+
+        registers
+    }
+
+    /// A word at each of the labels in the data segment.
+    pub fn data(&self, hex: bool) -> Vec<Variable> {
+        let mut labels = self
+            .binary
+            .labels
+            .iter()
+            .filter(|&(_, &addr)| get_segment(addr) == Segment::Data)
+            .collect::<Vec<_>>();
+        labels.sort_by_key(|&(_, &addr)| addr);
+
+        labels
+            .into_iter()
+            .map(|(name, &addr)| self.word(source_label_name(name).to_string(), addr, hex))
+            .collect()
+    }
+
+    /// Each word from the stack pointer up to the top of the stack.
+    pub fn stack(&self, hex: bool) -> Vec<Variable> {
+        let sp = match self.stack_pointer() {
+            Some(sp) => sp,
+            None => return vec![],
+        };
+
+        (0..MAX_STACK_WORDS)
+            .map(|index| index * 4)
+            .take_while(|&offset| sp.checked_add(offset).is_some_and(|addr| addr <= STACK_PTR))
+            .map(|offset| self.word(format!("$sp+{offset}"), sp + offset, hex))
+            .collect()
+    }
+
+    fn stack_pointer(&self) -> Option<u32> {
+        let sp = self
+            .runtime
+            .timeline()
+            .state()
+            .read_register(Register::Sp.to_u32())
+            .ok()? as u32;
+
+        (get_segment(sp) == Segment::Stack).then_some(sp & !3)
+    }
+
+    fn word(&self, name: String, addr: u32, hex: bool) -> Variable {
+        let value = self.runtime.timeline().state().read_mem_word(addr).ok();
+
+        Variable {
+            name,
+            value: format_value(value.map(|value| value as i32), hex),
+            memory_reference: Some(format!("0x{addr:08x}")),
+        }
+    }
+
+    /// Evaluates an expression like `$sp + 4` or `*(array + 8)`.
+    pub fn evaluate(&self, expression: &str) -> Result<i32, String> {
+        Expr::parse(expression, &self.binary)
+            .and_then(|expr| expr.evaluate(self.runtime.timeline().state()))
+            .map_err(|err| err.to_string())
+    }
+
+    /// Reads `count` bytes of memory, stopping early at memory that can't be read at all.
+    /// Uninitialized bytes are read as zero.
+    pub fn read_memory(&self, addr: u32, count: u32) -> Vec<u8> {
+        let state = self.runtime.timeline().state();
+
+        (0..count)
+            .map_while(|offset| {
+                let byte = state.read_mem_byte_uninit(addr.checked_add(offset)?).ok()?;
+                Some(byte.into_option().unwrap_or(0))
+            })
+            .collect()
+    }
+}
+
+fn compile_error(error: MipsyError) -> String {
+    match error {
+        MipsyError::Parser(error) => format!(
+            "{}:{}:{}: {}",
+            error.file_tag(),
+            error.line(),
+            error.col(),
+            error.error().message()
+        ),
+        MipsyError::Compiler(error) => format!(
+            "{}:{}:{}: {}",
+            error.file_tag(),
+            error.line(),
+            error.col(),
+            error.error().message()
+        ),
+        MipsyError::Runtime(error) => format!("{:?}", error.error()),
+    }
+}
+
+fn canonical(path: &str) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+}
+
+fn non_empty(text: &Option<String>) -> Option<&str> {
+    text.as_deref()
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+}
+
+/// A hit condition of `n` stops on the `n`th hit, ignoring the ones before.
+fn hit_count(hit_condition: &Option<String>) -> Result<u32, String> {
+    match non_empty(hit_condition) {
+        Some(count) => match count.parse::<u32>() {
+            Ok(count) => Ok(count.saturating_sub(1)),
+            Err(_) => Err(format!("hit count `{count}` should be a number")),
+        },
+        None => Ok(0),
+    }
+}
+
+fn parse_data_id(data_id: &str) -> Option<WatchpointTarget> {
+    if let Some(register) = data_id.strip_prefix('$') {
+        return Register::from_str(register)
+            .ok()
+            .map(WatchpointTarget::Register);
+    }
+
+    let addr = u32::from_str_radix(data_id.strip_prefix("0x")?, 16).ok()?;
+    Some(WatchpointTarget::mem_word(addr))
+}
+
+fn to_safe(value: Option<i32>) -> Safe<i32> {
+    value.map(Safe::Valid).unwrap_or(Safe::Uninitialised)
+}
+
+fn format_value(value: Option<i32>, hex: bool) -> String {
+    match value {
+        Some(value) if hex => format!("0x{:08x}", value as u32),
+        Some(value) => value.to_string(),
+        None => String::from("uninitialized"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PROGRAM: &str = "\
+main:
+\tli\t$v0, 5
+\tsyscall
+
+\tmove\t$t0, $v0
+\tjal\tdouble
+\tmove\t$a0, $v0
+\tli\t$v0, 1
+\tsyscall
+\tli\t$v0, 10
+\tsyscall
+
+double:
+\tadd\t$v0, $t0, $t0
+\tsw\t$v0, result
+\tjr\t$ra
+
+\t.data
+result:\t.word 0
+";
+
+    fn session() -> Session {
+        Session::new(
+            vec![(String::from("test.s"), String::from(PROGRAM))],
+            &[],
+            &MipsyConfig::default(),
+        )
+        .unwrap()
+    }
+
+    fn run(session: &mut Session, run: Run) -> Stop {
+        loop {
+            if let Some(stop) = session.advance(run) {
+                return stop;
+            }
+        }
+    }
+
+    fn breakpoint(line: u32) -> SourceBreakpoint {
+        SourceBreakpoint {
+            line,
+            ..SourceBreakpoint::default()
+        }
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut session = session();
+
+        let placed = session.set_breakpoints("test.s", &[breakpoint(4), breakpoint(30)]);
+        assert_eq!(placed[0].line, Some(5));
+        assert_eq!(placed[1].id, None);
+
+        let forward = Run::Forward { until: None };
+        assert_eq!(run(&mut session, forward), Stop::Input);
+
+        session.input().push_line("oops");
+        assert_eq!(run(&mut session, forward), Stop::Input);
+        assert_eq!(
+            session.take_output(),
+            b"[mipsy] bad input (expected int), try again\n"
+        );
+
+        session.input().push_line("21");
+        assert_eq!(run(&mut session, forward), Stop::Breakpoint(placed[0].id));
+        assert_eq!(session.location().map(|(_, line)| line), Some(5));
+        assert_eq!(session.function(), Some("main"));
+
+        assert_eq!(run(&mut session, forward), Stop::Exited(0));
+        assert_eq!(session.take_output(), b"42");
+
+        // running backwards stops at the same breakpoint
+        assert_eq!(
+            run(&mut session, Run::Backward),
+            Stop::Breakpoint(placed[0].id)
+        );
+        assert_eq!(run(&mut session, Run::Backward), Stop::Start);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut session = session();
+        session.input().push_line("4");
+
+        let (id, _) = session.watch_target("result").unwrap();
+        let placed = session.set_watchpoints(&[DataBreakpoint {
+            data_id: id,
+            ..DataBreakpoint::default()
+        }]);
+
+        let stop = run(&mut session, Run::Forward { until: None });
+        assert_eq!(
+            stop,
+            Stop::Watchpoint {
+                ids: vec![placed[0].id.unwrap()],
+                description: String::from("result (0x10010000) was written to: 0 -> 8"),
+            }
+        );
+
+        let result = session.data(false);
+        assert_eq!(result[0].name, "result");
+        assert_eq!(result[0].value, "8");
+        assert_eq!(session.read_memory(0x10010000, 4), [8, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_step_over() {
+        let mut session = session();
+        session.input().push_line("1");
+
+        let entry = session.entry();
+        run(&mut session, Run::Forward { until: entry });
+
+        while session.step_over_target().is_none() {
+            session.step();
+        }
+
+        let until = session.step_over_target();
+        assert_eq!(run(&mut session, Run::Forward { until }), Stop::Step);
+        assert_eq!(session.location().map(|(_, line)| line), Some(7));
+
+        let registers = session.registers(true);
+        assert_eq!(registers.len(), 35);
+        assert_eq!(registers[2].name, "$v0");
+        assert_eq!(registers[2].value, "0x00000002");
+        assert_eq!(session.evaluate("$v0 * 3"), Ok(6));
+        assert_eq!(
+            session.evaluate("$v1"),
+            Err(String::from("`$v1` is uninitialized"))
+        );
+    }
+
+    #[test]
+    fn test_read_int_eof() {
+        let mut session = session();
+        session.input().close();
+
+        assert_eq!(
+            run(&mut session, Run::Forward { until: None }),
+            Stop::Exited(0)
+        );
+        assert_eq!(session.take_output(), b"0");
+        assert_eq!(session.evaluate("$v1"), Ok(1));
+    }
+
+    #[test]
+    fn test_read_int_keeps_v1() {
+        let program = "main:\n\tli\t$v1, 7\n\tli\t$v0, 5\n\tsyscall\n\tli\t$v0, 10\n\tsyscall\n";
+        let mut session = Session::new(
+            vec![(String::from("test.s"), String::from(program))],
+            &[],
+            &MipsyConfig::default(),
+        )
+        .unwrap();
+        session.input().push_line("0");
+
+        assert_eq!(
+            run(&mut session, Run::Forward { until: None }),
+            Stop::Exited(0)
+        );
+        assert_eq!(session.evaluate("$v1"), Ok(7));
+    }
+}
@@ -180,6 +180,7 @@ impl From<PseudoInstructionYaml> for PseudoSignature {
             x.name.to_ascii_lowercase(),
             x.compile.into(),
             x.expand.into_iter().map(Into::into).collect(),
+            InstMetadata::new(x.desc_short, x.desc_long),
         )
     }
 }
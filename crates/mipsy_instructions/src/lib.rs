@@ -21,20 +21,26 @@
 
 pub mod base;
 pub mod meta;
+pub mod validate;
 
 use crate::meta::DeriveStatementYaml;
 #[allow(unused_imports)] // rust-analyzer seems to think this is unused, but it's not
 use mipsy_lib::InstSet;
 
+/// The YAML that the usual instruction set is loaded from.
 #[cfg(feature = "rt_yaml")]
-static MIPS_YAML: &str = include_str!("../../../mips.yaml");
+pub static MIPS_YAML: &str = include_str!("../../../mips.yaml");
 
 #[cfg(feature = "rt_yaml")]
 pub fn inst_set() -> InstSet {
-    let meta_yaml: meta::YamlFile =
-        serde_yaml::from_str(MIPS_YAML).unwrap_or_else(|_| panic!("Failed to parse mips.yaml"));
+    load_inst_set(MIPS_YAML).unwrap_or_else(|_| panic!("Failed to parse mips.yaml"))
+}
 
-    let base_yaml = load_instructions(meta_yaml);
+/// Loads an instruction set from YAML in the same format as `mips.yaml`,
+/// such as one written for a particular course.
+#[cfg(feature = "rt_yaml")]
+pub fn load_inst_set(yaml: &str) -> Result<InstSet, Vec<validate::InstSetError>> {
+    let base_yaml = parse_inst_set(yaml)?;
 
     let inst_set_native = base_yaml.instructions.into_iter().map(Into::into).collect();
     let inst_set_pseudo = base_yaml
@@ -43,7 +49,27 @@ pub fn inst_set() -> InstSet {
         .map(Into::into)
         .collect();
 
-    InstSet::new(inst_set_native, inst_set_pseudo)
+    Ok(InstSet::new(inst_set_native, inst_set_pseudo))
+}
+
+/// Finds everything wrong with an instruction set in the same format as `mips.yaml`,
+/// without loading it.
+pub fn check_inst_set(yaml: &str) -> Vec<validate::InstSetError> {
+    parse_inst_set(yaml).err().unwrap_or_default()
+}
+
+fn parse_inst_set(yaml: &str) -> Result<base::YamlFile, Vec<validate::InstSetError>> {
+    let meta_yaml: meta::YamlFile =
+        serde_yaml::from_str(yaml).map_err(|err| vec![validate::InstSetError::Yaml(err)])?;
+
+    let base_yaml = load_instructions(meta_yaml);
+
+    let errors = validate::validate(&base_yaml);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(base_yaml)
 }
 
 pub fn load_instructions(meta_yaml: meta::YamlFile) -> base::YamlFile {
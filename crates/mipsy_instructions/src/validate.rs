@@ -0,0 +1,589 @@
+//! Checks that an instruction set makes sense, so that a mistake in its
+//! YAML is reported when it's loaded, rather than as a panic mid-compile.
+
+use std::{collections::HashSet, fmt, str::FromStr};
+
+use mipsy_lib::{
+    inst::{CompileSignature, RuntimeSignature},
+    Register,
+};
+
+use crate::base::{ArgumentType, CompileYaml, InstructionType, ReadsRegisterType, YamlFile};
+
+#[derive(Debug)]
+pub enum InstSetError {
+    /// The file isn't YAML, or doesn't have the shape of an instruction set.
+    Yaml(serde_yaml::Error),
+    MissingOpcode {
+        inst: String,
+    },
+    /// An `Rx` argument that no `Imm2Reg` derive replaced.
+    UnresolvedRx {
+        inst: String,
+    },
+    DuplicateEncoding {
+        inst: String,
+        first: String,
+    },
+    /// Every word `inst` could decode from already decodes as `first`.
+    UnreachableEncoding {
+        inst: String,
+        first: String,
+    },
+    /// Some words decode as both, so which one is used depends on their order.
+    AmbiguousEncoding {
+        inst: String,
+        other: String,
+    },
+    UnknownExpansion {
+        pseudo: String,
+        inst: String,
+    },
+    /// An expansion whose arguments don't fit any format of the native instruction.
+    MismatchedExpansion {
+        pseudo: String,
+        expansion: String,
+    },
+    /// A register in `reads` that isn't one of the instruction's arguments.
+    UnknownRead {
+        inst: String,
+        read: ReadsRegisterType,
+    },
+    /// A source register argument that's missing from `reads`.
+    MissingRead {
+        inst: String,
+        read: ReadsRegisterType,
+    },
+    /// A pseudo-instruction that's never used, as an earlier instruction accepts all of its arguments.
+    ShadowedPseudo {
+        inst: String,
+        native: bool,
+    },
+}
+
+impl fmt::Display for InstSetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstSetError::Yaml(err) => write!(f, "malformed YAML: {err}"),
+            InstSetError::MissingOpcode { inst } => {
+                write!(
+                    f,
+                    "`{inst}` needs an opcode, as it isn't an R-type instruction"
+                )
+            }
+            InstSetError::UnresolvedRx { inst } => write!(
+                f,
+                "`{inst}` takes an `Rx`, which has to be replaced by an `Imm2Reg` derive \
+                 (did you mean to set `only_derive`?)"
+            ),
+            InstSetError::DuplicateEncoding { inst, first } => write!(
+                f,
+                "`{inst}` has the same encoding as `{first}`, so they can't be told apart"
+            ),
+            InstSetError::UnreachableEncoding { inst, first } => write!(
+                f,
+                "`{inst}` is never decoded, as every encoding of it is also one of `{first}`"
+            ),
+            InstSetError::AmbiguousEncoding { inst, other } => write!(
+                f,
+                "some encodings of `{inst}` are also encodings of `{other}`, \
+                 so they can't always be told apart"
+            ),
+            InstSetError::UnknownExpansion { pseudo, inst } => write!(
+                f,
+                "`{pseudo}` expands to `{inst}`, which isn't a native instruction"
+            ),
+            InstSetError::MismatchedExpansion { pseudo, expansion } => write!(
+                f,
+                "`{pseudo}` expands to `{expansion}`, \
+                 which doesn't match any format of that native instruction"
+            ),
+            InstSetError::UnknownRead { inst, read } => write!(
+                f,
+                "`{inst}` reads `{read:?}`, which isn't one of its arguments"
+            ),
+            InstSetError::MissingRead { inst, read } => write!(
+                f,
+                "`{inst}` takes `{read:?}`, but it's missing from `reads`"
+            ),
+            InstSetError::ShadowedPseudo { inst, native } => write!(
+                f,
+                "`{inst}` is never used, as {} accepts all of its arguments",
+                if *native {
+                    "a native instruction"
+                } else {
+                    "an earlier pseudo-instruction"
+                }
+            ),
+        }
+    }
+}
+
+/// Finds everything wrong with an instruction set, after its derives have been expanded.
+pub fn validate(yaml: &YamlFile) -> Vec<InstSetError> {
+    let mut errors = vec![];
+    let mut encodings: Vec<(RuntimeSignature, String)> = vec![];
+
+    for inst in &yaml.instructions {
+        let name = describe(&inst.name, &inst.compile.format);
+
+        if inst.compile.format.contains(&ArgumentType::Rx) {
+            errors.push(InstSetError::UnresolvedRx { inst: name.clone() });
+        }
+
+        check_reads(
+            &name,
+            &inst.compile.format,
+            &inst.runtime.reads,
+            &mut errors,
+        );
+
+        if inst.runtime.inst_type != InstructionType::R && inst.runtime.opcode.is_none() {
+            errors.push(InstSetError::MissingOpcode { inst: name });
+            continue;
+        }
+
+        let encoding = RuntimeSignature::from(inst.runtime.clone());
+        if let Some(error) = check_encoding(&name, &encoding, &encodings) {
+            errors.push(error);
+        }
+
+        encodings.push((encoding, name));
+    }
+
+    // derived pseudo-instructions share their expansions, so each mistake is only reported once
+    let mut reported = HashSet::new();
+    let mut signatures = yaml
+        .instructions
+        .iter()
+        .map(|inst| (inst.name.to_ascii_lowercase(), signature(&inst.compile)))
+        .collect::<Vec<_>>();
+    let natives = signatures.len();
+
+    for pseudo in &yaml.pseudoinstructions {
+        let name = pseudo.name.to_ascii_lowercase();
+        let format = &pseudo.compile.format;
+
+        if format.contains(&ArgumentType::Rx) {
+            errors.push(InstSetError::UnresolvedRx {
+                inst: describe(&name, format),
+            });
+        }
+
+        // the compiler uses the first instruction that matches, trying natives first
+        let compile = signature(&pseudo.compile);
+        if let Some(index) = signatures.iter().position(|(other, other_compile)| {
+            *other == name
+                && match (other_compile, &compile) {
+                    (Some(other_compile), Some(compile)) => other_compile.covers(compile),
+                    _ => false,
+                }
+        }) {
+            errors.push(InstSetError::ShadowedPseudo {
+                inst: describe(&name, format),
+                native: index < natives,
+            });
+        }
+
+        signatures.push((name.clone(), compile));
+
+        for expand in &pseudo.expand {
+            let inst = expand.inst.to_ascii_lowercase();
+            let expansion = if expand.data.is_empty() {
+                inst.clone()
+            } else {
+                format!("{} {}", inst, expand.data.join(", "))
+            };
+
+            if !reported.insert((name.clone(), expansion.clone())) {
+                continue;
+            }
+
+            let mut formats = yaml
+                .instructions
+                .iter()
+                .filter(|native| native.name.eq_ignore_ascii_case(&inst))
+                .map(|native| &native.compile.format)
+                .peekable();
+
+            if formats.peek().is_none() {
+                errors.push(InstSetError::UnknownExpansion {
+                    pseudo: name.clone(),
+                    inst,
+                });
+                continue;
+            }
+
+            if !formats.any(|format| fits(&expand.data, format)) {
+                errors.push(InstSetError::MismatchedExpansion {
+                    pseudo: name.clone(),
+                    expansion,
+                });
+            }
+        }
+    }
+
+    errors
+}
+
+/// Checks a native instruction's encoding against those before it.
+/// The decompiler uses the first encoding that matches, so an earlier,
+/// more specific encoding followed by a more general one is fine.
+fn check_encoding(
+    name: &str,
+    encoding: &RuntimeSignature,
+    earlier: &[(RuntimeSignature, String)],
+) -> Option<InstSetError> {
+    let fields = encoding_fields(encoding);
+
+    earlier.iter().find_map(|(other, other_name)| {
+        let other_fields = encoding_fields(other);
+        let inst = name.to_string();
+
+        if fields == other_fields {
+            Some(InstSetError::DuplicateEncoding {
+                inst,
+                first: other_name.clone(),
+            })
+        } else if covers(&other_fields, &fields) {
+            Some(InstSetError::UnreachableEncoding {
+                inst,
+                first: other_name.clone(),
+            })
+        } else if overlaps(&fields, &other_fields) && !covers(&fields, &other_fields) {
+            Some(InstSetError::AmbiguousEncoding {
+                inst,
+                other: other_name.clone(),
+            })
+        } else {
+            None
+        }
+    })
+}
+
+/// The opcode, funct, shamt, rs, rt and rd that an encoding requires,
+/// where `None` means any value is decoded.
+fn encoding_fields(encoding: &RuntimeSignature) -> [Option<u8>; 6] {
+    match *encoding {
+        RuntimeSignature::R {
+            opcode,
+            funct,
+            shamt,
+            rs,
+            rt,
+            rd,
+        } => [Some(opcode), Some(funct), shamt, rs, rt, rd],
+        RuntimeSignature::I { opcode, rt } => [Some(opcode), None, None, None, rt, None],
+        RuntimeSignature::J { opcode } => [Some(opcode), None, None, None, None, None],
+    }
+}
+
+/// Whether every word decoded by `b` is also decoded by `a`.
+fn covers(a: &[Option<u8>; 6], b: &[Option<u8>; 6]) -> bool {
+    a.iter().zip(b).all(|(a, b)| a.is_none() || a == b)
+}
+
+/// Whether some word is decoded by both `a` and `b`.
+fn overlaps(a: &[Option<u8>; 6], b: &[Option<u8>; 6]) -> bool {
+    a.iter()
+        .zip(b)
+        .all(|(a, b)| a.is_none() || b.is_none() || a == b)
+}
+
+fn check_reads(
+    name: &str,
+    format: &[ArgumentType],
+    reads: &[ReadsRegisterType],
+    errors: &mut Vec<InstSetError>,
+) {
+    let argument = |read| match read {
+        ReadsRegisterType::Rs => ArgumentType::Rs,
+        ReadsRegisterType::Rt => ArgumentType::Rt,
+        ReadsRegisterType::OffRs => ArgumentType::OffRs,
+        ReadsRegisterType::OffRt => ArgumentType::OffRt,
+    };
+
+    for &read in reads {
+        if !format.contains(&argument(read)) {
+            errors.push(InstSetError::UnknownRead {
+                inst: name.to_string(),
+                read,
+            });
+        }
+    }
+
+    // `Rt` is a destination as often as a source, so only the others are always read
+    for read in [
+        ReadsRegisterType::Rs,
+        ReadsRegisterType::OffRs,
+        ReadsRegisterType::OffRt,
+    ] {
+        if format.contains(&argument(read)) && !reads.contains(&read) {
+            errors.push(InstSetError::MissingRead {
+                inst: name.to_string(),
+                read,
+            });
+        }
+    }
+}
+
+/// The kinds of argument an expansion can pass to a native instruction.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ExpandedArgument {
+    Register,
+    Immediate,
+    Offset,
+}
+
+impl ExpandedArgument {
+    fn of(data: &str) -> Self {
+        if data.contains('(') {
+            return Self::Offset;
+        }
+
+        match data.strip_prefix('$') {
+            Some(name) => match name.to_ascii_lowercase().as_str() {
+                "rd" | "rs" | "rt" | "rx" => Self::Register,
+                "offrs" | "offrt" | "off32rs" | "off32rt" => Self::Offset,
+                _ if Register::from_str(name).is_ok() => Self::Register,
+                // any other variable is substituted with a number or label
+                _ => Self::Immediate,
+            },
+            None => Self::Immediate,
+        }
+    }
+
+    fn fits(&self, arg: ArgumentType) -> bool {
+        match self {
+            Self::Register => matches!(arg, ArgumentType::Rd | ArgumentType::Rs | ArgumentType::Rt),
+            Self::Immediate => !matches!(
+                arg,
+                ArgumentType::Rd
+                    | ArgumentType::Rs
+                    | ArgumentType::Rt
+                    | ArgumentType::Rx
+                    | ArgumentType::OffRs
+                    | ArgumentType::OffRt
+            ),
+            Self::Offset => matches!(
+                arg,
+                ArgumentType::OffRs
+                    | ArgumentType::OffRt
+                    | ArgumentType::Off32Rs
+                    | ArgumentType::Off32Rt
+            ),
+        }
+    }
+}
+
+/// Whether the arguments of an expansion could match a native instruction's format.
+fn fits(data: &[String], format: &[ArgumentType]) -> bool {
+    data.len() == format.len()
+        && data
+            .iter()
+            .zip(format)
+            .all(|(data, &arg)| ExpandedArgument::of(data).fits(arg))
+}
+
+/// The signature the compiler matches an instruction with,
+/// unless it still has an `Rx` (which is reported on its own).
+fn signature(compile: &CompileYaml) -> Option<CompileSignature> {
+    if compile.format.contains(&ArgumentType::Rx) {
+        return None;
+    }
+
+    Some(CompileSignature::new(
+        compile.format.iter().copied().map(Into::into).collect(),
+        compile.relative_label,
+    ))
+}
+
+/// How an instruction is written, like `add $Rd, $Rs, $Rt`.
+fn describe(name: &str, format: &[ArgumentType]) -> String {
+    let args = format
+        .iter()
+        .map(|&arg| match arg {
+            ArgumentType::Rx => String::from("$Rx"),
+            arg => mipsy_lib::ArgumentType::from(arg).to_string(),
+        })
+        .collect::<Vec<_>>();
+
+    if args.is_empty() {
+        name.to_ascii_lowercase()
+    } else {
+        format!("{} {}", name.to_ascii_lowercase(), args.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{load_instructions, meta};
+
+    fn errors(yaml: &str) -> Vec<String> {
+        let meta_yaml: meta::YamlFile = serde_yaml::from_str(yaml).unwrap();
+
+        validate(&load_instructions(meta_yaml))
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_bundled_set() {
+        assert_eq!(
+            errors(include_str!("../../../mips.yaml")),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_mistakes() {
+        let yaml = "
+instructions:
+  - name: ADD
+    compile:
+      format: [Rd, Rs, Rt]
+    runtime:
+      type: R
+      funct: 0x20
+      reads: [Rs, Rt]
+  - name: PLUS
+    compile:
+      format: [Rd, Rs, Rt]
+    runtime:
+      type: R
+      funct: 0x20
+      reads: [Rs, Rt]
+  - name: ADDI
+    compile:
+      format: [Rt, Rs, I16]
+    runtime:
+      type: I
+      reads: [Rs]
+pseudoinstructions:
+  - name: MOVE
+    compile:
+      format: [Rd, Rs]
+    expand:
+      - inst: ADDU
+        data: [$Rd, $Rs, $0]
+  - name: INC
+    compile:
+      format: [Rx]
+    expand:
+      - inst: ADD
+        data: [$Rx, $Rx, $Rx]
+";
+
+        assert_eq!(
+            errors(yaml),
+            vec![
+                "`plus $Rd, $Rs, $Rt` has the same encoding as `add $Rd, $Rs, $Rt`, \
+                 so they can't be told apart",
+                "`addi $Rt, $Rs, i16` needs an opcode, as it isn't an R-type instruction",
+                "`move` expands to `addu`, which isn't a native instruction",
+                "`inc $Rx` takes an `Rx`, which has to be replaced by an `Imm2Reg` derive \
+                 (did you mean to set `only_derive`?)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inconsistencies() {
+        let yaml = "
+instructions:
+  - name: SLL
+    compile:
+      format: [Rd, Rt, Shamt]
+    runtime:
+      type: R
+      funct: 0x00
+      rs: 0x00
+      reads: [Rt]
+  - name: NOP
+    compile:
+      format: []
+    runtime:
+      type: R
+      funct: 0x00
+      shamt: 0x00
+      rs: 0x00
+      rt: 0x00
+      rd: 0x00
+      reads: []
+  - name: JR
+    compile:
+      format: [Rs]
+    runtime:
+      type: R
+      funct: 0x08
+      shamt: 0x00
+      rt: 0x00
+      reads: [Rt]
+  - name: JALR
+    compile:
+      format: [Rs]
+    runtime:
+      type: R
+      funct: 0x08
+      rd: 0x1f
+      reads: [Rs]
+  - name: ADDI
+    compile:
+      format: [Rt, Rs, I16]
+    runtime:
+      type: I
+      opcode: 0x08
+      reads: [Rs]
+pseudoinstructions:
+  - name: ADDI
+    compile:
+      format: [Rd, Rs, I16]
+    expand:
+      - inst: ADDI
+        data: [$Rd, $Rs, $I16]
+  - name: ADDI
+    compile:
+      format: [Rt, Rs, I32]
+    expand:
+      - inst: ADDI
+        data: [$Rt, $Rs, 0]
+  - name: ADDI
+    compile:
+      format: [Rt, Rs, U16]
+    expand:
+      - inst: ADDI
+        data: [$Rt, $Rs, 0]
+  - name: INC
+    compile:
+      format: [Rt]
+    expand:
+      - inst: ADDI
+        data: [$Rt, 1]
+  - name: INC
+    compile:
+      format: [Rt]
+    expand:
+      - inst: ADDI
+        data: [$Rt, $Rt, 1]
+";
+
+        assert_eq!(
+            errors(yaml),
+            vec![
+                "`nop` is never decoded, as every encoding of it is also one of `sll $Rd, $Rt, shift`",
+                "`jr $Rs` reads `Rt`, which isn't one of its arguments",
+                "`jr $Rs` takes `Rs`, but it's missing from `reads`",
+                "some encodings of `jalr $Rs` are also encodings of `jr $Rs`, \
+                 so they can't always be told apart",
+                "`addi $Rd, $Rs, i16` is never used, as a native instruction accepts all of its arguments",
+                "`addi $Rt, $Rs, u16` is never used, \
+                 as an earlier pseudo-instruction accepts all of its arguments",
+                "`inc` expands to `addi $Rt, 1`, \
+                 which doesn't match any format of that native instruction",
+                "`inc $Rt` is never used, as an earlier pseudo-instruction accepts all of its arguments",
+            ]
+        );
+    }
+}
@@ -1,9 +1,13 @@
 use crate::interactive::{error::CommandError, prompt};
 use std::iter::successors;
 
-use super::{commands::handle_commands, *};
+use super::{commands::handle_commands, condition::handle_condition, *};
 use colored::*;
-use mipsy_lib::{compile::breakpoints::Breakpoint, Binary};
+use mipsy_lib::{
+    compile::{breakpoints::Breakpoint, source_label_name},
+    error::compiler,
+    Binary,
+};
 use mipsy_parser::*;
 
 enum EnableOp {
@@ -109,6 +113,15 @@ pub(crate) fn breakpoint_command() -> Command {
             "",
             |_, state, label, args| breakpoint_commands(state, label, args),
         ),
+        command(
+            "condition",
+            vec!["cond", "if"],
+            vec![],
+            vec![],
+            vec![],
+            "",
+            |_, state, label, args| breakpoint_condition(state, label, args),
+        ),
     ];
 
     command(
@@ -150,6 +163,7 @@ fn get_long_help() -> String {
          {1} {6}\n\
          {1} {7}\n\
          {0} {12}    : ignore a breakpoint for a specified number of hits\n\
+         {0} {14} : only trigger a breakpoint when an expression holds\n\
          {0} {4}      : list currently set breakpoints\n\n\
          {8} {9} will provide more information about the specified subcommand.
         ",
@@ -167,6 +181,7 @@ fn get_long_help() -> String {
         "temporary".purple(),
         "ignore".purple(),
         "commands".purple(),
+        "condition".purple(),
     )
 }
 
@@ -274,14 +289,14 @@ fn breakpoint_insert(
 
     let label = match arg_type {
         MipsyArgType::Immediate => None,
-        MipsyArgType::Label => Some(&args[0]),
+        MipsyArgType::Label => Some(args[0].as_str()),
         MipsyArgType::Id | MipsyArgType::LineNumber => {
             let binary = state.binary.as_ref().ok_or(CommandError::MustLoadFile)?;
             binary
                 .labels
                 .iter()
                 .find(|(_, &_addr)| _addr == addr)
-                .map(|(name, _)| name)
+                .map(|(name, _)| source_label_name(name))
         }
     };
 
@@ -346,7 +361,7 @@ fn breakpoint_list(state: &State, label: &str, _args: &[String]) -> Result<Strin
                     .labels
                     .iter()
                     .find(|(_, &val)| val == addr)
-                    .map(|(name, _)| name),
+                    .map(|(name, _)| source_label_name(name)),
                 bp,
             )
         })
@@ -372,28 +387,35 @@ fn breakpoint_list(state: &State, label: &str, _args: &[String]) -> Result<Strin
             i => format!(" (ignored for the next {} hits)", i.to_string().bold()),
         };
 
+        let condition = match &bp.condition {
+            Some(condition) => format!(" (if {})", condition.source().bold()),
+            None => "".to_string(),
+        };
+
         match text {
             Some(name) => {
                 println!(
-                    "{}{}: {}{:08x} ({}){}{}",
+                    "{}{}: {}{:08x} ({}){}{}{}",
                     " ".repeat(max_id_len - id.1),
                     id.0.to_string().blue(),
                     "0x".magenta(),
                     addr,
                     name.yellow().bold(),
                     disabled.bright_black(),
-                    ignored
+                    ignored,
+                    condition
                 );
             }
             None => {
                 println!(
-                    "{}{}: {}{:08x}{}{}",
+                    "{}{}: {}{:08x}{}{}{}",
                     " ".repeat(max_id_len - id.1),
                     id.0.to_string().blue(),
                     "0x".magenta(),
                     addr,
                     disabled.bright_black(),
-                    ignored
+                    ignored,
+                    condition
                 );
             }
         }
@@ -485,14 +507,14 @@ fn breakpoint_toggle(
 
     let label = match arg_type {
         MipsyArgType::Immediate => None,
-        MipsyArgType::Label => Some(&args[0]),
+        MipsyArgType::Label => Some(args[0].as_str()),
         MipsyArgType::Id | MipsyArgType::LineNumber => {
             let binary = state.binary.as_ref().ok_or(CommandError::MustLoadFile)?;
             binary
                 .labels
                 .iter()
                 .find(|(_, &_addr)| _addr == addr)
-                .map(|(name, _)| name)
+                .map(|(name, _)| source_label_name(name))
         }
     };
 
@@ -632,6 +654,37 @@ fn breakpoint_commands(
     handle_commands(args, &mut binary.breakpoints)
 }
 
+fn breakpoint_condition(
+    state: &mut State,
+    label: &str,
+    args: &[String],
+) -> Result<String, CommandError> {
+    if label == "__help__" {
+        return Ok(format!(
+            "Usage: {4} {5} {0} {1}\n\
+                 Makes the breakpoint with the specified {0} only trigger\n\
+                 when {1} evaluates to a non-zero value.\n\
+                 If no {1} is given, the condition is removed.\n\
+                 {1} may use registers (`{2}`, `$pc`, `$hi`), labels, numbers (`57`, `0x39`, `'9'`),\n\
+                 word loads (`*($sp + 4)`), and the usual C operators (`==`, `<`, `&&`, `+`, ...).\n\
+                 If the condition can't be evaluated, the breakpoint triggers anyway.\n\
+               \n{6}{7} {4} {5} {3} $t0 == 57
+                ",
+            "<id>".purple(),
+            "[expression]".purple(),
+            "$t0".yellow(),
+            "!1".blue(),
+            "breakpoint".yellow().bold(),
+            "condition".purple(),
+            "example".yellow().bold(),
+            ":".bold(),
+        ));
+    }
+
+    let binary = state.binary.as_mut().ok_or(CommandError::MustLoadFile)?;
+    handle_condition(args, binary, |binary| &mut binary.breakpoints, "breakpoint")
+}
+
 fn generate_err(error: CommandError, command_name: impl Into<String>) -> CommandError {
     let mut help = String::from("help breakpoint");
     let command_name = command_name.into();
@@ -728,6 +781,17 @@ fn parse_breakpoint_arg(state: &State, arg: &String) -> Result<(u32, MipsyArgTyp
                     })?,
                 MipsyArgType::Label,
             ),
+            MpImmediate::Expression(expr) => (
+                binary.eval_expression(expr).map_err(|err| match err {
+                    compiler::Error::UnresolvedConstant { label } => {
+                        CommandError::UnknownLabel { label }
+                    }
+                    // dividing by zero, or a result that's too large
+                    _ => get_error("<addr>"),
+                })? as u32,
+                MipsyArgType::Label,
+            ),
+            MpImmediate::Relocation(..) => return Err(get_error("<addr>")),
         })
     } else {
         Err(get_error("<addr>"))
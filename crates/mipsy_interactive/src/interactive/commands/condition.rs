@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use colored::Colorize;
+use mipsy_lib::{
+    compile::breakpoints::{Condition, Point},
+    Binary,
+};
+
+use crate::{interactive::error::CommandError, prompt};
+
+pub fn handle_condition<K, V: Point>(
+    args: &[String],
+    binary: &mut Binary,
+    points: fn(&mut Binary) -> &mut HashMap<K, V>,
+    kind: &str,
+) -> Result<String, CommandError> {
+    if args.is_empty() {
+        return Err(generate_err(
+            CommandError::MissingArguments {
+                args: vec!["id".to_string()],
+                instead: args.to_vec(),
+            },
+            kind,
+        ));
+    }
+
+    let id: u32 = args[0]
+        .strip_prefix('!')
+        .unwrap_or(&args[0])
+        .parse()
+        .map_err(|_| {
+            generate_err(
+                CommandError::BadArgument {
+                    arg: "<id>".magenta().to_string(),
+                    instead: args[0].clone(),
+                },
+                kind,
+            )
+        })?;
+
+    let source = args[1..].join(" ");
+    let condition = if source.is_empty() {
+        None
+    } else {
+        match Condition::parse(&source, binary) {
+            Ok(condition) => Some(condition),
+            Err(err) => {
+                prompt::error_nl(format!("invalid condition: {err}"));
+                return Ok("".into());
+            }
+        }
+    };
+
+    let point = match points(binary)
+        .values_mut()
+        .find(|point| point.get_id() == id)
+    {
+        Some(point) => point,
+        None => {
+            prompt::error_nl(format!("{kind} {} doesn't exist", format!("!{id}").blue()));
+            return Ok("".into());
+        }
+    };
+
+    match condition {
+        Some(condition) => {
+            prompt::success_nl(format!(
+                "{kind} {} will only trigger if {}",
+                format!("!{id}").blue(),
+                condition.source().bold()
+            ));
+            *point.get_condition() = Some(condition);
+        }
+        None => {
+            prompt::success_nl(format!(
+                "{kind} {} is now unconditional",
+                format!("!{id}").blue()
+            ));
+            *point.get_condition() = None;
+        }
+    }
+
+    Ok("".into())
+}
+
+fn generate_err(error: CommandError, kind: &str) -> CommandError {
+    CommandError::WithTip {
+        error: Box::new(error),
+        tip: format!("try `{}`", format!("help {kind} condition").bold()),
+    }
+}
@@ -42,22 +42,30 @@ pub(crate) fn dot_command() -> Command {
                 vec![],
             );
 
-            compile::check_pre(&program).map_err(|error| CommandError::CannotCompileLine {
-                line: line.to_string(),
-                error,
+            // lints don't make much sense for a single line, so the warnings are ignored
+            let no_kernel = MpProgram::new(vec![], vec![]);
+
+            // there's only one line, so there's only ever one error
+            compile::check_pre(&program, &no_kernel, &state.iset).map_err(|mut errors| {
+                CommandError::CannotCompileLine {
+                    line: line.to_string(),
+                    error: errors.remove(0),
+                }
             })?;
 
             let empty_binary = Binary::default();
             let binary = state.binary.as_ref().unwrap_or(&empty_binary);
 
-            compile::check_post_data_label(&program, binary).map_err(|error| {
+            compile::check_post_data_label(&program, binary).map_err(|mut errors| {
                 CommandError::CannotCompileLine {
                     line: line.to_string(),
-                    error,
+                    error: errors.remove(0),
                 }
             })?;
 
-            let opcodes = mipsy_lib::compile1(binary, &state.iset, &inst)
+            let restriction = compile::Restriction::configured(&state.config);
+
+            let opcodes = mipsy_lib::compile1(binary, &state.iset, restriction.as_ref(), &inst)
                 .map_err(|error| {
                     error.into_compiler_mipsy_error(Rc::from(""), 1, inst.col(), inst.col_end())
                 })
@@ -1,5 +1,7 @@
 use colored::Colorize;
 use mipsy_lib::{
+    compile::source_label_name,
+    error::compiler,
     util::{get_segment, Segment},
     Register, Safe,
 };
@@ -152,7 +154,7 @@ pub(crate) fn examine_command() -> Command {
                                 offset,
                                 format!(
                                     "{}: {}{}",
-                                    label.bold().yellow(),
+                                    source_label_name(label).bold().yellow(),
                                     "0x".yellow(),
                                     format!("{addr:08x}").purple()
                                 )
@@ -293,6 +295,16 @@ fn parse_arg(state: &State, arg: &String) -> Result<u32, CommandError> {
                         label: label.to_string(),
                     })?
             }
+            MpImmediate::Expression(expr) => {
+                binary.eval_expression(expr).map_err(|err| match err {
+                    compiler::Error::UnresolvedConstant { label } => {
+                        CommandError::UnknownLabel { label }
+                    }
+                    // dividing by zero, or a result that's too large
+                    _ => get_error("<addr>"),
+                })? as u32
+            }
+            MpImmediate::Relocation(..) => return Err(get_error("<addr>")),
         })
     } else {
         Err(get_error("<addr>"))
@@ -0,0 +1,194 @@
+use crate::interactive::{error::CommandError, prompt};
+
+use super::*;
+use colored::*;
+use mipsy_lib::{error::util::syntax_highlight_argument, inst::SignatureRef};
+
+pub(crate) fn isa_command() -> Command {
+    let subcommands = vec![command(
+        "search",
+        vec!["find"],
+        vec!["text"],
+        vec![],
+        vec![],
+        "",
+        |_, state, label, args| isa_search(state, label, args),
+    )];
+
+    command(
+        "isa",
+        vec!["inst", "instruction"],
+        vec!["instruction"],
+        vec![],
+        subcommands,
+        "show the signatures and description of an instruction",
+        |cmd, state, label, args| {
+            if label == "__help__" && args.is_empty() {
+                return Ok(get_long_help());
+            }
+
+            let cmd = cmd
+                .subcommands
+                .iter()
+                .find(|c| c.name == args[0] || c.aliases.contains(&args[0]));
+            match cmd {
+                None if label == "__help__" => Ok(get_long_help()),
+                Some(cmd) => cmd.exec(state, label, &args[1..]),
+                None => isa_show(state, &args[0]),
+            }
+        },
+    )
+}
+
+fn get_long_help() -> String {
+    format!(
+        "Shows every signature of an {0}: its arguments, how it's encoded,\n\
+         which registers it reads, its description, and what a pseudo-instruction\n\
+         expands to.\n\
+         {1} {2} {3} searches the descriptions of every instruction instead.",
+        "<instruction>".magenta(),
+        "isa".yellow().bold(),
+        "search".purple(),
+        "<text>".magenta(),
+    )
+}
+
+fn isa_show(state: &State, name: &str) -> Result<String, CommandError> {
+    let signatures = state.iset.find_signatures(name);
+
+    if signatures.is_empty() {
+        return Err(CommandError::WithTip {
+            error: Box::new(CommandError::UnknownInstruction {
+                instruction: name.to_string(),
+            }),
+            tip: format!(
+                "try `{} {} {}` to search the descriptions of instructions",
+                "isa".bold(),
+                "search".bold(),
+                name.bold()
+            ),
+        });
+    }
+
+    println!();
+
+    for sig in signatures {
+        let kind = match sig {
+            SignatureRef::Native(_) => "native",
+            SignatureRef::Pseudo(_) => "pseudo",
+        };
+
+        println!(
+            "{} {}",
+            format_signature(&sig),
+            format!("({kind})").dimmed()
+        );
+
+        let meta = sig.metadata();
+        if let Some(desc) = meta.desc_short() {
+            println!("    {desc}");
+        }
+
+        if let Some(desc) = meta.desc_long() {
+            for line in desc.trim_end().lines() {
+                println!("    {line}");
+            }
+        }
+
+        match sig {
+            SignatureRef::Native(native) => {
+                println!(
+                    "    {} {}",
+                    "encoding:".green().bold(),
+                    native.runtime_signature()
+                );
+
+                let reads = native
+                    .runtime_metadata()
+                    .reads()
+                    .iter()
+                    .map(|read| syntax_highlight_argument(&read.argument_type()))
+                    .collect::<Vec<_>>();
+
+                if !reads.is_empty() {
+                    println!("    {} {}", "reads:".green().bold(), reads.join(", "));
+                }
+            }
+            SignatureRef::Pseudo(pseudo) => {
+                println!("    {}", "expands to:".green().bold());
+
+                for expand in pseudo.expansion() {
+                    println!("      {}", expand.to_string().bold());
+                }
+            }
+        }
+
+        println!();
+    }
+
+    Ok("".into())
+}
+
+fn isa_search(state: &State, label: &str, args: &[String]) -> Result<String, CommandError> {
+    if label == "__help__" {
+        return Ok(format!(
+            "Lists every instruction whose description contains {0}, ignoring case.\n\
+             Use {1} {2} to see more about one of them.",
+            "<text>".magenta(),
+            "isa".yellow().bold(),
+            "<instruction>".magenta(),
+        ));
+    }
+
+    if args.is_empty() {
+        return Err(CommandError::MissingArguments {
+            args: vec!["text".into()],
+            instead: vec![],
+        });
+    }
+
+    let text = args.join(" ");
+    let signatures = state.iset.search_descriptions(&text);
+
+    if signatures.is_empty() {
+        prompt::error_nl(format!("no instructions mention \"{text}\""));
+        return Ok("".into());
+    }
+
+    println!();
+
+    for sig in signatures {
+        let desc = sig.metadata().desc_short().unwrap_or_default();
+
+        println!("{} - {}", format_signature(&sig), desc);
+    }
+
+    println!();
+
+    Ok("".into())
+}
+
+/// How an instruction is written, like `addi $Rt, $Rs, i16`.
+fn format_signature(sig: &SignatureRef<'_>) -> String {
+    let compile = sig.compile_sig();
+
+    let args = compile
+        .format()
+        .iter()
+        .enumerate()
+        .map(|(i, arg)| {
+            // special case for relative labels
+            if compile.relative_label() && i == compile.format().len() - 1 {
+                "label".yellow().bold().to_string()
+            } else {
+                syntax_highlight_argument(arg)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if args.is_empty() {
+        sig.name().bold().to_string()
+    } else {
+        format!("{} {}", sig.name().bold(), args.join(", "))
+    }
+}
@@ -1,5 +1,5 @@
 use crate::interactive::error::CommandError;
-use mipsy_lib::DATA_BOT;
+use mipsy_lib::{compile::source_label_name, DATA_BOT};
 
 use super::*;
 use colored::*;
@@ -27,14 +27,14 @@ pub(crate) fn labels_command() -> Command {
                 .filter(|&label| {
                     !(label.starts_with("kernel__") || label == &String::from("_start"))
                 })
-                .map(|label| label.len())
+                .map(|label| source_label_name(label).len())
                 .max()
                 .unwrap_or(0);
 
             let mut entries: Vec<(String, u32)> = binary
                 .labels
                 .iter()
-                .map(|(key, &val)| (key.to_string(), val))
+                .map(|(key, &val)| (source_label_name(key).to_string(), val))
                 .filter(|(key, _)| !(key.starts_with("kernel__") || key == &String::from("_start")))
                 .collect();
 
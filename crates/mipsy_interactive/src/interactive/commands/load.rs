@@ -2,9 +2,10 @@ use crate::interactive::{error::CommandError, prompt};
 
 use super::*;
 use colored::*;
-use mipsy_lib::compile::CompilerOptions;
+use mipsy_lib::{compile::CompilerOptions, Binary};
 use mipsy_parser::TaggedFile;
 use mipsy_utils::expand_tilde;
+use std::rc::Rc;
 
 pub(crate) fn load_command() -> Command {
     command_varargs(
@@ -40,7 +41,7 @@ pub(crate) fn load_command() -> Command {
             #[cfg(unix)]
             let stdin = String::from("/dev/stdin");
 
-            let program: Vec<_> = files
+            let mut contents: Vec<_> = files
                 .iter()
                 .map(|name| {
                     let mut path = name;
@@ -50,7 +51,7 @@ pub(crate) fn load_command() -> Command {
                         path = &stdin;
                     }
 
-                    match std::fs::read_to_string(expand_tilde(path)) {
+                    match std::fs::read(expand_tilde(path)) {
                         Ok(content) => Ok((path.to_string(), content)),
                         Err(err) => Err(CommandError::CannotReadFile {
                             path: path.clone(),
@@ -60,6 +61,51 @@ pub(crate) fn load_command() -> Command {
                 })
                 .collect::<Result<_, _>>()?;
 
+            if let Some((path, _)) = contents
+                .iter()
+                .find(|(_, content)| mipsy_lib::cache::is_cache(content))
+            {
+                if contents.len() > 1 {
+                    return Err(CommandError::CannotLoadBinary {
+                        path: path.clone(),
+                        error: "it is already compiled, so it has to be the only file".into(),
+                    });
+                }
+
+                let (path, content) = contents.remove(0);
+                let binary =
+                    mipsy_lib::cache::read_cache(&content, &state.iset).map_err(|err| {
+                        CommandError::CannotLoadBinary {
+                            path,
+                            error: err.to_string(),
+                        }
+                    })?;
+
+                state.program = Some(vec![]);
+                load_binary(state, binary, arguments, "file loaded");
+
+                return Ok("".into());
+            }
+
+            let mut program: Vec<_> = contents
+                .into_iter()
+                .map(|(path, content)| match String::from_utf8(content) {
+                    Ok(content) => Ok((path, content)),
+                    Err(_) => Err(CommandError::CannotReadFile {
+                        path,
+                        os_error: "stream did not contain valid UTF-8".into(),
+                    }),
+                })
+                .collect::<Result<_, _>>()?;
+
+            mipsy_parser::load_includes(&mut program, |path| {
+                std::fs::read_to_string(expand_tilde(path))
+            })
+            .map_err(|(path, err)| CommandError::CannotReadFile {
+                path,
+                os_error: err.to_string(),
+            })?;
+
             state.program = Some(program);
             let program = state.program.as_ref().unwrap();
 
@@ -68,20 +114,30 @@ pub(crate) fn load_command() -> Command {
                 .map(|(path, file)| TaggedFile::new(Some(path), file))
                 .collect::<Vec<_>>();
 
-            let binary = mipsy_lib::compile(
+            let (binary, warnings) = mipsy_lib::compile_with_warnings(
                 &state.iset,
                 binary_files,
                 &CompilerOptions::default(),
                 &state.config,
             )
-            .map_err(|err| CommandError::CannotCompile { mipsy_error: err })?;
-
-            let runtime =
-                mipsy_lib::runtime(&binary, &arguments.iter().map(|x| &**x).collect::<Vec<_>>());
-
-            state.binary = Some(binary);
-            state.runtime = runtime;
-            state.exited = false;
+            .map_err(|errors| CommandError::CannotCompile {
+                mipsy_errors: errors,
+            })?;
+
+            for warning in warnings {
+                let file = program
+                    .iter()
+                    .find(|(path, _)| **path == *warning.file_tag())
+                    .map(|(_, file)| Rc::from(&**file))
+                    .unwrap_or_else(|| Rc::from(""));
+
+                prompt::warning(format!(
+                    "`{}` in `{}`",
+                    warning.warning().code(),
+                    warning.file_tag()
+                ));
+                warning.show_warning(&state.config, file);
+            }
 
             let loaded = if program.len() == 1 {
                 "file loaded"
@@ -89,9 +145,23 @@ pub(crate) fn load_command() -> Command {
                 "files loaded"
             };
 
-            prompt::success_nl(loaded);
+            load_binary(state, binary, arguments, loaded);
 
             Ok("".into())
         },
     )
 }
+
+fn load_binary(state: &mut State, binary: Binary, arguments: &[String], loaded: &str) {
+    let runtime = mipsy_lib::runtime(
+        &binary,
+        &arguments.iter().map(|x| &**x).collect::<Vec<_>>(),
+        &state.config,
+    );
+
+    state.binary = Some(binary);
+    state.runtime = runtime;
+    state.exited = false;
+
+    prompt::success_nl(loaded);
+}
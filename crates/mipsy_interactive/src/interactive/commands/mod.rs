@@ -1,12 +1,14 @@
 mod breakpoint;
 #[allow(clippy::module_inception)]
 mod commands;
+mod condition;
 mod context;
 mod disassemble;
 mod dot;
 mod examine;
 mod exit;
 mod help;
+mod isa;
 mod label;
 mod labels;
 mod load;
@@ -24,6 +26,7 @@ pub(crate) use dot::dot_command;
 pub(crate) use examine::examine_command;
 pub(crate) use exit::exit_command;
 pub(crate) use help::help_command;
+pub(crate) use isa::isa_command;
 pub(crate) use label::label_command;
 pub(crate) use labels::labels_command;
 pub(crate) use load::load_command;
@@ -4,7 +4,11 @@ use crate::interactive::{error::CommandError, prompt};
 
 use super::*;
 use colored::*;
-use mipsy_lib::{Binary, Register};
+use mipsy_lib::{
+    compile::structs::{FieldKind, StructLayout},
+    error::compiler,
+    Binary, Register, State,
+};
 use mipsy_parser::*;
 
 #[allow(clippy::format_in_format_args)]
@@ -28,7 +32,8 @@ pub(crate) fn print_command() -> Command {
                     \x20- {12}: `{2}{13}` - prints all currently initialised registers.\n\
                          {14} can optionally be specified (default: `{15}`) to specify how the value\n\
                     \x20 should be printed. Options: `{16}`, `{17}`, `{15}`, `{18}{16}`, `{18}{17}`,\n\
-                    \x20                             `{18}{15}` / `{19}{18}`, `{20}`, `{21}`.",
+                    \x20                             `{18}{15}` / `{19}{18}`, `{20}`, `{21}`,\n\
+                    \x20 or the name of a `{22}`, to show the memory at the {9} as its fields.",
                         "<item>".magenta(),
                         "register".yellow().bold(),
                         "$".yellow(),
@@ -51,6 +56,7 @@ pub(crate) fn print_command() -> Command {
                         "he".bold(),
                         format!("{}{}", "c".yellow().bold(), "har".bold()),
                         format!("{}{}", "s".yellow().bold(), "tring".bold()),
+                        ".struct".yellow().bold(),
                     ),
                 );
             }
@@ -66,10 +72,15 @@ pub(crate) fn print_command() -> Command {
             let arg = mipsy_parser::parse_argument(&args[0], state.config.tab_size)
                 .map_err(|_| get_error())?;
 
+            let empty_binary = Binary::default();
+            let binary = state.binary.as_ref().unwrap_or(&empty_binary);
+            let runtime = &state.runtime;
+
             let print_type = &*args.get(1).cloned().unwrap_or_else(|| "word".to_string());
             match print_type {
                 "byte" | "half" | "word" | "xbyte" | "xhalf" | "xword" | "hex" | "char"
                 | "string" | "b" | "h" | "w" | "xb" | "xh" | "xw" | "x" | "c" | "s" => {}
+                other if binary.structs.contains_key(other) => {}
                 other => {
                     return Err(CommandError::BadArgument {
                         arg: "[format]".magenta().to_string(),
@@ -78,9 +89,7 @@ pub(crate) fn print_command() -> Command {
                 }
             }
 
-            let empty_binary = Binary::default();
-            let binary = state.binary.as_ref().unwrap_or(&empty_binary);
-            let runtime = &state.runtime;
+            let layout = binary.structs.get(print_type);
 
             match arg {
                 MpArgument::Register(MpRegister::Normal(ident)) => {
@@ -100,7 +109,20 @@ pub(crate) fn print_command() -> Command {
                         _ => {}
                     }
 
-                    if matches!(ident, MpRegisterIdentifier::Named(ref name) if name == "all") {
+                    let all =
+                        matches!(ident, MpRegisterIdentifier::Named(ref name) if name == "all");
+
+                    if all && layout.is_some() {
+                        prompt::error(format!(
+                            "{} `{}` unsupported for all registers",
+                            "[format]".magenta(),
+                            print_type
+                        ));
+                        prompt::tip_nl("try printing a single register that holds an address");
+                        return Ok("".into());
+                    }
+
+                    if all {
                         for register in &Register::all() {
                             if let Ok(val) =
                                 runtime.timeline().state().read_register(register.to_u32())
@@ -196,7 +218,12 @@ pub(crate) fn print_command() -> Command {
                             (val, reg_name)
                         };
 
-                        let value = format_simple_print(val, print_type);
+                        let value = match layout {
+                            Some(layout) => {
+                                format_struct(runtime.timeline().state(), val as u32, layout)
+                            }
+                            None => format_simple_print(val, print_type),
+                        };
                         prompt::success_nl(format!(
                             "{}{} = {}",
                             "$".yellow(),
@@ -217,8 +244,24 @@ pub(crate) fn print_command() -> Command {
                                 .map_err(|_| CommandError::UnknownLabel {
                                     label: label.to_string(),
                                 })?,
+                            MpImmediate::Expression(expr) => {
+                                binary.eval_expression(&expr).map_err(|err| match err {
+                                    compiler::Error::UnresolvedConstant { label } => {
+                                        CommandError::UnknownLabel { label }
+                                    }
+                                    // dividing by zero, or a result that's too large
+                                    _ => get_error(),
+                                })? as u32
+                            }
+                            MpImmediate::Relocation(..) => return Err(get_error()),
                         };
 
+                    if let Some(layout) = layout {
+                        let value = format_struct(runtime.timeline().state(), imm, layout);
+                        prompt::success_nl(format!("{} = {}", args[0], value));
+                        return Ok("".into());
+                    }
+
                     let map_err = |_err| CommandError::UninitialisedPrint { addr: imm };
 
                     let value = match print_type {
@@ -326,3 +369,71 @@ fn format_simple_print(val: i32, print_type: &str) -> String {
         _ => unreachable!(),
     }
 }
+
+/// Shows the memory at `addr` as each of the struct's fields, one per line.
+fn format_struct(state: &State, addr: u32, layout: &StructLayout) -> String {
+    let mut text = format!("{} {{\n", layout.name.bold());
+
+    for field in &layout.fields {
+        let field_addr = addr.wrapping_add(field.offset);
+        let element = |index: u32| field_addr.wrapping_add(index * field.kind.size());
+        let uninit = || "_".bright_black().to_string();
+
+        let value = match field.kind {
+            FieldKind::String => {
+                let bytes = (0..field.len)
+                    .map(|index| state.read_mem_byte(element(index)))
+                    .take_while(|byte| !matches!(byte, Ok(0)))
+                    .map(|byte| match byte {
+                        Ok(byte) => ascii::escape_default(byte).to_string(),
+                        Err(_) => uninit(),
+                    })
+                    .collect::<String>();
+
+                format!("\"{}\"", bytes)
+            }
+            kind => {
+                let values = (0..field.len)
+                    .map(|index| {
+                        let addr = element(index);
+
+                        let value = match kind {
+                            FieldKind::Byte => {
+                                state.read_mem_byte(addr).map(|byte| byte.to_string())
+                            }
+                            FieldKind::Space => state
+                                .read_mem_byte(addr)
+                                .map(|byte| format!("0x{:02x}", byte)),
+                            FieldKind::Half => {
+                                state.read_mem_half(addr).map(|half| half.to_string())
+                            }
+                            FieldKind::Word => state
+                                .read_mem_word(addr)
+                                .map(|word| (word as i32).to_string()),
+                            FieldKind::Float => state
+                                .read_mem_word(addr)
+                                .map(|word| f32::from_bits(word).to_string()),
+                            FieldKind::Double => state.read_mem_word(addr).and_then(|low| {
+                                let high = state.read_mem_word(addr.wrapping_add(4))?;
+                                Ok(f64::from_bits((high as u64) << 32 | low as u64).to_string())
+                            }),
+                            FieldKind::String => unreachable!(),
+                        };
+
+                        value.unwrap_or_else(|_| uninit())
+                    })
+                    .collect::<Vec<_>>();
+
+                match &values[..] {
+                    [value] => value.to_string(),
+                    values => format!("[{}]", values.join(", ")),
+                }
+            }
+        };
+
+        text.push_str(&format!("    {}: {},\n", field.name.bold(), value));
+    }
+
+    text.push('}');
+    text
+}
@@ -1,13 +1,16 @@
 use crate::interactive::{error::CommandError, prompt};
 use std::{iter::successors, str::FromStr};
 
-use super::{commands::handle_commands, *};
+use super::{commands::handle_commands, condition::handle_condition, *};
 use colored::*;
 use mipsy_lib::{
-    compile::breakpoints::{TargetAction, Watchpoint, WatchpointTarget},
+    compile::{
+        breakpoints::{TargetAction, Watchpoint, WatchpointTarget},
+        expr::{Expr, ExprError},
+        source_label_name,
+    },
     Binary, Register,
 };
-use mipsy_parser::{MpArgument, MpImmediate, MpNumber};
 
 enum WpState {
     Enable,
@@ -22,6 +25,7 @@ enum InsertOp {
     Temporary,
 }
 
+#[derive(PartialEq)]
 enum MipsyArgType {
     Target,
     Label,
@@ -111,6 +115,15 @@ pub(crate) fn watchpoint_command() -> Command {
             "",
             |_, state, label, args| watchpoint_commands(state, label, args),
         ),
+        command(
+            "condition",
+            vec!["cond", "if"],
+            vec![],
+            vec![],
+            vec![],
+            "",
+            |_, state, label, args| watchpoint_condition(state, label, args),
+        ),
     ];
 
     command(
@@ -152,6 +165,7 @@ fn get_long_help() -> String {
          {1} {6}\n\
          {1} {7}\n\
          {0} {11}    : ignore a watchpoint for a specified number of hits\n\
+         {0} {14} : only trigger a watchpoint when an expression holds\n\
          {0} {4}      : list currently set watchpoints\n\n\
          {8} {9} will provide more information about the specified subcommand.
         ",
@@ -169,6 +183,7 @@ fn get_long_help() -> String {
         "ignore".purple(),
         "temporary".purple(),
         "commands".purple(),
+        "condition".purple(),
     )
 }
 
@@ -181,10 +196,13 @@ fn watchpoint_insert(
     if label == "__help__" {
         return Ok(
             format!(
-                "Usage: {5} {6} {2} {7}\n\
+                "Usage: {5} {6} {2} {11} {7}\n\
                  {0}s or {1}s a watchpoint at the specified {2}.\n\
                  {2} may be: a register name (`$t0`, `t0`), a register number (`$14`, `14`),\n\
-                 a decimal address (`4194304`), a hex address (`{8}400000`), or a label (`{9}`).\n\
+                 a decimal address (`4194304`), a hex address (`{8}400000`), a label (`{9}`),\n\
+                 an address expression (`$sp+8`), or a range of memory (`$sp-16..$sp`).\n\
+                 A memory {2} watches a single word, unless a {11} in bytes is given (`{9} 40`).\n\
+                 Any byte, half or word access that overlaps the watched memory triggers the watchpoint.\n\
                  If you are removing a watchpoint, you can also use its id (`{3}`).\n\
                  {4} must be `i`, `in`, `ins`, `insert`, or `add` to insert the watchpoint, or\n\
             \x20             `del`, `delete`, `rm` or `remove` to remove the watchpoint.\n\
@@ -192,7 +210,7 @@ fn watchpoint_insert(
                  be created as a temporary watchpoint, which automatically deletes itself after being hit.\n\
                  If {4} is none of these option, it defaults to inserting a watchpoint at {4}.\n\
                  When running or stepping through your program, a watchpoint will cause execution to\n\
-                 pause temporarily when the specified register is read from or written to,\n\
+                 pause temporarily when the specified target is read from or written to,\n\
                  allowing you to debug the current state.\n\
                  May error if provided a {2} that doesn't exist.",
                 "<insert>".magenta(),
@@ -206,6 +224,7 @@ fn watchpoint_insert(
                 "0x".yellow(),
                 "main".yellow().bold(),
                 "<temporary>".purple(),
+                "[size]".purple(),
             )
         );
     }
@@ -224,7 +243,38 @@ fn watchpoint_insert(
         ));
     }
 
-    let (target, arg_type) = parse_watchpoint_arg(state, &args[0])?;
+    let target_arg = &args[0];
+    let (mut target, arg_type) = parse_watchpoint_arg(state, target_arg)?;
+
+    let mut args = &args[1..];
+    if op == InsertOp::Delete {
+        target = existing_target(state, target);
+    } else if let WatchpointTarget::MemRange { start, .. } = target {
+        // an explicit size, e.g. `watch my_array 40`
+        let sized = arg_type != MipsyArgType::Id && !target_arg.contains("..");
+        if let Some(size) = args
+            .first()
+            .filter(|arg| sized && parse_action(arg).is_none())
+        {
+            let len = eval_watchpoint_expr(state, size)?;
+            if len <= 0 {
+                return Err(generate_err(
+                    CommandError::BadArgument {
+                        arg: "[size]".magenta().to_string(),
+                        instead: size.clone(),
+                    },
+                    "insert",
+                ));
+            }
+
+            target = WatchpointTarget::MemRange {
+                start,
+                len: len as u32,
+            };
+            args = &args[1..];
+        }
+    }
+
     let binary = state.binary.as_mut().ok_or(CommandError::MustLoadFile)?;
 
     let id;
@@ -239,40 +289,38 @@ fn watchpoint_insert(
                 "watchpoint at {} doesn't exist",
                 match arg_type {
                     MipsyArgType::Target => target.to_string().as_str().into(),
-                    MipsyArgType::Label => args[0].yellow().bold(),
-                    MipsyArgType::Id => args[0].blue(),
+                    MipsyArgType::Label => target_arg.yellow().bold(),
+                    MipsyArgType::Id => target_arg.blue(),
                 }
             ));
             return Ok("".into());
         }
     } else {
-        let args = &args[1..];
-        if args.is_empty() {
-            return Err(generate_err(
+        let subcommand = if op == InsertOp::Temporary {
+            "temporary"
+        } else {
+            "insert"
+        };
+
+        let arg = args.first().ok_or_else(|| {
+            generate_err(
                 CommandError::MissingArguments {
                     args: vec!["action".to_string()],
                     instead: args.to_vec(),
                 },
-                "rm",
-            ));
-        }
+                subcommand,
+            )
+        })?;
 
-        action = match args[0].as_str() {
-            "r" | "read" => TargetAction::ReadOnly,
-            "w" | "write" => TargetAction::WriteOnly,
-            "rw" | "r/w" | "r+w" | "w/r" | "w+r" | "read/write" | "read+write" => {
-                TargetAction::ReadWrite
-            }
-            _ => {
-                return Err(generate_err(
-                    CommandError::BadArgument {
-                        arg: "action".to_owned(),
-                        instead: args[0].clone(),
-                    },
-                    "insert",
-                ))
-            }
-        };
+        action = parse_action(arg).ok_or_else(|| {
+            generate_err(
+                CommandError::BadArgument {
+                    arg: "action".to_owned(),
+                    instead: arg.clone(),
+                },
+                subcommand,
+            )
+        })?;
 
         let task = if binary.watchpoints.contains_key(&target) {
             "updated"
@@ -291,14 +339,14 @@ fn watchpoint_insert(
 
     let label = match arg_type {
         MipsyArgType::Target => None,
-        MipsyArgType::Label => Some(&args[0]),
+        MipsyArgType::Label => Some(target_arg.as_str()),
         MipsyArgType::Id => match target {
             WatchpointTarget::Register(_) => None,
-            WatchpointTarget::MemAddr(addr) => binary
+            WatchpointTarget::MemRange { start: addr, .. } => binary
                 .labels
                 .iter()
                 .find(|(_, &_addr)| _addr == addr)
-                .map(|(name, _)| name),
+                .map(|(name, _)| source_label_name(name)),
         },
     };
 
@@ -346,7 +394,7 @@ fn watchpoint_list(state: &State, label: &str, _args: &[String]) -> Result<Strin
         .map(|wp| {
             let addr = match wp.0 {
                 WatchpointTarget::Register(_) => None,
-                WatchpointTarget::MemAddr(m) => Some(m),
+                WatchpointTarget::MemRange { start, .. } => Some(start),
             };
             let id = wp.1.id;
             (
@@ -362,7 +410,7 @@ fn watchpoint_list(state: &State, label: &str, _args: &[String]) -> Result<Strin
                         .labels
                         .iter()
                         .find(|(_, &val)| val == addr)
-                        .map(|(name, _)| name)
+                        .map(|(name, _)| source_label_name(name))
                 } else {
                     None
                 },
@@ -387,6 +435,11 @@ fn watchpoint_list(state: &State, label: &str, _args: &[String]) -> Result<Strin
             i => format!(" (ignored for the next {} hits)", i.to_string().bold()),
         };
 
+        let condition = match &wp.condition {
+            Some(condition) => format!(" (if {})", condition.source().bold()),
+            None => "".to_string(),
+        };
+
         let target = if let Some(label) = label {
             format!("{} ({})", label.yellow().bold(), target)
         } else {
@@ -394,13 +447,14 @@ fn watchpoint_list(state: &State, label: &str, _args: &[String]) -> Result<Strin
         };
 
         println!(
-            "{}{}: {} ({}){}{}",
+            "{}{}: {} ({}){}{}{}",
             " ".repeat(max_id_len - id.1),
             id.0.to_string().blue(),
             target,
             wp.action.to_string().purple(),
             disabled.bright_black(),
             ignored,
+            condition,
         );
     }
     println!();
@@ -450,6 +504,7 @@ fn watchpoint_toggle(
     }
 
     let (target, arg_type) = parse_watchpoint_arg(state, &args[0])?;
+    let target = existing_target(state, target);
 
     let binary = state.binary.as_mut().ok_or(CommandError::MustLoadFile)?;
 
@@ -481,14 +536,14 @@ fn watchpoint_toggle(
 
     let label = match arg_type {
         MipsyArgType::Target => None,
-        MipsyArgType::Label => Some(&args[0]),
+        MipsyArgType::Label => Some(args[0].as_str()),
         MipsyArgType::Id => match target {
             WatchpointTarget::Register(_) => None,
-            WatchpointTarget::MemAddr(addr) => binary
+            WatchpointTarget::MemRange { start: addr, .. } => binary
                 .labels
                 .iter()
                 .find(|(_, &_addr)| _addr == addr)
-                .map(|(name, _)| name),
+                .map(|(name, _)| source_label_name(name)),
         },
     };
 
@@ -545,6 +600,7 @@ fn watchpoint_ignore(
     }
 
     let (target, arg_type) = parse_watchpoint_arg(state, &args[0])?;
+    let target = existing_target(state, target);
 
     args = &args[1..];
     if args.is_empty() {
@@ -616,6 +672,37 @@ fn watchpoint_commands(
     handle_commands(args, &mut binary.watchpoints)
 }
 
+fn watchpoint_condition(
+    state: &mut State,
+    label: &str,
+    args: &[String],
+) -> Result<String, CommandError> {
+    if label == "__help__" {
+        return Ok(format!(
+            "Usage: {4} {5} {0} {1}\n\
+                 Makes the watchpoint with the specified {0} only trigger\n\
+                 when {1} evaluates to a non-zero value.\n\
+                 If no {1} is given, the condition is removed.\n\
+                 {1} may use registers (`{2}`, `$pc`, `$hi`), labels, numbers (`57`, `0x39`, `'9'`),\n\
+                 word loads (`*($sp + 4)`), and the usual C operators (`==`, `<`, `&&`, `+`, ...).\n\
+                 If the condition can't be evaluated, the watchpoint triggers anyway.\n\
+               \n{6}{7} {4} {5} {3} $t0 == 57
+                ",
+            "<id>".purple(),
+            "[expression]".purple(),
+            "$t0".yellow(),
+            "!1".blue(),
+            "watchpoint".yellow().bold(),
+            "condition".purple(),
+            "example".yellow().bold(),
+            ":".bold(),
+        ));
+    }
+
+    let binary = state.binary.as_mut().ok_or(CommandError::MustLoadFile)?;
+    handle_condition(args, binary, |binary| &mut binary.watchpoints, "watchpoint")
+}
+
 fn generate_err(error: CommandError, command_name: impl Into<String>) -> CommandError {
     let mut help = String::from("help watchpoint");
     let command_name = command_name.into();
@@ -659,31 +746,86 @@ fn parse_watchpoint_arg(
         return Ok((*target, MipsyArgType::Id));
     }
 
-    let target = if let Ok(register) = Register::from_str(arg.strip_prefix('$').unwrap_or(arg)) {
-        WatchpointTarget::Register(register)
-    } else {
-        let arg = mipsy_parser::parse_argument(arg, state.config.tab_size)
-            .map_err(|_| get_error("<addr>"))?;
-
-        if let MpArgument::Number(MpNumber::Immediate(ref imm)) = arg {
-            WatchpointTarget::MemAddr(match imm {
-                MpImmediate::I16(imm) => *imm as u32,
-                MpImmediate::U16(imm) => *imm as u32,
-                MpImmediate::I32(imm) => *imm as u32,
-                MpImmediate::U32(imm) => *imm,
-                MpImmediate::LabelReference(label) => {
-                    let addr = binary
-                        .get_label(label)
-                        .map_err(|_| CommandError::UnknownLabel {
-                            label: label.to_string(),
-                        })?;
-                    return Ok((WatchpointTarget::MemAddr(addr), MipsyArgType::Label));
-                }
-            })
-        } else {
-            return Err(get_error("<addr>"));
+    if let Ok(register) = Register::from_str(arg.strip_prefix('$').unwrap_or(arg)) {
+        return Ok((WatchpointTarget::Register(register), MipsyArgType::Target));
+    }
+
+    // a range of memory, e.g. `$sp-16..$sp`
+    if let Some((start, end)) = arg.split_once("..") {
+        let start = eval_watchpoint_expr(state, start)? as u32;
+        let end = eval_watchpoint_expr(state, end)? as u32;
+        if end <= start {
+            return Err(get_error("<start>..<end>"));
         }
+
+        return Ok((
+            WatchpointTarget::MemRange {
+                start,
+                len: end - start,
+            },
+            MipsyArgType::Target,
+        ));
+    }
+
+    let addr = eval_watchpoint_expr(state, arg)? as u32;
+    let arg_type = if binary.labels.contains_key(arg.as_str()) {
+        MipsyArgType::Label
+    } else {
+        MipsyArgType::Target
     };
 
-    Ok((target, MipsyArgType::Target))
+    Ok((WatchpointTarget::mem_word(addr), arg_type))
+}
+
+/// Evaluates an address or size against the current state,
+/// so that registers can be used, e.g. `$sp-16`.
+fn eval_watchpoint_expr(state: &State, arg: &str) -> Result<i32, CommandError> {
+    let binary = state.binary.as_ref().ok_or(CommandError::MustLoadFile)?;
+
+    Expr::parse(arg, binary)
+        .and_then(|expr| expr.evaluate(state.runtime.timeline().state()))
+        .map_err(|err| match err {
+            ExprError::UnknownLabel(label) => CommandError::UnknownLabel { label },
+            err => CommandError::WithTip {
+                error: Box::new(CommandError::BadArgument {
+                    arg: "<target>".magenta().to_string(),
+                    instead: arg.to_string(),
+                }),
+                tip: err.to_string(),
+            },
+        })
+}
+
+/// Memory watchpoints can be referred to by their start address alone,
+/// so `watch rm my_array` removes the watchpoint set by `watch my_array 40`.
+fn existing_target(state: &State, target: WatchpointTarget) -> WatchpointTarget {
+    let binary = match state.binary.as_ref() {
+        Some(binary) => binary,
+        None => return target,
+    };
+
+    match target {
+        WatchpointTarget::MemRange { start, .. } if !binary.watchpoints.contains_key(&target) => {
+            binary
+                .watchpoints
+                .keys()
+                .find(
+                    |key| matches!(key, WatchpointTarget::MemRange { start: s, .. } if *s == start),
+                )
+                .copied()
+                .unwrap_or(target)
+        }
+        _ => target,
+    }
+}
+
+fn parse_action(arg: &str) -> Option<TargetAction> {
+    match arg {
+        "r" | "read" => Some(TargetAction::ReadOnly),
+        "w" | "write" => Some(TargetAction::WriteOnly),
+        "rw" | "r/w" | "r+w" | "w/r" | "w+r" | "read/write" | "read+write" => {
+            Some(TargetAction::ReadWrite)
+        }
+        _ => None,
+    }
 }
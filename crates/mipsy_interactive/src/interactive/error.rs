@@ -32,8 +32,12 @@ pub enum CommandError {
         path: String,
         os_error: String,
     },
+    CannotLoadBinary {
+        path: String,
+        error: String,
+    },
     CannotCompile {
-        mipsy_error: MipsyError,
+        mipsy_errors: Vec<MipsyError>,
     },
     CannotParseLine {
         line: String,
@@ -52,6 +56,9 @@ pub enum CommandError {
     UnknownLabel {
         label: String,
     },
+    UnknownInstruction {
+        instruction: String,
+    },
     UninitialisedRegister {
         register: Register,
     },
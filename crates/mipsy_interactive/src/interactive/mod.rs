@@ -18,8 +18,13 @@ use helper::MyHelper;
 use mipsy_lib::error::runtime::{Error, ErrorContext, InvalidSyscallReason, RuntimeError};
 use mipsy_lib::runtime::{SYS13_OPEN, SYS14_READ, SYS15_WRITE, SYS16_CLOSE};
 use mipsy_lib::{
-    compile::breakpoints::{get_affected_registers, TargetAction, TargetWatch},
-    error::parser,
+    compile::{
+        breakpoints::{
+            find_watchpoint_hits, get_affected_registers, TargetAction, WatchpointHit,
+            WatchpointTarget,
+        },
+        source_label_name,
+    },
     runtime::{state::TIMELINE_MAX_LEN, SteppedRuntime},
     Binary, InstSet, MipsyError, ParserError, Runtime,
 };
@@ -49,10 +54,10 @@ pub(crate) struct State {
 }
 
 impl State {
-    fn new(config: MipsyConfig) -> Self {
+    fn new(config: MipsyConfig, iset: InstSet) -> Self {
         Self {
             config,
-            iset: mipsy_instructions::inst_set(),
+            iset,
             commands: vec![],
             program: None,
             binary: None,
@@ -184,31 +189,42 @@ impl State {
             CommandError::CannotReadFile { path, os_error } => {
                 prompt::error(format!("failed to read file `{}`: {}", path, os_error));
             }
-            CommandError::CannotCompile { mipsy_error } => {
-                let file_tag = match mipsy_error {
-                    MipsyError::Parser(ref error) => error.file_tag(),
-                    MipsyError::Compiler(ref error) => error.file_tag(),
-                    // unreachable: can't have a runtime error at compile time (hopefully)
-                    MipsyError::Runtime(_) => unreachable!(),
-                };
-
-                let file_prompt = {
-                    if file_tag.is_empty() {
-                        String::new()
-                    } else {
-                        format!("`{}`", file_tag)
-                    }
-                };
+            CommandError::CannotLoadBinary { path, error } => {
+                prompt::error(format!("failed to load `{}`: {}", path, error));
+            }
+            CommandError::CannotCompile { mipsy_errors } => {
+                let count = mipsy_errors.len();
+
+                for mipsy_error in mipsy_errors {
+                    let file_tag = match mipsy_error {
+                        MipsyError::Parser(ref error) => error.file_tag(),
+                        MipsyError::Compiler(ref error) => error.file_tag(),
+                        // unreachable: can't have a runtime error at compile time (hopefully)
+                        MipsyError::Runtime(_) => unreachable!(),
+                    };
+
+                    let file_prompt = {
+                        if file_tag.is_empty() {
+                            String::new()
+                        } else {
+                            format!("`{}`", file_tag)
+                        }
+                    };
+
+                    prompt::error(format!("failed to compile {}", file_prompt));
+                    self.mipsy_error(mipsy_error, ErrorContext::Interactive, None);
+                }
 
-                prompt::error(format!("failed to compile {}", file_prompt));
-                self.mipsy_error(mipsy_error, ErrorContext::Interactive, None);
+                if count > 1 {
+                    prompt::error(format!("aborting due to {count} errors"));
+                }
             }
             CommandError::CannotParseLine { line, error } => {
                 prompt::error("failed to parse");
 
                 self.mipsy_error(
                     MipsyError::Parser(ParserError::new(
-                        parser::Error::ParseFailure,
+                        error.kind.into(),
                         Rc::from(""),
                         error.line,
                         error.col as u32,
@@ -268,6 +284,9 @@ impl State {
             CommandError::UnknownLabel { label } => {
                 prompt::error(format!("unknown label: \"{}\"", label));
             }
+            CommandError::UnknownInstruction { instruction } => {
+                prompt::error(format!("unknown instruction: \"{}\"", instruction));
+            }
             CommandError::UninitialisedRegister { register } => {
                 prompt::error(format!("register {register} is uninitialized"));
             }
@@ -500,6 +519,30 @@ impl State {
 
                         runtime_handler::sys17_exit_status(verbose, args.exit_code);
                     }
+                    Time(guard) => {
+                        let value = runtime_handler::sys30_time(verbose);
+                        self.runtime = guard(value);
+                    }
+                    Sleep(args, new_runtime) => {
+                        self.runtime = new_runtime;
+                        runtime_handler::sys32_sleep(verbose, args.millis);
+                    }
+                    ConfirmDialog(args, guard) => {
+                        let value = runtime_handler::sys50_confirm_dialog(verbose, &args.message);
+                        self.runtime = guard(value);
+                    }
+                    InputDialogInt(args, guard) => {
+                        let value = runtime_handler::sys51_input_dialog_int(verbose, &args.message);
+                        self.runtime = guard(value);
+                    }
+                    InputDialogString(args, guard) => {
+                        let value = runtime_handler::sys54_input_dialog_string(
+                            verbose,
+                            &args.message,
+                            args.max_len,
+                        );
+                        self.runtime = guard(value);
+                    }
                     Breakpoint(new_runtime) => {
                         self.runtime = new_runtime;
                         breakpoint = true;
@@ -522,22 +565,21 @@ impl State {
         let binary = self.binary.as_mut().unwrap_or(&mut empty_binary);
         let affected_registers = get_affected_registers(&self.runtime, inst);
         // TODO(joshh): move this into else if once let-chains are stabilised (1.64 baited me smh)
-        let watchpoints = affected_registers
-            .iter()
-            .filter(|&wp| {
-                binary.watchpoints.get(&wp.target).map_or(false, |watch| {
-                    watch.action.fits(&wp.action) && watch.enabled
-                })
-            })
-            .collect::<Vec<_>>();
+        let state = self.runtime.timeline().state();
+        let watchpoints = find_watchpoint_hits(&binary.watchpoints, &affected_registers, state);
 
         Ok(if self.exited {
             true
         } else {
-            let pc = self.runtime.timeline().state().pc();
+            let pc = state.pc();
             let bp = binary.breakpoints.get_mut(&pc);
+            let condition = bp
+                .as_ref()
+                .and_then(|bp| bp.condition.as_ref())
+                .map(|cond| cond.holds(state));
+            let condition_failed = matches!(condition, Some(Ok(false)));
 
-            if breakpoint || (bp.is_some() && bp.as_ref().unwrap().enabled) {
+            if breakpoint || (bp.is_some() && bp.as_ref().unwrap().enabled && !condition_failed) {
                 if bp.is_some() && bp.as_ref().unwrap().ignore_count > 0 {
                     bp.unwrap().ignore_count -= 1;
                     trapped
@@ -546,8 +588,11 @@ impl State {
                         .labels
                         .iter()
                         .find(|(_, &addr)| addr == pc)
-                        .map(|(name, _)| name.yellow().bold().to_string());
+                        .map(|(name, _)| source_label_name(name).yellow().bold().to_string());
 
+                    if let Some(Err(err)) = condition {
+                        prompt::error(format!("failed to evaluate breakpoint condition: {err}"));
+                    }
                     runtime_handler::breakpoint(label.as_deref(), pc, &binary.line_numbers);
                     if let Some(bp) = bp {
                         bp.commands.clone().iter().for_each(|command| {
@@ -560,15 +605,35 @@ impl State {
             } else if !watchpoints.is_empty() {
                 let mut all_ignored = true;
                 let mut to_exec = Vec::new();
-                for watchpoint in watchpoints {
+                for hit in watchpoints {
+                    let label = match hit.target {
+                        WatchpointTarget::MemRange { start, .. } => binary
+                            .labels
+                            .iter()
+                            .find(|(_, &addr)| addr == start)
+                            .map(|(name, _)| source_label_name(name).to_string()),
+                        WatchpointTarget::Register(_) => None,
+                    };
                     let wp = binary
                         .watchpoints
-                        .get_mut(&watchpoint.target)
+                        .get_mut(&hit.target)
                         .expect("I got the condition wrong");
                     if wp.ignore_count > 0 {
                         wp.ignore_count -= 1;
                     } else {
-                        runtime_handler::watchpoint(watchpoint, original_pc, &binary.line_numbers);
+                        if let Some(Err(err)) = wp.condition.as_ref().map(|cond| cond.holds(state))
+                        {
+                            prompt::error(format!(
+                                "failed to evaluate watchpoint condition: {err}"
+                            ));
+                        }
+                        runtime_handler::watchpoint(
+                            &hit,
+                            label.as_deref(),
+                            hit.values(&self.runtime),
+                            original_pc,
+                            &binary.line_numbers,
+                        );
                         to_exec.extend(wp.commands.clone().into_iter());
                         all_ignored = false;
                     }
@@ -658,8 +723,8 @@ pub(crate) fn editor() -> Editor<MyHelper> {
     rl
 }
 
-fn state(config: MipsyConfig) -> State {
-    let mut state = State::new(config);
+fn state(config: MipsyConfig, iset: InstSet) -> State {
+    let mut state = State::new(config, iset);
 
     state.add_command(commands::load_command());
     state.add_command(commands::run_command());
@@ -674,15 +739,16 @@ fn state(config: MipsyConfig) -> State {
     state.add_command(commands::examine_command());
     state.add_command(commands::print_command());
     state.add_command(commands::dot_command());
+    state.add_command(commands::isa_command());
     state.add_command(commands::help_command());
     state.add_command(commands::exit_command());
 
     state
 }
 
-pub fn launch(config: MipsyConfig) -> ! {
+pub fn launch(config: MipsyConfig, iset: InstSet) -> ! {
     let mut rl = editor();
-    let mut state = state(config);
+    let mut state = state(config, iset);
     let interrupted = state.interrupted.clone();
     ctrlc::set_handler(move || interrupted.store(true, Ordering::SeqCst))
         .expect("Failed to set signal handler!");
@@ -1,133 +1,49 @@
 use crate::interactive::TargetAction;
-use std::{
-    collections::HashMap,
-    fmt::{Debug, Display},
-    rc::Rc,
-    str::FromStr,
-};
+use std::{collections::HashMap, rc::Rc};
 
-use super::{prompt, TargetWatch};
+use super::{prompt, WatchpointHit, WatchpointTarget};
 use colored::*;
-use mipsy_lib::runtime::{CloseArgs, OpenArgs, ReadArgs, WriteArgs};
-use std::io::Write;
-use text_io::try_read;
-
-fn get_input<T>(name: &str, verbose: bool, line: bool) -> T
-where
-    T: FromStr + Display,
-    <T as FromStr>::Err: Debug,
-{
-    let prompt: Box<dyn Fn()> = if verbose {
-        Box::new(|| prompt::error_nonl(format!("bad input (expected {}), try again: ", name)))
-    } else {
-        Box::new(|| print!("[mipsy] bad input (expected {}), try again: ", name))
-    };
-
-    loop {
-        let result: Result<T, _> = if line {
-            let mut input = String::new();
-            std::io::stdin().read_line(&mut input).unwrap();
-
-            input.parse().map_err(|_| ())
-        } else {
-            try_read!().map_err(|_| ())
-        };
-
-        match result {
-            Ok(n) => return n,
-            Err(_) => {
-                (prompt)();
-                std::io::stdout().flush().unwrap();
-                continue;
-            }
-        };
-    }
-}
-
-fn get_input_eof<T>(name: &str, verbose: bool) -> Option<T>
-where
-    T: FromStr + Display,
-    <T as FromStr>::Err: Debug,
-{
-    let prompt: Box<dyn Fn()> = if verbose {
-        Box::new(|| prompt::error_nonl(format!("bad input (expected {}), try again: ", name)))
-    } else {
-        Box::new(|| print!("[mipsy] bad input (expected {}), try again: ", name))
-    };
-
-    loop {
-        let result: Result<T, _> = try_read!();
+use mipsy_lib::runtime::{
+    io::{self, Input, ReadError, StdinInput},
+    CloseArgs, OpenArgs, ReadArgs, WriteArgs, CONFIRM_DIALOG_CANCEL, CONFIRM_DIALOG_NO,
+    CONFIRM_DIALOG_YES,
+};
+use std::{
+    io::Write,
+    time::{Duration, SystemTime},
+};
 
-        match result {
-            Ok(n) => return Some(n),
-            Err(text_io::Error::Parse(leftover, _)) => {
-                if leftover.is_empty() {
-                    return None;
+fn get_input<T>(
+    name: &str,
+    verbose: bool,
+    read: fn(&mut dyn Input) -> Result<T, ReadError>,
+) -> Option<T> {
+    io::read_retrying(&mut StdinInput, name, read, &mut |name, err| {
+        match err {
+            ReadError::TooBig(n) => {
+                if verbose {
+                    prompt::error("bad input (too big to fit in 32 bits)")
+                } else {
+                    println!("[mipsy] bad input (too big to fit in 32 bits)")
                 }
 
-                (prompt)();
-                std::io::stdout().flush().unwrap();
-                continue;
+                println!(
+                    "[mipsy] if you want the value to be truncated to 32 bits, try {}",
+                    *n as i32
+                );
+                print!("[mipsy] try again: ");
             }
-            Err(_) => {
-                (prompt)();
-                std::io::stdout().flush().unwrap();
-                continue;
+            ReadError::Invalid(_) | ReadError::Eof => {
+                if verbose {
+                    prompt::error_nonl(format!("bad input (expected {}), try again: ", name))
+                } else {
+                    print!("[mipsy] bad input (expected {}), try again: ", name)
+                }
             }
-        };
-    }
-}
-
-fn get_input_int(name: &str, verbose: bool) -> Option<i32> {
-    let bad_input_prompt: &dyn Fn() = &|| {
-        if verbose {
-            prompt::error_nonl(format!("bad input (expected {}), try again: ", name))
-        } else {
-            print!("[mipsy] bad input (expected {}), try again: ", name)
-        };
-    };
-
-    let too_big_prompt: &dyn Fn() = &|| {
-        if verbose {
-            prompt::error("bad input (too big to fit in 32 bits)")
-        } else {
-            println!("[mipsy] bad input (too big to fit in 32 bits)")
         }
-    };
-
-    loop {
-        let result: Result<i128, _> = try_read!();
-
-        match result {
-            Ok(n) => match i32::try_from(n) {
-                Ok(n) => return Some(n),
-                Err(_) => {
-                    (too_big_prompt)();
-                    println!(
-                        "[mipsy] if you want the value to be truncated to 32 bits, try {}",
-                        n as i32
-                    );
-                    print!("[mipsy] try again: ");
-                    std::io::stdout().flush().unwrap();
-                    continue;
-                }
-            },
-            Err(text_io::Error::Parse(leftover, _)) => {
-                if leftover.is_empty() {
-                    return None;
-                }
 
-                (bad_input_prompt)();
-                std::io::stdout().flush().unwrap();
-                continue;
-            }
-            Err(_) => {
-                (bad_input_prompt)();
-                std::io::stdout().flush().unwrap();
-                continue;
-            }
-        };
-    }
+        std::io::stdout().flush().unwrap();
+    })
 }
 
 pub(crate) fn sys1_print_int(verbose: bool, val: i32) {
@@ -178,31 +94,31 @@ pub(crate) fn sys4_print_string(verbose: bool, val: &[u8]) {
     std::io::stdout().flush().unwrap();
 }
 
-pub(crate) fn sys5_read_int(verbose: bool) -> i32 {
+pub(crate) fn sys5_read_int(verbose: bool) -> Option<i32> {
     if verbose {
         prompt::syscall(5, "read_int: ");
         std::io::stdout().flush().unwrap();
     }
 
-    get_input_int("int", verbose).unwrap_or(0)
+    get_input("int", verbose, io::read_int)
 }
 
-pub(crate) fn sys6_read_float(verbose: bool) -> f32 {
+pub(crate) fn sys6_read_float(verbose: bool) -> Option<f32> {
     if verbose {
         prompt::syscall(6, "read_float: ");
         std::io::stdout().flush().unwrap();
     }
 
-    get_input_eof("float", verbose).unwrap_or(0.0)
+    get_input("float", verbose, io::read_float)
 }
 
-pub(crate) fn sys7_read_double(verbose: bool) -> f64 {
+pub(crate) fn sys7_read_double(verbose: bool) -> Option<f64> {
     if verbose {
         prompt::syscall(7, "read_double: ");
         std::io::stdout().flush().unwrap();
     }
 
-    get_input_eof("double", verbose).unwrap_or(0.0)
+    get_input("double", verbose, io::read_double)
 }
 
 pub(crate) fn sys8_read_string(verbose: bool, max_len: u32) -> Vec<u8> {
@@ -211,23 +127,7 @@ pub(crate) fn sys8_read_string(verbose: bool, max_len: u32) -> Vec<u8> {
         std::io::stdout().flush().unwrap();
     }
 
-    let input: String = get_input("string", verbose, true);
-
-    // if input.len() > max_len as usize {
-    //     prompt::error(format!("bad input (max string length specified as {}, given string is {} bytes), try again: ", max_len, input.len()));
-    //     prompt::error_nonl("please try again: ");
-    //     std::io::stdout().flush().unwrap();
-    //     continue;
-    // }
-
-    // if input.len() == max_len as usize {
-    //     prompt::error(format!("bad input (max string length specified as {}, given string is {} bytes -- must be at least one byte fewer, for NULL character), try again: ", max_len, input.len()));
-    //     prompt::error_nonl("please try again: ");
-    //     std::io::stdout().flush().unwrap();
-    //     continue;
-    // }
-
-    input.into_bytes()
+    get_input("string", verbose, io::read_line).unwrap_or_default()
 }
 
 pub(crate) fn sys9_sbrk(verbose: bool, val: i32) {
@@ -257,14 +157,13 @@ pub(crate) fn sys11_print_char(verbose: bool, val: u8) {
     std::io::stdout().flush().unwrap();
 }
 
-pub(crate) fn sys12_read_char(verbose: bool) -> u8 {
+pub(crate) fn sys12_read_char(verbose: bool) -> Option<u8> {
     if verbose {
         prompt::syscall(5, "read_character: ");
         std::io::stdout().flush().unwrap();
     }
 
-    let character: char = get_input_eof("character", verbose).unwrap_or('\0');
-    character as u8
+    get_input("character", verbose, io::read_char)
 }
 
 // TODO: implement file handling in mipsy interactive
@@ -305,6 +204,85 @@ pub(crate) fn sys17_exit_status(verbose: bool, val: i32) {
     }
 }
 
+pub(crate) fn sys30_time(verbose: bool) -> u64 {
+    let millis = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|time| time.as_millis() as u64)
+        .unwrap_or(0);
+
+    if verbose {
+        prompt::syscall_nl(30, format!("time: {}", millis.to_string().green()));
+    }
+
+    millis
+}
+
+pub(crate) fn sys32_sleep(verbose: bool, millis: u32) {
+    if verbose {
+        prompt::syscall_nl(32, format!("sleep: {}ms", millis.to_string().green()));
+    }
+
+    std::thread::sleep(Duration::from_millis(millis as u64));
+}
+
+pub(crate) fn sys50_confirm_dialog(verbose: bool, message: &[u8]) -> i32 {
+    let message = String::from_utf8_lossy(message);
+
+    if verbose {
+        prompt::syscall(50, format!("confirm_dialog: \"{}\" [y/n] ", message));
+    } else {
+        print!("{} [y/n] ", message);
+    }
+    std::io::stdout().flush().unwrap();
+
+    match io::read_line(&mut StdinInput)
+        .map(|line| String::from_utf8_lossy(&line).trim().to_ascii_lowercase())
+        .as_deref()
+    {
+        Ok("y" | "yes") => CONFIRM_DIALOG_YES,
+        Ok("n" | "no") => CONFIRM_DIALOG_NO,
+        _ => CONFIRM_DIALOG_CANCEL,
+    }
+}
+
+pub(crate) fn sys51_input_dialog_int(verbose: bool, message: &[u8]) -> Option<i32> {
+    let message = String::from_utf8_lossy(message);
+
+    if verbose {
+        prompt::syscall(51, format!("input_dialog_int: \"{}\" ", message));
+    } else {
+        print!("{}", message);
+    }
+    std::io::stdout().flush().unwrap();
+
+    get_input("int", verbose, io::read_int)
+}
+
+pub(crate) fn sys54_input_dialog_string(
+    verbose: bool,
+    message: &[u8],
+    max_len: u32,
+) -> Option<Vec<u8>> {
+    let message = String::from_utf8_lossy(message);
+
+    if verbose {
+        prompt::syscall(
+            54,
+            format!("input_dialog_string [size={}]: \"{}\" ", max_len, message),
+        );
+    } else {
+        print!("{}", message);
+    }
+    std::io::stdout().flush().unwrap();
+
+    get_input("string", verbose, io::read_line).map(|mut line| {
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        line
+    })
+}
+
 pub(crate) fn trap(_verbose: bool) {
     // TODO(zkol): This should provide actual diagnostics
     println!("{}\n", "[TRAP]".bright_red().bold());
@@ -326,23 +304,62 @@ pub(crate) fn breakpoint(
 }
 
 pub(crate) fn watchpoint(
-    watchpoint: &TargetWatch,
+    hit: &WatchpointHit,
+    label: Option<&str>,
+    (old, new): (Option<u32>, Option<u32>),
     pc: u32,
     line_numbers: &HashMap<u32, (Rc<str>, u32)>,
 ) {
     let (filename, line_num, addr) = get_line_info(line_numbers, pc);
+
+    let target = match (hit.target, hit.access.target) {
+        (
+            WatchpointTarget::MemRange { start, .. },
+            WatchpointTarget::MemRange { start: access, .. },
+        ) => {
+            let name = match label {
+                Some(label) => label.yellow().bold().to_string(),
+                None => format!("{}{:08x}", "0x".yellow(), start),
+            };
+
+            match hit.element_index() {
+                Some(index) => format!("{name}[{index}] ({}{:08x})", "0x".yellow(), access),
+                None if label.is_some() => format!("{name} ({}{:08x})", "0x".yellow(), access),
+                None => format!("{}{:08x}", "0x".yellow(), access),
+            }
+        }
+        (_, access) => access.to_string(),
+    };
+
+    // words and registers are shown signed, bytes and halves unsigned
+    let format_value = |value: Option<u32>| match value {
+        Some(value) if matches!(hit.access.target, WatchpointTarget::MemRange { len, .. } if len < 4) => {
+            value.to_string().bold()
+        }
+        Some(value) => (value as i32).to_string().bold(),
+        None => "uninitialized".bright_black(),
+    };
+
+    let values = match hit.access.action {
+        TargetAction::ReadOnly => format_value(new).to_string(),
+        TargetAction::WriteOnly | TargetAction::ReadWrite => {
+            format!("{} -> {}", format_value(old), format_value(new))
+        }
+    };
+
     println!(
-        "{} {}:{}{}{} - {} was {}\n",
+        "{} {}:{}{}{} - {} was {}: {}\n",
         "\n[WATCHPOINT".cyan().bold(),
         filename,
         line_num,
         addr,
         "]".cyan().bold(),
-        watchpoint.target,
-        match watchpoint.action {
+        target,
+        match hit.access.action {
             TargetAction::ReadOnly => "read from",
             TargetAction::WriteOnly | TargetAction::ReadWrite => "written to",
-        }
+        },
+        values,
     );
 }
 
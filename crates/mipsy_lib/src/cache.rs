@@ -0,0 +1,206 @@
+//! `.mipsy` files: a compiled [`Binary`], so that a program can be
+//! run again without compiling it.
+//!
+//! A file starts with [`MAGIC`] and the format version, followed by a header
+//! with the version of mipsy that compiled it and a hash of the instruction set
+//! it was compiled with, and then the binary itself.
+
+use std::fmt::Display;
+
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+use crate::{Binary, InstSet};
+
+pub const MAGIC: &[u8] = b"\0mipsy\n\0";
+
+/// Bumped whenever [`Binary`] (or anything it contains) changes shape.
+const FORMAT_VERSION: u32 = 2;
+
+/// The same version that `mipsy --version` reports.
+const MIPSY_VERSION: &str = concat!(
+    env!("VERGEN_GIT_COMMIT_DATE"),
+    " ",
+    env!("VERGEN_GIT_SHA_SHORT")
+);
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    mipsy_version: String,
+    iset_hash: u64,
+}
+
+/// Whether a file looks like a compiled `.mipsy` binary, rather than assembly source.
+pub fn is_cache(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub fn write_cache(binary: &Binary, iset: &InstSet) -> Vec<u8> {
+    let header = Header {
+        mipsy_version: MIPSY_VERSION.to_string(),
+        iset_hash: iset_hash(iset),
+    };
+
+    let options = bincode_options(u64::MAX);
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(FORMAT_VERSION.to_le_bytes());
+    options
+        .serialize_into(&mut bytes, &header)
+        .expect("header should always serialise");
+    options
+        .serialize_into(&mut bytes, binary)
+        .expect("binary should always serialise");
+
+    bytes
+}
+
+pub fn read_cache(bytes: &[u8], iset: &InstSet) -> Result<Binary, CacheError> {
+    if !is_cache(bytes) {
+        return Err(CacheError::NotCache);
+    }
+
+    let bytes = &bytes[MAGIC.len()..];
+    let format_version = match bytes.get(..4) {
+        Some(version) => u32::from_le_bytes([version[0], version[1], version[2], version[3]]),
+        None => return Err(CacheError::Corrupt),
+    };
+
+    if format_version != FORMAT_VERSION {
+        return Err(CacheError::FormatVersion {
+            found: format_version,
+        });
+    }
+
+    // nothing in a file can take up more than the whole file, so a corrupt
+    // length can't make us allocate more memory than that
+    let options = bincode_options(bytes.len() as u64);
+
+    let mut bytes = &bytes[4..];
+    let header: Header = options
+        .deserialize_from(&mut bytes)
+        .map_err(|_| CacheError::Corrupt)?;
+
+    if header.iset_hash != iset_hash(iset) {
+        return Err(CacheError::InstSet {
+            mipsy_version: header.mipsy_version,
+        });
+    }
+
+    options.deserialize(bytes).map_err(|_| CacheError::Corrupt)
+}
+
+/// The same encoding as `bincode::serialize`, but limited to `limit` bytes.
+fn bincode_options(limit: u64) -> impl Options + Copy {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(limit)
+}
+
+/// A hash of the instruction set that's the same on every platform,
+/// so a binary compiled on one machine can be checked on another.
+pub fn iset_hash(iset: &InstSet) -> u64 {
+    let bytes = bincode::serialize(&(iset.native_set(), iset.pseudo_set()))
+        .expect("instruction set should always serialise");
+
+    // FNV-1a
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CacheError {
+    NotCache,
+    Corrupt,
+    FormatVersion { found: u32 },
+    InstSet { mipsy_version: String },
+}
+
+impl Display for CacheError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheError::NotCache => write!(f, "the file is not a compiled mipsy binary"),
+            CacheError::Corrupt => write!(f, "the file is corrupt"),
+            CacheError::FormatVersion { found } => write!(
+                f,
+                "the file was compiled by an incompatible version of mipsy \
+                 (format version {found}, expected {FORMAT_VERSION}), so it needs to be recompiled"
+            ),
+            CacheError::InstSet { mipsy_version } => write!(
+                f,
+                "the file was compiled by mipsy {mipsy_version} with a different instruction set, \
+                 so it needs to be recompiled"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{compile::breakpoints::Breakpoint, Safe, TEXT_BOT};
+    use std::{collections::HashMap, rc::Rc};
+
+    fn binary() -> Binary {
+        let mut binary = Binary {
+            text: vec![
+                Safe::Valid(0x0c),
+                Safe::Valid(0),
+                Safe::Uninitialised,
+                Safe::Valid(0),
+            ],
+            line_numbers: HashMap::from([(TEXT_BOT, (Rc::from("prog.s"), 3))]),
+            breakpoints: HashMap::from([(TEXT_BOT, Breakpoint::new(0))]),
+            ..Default::default()
+        };
+
+        binary.insert_label("main", TEXT_BOT);
+
+        binary
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let iset = InstSet::new(vec![], vec![]);
+        let binary = binary();
+
+        assert_eq!(read_cache(&write_cache(&binary, &iset), &iset), Ok(binary));
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let iset = InstSet::new(vec![], vec![]);
+        let cache = write_cache(&binary(), &iset);
+
+        assert_eq!(read_cache(b"main:\n", &iset), Err(CacheError::NotCache));
+        assert_eq!(
+            read_cache(&cache[..cache.len() - 1], &iset),
+            Err(CacheError::Corrupt)
+        );
+
+        let mut old = cache.clone();
+        old[MAGIC.len()] = 0;
+        assert_eq!(
+            read_cache(&old, &iset),
+            Err(CacheError::FormatVersion { found: 0 })
+        );
+
+        // a length that's longer than the file doesn't get allocated
+        let mut too_long = cache.clone();
+        let text_len = MAGIC.len() + 4 + 8 + MIPSY_VERSION.len() + 8;
+        too_long[text_len..text_len + 8].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+        assert_eq!(read_cache(&too_long, &iset), Err(CacheError::Corrupt));
+
+        // as if it was compiled with some other instruction set
+        let mut other_iset = cache;
+        other_iset[MAGIC.len() + 4 + 8 + MIPSY_VERSION.len()] ^= 1;
+        assert_eq!(
+            read_cache(&other_iset, &iset),
+            Err(CacheError::InstSet {
+                mipsy_version: MIPSY_VERSION.to_string()
+            })
+        );
+    }
+}
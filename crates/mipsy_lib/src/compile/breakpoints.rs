@@ -1,11 +1,12 @@
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
 
+use super::expr::{Expr, ExprError};
 use crate::{
     runtime::{JAL, JUMP, SPECIAL, SPECIAL2, SPECIAL3},
-    Register, Runtime,
+    Binary, Register, Runtime, State,
 };
-use std::{fmt::Display, ops::Sub};
+use std::{collections::HashMap, fmt::Display, ops::Sub};
 
 #[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum TargetAction {
@@ -76,7 +77,40 @@ impl Display for TargetAction {
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum WatchpointTarget {
     Register(Register),
-    MemAddr(u32),
+    /// `len` bytes of memory, starting at `start`.
+    MemRange {
+        start: u32,
+        len: u32,
+    },
+}
+
+impl WatchpointTarget {
+    /// A single word of memory.
+    pub fn mem_word(addr: u32) -> Self {
+        WatchpointTarget::MemRange {
+            start: addr,
+            len: 4,
+        }
+    }
+
+    /// Whether an access to `other` touches any part of this target.
+    pub fn overlaps(&self, other: &WatchpointTarget) -> bool {
+        match (*self, *other) {
+            (WatchpointTarget::Register(reg), WatchpointTarget::Register(other)) => reg == other,
+            (
+                WatchpointTarget::MemRange { start, len },
+                WatchpointTarget::MemRange {
+                    start: other_start,
+                    len: other_len,
+                },
+            ) => {
+                // widen, so that ranges at the very top of memory don't overflow
+                let (start, other_start) = (start as u64, other_start as u64);
+                start < other_start + other_len as u64 && other_start < start + len as u64
+            }
+            _ => false,
+        }
+    }
 }
 
 impl Display for WatchpointTarget {
@@ -84,20 +118,147 @@ impl Display for WatchpointTarget {
         write!(
             f,
             "{}",
-            match self {
+            match *self {
                 WatchpointTarget::Register(r) => r.to_string(),
-                WatchpointTarget::MemAddr(m) => format!("{}{:08x}", "0x".yellow(), m),
+                WatchpointTarget::MemRange { start, len: 4 } => {
+                    format!("{}{:08x}", "0x".yellow(), start)
+                }
+                WatchpointTarget::MemRange { start, len } => format!(
+                    "{}{:08x}..{}{:08x}",
+                    "0x".yellow(),
+                    start,
+                    "0x".yellow(),
+                    start.wrapping_add(len)
+                ),
             }
         )
     }
 }
 
-#[derive(PartialEq)]
+/// # A register or memory access made by an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct TargetWatch {
     pub target: WatchpointTarget,
     pub action: TargetAction,
 }
 
+/// # A watchpoint that was triggered by an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WatchpointHit {
+    /// The target of the triggered watchpoint.
+    pub target: WatchpointTarget,
+    /// What the instruction actually accessed.
+    pub access: TargetWatch,
+}
+
+impl WatchpointHit {
+    /// The index of the accessed element within the watched range,
+    /// treating the range as an array of elements the size of the access.
+    ///
+    /// Returns `None` when the range holds just a single element,
+    /// or the access isn't aligned to the start of the range.
+    pub fn element_index(&self) -> Option<u32> {
+        match (self.target, self.access.target) {
+            (
+                WatchpointTarget::MemRange { start, len },
+                WatchpointTarget::MemRange {
+                    start: access_start,
+                    len: access_len,
+                },
+            ) => {
+                let offset = access_start.checked_sub(start)?;
+                (len > access_len && offset % access_len == 0).then_some(offset / access_len)
+            }
+            _ => None,
+        }
+    }
+
+    /// The accessed value, before and after the instruction ran.
+    /// Either may be `None` if it was uninitialized.
+    pub fn values(&self, runtime: &Runtime) -> (Option<u32>, Option<u32>) {
+        let read = |state: &State| match self.access.target {
+            WatchpointTarget::Register(reg) => {
+                state.read_register(reg.to_u32()).ok().map(|val| val as u32)
+            }
+            WatchpointTarget::MemRange { start, len: 1 } => {
+                state.read_mem_byte(start).ok().map(u32::from)
+            }
+            WatchpointTarget::MemRange { start, len: 2 } => {
+                state.read_mem_half(start).ok().map(u32::from)
+            }
+            WatchpointTarget::MemRange { start, .. } => state.read_mem_word(start).ok(),
+        };
+
+        let timeline = runtime.timeline();
+        (timeline.prev_state().and_then(read), read(timeline.state()))
+    }
+}
+
+/// Finds the enabled watchpoints that are triggered by `accesses`,
+/// taking their actions and conditions into account.
+pub fn find_watchpoint_hits(
+    watchpoints: &HashMap<WatchpointTarget, Watchpoint>,
+    accesses: &[TargetWatch],
+    state: &State,
+) -> Vec<WatchpointHit> {
+    let mut hits = Vec::new();
+
+    for access in accesses {
+        for (target, watchpoint) in watchpoints {
+            let condition_failed = matches!(
+                watchpoint.condition.as_ref().map(|cond| cond.holds(state)),
+                Some(Ok(false))
+            );
+
+            if watchpoint.enabled
+                && watchpoint.action.fits(&access.action)
+                && target.overlaps(&access.target)
+                && !condition_failed
+            {
+                hits.push(WatchpointHit {
+                    target: *target,
+                    access: *access,
+                });
+            }
+        }
+    }
+
+    hits
+}
+
+/// # A condition attached to a breakpoint or watchpoint.
+///
+/// The point only triggers if its expression
+/// evaluates to a non-zero value when it is hit.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(source: &str, binary: &Binary) -> Result<Self, ExprError> {
+        Ok(Self {
+            source: source.trim().to_string(),
+            expr: Expr::parse(source, binary)?,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn holds(&self, state: &State) -> Result<bool, ExprError> {
+        Ok(self.expr.evaluate(state)? != 0)
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Watchpoint {
     pub id: u32,
@@ -105,6 +266,7 @@ pub struct Watchpoint {
     pub ignore_count: u32,
     pub enabled: bool,
     pub commands: Vec<String>,
+    pub condition: Option<Condition>,
 }
 
 impl Watchpoint {
@@ -115,6 +277,7 @@ impl Watchpoint {
             ignore_count: 0,
             enabled: true,
             commands: Vec::new(),
+            condition: None,
         }
     }
 }
@@ -126,6 +289,7 @@ pub struct Breakpoint {
     pub enabled: bool,
     pub commands: Vec<String>,
     pub ignore_count: u32,
+    pub condition: Option<Condition>,
 }
 
 impl Breakpoint {
@@ -135,6 +299,7 @@ impl Breakpoint {
             enabled: true,
             commands: Vec::new(),
             ignore_count: 0,
+            condition: None,
         }
     }
 }
@@ -142,6 +307,7 @@ impl Breakpoint {
 pub trait Point {
     fn get_id(&self) -> u32;
     fn get_commands(&'_ mut self) -> &'_ mut Vec<String>;
+    fn get_condition(&'_ mut self) -> &'_ mut Option<Condition>;
 }
 
 impl Point for Breakpoint {
@@ -152,6 +318,10 @@ impl Point for Breakpoint {
     fn get_commands(&'_ mut self) -> &'_ mut Vec<String> {
         &mut self.commands
     }
+
+    fn get_condition(&'_ mut self) -> &'_ mut Option<Condition> {
+        &mut self.condition
+    }
 }
 
 impl Point for Watchpoint {
@@ -162,6 +332,10 @@ impl Point for Watchpoint {
     fn get_commands(&'_ mut self) -> &'_ mut Vec<String> {
         &mut self.commands
     }
+
+    fn get_condition(&'_ mut self) -> &'_ mut Option<Condition> {
+        &mut self.condition
+    }
 }
 
 const LB: u32 = 0b100000;
@@ -181,10 +355,16 @@ pub fn get_affected_registers(runtime: &Runtime, inst: u32) -> Vec<TargetWatch>
     let rs = (inst >> 21) & 0x1F;
     let rt = (inst >> 16) & 0x1F;
     let rd = (inst >> 11) & 0x1F;
-    let offset = (inst & 0xFF) as i32;
+    let offset = (inst & 0xFFFF) as i16 as i32;
+
+    let access_size = match opcode {
+        LB | LBU | SB => 1,
+        LH | LHU | SH => 2,
+        _ => 4,
+    };
 
     match opcode {
-        LB | LBU | LH | LHU | LW | LWU | LUI => vec![
+        LB | LBU | LH | LHU | LW | LWU => vec![
             TargetWatch {
                 target: WatchpointTarget::Register(Register::from_u32(rt).unwrap()),
                 action: TargetAction::WriteOnly,
@@ -194,28 +374,34 @@ pub fn get_affected_registers(runtime: &Runtime, inst: u32) -> Vec<TargetWatch>
                 action: TargetAction::ReadOnly,
             },
             TargetWatch {
-                target: WatchpointTarget::MemAddr(
-                    (runtime
+                target: WatchpointTarget::MemRange {
+                    start: runtime
                         .timeline()
                         .prev_state()
                         .expect("there should be a previous state")
                         .read_register(rb)
                         .expect("uninitialized read should already have been handled")
-                        + offset) as u32,
-                ),
+                        .wrapping_add(offset) as u32,
+                    len: access_size,
+                },
                 action: TargetAction::ReadOnly,
             },
         ],
+        LUI => vec![TargetWatch {
+            target: WatchpointTarget::Register(Register::from_u32(rt).unwrap()),
+            action: TargetAction::WriteOnly,
+        }],
         SB | SH | SW => vec![
             TargetWatch {
-                target: WatchpointTarget::MemAddr(
-                    (runtime
+                target: WatchpointTarget::MemRange {
+                    start: runtime
                         .timeline()
                         .state()
                         .read_register(rb)
                         .expect("uninitialized read should already have been handled")
-                        + offset) as u32,
-                ),
+                        .wrapping_add(offset) as u32,
+                    len: access_size,
+                },
                 action: TargetAction::WriteOnly,
             },
             TargetWatch {
@@ -258,3 +444,51 @@ pub fn get_affected_registers(runtime: &Runtime, inst: u32) -> Vec<TargetWatch>
         ],
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hit(start: u32, len: u32, access: u32, access_len: u32) -> WatchpointHit {
+        WatchpointHit {
+            target: WatchpointTarget::MemRange { start, len },
+            access: TargetWatch {
+                target: WatchpointTarget::MemRange {
+                    start: access,
+                    len: access_len,
+                },
+                action: TargetAction::ReadOnly,
+            },
+        }
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let array = WatchpointTarget::MemRange {
+            start: 0x1000,
+            len: 40,
+        };
+
+        assert!(array.overlaps(&WatchpointTarget::mem_word(0x1000)));
+        assert!(array.overlaps(&WatchpointTarget::mem_word(0x1024)));
+        assert!(!array.overlaps(&WatchpointTarget::mem_word(0x1028)));
+        assert!(array.overlaps(&WatchpointTarget::MemRange {
+            start: 0x0FFF,
+            len: 2
+        }));
+        assert!(!array.overlaps(&WatchpointTarget::MemRange {
+            start: 0x0FFE,
+            len: 2
+        }));
+        assert!(!array.overlaps(&WatchpointTarget::Register(Register::T0)));
+    }
+
+    #[test]
+    fn test_element_index() {
+        assert_eq!(hit(0x1000, 40, 0x1008, 4).element_index(), Some(2));
+        assert_eq!(hit(0x1000, 40, 0x1009, 1).element_index(), Some(9));
+        assert_eq!(hit(0x1000, 40, 0x1002, 4).element_index(), None);
+        assert_eq!(hit(0x1000, 4, 0x1000, 4).element_index(), None);
+        assert_eq!(hit(0x1000, 40, 0x0FFE, 4).element_index(), None);
+    }
+}
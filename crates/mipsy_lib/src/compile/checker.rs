@@ -1,17 +1,29 @@
-use std::rc::Rc;
+use std::{collections::HashSet, rc::Rc};
 
-use mipsy_parser::{MpArgument, MpImmediate, MpItem, MpNumber};
+use mipsy_parser::{
+    parser::MpAttributedItem, MpArgument, MpConstValue, MpConstValueLoc, MpDirective, MpImmediate,
+    MpItem, MpNumber,
+};
 
 use crate::{
-    error::{compiler, ToMipsyResult},
-    inst::instruction::ToRegister,
-    Binary, CompilerError, MipsyError, MipsyResult, MpProgram, DATA_BOT, HEAP_BOT,
+    error::{
+        compiler::{self, Warning, ALL_WARNINGS},
+        CompileResult, ToMipsyResult,
+    },
+    inst::instruction::{SignatureRef, ToRegister},
+    Binary, CompilerError, CompilerWarning, InstSet, MipsyError, MipsyResult, MpProgram, DATA_BOT,
+    HEAP_BOT,
 };
 
-pub enum Warning {}
+use super::{eval_expression, text::find_instruction};
 
-pub fn check_pre(program: &MpProgram) -> MipsyResult<Vec<Warning>> {
-    let warnings = vec![];
+pub fn check_pre(
+    program: &MpProgram,
+    kernel: &MpProgram,
+    iset: &InstSet,
+) -> CompileResult<Vec<CompilerWarning>> {
+    let mut warnings = vec![];
+    let mut errors = vec![];
 
     for attributed_item in program.items() {
         let item = attributed_item.item();
@@ -24,30 +36,68 @@ pub fn check_pre(program: &MpProgram) -> MipsyResult<Vec<Warning>> {
                     match argument {
                         MpArgument::Register(register) => {
                             let ident = register.get_identifier();
-                            ident.to_register().into_compiler_mipsy_result(
+                            if let Err(err) = ident.to_register().into_compiler_mipsy_result(
                                 file_tag.clone(),
                                 line,
                                 *col,
                                 *col_end,
-                            )?;
+                            ) {
+                                errors.push(err.with_expansion(attributed_item.expansion()));
+                            }
                         }
                         MpArgument::Number(_) => {} // MpArgument::LabelPlusConst(..) => {}
                     }
                 }
             }
             MpItem::Label(_) => {}
+            MpItem::Directive((MpDirective::Set(option), position)) => {
+                warn(
+                    &mut warnings,
+                    attributed_item,
+                    Warning::IgnoredSetOption {
+                        option: option.to_string(),
+                    },
+                    position.col(),
+                    position.col_end(),
+                );
+            }
             MpItem::Directive(_) => {}
             MpItem::Constant(_) => {}
+            MpItem::Struct(_) => {}
         }
     }
 
-    // TODO
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    check_unused_labels(program, kernel, &mut warnings);
+    check_instructions(program, iset, &mut warnings);
+    check_layout(program, &mut warnings);
+
+    // report the warnings in the order they appear in the source
+    let files = program
+        .items()
+        .iter()
+        .map(|attributed_item| attributed_item.file_tag())
+        .collect::<Vec<_>>();
+    warnings.sort_by_key(|warning| {
+        let file_index = files
+            .iter()
+            .position(|file_tag| file_tag.as_deref().unwrap_or("") == &*warning.file_tag());
+
+        (file_index, warning.line(), warning.col())
+    });
 
     Ok(warnings)
 }
 
-pub fn check_post_data_label(program: &MpProgram, binary: &Binary) -> MipsyResult<Vec<Warning>> {
+pub fn check_post_data_label(
+    program: &MpProgram,
+    binary: &Binary,
+) -> CompileResult<Vec<CompilerWarning>> {
     let warnings = vec![];
+    let mut errors = vec![];
 
     for attributed_item in program.items() {
         let item = attributed_item.item();
@@ -61,11 +111,29 @@ pub fn check_post_data_label(program: &MpProgram, binary: &Binary) -> MipsyResul
                         MpArgument::Register(_) => {}
                         MpArgument::Number(number) => match number {
                             MpNumber::Immediate(imm) => {
-                                check_imm(binary, imm, file_tag.clone(), line, *col, *col_end)?
+                                if let Err(err) =
+                                    check_imm(binary, imm, file_tag.clone(), line, *col, *col_end)
+                                {
+                                    errors.push(err.with_expansion(attributed_item.expansion()));
+                                }
                             }
                             MpNumber::BinaryOpImmediate(i1, _, i2) => {
-                                check_imm(binary, i1, file_tag.clone(), line, *col, *col_end)?;
-                                check_imm(binary, i2, file_tag.clone(), line, *col, *col_end)?;
+                                let checked =
+                                    check_imm(binary, i1, file_tag.clone(), line, *col, *col_end)
+                                        .and_then(|_| {
+                                            check_imm(
+                                                binary,
+                                                i2,
+                                                file_tag.clone(),
+                                                line,
+                                                *col,
+                                                *col_end,
+                                            )
+                                        });
+
+                                if let Err(err) = checked {
+                                    errors.push(err.with_expansion(attributed_item.expansion()));
+                                }
                             }
                             MpNumber::Float32(_) => {}
                             MpNumber::Float64(_) => {}
@@ -82,11 +150,12 @@ pub fn check_post_data_label(program: &MpProgram, binary: &Binary) -> MipsyResul
             MpItem::Label(_) => {}
             MpItem::Directive(_) => {}
             MpItem::Constant(_) => {}
+            MpItem::Struct(_) => {}
         }
     }
 
     if binary.data.len() > (HEAP_BOT - DATA_BOT) as usize {
-        return Err(MipsyError::Compiler(CompilerError::new(
+        errors.push(MipsyError::Compiler(CompilerError::new(
             compiler::Error::TooMuchData {
                 data_size: binary.data.len() as u32,
             },
@@ -98,7 +167,9 @@ pub fn check_post_data_label(program: &MpProgram, binary: &Binary) -> MipsyResul
         )));
     }
 
-    // TODO
+    if !errors.is_empty() {
+        return Err(errors);
+    }
 
     Ok(warnings)
 }
@@ -119,8 +190,341 @@ fn check_imm(
                     .into_compiler_mipsy_result(file_tag, line, col, col_end)?;
             }
         }
+        MpImmediate::Expression(expr) | MpImmediate::Relocation(_, expr) => {
+            eval_expression(binary, expr)
+                .into_compiler_mipsy_result(file_tag, line, col, col_end)?;
+        }
         MpImmediate::I16(_) | MpImmediate::U16(_) | MpImmediate::I32(_) | MpImmediate::U32(_) => {}
     }
 
     Ok(())
 }
+
+/// Whether a warning has been silenced with an
+/// `#[allow(...)]` attribute on the item that caused it.
+fn is_allowed(attributed_item: &MpAttributedItem, warning: &Warning) -> bool {
+    attributed_item
+        .attributes()
+        .iter()
+        .filter(|attr| attr.key().eq_ignore_ascii_case("allow"))
+        .filter_map(|attr| attr.value())
+        .flat_map(|codes| codes.split(','))
+        .map(str::trim)
+        .any(|code| code == warning.code() || code == ALL_WARNINGS)
+}
+
+fn warn(
+    warnings: &mut Vec<CompilerWarning>,
+    attributed_item: &MpAttributedItem,
+    warning: Warning,
+    col: u32,
+    col_end: u32,
+) {
+    if is_allowed(attributed_item, &warning) {
+        return;
+    }
+
+    warnings.push(CompilerWarning::new(
+        warning,
+        attributed_item.file_tag().unwrap_or_else(|| Rc::from("")),
+        attributed_item.line_number(),
+        col,
+        col_end.max(col + 1),
+    ));
+}
+
+fn check_unused_labels(
+    program: &MpProgram,
+    kernel: &MpProgram,
+    warnings: &mut Vec<CompilerWarning>,
+) {
+    let mut used = HashSet::new();
+    // the entry point is used by whichever kernel ends up running the program
+    used.insert("main".to_string());
+
+    for attributed_item in program.items().iter().chain(kernel.items()) {
+        match attributed_item.item() {
+            MpItem::Instruction(instruction) => {
+                for (argument, _, _) in instruction.arguments() {
+                    match argument {
+                        MpArgument::Number(MpNumber::Immediate(imm)) => {
+                            insert_label_reference(&mut used, imm);
+                        }
+                        MpArgument::Number(MpNumber::BinaryOpImmediate(i1, _, i2)) => {
+                            insert_label_reference(&mut used, i1);
+                            insert_label_reference(&mut used, i2);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            MpItem::Directive((directive, _)) => insert_directive_references(&mut used, directive),
+            MpItem::Constant(constant) => insert_const_references(&mut used, constant.value()),
+            MpItem::Struct(mpstruct) => {
+                for field in mpstruct.fields() {
+                    insert_directive_references(&mut used, field.directive());
+                }
+            }
+            MpItem::Label(_) => {}
+        }
+    }
+
+    for attributed_item in program.items() {
+        if !attributed_item.expansion().is_empty() {
+            continue;
+        }
+
+        if let MpItem::Label(label) = attributed_item.item() {
+            let is_used = if label.is_local() {
+                used.contains(&format!("{}b", label.label()))
+                    || used.contains(&format!("{}f", label.label()))
+            } else {
+                used.contains(&label.label())
+            };
+
+            if !is_used {
+                warn(
+                    warnings,
+                    attributed_item,
+                    Warning::UnusedLabel {
+                        label: label.label(),
+                    },
+                    label.col(),
+                    label.col_end(),
+                );
+            }
+        }
+    }
+}
+
+fn insert_directive_references(used: &mut HashSet<String>, directive: &MpDirective) {
+    match directive {
+        MpDirective::Byte(values) | MpDirective::Half(values) | MpDirective::Word(values) => {
+            for (value, repeat) in values {
+                insert_const_references(used, value);
+
+                if let Some(repeat) = repeat {
+                    insert_const_references(used, repeat);
+                }
+            }
+        }
+        MpDirective::Align(value) | MpDirective::Space(value) => {
+            insert_const_references(used, value);
+        }
+        MpDirective::Globl(label) => {
+            used.insert(label.to_string());
+        }
+        _ => {}
+    }
+}
+
+fn insert_label_reference(used: &mut HashSet<String>, imm: &MpImmediate) {
+    match imm {
+        MpImmediate::LabelReference(label) => {
+            used.insert(label.to_string());
+        }
+        MpImmediate::Expression(expr) | MpImmediate::Relocation(_, expr) => {
+            insert_const_references(used, expr);
+        }
+        _ => {}
+    }
+}
+
+fn insert_const_references(used: &mut HashSet<String>, value: &MpConstValueLoc) {
+    match &value.0 {
+        MpConstValue::Value(_) => {}
+        MpConstValue::Const(label) => {
+            used.insert(label.to_string());
+        }
+        MpConstValue::Minus(x) | MpConstValue::Neg(x) => insert_const_references(used, x),
+        MpConstValue::Mult(x, y)
+        | MpConstValue::Sum(x, y)
+        | MpConstValue::Sub(x, y)
+        | MpConstValue::Div(x, y)
+        | MpConstValue::Mod(x, y)
+        | MpConstValue::And(x, y)
+        | MpConstValue::Or(x, y)
+        | MpConstValue::Xor(x, y)
+        | MpConstValue::Shl(x, y)
+        | MpConstValue::Shr(x, y) => {
+            insert_const_references(used, x);
+            insert_const_references(used, y);
+        }
+    }
+}
+
+/// Pseudo-instructions that share a name with a native instruction,
+/// but only expand to a single native instruction,
+/// e.g. `add $t0, $t1` is really just `add $t0, $t0, $t1`.
+fn check_instructions(program: &MpProgram, iset: &InstSet, warnings: &mut Vec<CompilerWarning>) {
+    for attributed_item in program.items() {
+        let instruction = match attributed_item.item() {
+            MpItem::Instruction(instruction) if attributed_item.expansion().is_empty() => {
+                instruction
+            }
+            _ => continue,
+        };
+
+        // any errors here get reported properly once we compile the text segment
+        let pseudo = match find_instruction(iset, None, instruction) {
+            Ok(SignatureRef::Pseudo(pseudo)) => pseudo,
+            _ => continue,
+        };
+
+        let has_native = iset
+            .native_set()
+            .iter()
+            .any(|native| native.name().eq_ignore_ascii_case(pseudo.name()));
+
+        if let (true, [expand]) = (has_native, pseudo.expansion()) {
+            warn(
+                warnings,
+                attributed_item,
+                Warning::TrivialPseudoInstruction {
+                    inst_name: instruction.name().to_string(),
+                    native: expand.inst().to_ascii_lowercase(),
+                },
+                instruction.col(),
+                instruction.col_end(),
+            );
+        }
+    }
+}
+
+/// Instructions should be indented, labels shouldn't be,
+/// and a label in the text segment should start a new "paragraph".
+fn check_layout(program: &MpProgram, warnings: &mut Vec<CompilerWarning>) {
+    let mut in_text = true;
+    let mut previous: Option<&MpAttributedItem> = None;
+
+    for attributed_item in program.items() {
+        // items inserted between files when merging them don't have a line,
+        // and macro bodies are laid out wherever they were defined
+        if attributed_item.line_number() == 0 || !attributed_item.expansion().is_empty() {
+            previous = None;
+            continue;
+        }
+
+        match attributed_item.item() {
+            MpItem::Instruction(instruction) => {
+                if instruction.col() == 1 {
+                    warn(
+                        warnings,
+                        attributed_item,
+                        Warning::InstructionNotIndented,
+                        instruction.col(),
+                        instruction.col_end(),
+                    );
+                }
+            }
+            MpItem::Label(label) => {
+                if label.col() != 1 {
+                    warn(
+                        warnings,
+                        attributed_item,
+                        Warning::LabelIndented {
+                            label: label.label(),
+                        },
+                        label.col(),
+                        label.col_end(),
+                    );
+                }
+
+                let follows_instruction = previous.is_some_and(|previous| {
+                    matches!(previous.item(), MpItem::Instruction(_))
+                        && previous.file_tag() == attributed_item.file_tag()
+                        && previous.line_number() + 1 >= attributed_item.line_number()
+                });
+
+                // local labels usually mark a spot within a block, like the top of a loop
+                if in_text && follows_instruction && !label.is_local() {
+                    warn(
+                        warnings,
+                        attributed_item,
+                        Warning::NoBlankLineBeforeLabel {
+                            label: label.label(),
+                        },
+                        label.col(),
+                        label.col_end(),
+                    );
+                }
+            }
+            MpItem::Directive((directive, _)) => match directive {
+                MpDirective::Text | MpDirective::KText => in_text = true,
+                MpDirective::Data | MpDirective::KData => in_text = false,
+                _ => {}
+            },
+            MpItem::Constant(_) => {}
+            MpItem::Struct(_) => {}
+        }
+
+        previous = Some(attributed_item);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mipsy_parser::TaggedFile;
+
+    fn lint(source: &str) -> Vec<&'static str> {
+        let program = mipsy_parser::parse_mips(vec![TaggedFile::new(None, source)], 8).unwrap();
+        let kernel = MpProgram::new(vec![], vec![]);
+
+        let mut warnings = vec![];
+        check_unused_labels(&program, &kernel, &mut warnings);
+        check_layout(&program, &mut warnings);
+
+        warnings
+            .iter()
+            .map(|warning| warning.warning().code())
+            .collect()
+    }
+
+    #[test]
+    fn test_unused_labels() {
+        assert_eq!(
+            lint("main:\n\tj\tfoo\n\nfoo:\n\tjr\t$ra\n"),
+            Vec::<&str>::new()
+        );
+        assert_eq!(
+            lint("main:\n\tjr\t$ra\n\nfoo:\n\tjr\t$ra\n"),
+            vec!["unused"]
+        );
+        assert_eq!(
+            lint(".data\nx:\t.word 1\nptr:\t.word x\n\n.text\nmain:\n\tla\t$t0, ptr\n"),
+            Vec::<&str>::new()
+        );
+        assert_eq!(lint("main:\n\tnop\n1:\n\tb\t1b\n"), Vec::<&str>::new());
+        assert_eq!(lint("main:\n\tjr\t$ra\n\n1:\n\tb\t2f\n"), vec!["unused"]);
+    }
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(lint("main:\nli $v0, 10\n"), vec!["indentation"]);
+        assert_eq!(
+            lint("main:\n\tnop\nfoo:\n\tj\tfoo\n"),
+            vec!["label_spacing"]
+        );
+        assert_eq!(
+            lint(".data\nx:\t.word 1\ny:\t.word x, y\n"),
+            Vec::<&str>::new()
+        );
+    }
+
+    #[test]
+    fn test_allow() {
+        assert_eq!(
+            lint("main:\n\tjr\t$ra\n#[allow(unused)]\nfoo:\n\tnop\n"),
+            Vec::<&str>::new()
+        );
+        assert_eq!(
+            lint("main:\n\tjr\t$ra\n\n#[allow(indentation)]\n  foo:\n"),
+            vec!["unused"]
+        );
+        assert_eq!(
+            lint("main:\n\tjr\t$ra\n#[allow(warnings)]\n  foo:\n"),
+            Vec::<&str>::new()
+        );
+    }
+}
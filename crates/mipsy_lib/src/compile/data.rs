@@ -1,21 +1,29 @@
 use std::rc::Rc;
 
-use super::{bytes::ToBytes, text::instruction_length, Binary, DATA_BOT, TEXT_BOT};
+use super::{
+    bytes::ToBytes,
+    link::link_labels,
+    local_labels::resolve_local_labels,
+    structs::{field_constant_name, layout_struct},
+    text::instruction_length,
+    Binary, DATA_BOT, TEXT_BOT,
+};
 use crate::{
     error::{
         compiler::{DirectiveType, Error},
-        ToMipsyResult,
+        CompileResult, InternalError, MipsyInternalResult, ToMipsyResult,
     },
     inst::instruction::InstSet,
     util::Safe,
     CompilerError, MipsyError, MipsyResult, MpProgram, KDATA_BOT, KTEXT_BOT,
 };
 use mipsy_parser::{
-    MpArgument, MpConstValue, MpConstValueLoc, MpDirective, MpImmediate, MpItem, MpNumber,
+    parser::MpAttributedItem, MpArgument, MpConstValue, MpConstValueLoc, MpDirective, MpImmediate,
+    MpItem, MpMacroInvocation, MpNumber, MpRelocation,
 };
 use mipsy_utils::MipsyConfig;
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 pub(crate) enum Segment {
     Text,
     Data,
@@ -23,6 +31,17 @@ pub(crate) enum Segment {
     KData,
 }
 
+/// A value in the data segment that refers to a label that hadn't been seen
+/// yet on the first pass, to be filled in once every label is known.
+pub(super) struct Fixup {
+    segment: Segment,
+    offset: usize,
+    size: usize,
+    value: MpConstValueLoc,
+    file_tag: Rc<str>,
+    expansion: Vec<MpMacroInvocation>,
+}
+
 fn align<T: Clone>(binary: &mut Binary, segment: &Segment, align_to: usize) -> Vec<Safe<T>> {
     let (bot, curr_size) = match segment {
         Segment::Data => (DATA_BOT, binary.data.len()),
@@ -57,7 +76,7 @@ pub(super) fn eval_directive(
     config: &MipsyConfig,
     file_tag: Rc<str>,
     segment: &mut Segment,
-    first_pass: bool,
+    fixups: Option<&mut Vec<Fixup>>,
 ) -> MipsyResult<Vec<Safe<u8>>> {
     let bytes = match directive {
         MpDirective::Text => {
@@ -95,101 +114,22 @@ pub(super) fn eval_directive(
 
             chars
         }
-        MpDirective::Byte(bytes) => bytes
-            .iter()
-            .map(|(byte, n)| {
-                Ok((
-                    eval_constant_in_range(
-                        byte,
-                        i8::MIN as _,
-                        u8::MAX as _,
-                        binary,
-                        file_tag.clone(),
-                    )? as u8,
-                    if let Some(n) = n {
-                        eval_constant_in_range(
-                            n,
-                            u32::MIN as _,
-                            u32::MAX as _,
-                            binary,
-                            file_tag.clone(),
-                        )? as u32
-                    } else {
-                        1
-                    },
-                ))
-            })
-            .collect::<Result<Vec<_>, _>>()?
-            .into_iter()
-            .flat_map(|(byte, n)| (0..n).map(move |_| Safe::Valid(byte)))
-            .collect::<Vec<_>>(),
+        MpDirective::Byte(bytes) => {
+            let offset = segment_len(binary, segment);
+
+            eval_integers(bytes, 1, binary, file_tag, segment, offset, fixups)?
+        }
         MpDirective::Half(halfs) => {
             let alignment = align(binary, segment, 2);
-
-            let halfs = halfs
-                .iter()
-                .map(|(half, n)| {
-                    Ok((
-                        eval_constant_in_range(
-                            half,
-                            i16::MIN as _,
-                            u16::MAX as _,
-                            binary,
-                            file_tag.clone(),
-                        )? as u16,
-                        if let Some(n) = n {
-                            eval_constant_in_range(
-                                n,
-                                u32::MIN as _,
-                                u32::MAX as _,
-                                binary,
-                                file_tag.clone(),
-                            )? as u32
-                        } else {
-                            1
-                        },
-                    ))
-                })
-                .collect::<Result<Vec<_>, _>>()?
-                .into_iter()
-                .flat_map(|(half, n)| (0..n).map(move |_| half))
-                .flat_map(|half| half.to_bytes())
-                .map(Safe::Valid);
+            let offset = segment_len(binary, segment) + alignment.len();
+            let halfs = eval_integers(halfs, 2, binary, file_tag, segment, offset, fixups)?;
 
             alignment.into_iter().chain(halfs).collect()
         }
         MpDirective::Word(words) => {
             let alignment = align(binary, segment, 4);
-
-            let words = words
-                .iter()
-                .map(|(word, n)| {
-                    Ok((
-                        eval_constant_in_range(
-                            word,
-                            i32::MIN as _,
-                            u32::MAX as _,
-                            binary,
-                            file_tag.clone(),
-                        )? as u32,
-                        if let Some(n) = n {
-                            eval_constant_in_range(
-                                n,
-                                u32::MIN as _,
-                                u32::MAX as _,
-                                binary,
-                                file_tag.clone(),
-                            )? as u32
-                        } else {
-                            1
-                        },
-                    ))
-                })
-                .collect::<Result<Vec<_>, _>>()?
-                .into_iter()
-                .flat_map(|(word, n)| (0..n).map(move |_| word))
-                .flat_map(|word| word.to_bytes())
-                .map(Safe::Valid);
+            let offset = segment_len(binary, segment) + alignment.len();
+            let words = eval_integers(words, 4, binary, file_tag, segment, offset, fixups)?;
 
             alignment.into_iter().chain(words).collect()
         }
@@ -271,12 +211,14 @@ pub(super) fn eval_directive(
             vec![space_byte; num as usize]
         }
         MpDirective::Globl(label) => {
-            if first_pass {
+            if fixups.is_some() {
                 binary.globals.push(label.to_string());
             }
 
             vec![]
         }
+        // there's nothing for an assembler option to change
+        MpDirective::Set(_) => vec![],
     };
 
     Ok(bytes)
@@ -287,230 +229,413 @@ pub fn populate_labels_and_data(
     config: &MipsyConfig,
     iset: &InstSet,
     program: &mut MpProgram,
-) -> MipsyResult<()> {
+) -> CompileResult<Vec<Fixup>> {
     let mut text_len = 0;
     let mut ktext_len = 0;
     let mut segment = Segment::Text;
+    let mut fixups = vec![];
+
+    // the labels are still populated after a linking error, so that
+    // any errors in the rest of the program can be found too
+    let mut errors = link_labels(program).err().unwrap_or_default();
+    resolve_local_labels(binary, program);
 
     for attributed_item in program.items_mut() {
-        let line = attributed_item.line_number();
-        let file_tag = attributed_item.file_tag().unwrap_or_else(|| Rc::from(""));
-        let item = attributed_item.item_mut();
-
-        match item {
-            MpItem::Directive(directive) => {
-                // Only allow .text and .data in a Text segment
-                // if segment == Segment::Text || segment == Segment::KText {
-                //     match &*directive {
-                //         (MpDirective::Text | MpDirective::Data | MpDirective::KText | MpDirective::KData, _) => {}
-                //         (other, position) => {
-                //             return Err(
-                //                 MipsyError::Compiler(
-                //                     CompilerError::new(
-                //                         Error::DataInTextSegment { directive_type: other.clone() },
-                //                         file_tag,
-                //                         position.line(),
-                //                         position.col(),
-                //                         position.col_end(),
-                //                     )
-                //                 )
-                //             );
-                //         }
-                //     }
-                // }
-
-                let bytes =
-                    eval_directive(&directive.0, binary, config, file_tag, &mut segment, true)?;
-                insert_safe_data(&segment, binary, &bytes);
-
-                match segment {
-                    Segment::Text => {
-                        text_len += bytes.len();
-                    }
-                    Segment::KText => {
-                        ktext_len += bytes.len();
-                    }
-                    _ => {}
+        let expansion = attributed_item.expansion().to_vec();
+
+        let new_fixups = populate_item(
+            binary,
+            config,
+            iset,
+            attributed_item,
+            &mut segment,
+            &mut text_len,
+            &mut ktext_len,
+        );
+
+        match new_fixups {
+            Ok(new_fixups) => fixups.extend(new_fixups.into_iter().map(|fixup| Fixup {
+                expansion: expansion.clone(),
+                ..fixup
+            })),
+            Err(err) => errors.push(err.with_expansion(&expansion)),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(fixups)
+}
+
+fn populate_item(
+    binary: &mut Binary,
+    config: &MipsyConfig,
+    iset: &InstSet,
+    attributed_item: &mut MpAttributedItem,
+    segment: &mut Segment,
+    text_len: &mut usize,
+    ktext_len: &mut usize,
+) -> MipsyResult<Vec<Fixup>> {
+    let line = attributed_item.line_number();
+    let mut fixups = vec![];
+    let file_tag = attributed_item.file_tag().unwrap_or_else(|| Rc::from(""));
+    let item = attributed_item.item_mut();
+
+    match item {
+        MpItem::Directive(directive) => {
+            // Only allow .text and .data in a Text segment
+            // if segment == Segment::Text || segment == Segment::KText {
+            //     match &*directive {
+            //         (MpDirective::Text | MpDirective::Data | MpDirective::KText | MpDirective::KData, _) => {}
+            //         (other, position) => {
+            //             return Err(
+            //                 MipsyError::Compiler(
+            //                     CompilerError::new(
+            //                         Error::DataInTextSegment { directive_type: other.clone() },
+            //                         file_tag,
+            //                         position.line(),
+            //                         position.col(),
+            //                         position.col_end(),
+            //                     )
+            //                 )
+            //             );
+            //         }
+            //     }
+            // }
+
+            let bytes = eval_directive(
+                &directive.0,
+                binary,
+                config,
+                file_tag,
+                segment,
+                Some(&mut fixups),
+            )?;
+            insert_safe_data(segment, binary, &bytes);
+
+            match *segment {
+                Segment::Text => {
+                    *text_len += bytes.len();
+                }
+                Segment::KText => {
+                    *ktext_len += bytes.len();
                 }
+                _ => {}
             }
-            MpItem::Instruction(instruction) => {
-                for arg in instruction.arguments_mut() {
-                    if let MpArgument::Number(MpNumber::Immediate(MpImmediate::LabelReference(
+        }
+        MpItem::Instruction(instruction) => {
+            for arg in instruction.arguments_mut() {
+                let value = match arg.0 {
+                    MpArgument::Number(MpNumber::Immediate(MpImmediate::LabelReference(
                         ref label,
-                    ))) = arg.0
-                    {
-                        if let Some(&value) = binary.constants.get(label) {
-                            if u16::MIN as i64 <= value && u16::MAX as i64 >= value {
-                                arg.0 = MpArgument::Number(MpNumber::Immediate(MpImmediate::U16(
-                                    value as _,
-                                )));
-                            } else if i16::MIN as i64 <= value && i16::MAX as i64 >= value {
-                                arg.0 = MpArgument::Number(MpNumber::Immediate(MpImmediate::I16(
-                                    value as _,
-                                )));
-                            } else if u32::MIN as i64 <= value && u32::MAX as i64 >= value {
-                                arg.0 = MpArgument::Number(MpNumber::Immediate(MpImmediate::U32(
-                                    value as _,
-                                )));
-                            } else if i32::MIN as i64 <= value && i32::MAX as i64 >= value {
-                                arg.0 = MpArgument::Number(MpNumber::Immediate(MpImmediate::I32(
-                                    value as _,
-                                )));
-                            } else {
-                                todo!();
+                    ))) => match binary.constants.get(label) {
+                        Some(&value) => value,
+                        None => continue,
+                    },
+                    // an expression that only uses constants can be used like any other number,
+                    // but one with labels in it has to wait until the labels are in their final place
+                    MpArgument::Number(MpNumber::Immediate(MpImmediate::Expression(ref expr))) => {
+                        match eval_with(expr, &|name| binary.constants.get(name).copied()) {
+                            Ok(value) if i32::MIN as i64 <= value && u32::MAX as i64 >= value => {
+                                value
                             }
+                            _ => continue,
                         }
                     }
+                    _ => continue,
+                };
+
+                if u16::MIN as i64 <= value && u16::MAX as i64 >= value {
+                    arg.0 = MpArgument::Number(MpNumber::Immediate(MpImmediate::U16(value as _)));
+                } else if i16::MIN as i64 <= value && i16::MAX as i64 >= value {
+                    arg.0 = MpArgument::Number(MpNumber::Immediate(MpImmediate::I16(value as _)));
+                } else if u32::MIN as i64 <= value && u32::MAX as i64 >= value {
+                    arg.0 = MpArgument::Number(MpNumber::Immediate(MpImmediate::U32(value as _)));
+                } else if i32::MIN as i64 <= value && i32::MAX as i64 >= value {
+                    arg.0 = MpArgument::Number(MpNumber::Immediate(MpImmediate::I32(value as _)));
+                } else {
+                    todo!();
                 }
+            }
 
-                // We can't compile instructions yet - so just keep track of
-                // how many bytes-worth we've seen so far
-                let inst_length =
-                    instruction_length(iset, instruction).into_compiler_mipsy_result(
-                        file_tag.clone(),
-                        line,
+            // We can't compile instructions yet - so just keep track of
+            // how many bytes-worth we've seen so far
+            let inst_length = instruction_length(iset, instruction).into_compiler_mipsy_result(
+                file_tag.clone(),
+                line,
+                instruction.col(),
+                instruction.col_end(),
+            )? * 4;
+
+            let (bot, length) = match *segment {
+                Segment::Text => (TEXT_BOT, &mut *text_len),
+                Segment::KText => (KTEXT_BOT, &mut *ktext_len),
+                _ => {
+                    return Err(MipsyError::Compiler(CompilerError::new(
+                        Error::InstructionInDataSegment,
+                        file_tag,
+                        instruction.line(),
                         instruction.col(),
                         instruction.col_end(),
-                    )? * 4;
-
-                let (bot, length) = match segment {
-                    Segment::Text => (TEXT_BOT, &mut text_len),
-                    Segment::KText => (KTEXT_BOT, &mut ktext_len),
-                    _ => {
-                        return Err(MipsyError::Compiler(CompilerError::new(
-                            Error::InstructionInDataSegment,
-                            file_tag,
-                            instruction.line(),
-                            instruction.col(),
-                            instruction.col_end(),
-                        )));
-                    }
-                };
+                    )));
+                }
+            };
 
-                let alignment = (4 - *length % 4) % 4;
+            let alignment = (4 - *length % 4) % 4;
 
-                if alignment != 0 {
-                    let mut labels = vec![];
+            if alignment != 0 {
+                let mut labels = vec![];
 
-                    for (label, &addr) in binary.labels.iter() {
-                        if addr as usize == (bot as usize + *length) {
-                            labels.push(label.to_string());
-                        }
+                for (label, &addr) in binary.labels.iter() {
+                    if addr as usize == (bot as usize + *length) {
+                        labels.push(label.to_string());
                     }
+                }
 
-                    for label in labels {
-                        binary
-                            .labels
-                            .insert(label, bot + (*length + alignment) as u32);
-                    }
+                for label in labels {
+                    binary
+                        .labels
+                        .insert(label, bot + (*length + alignment) as u32);
                 }
+            }
 
-                *length += alignment + inst_length;
+            *length += alignment + inst_length;
+        }
+        MpItem::Label(mplabel) => {
+            let label = mplabel.label();
+            let col = mplabel.col();
+            let col_end = mplabel.col_end();
+
+            if binary.labels.contains_key(&*label) {
+                return Err(MipsyError::Compiler(CompilerError::new(
+                    Error::RedefinedLabel { label },
+                    file_tag,
+                    line,
+                    col,
+                    col_end,
+                )));
             }
-            MpItem::Label(mplabel) => {
-                let label = mplabel.label();
-                let col = mplabel.col();
-                let col_end = mplabel.col_end();
 
-                if binary.labels.contains_key(&*label) {
-                    return Err(MipsyError::Compiler(CompilerError::new(
-                        Error::RedefinedLabel { label },
-                        file_tag,
-                        line,
-                        col,
-                        col_end,
-                    )));
-                }
+            binary.labels.insert(
+                label.to_string(),
+                match *segment {
+                    Segment::Text => TEXT_BOT + *text_len as u32,
+                    Segment::Data => DATA_BOT + binary.data.len() as u32,
+                    Segment::KText => KTEXT_BOT + *ktext_len as u32,
+                    Segment::KData => KDATA_BOT + binary.kdata.len() as u32,
+                },
+            );
+        }
+        MpItem::Constant(constant) => {
+            let label = constant.label();
 
-                binary.labels.insert(
-                    label.to_string(),
-                    match segment {
-                        Segment::Text => TEXT_BOT + text_len as u32,
-                        Segment::Data => DATA_BOT + binary.data.len() as u32,
-                        Segment::KText => KTEXT_BOT + ktext_len as u32,
-                        Segment::KData => KDATA_BOT + binary.kdata.len() as u32,
+            if binary.constants.contains_key(label) {
+                return Err(MipsyError::Compiler(CompilerError::new(
+                    Error::RedefinedConstant {
+                        label: label.to_string(),
                     },
-                );
+                    file_tag,
+                    line,
+                    constant.col(),
+                    constant.col_end(),
+                )));
             }
-            MpItem::Constant(constant) => {
-                let label = constant.label();
 
-                if binary.constants.contains_key(label) {
+            let value = eval_constant(binary, constant.value(), file_tag)?;
+            binary.constants.insert(label.to_string(), value);
+        }
+        MpItem::Struct(mpstruct) => {
+            let layout = layout_struct(binary, mpstruct, file_tag.clone())?;
+
+            let constants = layout
+                .fields
+                .iter()
+                .map(|field| (field_constant_name(&layout.name, &field.name), field.offset))
+                .chain([(field_constant_name(&layout.name, "size"), layout.size)]);
+
+            for (label, value) in constants {
+                if binary.constants.contains_key(&label) {
                     return Err(MipsyError::Compiler(CompilerError::new(
-                        Error::RedefinedConstant {
-                            label: label.to_string(),
-                        },
+                        Error::RedefinedConstant { label },
                         file_tag,
                         line,
-                        constant.col(),
-                        constant.col_end(),
+                        mpstruct.col(),
+                        mpstruct.col_end(),
                     )));
                 }
 
-                let value = eval_constant(binary, constant.value(), file_tag)?;
-                binary.constants.insert(label.to_string(), value);
+                binary.constants.insert(label, value as i64);
             }
+
+            binary.structs.insert(layout.name.clone(), layout);
         }
     }
 
-    Ok(())
+    Ok(fixups)
 }
 
 fn eval_constant(binary: &Binary, constant: &MpConstValueLoc, file: Rc<str>) -> MipsyResult<i64> {
-    Ok(match &constant.0 {
-        &MpConstValue::Value(value) => value as _,
-        MpConstValue::Const(label) => binary
-            .constants
-            .get(label)
-            .copied()
-            .or_else(|| binary.get_label(label).map(|x| x as i64).ok())
-            .ok_or_else(|| {
-                MipsyError::Compiler(CompilerError::new(
-                    Error::UnresolvedConstant {
-                        label: label.to_string(),
-                    },
-                    file.clone(),
-                    constant.1.line(),
-                    constant.1.col(),
-                    constant.1.col_end(),
-                ))
-            })?,
-        MpConstValue::Minus(value) => -eval_constant(binary, value, file)?,
-        MpConstValue::Sum(v1, v2) => {
-            eval_constant(binary, v1, file.clone())? + eval_constant(binary, v2, file)?
-        }
-        MpConstValue::Sub(v1, v2) => {
-            eval_constant(binary, v1, file.clone())? - eval_constant(binary, v2, file)?
-        }
-        MpConstValue::Div(v1, v2) => {
-            eval_constant(binary, v1, file.clone())? / eval_constant(binary, v2, file)?
-        }
-        MpConstValue::Mult(v1, v2) => {
-            eval_constant(binary, v1, file.clone())? * eval_constant(binary, v2, file)?
-        }
-        MpConstValue::Mod(v1, v2) => {
-            eval_constant(binary, v1, file.clone())? % eval_constant(binary, v2, file)?
-        }
-        MpConstValue::And(v1, v2) => {
-            eval_constant(binary, v1, file.clone())? & eval_constant(binary, v2, file)?
-        }
-        MpConstValue::Or(v1, v2) => {
-            eval_constant(binary, v1, file.clone())? | eval_constant(binary, v2, file)?
-        }
-        MpConstValue::Xor(v1, v2) => {
-            eval_constant(binary, v1, file.clone())? ^ eval_constant(binary, v2, file)?
+    eval_const_value(binary, constant).map_err(|err| eval_error(err, file))
+}
+
+/// Why an assembly-time expression couldn't be evaluated.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum EvalError<'a> {
+    /// A name that can't be resolved (yet).
+    Unresolved(&'a MpConstValueLoc),
+    /// A `/` or `%` with zero on the right.
+    DivisionByZero(&'a MpConstValueLoc),
+    /// A result that doesn't fit in 64 bits, or a shift by more than 63.
+    Overflow(&'a MpConstValueLoc),
+}
+
+impl EvalError<'_> {
+    pub(crate) fn into_error(self) -> Error {
+        match self {
+            Self::Unresolved(unresolved) => Error::UnresolvedConstant {
+                label: unresolved.0.to_string(),
+            },
+            Self::DivisionByZero(expr) => Error::DivisionByZero {
+                expression: expr.0.to_string(),
+            },
+            Self::Overflow(expr) => Error::ExpressionOverflow {
+                expression: expr.0.to_string(),
+            },
         }
-        MpConstValue::Neg(value) => !eval_constant(binary, value, file)?,
-        MpConstValue::Shl(v1, v2) => {
-            eval_constant(binary, v1, file.clone())? << eval_constant(binary, v2, file)?
+    }
+
+    fn expr(&self) -> &MpConstValueLoc {
+        match self {
+            Self::Unresolved(expr) | Self::DivisionByZero(expr) | Self::Overflow(expr) => expr,
         }
-        MpConstValue::Shr(v1, v2) => {
-            eval_constant(binary, v1, file.clone())? >> eval_constant(binary, v2, file)?
+    }
+}
+
+/// Evaluates an assembly-time expression, where a name can be either a constant or a label.
+///
+/// If a name can't be resolved (yet), the reference to it is given back instead.
+pub(crate) fn eval_const_value<'a>(
+    binary: &Binary,
+    constant: &'a MpConstValueLoc,
+) -> Result<i64, EvalError<'a>> {
+    eval_with(constant, &|name| {
+        binary
+            .constants
+            .get(name)
+            .copied()
+            .or_else(|| binary.get_label(name).map(|x| x as i64).ok())
+    })
+}
+
+/// Evaluates an expression in an instruction's argument, like `end - start` or `table + 8`.
+pub(crate) fn eval_expression(binary: &Binary, expr: &MpConstValueLoc) -> MipsyInternalResult<i64> {
+    match eval_const_value(binary, expr) {
+        Ok(value) => Ok(value),
+        // the usual unresolved label error, with any similar labels
+        Err(EvalError::Unresolved(unresolved)) => binary
+            .get_label(&unresolved.0.to_string())
+            .map(|addr| addr as i64),
+        Err(err) => Err(InternalError::Compiler(err.into_error())),
+    }
+}
+
+/// Evaluates an expression that has to fit in a word, either signed or unsigned.
+pub(crate) fn eval_word_expression(
+    binary: &Binary,
+    expr: &MpConstValueLoc,
+) -> MipsyInternalResult<u32> {
+    let value = eval_expression(binary, expr)?;
+
+    if value < i32::MIN as i64 || value > u32::MAX as i64 {
+        return Err(InternalError::Compiler(Error::ExpressionDoesNotFit {
+            expression: expr.0.to_string(),
+            value,
+        }));
+    }
+
+    Ok(value as u32)
+}
+
+/// Evaluates `%hi(expr)` or `%lo(expr)`.
+pub(crate) fn eval_relocation(
+    binary: &Binary,
+    reloc: MpRelocation,
+    expr: &MpConstValueLoc,
+) -> MipsyInternalResult<u16> {
+    let value = eval_word_expression(binary, expr)?;
+
+    Ok(match reloc {
+        // %lo is sign-extended when it's added back on, so round up to make up for it
+        MpRelocation::Hi => (value.wrapping_add(0x8000) >> 16) as u16,
+        MpRelocation::Lo => value as u16,
+    })
+}
+
+fn eval_with<'a>(
+    constant: &'a MpConstValueLoc,
+    lookup: &impl Fn(&str) -> Option<i64>,
+) -> Result<i64, EvalError<'a>> {
+    let arithmetic = |v1, v2, op: fn(i64, i64) -> Option<i64>| {
+        op(eval_with(v1, lookup)?, eval_with(v2, lookup)?).ok_or(EvalError::Overflow(constant))
+    };
+
+    // shifts by a negative amount or by 64 or more don't mean anything
+    let shift = |v1, v2, op: fn(i64, u32) -> Option<i64>| {
+        let value = eval_with(v1, lookup)?;
+        let amount = eval_with(v2, lookup)?;
+
+        u32::try_from(amount)
+            .ok()
+            .and_then(|amount| op(value, amount))
+            .ok_or(EvalError::Overflow(constant))
+    };
+
+    let divide = |v1, v2, op: fn(i64, i64) -> Option<i64>| {
+        let value = eval_with(v1, lookup)?;
+        let divisor = eval_with(v2, lookup)?;
+
+        if divisor == 0 {
+            return Err(EvalError::DivisionByZero(constant));
         }
+
+        op(value, divisor).ok_or(EvalError::Overflow(constant))
+    };
+
+    Ok(match &constant.0 {
+        &MpConstValue::Value(value) => value as _,
+        MpConstValue::Const(label) => lookup(label).ok_or(EvalError::Unresolved(constant))?,
+        MpConstValue::Minus(value) => eval_with(value, lookup)?
+            .checked_neg()
+            .ok_or(EvalError::Overflow(constant))?,
+        MpConstValue::Sum(v1, v2) => arithmetic(v1, v2, i64::checked_add)?,
+        MpConstValue::Sub(v1, v2) => arithmetic(v1, v2, i64::checked_sub)?,
+        MpConstValue::Div(v1, v2) => divide(v1, v2, i64::checked_div)?,
+        MpConstValue::Mult(v1, v2) => arithmetic(v1, v2, i64::checked_mul)?,
+        MpConstValue::Mod(v1, v2) => divide(v1, v2, i64::checked_rem)?,
+        MpConstValue::And(v1, v2) => eval_with(v1, lookup)? & eval_with(v2, lookup)?,
+        MpConstValue::Or(v1, v2) => eval_with(v1, lookup)? | eval_with(v2, lookup)?,
+        MpConstValue::Xor(v1, v2) => eval_with(v1, lookup)? ^ eval_with(v2, lookup)?,
+        MpConstValue::Neg(value) => !eval_with(value, lookup)?,
+        MpConstValue::Shl(v1, v2) => shift(v1, v2, i64::checked_shl)?,
+        MpConstValue::Shr(v1, v2) => shift(v1, v2, i64::checked_shr)?,
     })
 }
 
-fn eval_constant_in_range(
+fn eval_error(err: EvalError<'_>, file: Rc<str>) -> MipsyError {
+    let expr = err.expr();
+
+    MipsyError::Compiler(CompilerError::new(
+        err.into_error(),
+        file,
+        expr.1.line(),
+        expr.1.col(),
+        expr.1.col_end(),
+    ))
+}
+
+pub(super) fn eval_constant_in_range(
     constant: &MpConstValueLoc,
     range_low: i64,
     range_high: i64,
@@ -519,6 +644,16 @@ fn eval_constant_in_range(
 ) -> MipsyResult<i64> {
     let value = eval_constant(binary, constant, file.clone())?;
 
+    check_in_range(value, constant, range_low, range_high, file)
+}
+
+fn check_in_range(
+    value: i64,
+    constant: &MpConstValueLoc,
+    range_low: i64,
+    range_high: i64,
+    file: Rc<str>,
+) -> MipsyResult<i64> {
     if value < range_low || value > range_high {
         return Err(MipsyError::Compiler(CompilerError::new(
             Error::ConstantValueDoesNotFit {
@@ -537,6 +672,117 @@ fn eval_constant_in_range(
     Ok(value)
 }
 
+/// The values that a `.byte`, `.half` or `.word` can hold, given its size in bytes.
+fn integer_range(size: usize) -> (i64, i64) {
+    match size {
+        1 => (i8::MIN as _, u8::MAX as _),
+        2 => (i16::MIN as _, u16::MAX as _),
+        _ => (i32::MIN as _, u32::MAX as _),
+    }
+}
+
+/// Evaluates the values of a `.byte`, `.half` or `.word`, each `size` bytes long.
+///
+/// On the first pass, a value in a data segment may refer to a label further on,
+/// in which case it's left as zero and a [`Fixup`] fills it in later.
+fn eval_integers(
+    values: &[(MpConstValueLoc, Option<MpConstValueLoc>)],
+    size: usize,
+    binary: &Binary,
+    file_tag: Rc<str>,
+    segment: &Segment,
+    offset: usize,
+    mut fixups: Option<&mut Vec<Fixup>>,
+) -> MipsyResult<Vec<Safe<u8>>> {
+    let (range_low, range_high) = integer_range(size);
+    let mut bytes = vec![];
+
+    for (value, n) in values {
+        let n = match n {
+            Some(n) => {
+                eval_constant_in_range(n, u32::MIN as _, u32::MAX as _, binary, file_tag.clone())?
+                    as usize
+            }
+            None => 1,
+        };
+
+        let value = match (eval_const_value(binary, value), fixups.as_deref_mut()) {
+            (Ok(result), _) => {
+                check_in_range(result, value, range_low, range_high, file_tag.clone())?
+            }
+            (Err(EvalError::Unresolved(_)), Some(fixups)) => {
+                // text segments are evaluated again once every label is known
+                if let Segment::Data | Segment::KData = segment {
+                    fixups.extend((0..n).map(|i| Fixup {
+                        segment: *segment,
+                        offset: offset + bytes.len() + i * size,
+                        size,
+                        value: value.clone(),
+                        file_tag: file_tag.clone(),
+                        expansion: vec![],
+                    }));
+                }
+
+                0
+            }
+            (Err(err), _) => return Err(eval_error(err, file_tag)),
+        };
+
+        let value = &(value as u32).to_le_bytes()[..size];
+        for _ in 0..n {
+            bytes.extend(value.iter().copied().map(Safe::Valid));
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Fills in the values in the data segments that referred to labels further on.
+pub(super) fn apply_fixups(binary: &mut Binary, fixups: Vec<Fixup>) -> CompileResult<()> {
+    let mut errors = vec![];
+
+    for fixup in fixups {
+        let (range_low, range_high) = integer_range(fixup.size);
+        let value =
+            eval_constant_in_range(&fixup.value, range_low, range_high, binary, fixup.file_tag);
+
+        let value = match value {
+            Ok(value) => value,
+            Err(err) => {
+                errors.push(err.with_expansion(&fixup.expansion));
+                continue;
+            }
+        };
+
+        let segment = match fixup.segment {
+            Segment::KData => &mut binary.kdata,
+            _ => &mut binary.data,
+        };
+
+        for (i, &byte) in (value as u32).to_le_bytes()[..fixup.size]
+            .iter()
+            .enumerate()
+        {
+            segment[fixup.offset + i] = Safe::Valid(byte);
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(())
+}
+
+fn segment_len(binary: &Binary, segment: &Segment) -> usize {
+    match segment {
+        Segment::Data => binary.data.len(),
+        Segment::KData => binary.kdata.len(),
+        Segment::Text => binary.text.len(),
+        Segment::KText => binary.ktext.len(),
+    }
+}
+
 fn insert_safe_data(segment: &Segment, binary: &mut Binary, values: &[Safe<u8>]) {
     match segment {
         Segment::Data => &mut binary.data,
@@ -546,3 +792,142 @@ fn insert_safe_data(segment: &Segment, binary: &mut Binary, values: &[Safe<u8>])
     }
     .append(&mut values.to_vec());
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{compile::compile_with_kernel, CompilerOptions};
+    use mipsy_parser::TaggedFile;
+
+    fn compile_data(source: &str) -> CompileResult<Vec<Safe<u8>>> {
+        let mut program = mipsy_parser::parse_mips(vec![TaggedFile::new(None, source)], 8).unwrap();
+        let mut kernel = MpProgram::new(vec![], vec![]);
+
+        let (binary, _) = compile_with_kernel(
+            &mut program,
+            &mut kernel,
+            &CompilerOptions::new(vec![]),
+            &MipsyConfig::default(),
+            &InstSet::new(vec![], vec![]),
+        )?;
+
+        Ok(binary.data)
+    }
+
+    fn words(data: &[Safe<u8>]) -> Vec<u32> {
+        data.chunks(4)
+            .map(|word| {
+                let bytes = word.iter().map(|byte| byte.into_option().unwrap_or(0));
+                bytes.rev().fold(0, |word, byte| (word << 8) | byte as u32)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_label_arithmetic() {
+        let data = compile_data(
+            ".data\nstart: .word 1, 2, 3\nend:\nlen: .word (end - start) / 4\ntable: .word table + 8, later\nlater: .word 0\nmain:\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            words(&data),
+            vec![1, 2, 3, 3, DATA_BOT + 16 + 8, DATA_BOT + 24, 0]
+        );
+    }
+
+    #[test]
+    fn test_fixup_errors() {
+        assert!(compile_data(".data\nx: .byte later\nlater: .word 1\nmain:\n").is_err());
+        assert!(compile_data(".data\nx: .word nope + 4\nmain:\n").is_err());
+        assert!(compile_data(".data\nx: .word later - x\nlater: .byte 1\nmain:\n").is_ok());
+    }
+
+    #[test]
+    fn test_every_error() {
+        let errors = compile_data(
+            ".data\nx: .word nope\ny: .byte 1000\nz: .word 1, nope, also_nope\nw: .word 1\nmain:\n",
+        )
+        .unwrap_err();
+
+        let lines = errors
+            .iter()
+            .map(|error| error.location().unwrap().1)
+            .collect::<Vec<_>>();
+
+        // only the first error on each line is reported
+        assert_eq!(lines, vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn test_relocations() {
+        let mut binary = Binary::default();
+        binary.insert_label("x", 0x1001_8004);
+        binary.insert_label("y", 0x1001_7ffc);
+
+        let reloc = |source: &str| match mipsy_parser::parse_argument(source, 8) {
+            Ok(MpArgument::Number(MpNumber::Immediate(MpImmediate::Relocation(reloc, expr)))) => {
+                eval_relocation(&binary, reloc, &expr).unwrap()
+            }
+            other => panic!("{other:?}"),
+        };
+
+        // %hi is rounded up when %lo is negative
+        assert_eq!(reloc("%hi(x)"), 0x1002);
+        assert_eq!(reloc("%lo(x)"), 0x8004);
+        assert_eq!(reloc("%hi(y)"), 0x1001);
+        assert_eq!(reloc("%lo(y)"), 0x7ffc);
+        assert_eq!(reloc("%lo(y + 8)"), 0x8004);
+    }
+    #[test]
+    fn test_invalid_expressions() {
+        let error = |source: &str| match &compile_data(source).unwrap_err()[..] {
+            [MipsyError::Compiler(error)] => error.error().clone(),
+            other => panic!("{other:?}"),
+        };
+
+        assert!(matches!(
+            error(".data\nx: .word 4 / 0\nmain:\n"),
+            Error::DivisionByZero { expression } if expression == "4 / 0"
+        ));
+        assert!(matches!(
+            error(".data\nx: .word 4 % (1 - 1)\nmain:\n"),
+            Error::DivisionByZero { expression } if expression == "4 % (1 - 1)"
+        ));
+        assert!(matches!(
+            error(".data\nx: .word 1 << 70\nmain:\n"),
+            Error::ExpressionOverflow { expression } if expression == "1 << 70"
+        ));
+        assert!(matches!(
+            error(".data\nx: .word 1 >> -1\nmain:\n"),
+            Error::ExpressionOverflow { .. }
+        ));
+        assert!(matches!(
+            error(".data\nx: .word 0x7FFFFFFF * 0x7FFFFFFF * 0x7FFFFFFF\nmain:\n"),
+            Error::ExpressionOverflow { .. }
+        ));
+    }
+
+    #[test]
+    fn test_word_expressions() {
+        let binary = Binary::default();
+
+        let word = |source: &str| match mipsy_parser::parse_argument(source, 8) {
+            Ok(MpArgument::Number(MpNumber::Immediate(MpImmediate::Expression(expr)))) => {
+                eval_word_expression(&binary, &expr)
+            }
+            other => panic!("{other:?}"),
+        };
+
+        assert_eq!(word("1 << 31").unwrap(), 0x8000_0000);
+        assert_eq!(word("(0 - 4)").unwrap(), 0xFFFF_FFFC);
+        assert!(matches!(
+            word("1 << 40"),
+            Err(InternalError::Compiler(Error::ExpressionDoesNotFit { value, .. })) if value == 1 << 40
+        ));
+        assert!(matches!(
+            word("4 / 0"),
+            Err(InternalError::Compiler(Error::DivisionByZero { .. }))
+        ));
+    }
+}
@@ -0,0 +1,471 @@
+//! # Debugger expressions
+//!
+//! A small C-like expression language, used for breakpoint
+//! and watchpoint conditions, e.g. `$t0 == 57 && $t1 != 0`.
+//!
+//! Operands are integer literals (`57`, `0x39`, `0b111001`, `'9'`),
+//! registers (`$t0`, `$8`, `$pc`, `$hi`, `$lo`), labels (which evaluate
+//! to their address), and word loads (`*my_label`, `*($sp + 4)`).
+//!
+//! Operators have the same precedence as they do in C.
+//! All arithmetic is 32-bit and wrapping, and comparisons are signed,
+//! evaluating to `1` or `0`.
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Binary, Register, State};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Expr {
+    Constant(i32),
+    Register(Register),
+    Pc,
+    Hi,
+    Lo,
+    /// Loads the word at the given address.
+    Deref(Box<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    BitNot,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+// longer operators must come first, so that `<=` isn't read as `<`
+const BINARY_OPS: [(&str, BinaryOp); 18] = [
+    ("||", BinaryOp::Or),
+    ("&&", BinaryOp::And),
+    ("==", BinaryOp::Eq),
+    ("!=", BinaryOp::Ne),
+    ("<=", BinaryOp::Le),
+    (">=", BinaryOp::Ge),
+    ("<<", BinaryOp::Shl),
+    (">>", BinaryOp::Shr),
+    ("<", BinaryOp::Lt),
+    (">", BinaryOp::Gt),
+    ("+", BinaryOp::Add),
+    ("-", BinaryOp::Sub),
+    ("*", BinaryOp::Mul),
+    ("/", BinaryOp::Div),
+    ("%", BinaryOp::Rem),
+    ("&", BinaryOp::BitAnd),
+    ("|", BinaryOp::BitOr),
+    ("^", BinaryOp::BitXor),
+];
+
+impl BinaryOp {
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::BitOr => 3,
+            BinaryOp::BitXor => 4,
+            BinaryOp::BitAnd => 5,
+            BinaryOp::Eq | BinaryOp::Ne => 6,
+            BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => 7,
+            BinaryOp::Shl | BinaryOp::Shr => 8,
+            BinaryOp::Add | BinaryOp::Sub => 9,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 10,
+        }
+    }
+
+    fn apply(self, lhs: i32, rhs: i32) -> Result<i32, ExprError> {
+        Ok(match self {
+            BinaryOp::Mul => lhs.wrapping_mul(rhs),
+            BinaryOp::Div | BinaryOp::Rem if rhs == 0 => return Err(ExprError::DivisionByZero),
+            BinaryOp::Div => lhs.wrapping_div(rhs),
+            BinaryOp::Rem => lhs.wrapping_rem(rhs),
+            BinaryOp::Add => lhs.wrapping_add(rhs),
+            BinaryOp::Sub => lhs.wrapping_sub(rhs),
+            BinaryOp::Shl => lhs.wrapping_shl(rhs as u32),
+            BinaryOp::Shr => lhs.wrapping_shr(rhs as u32),
+            BinaryOp::Lt => (lhs < rhs) as i32,
+            BinaryOp::Le => (lhs <= rhs) as i32,
+            BinaryOp::Gt => (lhs > rhs) as i32,
+            BinaryOp::Ge => (lhs >= rhs) as i32,
+            BinaryOp::Eq => (lhs == rhs) as i32,
+            BinaryOp::Ne => (lhs != rhs) as i32,
+            BinaryOp::BitAnd => lhs & rhs,
+            BinaryOp::BitXor => lhs ^ rhs,
+            BinaryOp::BitOr => lhs | rhs,
+            BinaryOp::And => (lhs != 0 && rhs != 0) as i32,
+            BinaryOp::Or => (lhs != 0 || rhs != 0) as i32,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExprError {
+    UnexpectedEnd,
+    UnexpectedChar { char: char, column: usize },
+    InvalidNumber(String),
+    UnknownRegister(String),
+    UnknownLabel(String),
+    UninitialisedRegister(String),
+    UninitialisedMemory(u32),
+    UnalignedLoad(u32),
+    DivisionByZero,
+}
+
+impl Display for ExprError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExprError::UnexpectedEnd => write!(f, "unexpected end of expression"),
+            ExprError::UnexpectedChar { char, column } => {
+                write!(f, "unexpected `{char}` at column {column}")
+            }
+            ExprError::InvalidNumber(number) => write!(f, "`{number}` is not a valid number"),
+            ExprError::UnknownRegister(name) => write!(f, "unknown register `${name}`"),
+            ExprError::UnknownLabel(label) => write!(f, "unknown label `{label}`"),
+            ExprError::UninitialisedRegister(name) => write!(f, "`${name}` is uninitialized"),
+            ExprError::UninitialisedMemory(addr) => {
+                write!(f, "memory at 0x{addr:08x} is uninitialized")
+            }
+            ExprError::UnalignedLoad(addr) => {
+                write!(f, "address 0x{addr:08x} should be word-aligned")
+            }
+            ExprError::DivisionByZero => write!(f, "division by zero"),
+        }
+    }
+}
+
+impl Expr {
+    /// Parses an entire expression.
+    /// Labels are resolved against `binary` straight away.
+    pub fn parse(source: &str, binary: &Binary) -> Result<Self, ExprError> {
+        let mut parser = ExprParser::new(source, binary);
+        let expr = parser.parse_expr(1)?;
+        parser.expect_end()?;
+
+        Ok(expr)
+    }
+
+    pub fn evaluate(&self, state: &State) -> Result<i32, ExprError> {
+        Ok(match self {
+            Expr::Constant(value) => *value,
+            Expr::Register(reg) => state
+                .read_register(reg.to_u32())
+                .map_err(|_| ExprError::UninitialisedRegister(reg.to_lower_str().to_string()))?,
+            Expr::Pc => state.pc() as i32,
+            Expr::Hi => state
+                .read_hi()
+                .map_err(|_| ExprError::UninitialisedRegister("hi".to_string()))?,
+            Expr::Lo => state
+                .read_lo()
+                .map_err(|_| ExprError::UninitialisedRegister("lo".to_string()))?,
+            Expr::Deref(addr) => {
+                let addr = addr.evaluate(state)? as u32;
+                if addr % 4 != 0 {
+                    return Err(ExprError::UnalignedLoad(addr));
+                }
+
+                state
+                    .read_mem_word(addr)
+                    .map_err(|_| ExprError::UninitialisedMemory(addr))? as i32
+            }
+            Expr::Unary(op, expr) => {
+                let value = expr.evaluate(state)?;
+                match op {
+                    UnaryOp::Neg => value.wrapping_neg(),
+                    UnaryOp::Not => (value == 0) as i32,
+                    UnaryOp::BitNot => !value,
+                }
+            }
+            // short-circuit, so that `$t0 != 0 && *$t0 == 5` is safe
+            Expr::Binary(BinaryOp::And, lhs, rhs) => {
+                (lhs.evaluate(state)? != 0 && rhs.evaluate(state)? != 0) as i32
+            }
+            Expr::Binary(BinaryOp::Or, lhs, rhs) => {
+                (lhs.evaluate(state)? != 0 || rhs.evaluate(state)? != 0) as i32
+            }
+            Expr::Binary(op, lhs, rhs) => op.apply(lhs.evaluate(state)?, rhs.evaluate(state)?)?,
+        })
+    }
+}
+
+struct ExprParser<'a> {
+    source: &'a str,
+    position: usize,
+    binary: &'a Binary,
+}
+
+impl<'a> ExprParser<'a> {
+    fn new(source: &'a str, binary: &'a Binary) -> Self {
+        Self {
+            source,
+            position: 0,
+            binary,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.rest().chars().next()
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.position += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn unexpected(&mut self) -> ExprError {
+        match self.peek() {
+            Some(char) => ExprError::UnexpectedChar {
+                char,
+                column: self.source[..self.position].chars().count() + 1,
+            },
+            None => ExprError::UnexpectedEnd,
+        }
+    }
+
+    fn expect_end(&mut self) -> Result<(), ExprError> {
+        match self.peek() {
+            Some(_) => Err(self.unexpected()),
+            None => Ok(()),
+        }
+    }
+
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> &'a str {
+        let rest = self.rest();
+        let len = rest.find(|c| !pred(c)).unwrap_or(rest.len());
+        self.position += len;
+
+        &rest[..len]
+    }
+
+    fn peek_binary_op(&mut self) -> Option<(&'static str, BinaryOp)> {
+        self.skip_whitespace();
+        let rest = self.rest();
+
+        BINARY_OPS
+            .iter()
+            .find(|(token, _)| rest.starts_with(token))
+            .copied()
+    }
+
+    fn parse_expr(&mut self, min_precedence: u8) -> Result<Expr, ExprError> {
+        let mut lhs = self.parse_unary()?;
+
+        while let Some((token, op)) = self.peek_binary_op() {
+            if op.precedence() < min_precedence {
+                break;
+            }
+
+            self.position += token.len();
+            let rhs = self.parse_expr(op.precedence() + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ExprError> {
+        let op = if self.eat("-") {
+            UnaryOp::Neg
+        } else if self.eat("!") {
+            UnaryOp::Not
+        } else if self.eat("~") {
+            UnaryOp::BitNot
+        } else if self.eat("*") {
+            return Ok(Expr::Deref(Box::new(self.parse_unary()?)));
+        } else {
+            return self.parse_primary();
+        };
+
+        Ok(Expr::Unary(op, Box::new(self.parse_unary()?)))
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ExprError> {
+        match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let expr = self.parse_expr(1)?;
+                if !self.eat(")") {
+                    return Err(self.unexpected());
+                }
+
+                Ok(expr)
+            }
+            Some('$') => {
+                self.position += 1;
+                let name = self.take_while(|c| c.is_ascii_alphanumeric());
+
+                match name.to_ascii_lowercase().as_str() {
+                    "" => Err(self.unexpected()),
+                    "pc" => Ok(Expr::Pc),
+                    "hi" => Ok(Expr::Hi),
+                    "lo" => Ok(Expr::Lo),
+                    _ => Register::from_str(name)
+                        .map(Expr::Register)
+                        .map_err(|_| ExprError::UnknownRegister(name.to_string())),
+                }
+            }
+            Some('\'') => {
+                self.position += 1;
+                let mut chars = self.rest().chars();
+                let char = match chars.next() {
+                    Some('\\') => match chars.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('0') => '\0',
+                        Some(c @ ('\\' | '\'' | '"')) => c,
+                        _ => return Err(self.unexpected()),
+                    },
+                    Some(c) => c,
+                    None => return Err(ExprError::UnexpectedEnd),
+                };
+
+                self.position = self.source.len() - chars.as_str().len();
+                if !self.rest().starts_with('\'') {
+                    return Err(self.unexpected());
+                }
+                self.position += 1;
+
+                Ok(Expr::Constant(char as i32))
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let number = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+                parse_number(number)
+                    .map(Expr::Constant)
+                    .ok_or_else(|| ExprError::InvalidNumber(number.to_string()))
+            }
+            Some(c) if c.is_alphabetic() || c == '_' || c == '.' => {
+                let start = self.position;
+                // a `..` can never be part of a label
+                while let Some(c) = self.rest().chars().next() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '.')
+                        || self.rest().starts_with("..")
+                    {
+                        break;
+                    }
+
+                    self.position += c.len_utf8();
+                }
+
+                let label = &self.source[start..self.position];
+                self.binary
+                    .get_label(label)
+                    .map(|addr| Expr::Constant(addr as i32))
+                    .map_err(|_| ExprError::UnknownLabel(label.to_string()))
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+}
+
+fn parse_number(number: &str) -> Option<i32> {
+    let number = number.replace('_', "");
+    let lower = number.to_ascii_lowercase();
+
+    let value = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2)
+    } else if let Some(oct) = lower.strip_prefix("0o") {
+        i64::from_str_radix(oct, 8)
+    } else {
+        lower.parse()
+    }
+    .ok()?;
+
+    // allow both signed and unsigned 32-bit values, e.g. `0xFFFFFFFF`
+    (i32::MIN as i64..=u32::MAX as i64)
+        .contains(&value)
+        .then_some(value as i32)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn eval(source: &str) -> Result<i32, ExprError> {
+        let binary = Binary::default();
+        Expr::parse(source, &binary)?.evaluate(&State::default())
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval("1 << 2 + 1"), Ok(8));
+        assert_eq!(eval("1 + 1 == 2 && 3 > 2"), Ok(1));
+        assert_eq!(eval("-8 / 3"), Ok(-2));
+        assert_eq!(eval("!0 + ~0"), Ok(0));
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(eval("0x39"), Ok(57));
+        assert_eq!(eval("0b111001"), Ok(57));
+        assert_eq!(eval("'9'"), Ok(57));
+        assert_eq!(eval("'\\n'"), Ok(10));
+        assert_eq!(eval("0xFFFFFFFF"), Ok(-1));
+        assert_eq!(
+            eval("0x1FFFFFFFF"),
+            Err(ExprError::InvalidNumber("0x1FFFFFFFF".into()))
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(eval("1 +"), Err(ExprError::UnexpectedEnd));
+        assert_eq!(
+            eval("1 2"),
+            Err(ExprError::UnexpectedChar {
+                char: '2',
+                column: 3
+            })
+        );
+        assert_eq!(eval("$foo"), Err(ExprError::UnknownRegister("foo".into())));
+        assert_eq!(eval("foo"), Err(ExprError::UnknownLabel("foo".into())));
+        assert_eq!(eval("1 / 0"), Err(ExprError::DivisionByZero));
+        assert_eq!(
+            eval("$t0 == 57"),
+            Err(ExprError::UninitialisedRegister("t0".into()))
+        );
+        assert_eq!(eval("0 && $t0"), Ok(0));
+    }
+}
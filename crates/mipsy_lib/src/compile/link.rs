@@ -0,0 +1,423 @@
+//! Labels are private to the file they're defined in, unless that file
+//! declares them `.globl`. Before a program is populated, private labels
+//! that more than one file defines are given a name of their own, and
+//! references from one file to another file's labels are checked.
+
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use mipsy_parser::{
+    MpArgument, MpConstValue, MpConstValueLoc, MpDirective, MpImmediate, MpItem, MpNumber,
+    MpProgram, MpRegister,
+};
+
+use super::structs::field_constant_name;
+use crate::{
+    error::{compiler::Error, CompileResult},
+    CompilerError, MipsyError,
+};
+
+/// Can't appear in a label written in the source.
+const SEPARATOR: char = '@';
+
+/// The name that a private label is stored under,
+/// when some other file defines a label with the same name.
+pub fn private_label_name(label: &str, file_tag: &str) -> String {
+    format!("{label}{SEPARATOR}{file_tag}")
+}
+
+/// The name of a label in [`Binary::labels`](crate::Binary::labels)
+/// without the file that it's private to, if any.
+pub fn source_private_label_name(label: &str) -> &str {
+    match label.split_once(SEPARATOR) {
+        Some((label, _)) => label,
+        None => label,
+    }
+}
+
+struct Definition {
+    unit: Rc<str>,
+    file_tag: Rc<str>,
+    line: u32,
+    global: bool,
+}
+
+pub(super) fn link_labels(program: &mut MpProgram) -> CompileResult<()> {
+    let unit_of = |unit: Option<Rc<str>>| unit.unwrap_or_else(|| Rc::from(""));
+
+    let mut globals = HashSet::new();
+    let mut constants = HashSet::new();
+
+    for attributed_item in program.items() {
+        match attributed_item.item() {
+            MpItem::Directive((MpDirective::Globl(label), _)) => {
+                globals.insert((unit_of(attributed_item.unit()), label.to_string()));
+            }
+            MpItem::Constant(constant) => {
+                constants.insert(constant.label().to_string());
+            }
+            MpItem::Struct(mpstruct) => {
+                let fields = mpstruct.fields().iter().filter_map(|field| field.name());
+
+                for field in fields.chain(["size"]) {
+                    constants.insert(field_constant_name(mpstruct.name(), field));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut definitions: HashMap<String, Vec<Definition>> = HashMap::new();
+    let mut errors = vec![];
+
+    for attributed_item in program.items() {
+        if let MpItem::Label(label) = attributed_item.item() {
+            if label.is_local() {
+                continue;
+            }
+
+            let unit = unit_of(attributed_item.unit());
+            let file_tag = attributed_item.file_tag().unwrap_or_else(|| Rc::from(""));
+
+            // the kernel always needs to be able to find `main`
+            let global =
+                label.label() == "main" || globals.contains(&(unit.clone(), label.label()));
+
+            let label_definitions = definitions.entry(label.label()).or_default();
+
+            if global {
+                let first = label_definitions
+                    .iter()
+                    .find(|definition| definition.global && definition.unit != unit);
+
+                if let Some(first) = first {
+                    errors.push(MipsyError::Compiler(CompilerError::new(
+                        Error::RedefinedGlobalLabel {
+                            label: label.label(),
+                            first_file_tag: first.file_tag.to_string(),
+                            first_line: first.line,
+                        },
+                        file_tag,
+                        attributed_item.line_number(),
+                        label.col(),
+                        label.col_end(),
+                    )));
+                    continue;
+                }
+            }
+
+            label_definitions.push(Definition {
+                unit,
+                file_tag,
+                line: attributed_item.line_number(),
+                global,
+            });
+        }
+    }
+
+    // private labels only need a name of their own if another file uses the same name
+    let renamed = definitions
+        .iter()
+        .filter(|(_, label_definitions)| {
+            label_definitions
+                .iter()
+                .any(|definition| definition.unit != label_definitions[0].unit)
+        })
+        .flat_map(|(label, label_definitions)| {
+            label_definitions
+                .iter()
+                .filter(|definition| !definition.global)
+                .map(move |definition| (definition.unit.clone(), label.to_string()))
+        })
+        .collect::<HashSet<_>>();
+
+    for attributed_item in program.items_mut() {
+        let unit = unit_of(attributed_item.unit());
+        let file_tag = attributed_item.file_tag().unwrap_or_else(|| Rc::from(""));
+        let line = attributed_item.line_number();
+
+        if let MpItem::Label(label) = attributed_item.item_mut() {
+            if renamed.contains(&(unit.clone(), label.label())) {
+                *label.label_mut() = private_label_name(&label.label(), &unit);
+            }
+
+            continue;
+        }
+
+        for (label, line, col, col_end) in label_references_mut(attributed_item.item_mut(), line) {
+            if constants.contains(label.as_str()) {
+                continue;
+            }
+
+            let label_definitions = match definitions.get(label.as_str()) {
+                Some(label_definitions) => label_definitions,
+                // reported as an unresolved label later on
+                None => continue,
+            };
+
+            if label_definitions
+                .iter()
+                .any(|definition| definition.unit == unit)
+            {
+                if renamed.contains(&(unit.clone(), label.to_string())) {
+                    *label = private_label_name(label, &unit);
+                }
+
+                continue;
+            }
+
+            if label_definitions.iter().any(|definition| definition.global) {
+                continue;
+            }
+
+            errors.push(MipsyError::Compiler(CompilerError::new(
+                Error::PrivateLabel {
+                    label: label.to_string(),
+                    file_tag: label_definitions[0].file_tag.to_string(),
+                },
+                file_tag.clone(),
+                line,
+                col,
+                col_end,
+            )));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(())
+}
+
+/// Points every reference to the label `from` at the label `to` instead.
+pub(super) fn retarget_label(program: &mut MpProgram, from: &str, to: &str) {
+    for attributed_item in program.items_mut() {
+        let line = attributed_item.line_number();
+
+        for (label, ..) in label_references_mut(attributed_item.item_mut(), line) {
+            if label == from {
+                *label = to.to_string();
+            }
+        }
+    }
+}
+
+/// Every label an item refers to, along with the line and columns it's at.
+pub(super) fn label_references_mut(
+    item: &mut MpItem,
+    line: u32,
+) -> Vec<(&mut String, u32, u32, u32)> {
+    let mut references = vec![];
+
+    match item {
+        MpItem::Instruction(instruction) => {
+            for (arg, col, col_end) in instruction.arguments_mut() {
+                let immediates = match arg {
+                    MpArgument::Number(MpNumber::Immediate(imm))
+                    | MpArgument::Register(MpRegister::Offset(imm, _)) => vec![imm],
+                    MpArgument::Number(MpNumber::BinaryOpImmediate(imm1, _, imm2))
+                    | MpArgument::Register(MpRegister::BinaryOpOffset(imm1, _, imm2, _)) => {
+                        vec![imm1, imm2]
+                    }
+                    _ => vec![],
+                };
+
+                for imm in immediates {
+                    match imm {
+                        MpImmediate::LabelReference(label) => {
+                            references.push((label, line, *col, *col_end));
+                        }
+                        MpImmediate::Expression(expr) | MpImmediate::Relocation(_, expr) => {
+                            const_references_mut(expr, &mut references);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        MpItem::Directive((directive, _)) => directive_references_mut(directive, &mut references),
+        MpItem::Constant(constant) => const_references_mut(constant.value_mut(), &mut references),
+        MpItem::Struct(mpstruct) => {
+            for field in mpstruct.fields_mut() {
+                directive_references_mut(field.directive_mut(), &mut references);
+            }
+        }
+        MpItem::Label(_) => {}
+    }
+
+    references
+}
+
+fn directive_references_mut<'a>(
+    directive: &'a mut MpDirective,
+    references: &mut Vec<(&'a mut String, u32, u32, u32)>,
+) {
+    match directive {
+        MpDirective::Byte(values) | MpDirective::Half(values) | MpDirective::Word(values) => {
+            for (value, repeat) in values.iter_mut() {
+                const_references_mut(value, references);
+
+                if let Some(repeat) = repeat {
+                    const_references_mut(repeat, references);
+                }
+            }
+        }
+        MpDirective::Float(values) => {
+            for repeat in values.iter_mut().filter_map(|(_, repeat)| repeat.as_mut()) {
+                const_references_mut(repeat, references);
+            }
+        }
+        MpDirective::Double(values) => {
+            for repeat in values.iter_mut().filter_map(|(_, repeat)| repeat.as_mut()) {
+                const_references_mut(repeat, references);
+            }
+        }
+        MpDirective::Align(value) | MpDirective::Space(value) => {
+            const_references_mut(value, references);
+        }
+        MpDirective::Text
+        | MpDirective::Data
+        | MpDirective::KText
+        | MpDirective::KData
+        | MpDirective::Ascii(_)
+        | MpDirective::Asciiz(_)
+        | MpDirective::Globl(_)
+        | MpDirective::Set(_) => {}
+    }
+}
+
+fn const_references_mut<'a>(
+    value: &'a mut MpConstValueLoc,
+    references: &mut Vec<(&'a mut String, u32, u32, u32)>,
+) {
+    let MpConstValueLoc(value, position) = value;
+
+    match value {
+        MpConstValue::Value(_) => {}
+        MpConstValue::Const(label) => {
+            references.push((label, position.line(), position.col(), position.col_end()));
+        }
+        MpConstValue::Minus(x) | MpConstValue::Neg(x) => const_references_mut(x, references),
+        MpConstValue::Mult(x, y)
+        | MpConstValue::Sum(x, y)
+        | MpConstValue::Sub(x, y)
+        | MpConstValue::Div(x, y)
+        | MpConstValue::Mod(x, y)
+        | MpConstValue::And(x, y)
+        | MpConstValue::Or(x, y)
+        | MpConstValue::Xor(x, y)
+        | MpConstValue::Shl(x, y)
+        | MpConstValue::Shr(x, y) => {
+            const_references_mut(x, references);
+            const_references_mut(y, references);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mipsy_parser::TaggedFile;
+
+    fn link(files: &[(&str, &str)]) -> CompileResult<Vec<String>> {
+        let mut program = mipsy_parser::parse_mips(
+            files
+                .iter()
+                .map(|(tag, source)| TaggedFile::new(Some(tag), source))
+                .collect(),
+            8,
+        )
+        .unwrap();
+
+        link_labels(&mut program)?;
+
+        let mut names = vec![];
+        for attributed_item in program.items_mut() {
+            let line = attributed_item.line_number();
+
+            if let MpItem::Label(label) = attributed_item.item() {
+                names.push(label.label());
+            }
+
+            for (label, ..) in label_references_mut(attributed_item.item_mut(), line) {
+                names.push(label.to_string());
+            }
+        }
+
+        Ok(names)
+    }
+
+    #[test]
+    fn test_private_labels() {
+        assert_eq!(
+            link(&[
+                ("a.s", "main:\n  jal f\nloop:\n  j loop\n"),
+                ("b.s", ".globl f\nf:\nloop:\n  j loop\n"),
+            ])
+            .unwrap(),
+            vec!["main", "f", "loop@a.s", "loop@a.s", "f", "loop@b.s", "loop@b.s"]
+        );
+
+        // labels only one file defines keep their name
+        assert_eq!(
+            link(&[("a.s", "main:\n  j loop\nloop:\n")]).unwrap(),
+            vec!["main", "loop", "loop"]
+        );
+    }
+
+    #[test]
+    fn test_retarget_label() {
+        let mut program = mipsy_parser::parse_mips(
+            vec![TaggedFile::new(
+                None,
+                "main:\n  jal main\n  .word main, mainly\n",
+            )],
+            8,
+        )
+        .unwrap();
+
+        retarget_label(&mut program, "main", "start");
+
+        let mut names = vec![];
+        for attributed_item in program.items_mut() {
+            let line = attributed_item.line_number();
+
+            if let MpItem::Label(label) = attributed_item.item() {
+                names.push(label.label());
+            }
+
+            for (label, ..) in label_references_mut(attributed_item.item_mut(), line) {
+                names.push(label.to_string());
+            }
+        }
+
+        // the label itself is left alone
+        assert_eq!(names, vec!["main", "start", "start", "mainly"]);
+    }
+
+    #[test]
+    fn test_link_errors() {
+        let private = link(&[("a.s", "main:\n  jal f\n"), ("b.s", "f:\n  jr $ra\n")]);
+        assert!(matches!(
+            &private.unwrap_err()[..],
+            [MipsyError::Compiler(err)] if matches!(err.error(), Error::PrivateLabel { .. })
+        ));
+
+        let redefined = link(&[("a.s", "main:\n"), ("b.s", "main:\n")]);
+        assert!(matches!(
+            &redefined.unwrap_err()[..],
+            [MipsyError::Compiler(err)]
+                if matches!(err.error(), Error::RedefinedGlobalLabel { first_line: 1, .. })
+        ));
+
+        // every private label that's used from another file is reported
+        let private = link(&[
+            ("a.s", "main:\n  jal f\n  jal g\n"),
+            ("b.s", "f:\n  jr $ra\ng:\n  jr $ra\n"),
+        ]);
+        assert_eq!(private.unwrap_err().len(), 2);
+    }
+}
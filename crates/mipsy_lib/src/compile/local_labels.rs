@@ -0,0 +1,143 @@
+//! Numeric local labels (`1:`) can be defined any number of times,
+//! so before a program is populated, each definition is renamed to a
+//! name of its own, and each `1b` or `1f` reference is renamed to match.
+
+use std::collections::HashMap;
+
+use mipsy_parser::{is_local_label, MpItem, MpProgram};
+
+use super::{
+    link::{label_references_mut, source_private_label_name},
+    Binary,
+};
+
+/// Can't appear in a label written in the source.
+const SEPARATOR: char = '~';
+
+/// The name that the `index`th definition of a local label is stored under.
+pub fn local_label_name(label: &str, index: usize) -> String {
+    format!("{label}{SEPARATOR}{index}")
+}
+
+/// The name of a label in [`Binary::labels`] as it was written in the source.
+pub fn source_label_name(label: &str) -> &str {
+    // the file a label is private to goes last, and may itself contain a `~`
+    let label = source_private_label_name(label);
+
+    match label.split_once(SEPARATOR) {
+        Some((label, _)) => label,
+        None => label,
+    }
+}
+
+pub(super) fn resolve_local_labels(binary: &Binary, program: &mut MpProgram) {
+    let mut local_labels = LocalLabels::new(binary, program);
+
+    for attributed_item in program.items_mut() {
+        let line = attributed_item.line_number();
+
+        if let MpItem::Label(label) = attributed_item.item_mut() {
+            if label.is_local() {
+                let name = local_labels.define(&label.label());
+                *label.label_mut() = name;
+            }
+
+            continue;
+        }
+
+        // unresolvable references are left alone,
+        // so that they get reported as unknown labels
+        for (label, ..) in label_references_mut(attributed_item.item_mut(), line) {
+            if let Some(resolved) = local_labels.resolve(label) {
+                *label = resolved;
+            }
+        }
+    }
+}
+
+struct LocalLabels {
+    /// how many times each local label has been defined so far
+    defined: HashMap<String, usize>,
+    /// how many times each local label is defined in total
+    total: HashMap<String, usize>,
+}
+
+impl LocalLabels {
+    fn new(binary: &Binary, program: &MpProgram) -> Self {
+        // carry on from any definitions in programs populated earlier (e.g. the kernel),
+        // so that the names stay unique
+        let mut defined = HashMap::new();
+        for label in binary.labels.keys() {
+            if let Some((label, _)) = source_private_label_name(label).split_once(SEPARATOR) {
+                *defined.entry(label.to_string()).or_default() += 1;
+            }
+        }
+
+        let mut total = defined.clone();
+        for attributed_item in program.items() {
+            if let MpItem::Label(label) = attributed_item.item() {
+                if label.is_local() {
+                    *total.entry(label.label()).or_default() += 1;
+                }
+            }
+        }
+
+        Self { defined, total }
+    }
+
+    /// The name to store the next definition of `label` under.
+    fn define(&mut self, label: &str) -> String {
+        let index = self.defined.entry(label.to_string()).or_default();
+        *index += 1;
+
+        local_label_name(label, *index - 1)
+    }
+
+    /// The label that a `1b` or `1f` refers to, from where we are up to.
+    fn resolve(&self, reference: &str) -> Option<String> {
+        let (label, direction) = reference.split_at(reference.len().checked_sub(1)?);
+        if !is_local_label(label) {
+            return None;
+        }
+
+        let defined = self.defined.get(label).copied().unwrap_or(0);
+        let total = self.total.get(label).copied().unwrap_or(0);
+
+        match direction {
+            "b" if defined > 0 => Some(local_label_name(label, defined - 1)),
+            "f" if defined < total => Some(local_label_name(label, defined)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mipsy_parser::TaggedFile;
+
+    #[test]
+    fn test_resolve() {
+        let source = "1:\n  nop\n1:\n  nop\n2:\n  nop\n";
+        let program = mipsy_parser::parse_mips(vec![TaggedFile::new(None, source)], 8).unwrap();
+        let mut local_labels = LocalLabels::new(&Binary::default(), &program);
+
+        assert_eq!(local_labels.resolve("1b"), None);
+        assert_eq!(local_labels.resolve("1f"), Some("1~0".to_string()));
+
+        assert_eq!(local_labels.define("1"), "1~0");
+        assert_eq!(local_labels.resolve("1b"), Some("1~0".to_string()));
+        assert_eq!(local_labels.resolve("1f"), Some("1~1".to_string()));
+
+        assert_eq!(local_labels.define("1"), "1~1");
+        assert_eq!(local_labels.resolve("1f"), None);
+        assert_eq!(local_labels.resolve("2f"), Some("2~0".to_string()));
+        assert_eq!(local_labels.resolve("3f"), None);
+        assert_eq!(local_labels.resolve("foo"), None);
+
+        assert_eq!(source_label_name("1~1"), "1");
+        assert_eq!(source_label_name("main"), "main");
+        assert_eq!(source_label_name("loop@a.s"), "loop");
+        assert_eq!(source_label_name("1~2@dir/b~c.s"), "1");
+    }
+}
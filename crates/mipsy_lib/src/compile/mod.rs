@@ -1,8 +1,8 @@
 use crate::compile::breakpoints::Breakpoint;
 use crate::{
-    error::{compiler, InternalError, MipsyInternalResult},
+    error::{compiler, CompileResult, InternalError, MipsyInternalResult},
     util::Safe,
-    InstSet, MipsyResult, MpProgram,
+    CompilerWarning, InstSet, MipsyError, MpProgram,
 };
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, rc::Rc};
@@ -10,14 +10,28 @@ mod bytes;
 
 pub mod breakpoints;
 mod checker;
+pub mod expr;
 pub use checker::{check_post_data_label, check_pre};
 
 mod data;
-use data::populate_labels_and_data;
+use data::{apply_fixups, populate_labels_and_data};
+pub(crate) use data::{eval_expression, eval_relocation, eval_word_expression};
+
+mod link;
+pub use link::{private_label_name, source_private_label_name};
+
+mod local_labels;
+pub use local_labels::{local_label_name, source_label_name};
+
+mod profile;
+use profile::Profiles;
+pub use profile::Restriction;
+
+pub mod structs;
 
 mod text;
 use linked_hash_map::LinkedHashMap;
-use mipsy_parser::TaggedFile;
+use mipsy_parser::{is_local_label, MpConstValueLoc, TaggedFile};
 use mipsy_utils::MipsyConfig;
 use text::populate_text;
 
@@ -28,10 +42,14 @@ pub use text::compile1;
 use self::{
     breakpoints::{Point, Watchpoint, WatchpointTarget},
     extra::move_labels,
+    structs::StructLayout,
 };
 
 static KERN_FILE: &str = include_str!("../../../../kern.s");
 
+/// Where the kernel starts a linked executable, in place of `main`.
+const KERNEL_ENTRY: &str = "kernel__entry";
+
 pub const TEXT_BOT: u32 = 0x00400000;
 pub const TEXT_TOP: u32 = 0x0FFFFFFF;
 pub const GLOBAL_BOT: u32 = 0x10000000;
@@ -52,6 +70,7 @@ pub struct Binary {
     pub kdata: Vec<Safe<u8>>,
     pub labels: LinkedHashMap<String, u32>,
     pub constants: HashMap<String, i64>,
+    pub structs: HashMap<String, StructLayout>,
     pub globals: Vec<String>,
     pub line_numbers: HashMap<u32, (Rc<str>, u32)>,
     pub breakpoints: HashMap<u32, Breakpoint>,
@@ -68,12 +87,18 @@ impl Binary {
             let mut similar = self
                 .labels
                 .keys()
+                .map(|label| source_label_name(label))
+                .filter(|label| !is_local_label(label))
                 .map(|label| label.to_ascii_lowercase())
                 .map(|label| (strsim::jaro_winkler(&label, &label_lower), label))
                 .filter(|&(sim, _)| sim >= 0.9)
                 .collect::<Vec<_>>();
 
-            similar.sort_by(|&(sim1, _), (sim2, _)| sim1.partial_cmp(sim2).unwrap());
+            similar.sort_by(|(sim1, label1), (sim2, label2)| {
+                sim1.partial_cmp(sim2).unwrap().then(label1.cmp(label2))
+            });
+            // private labels from different files can have the same name
+            similar.dedup();
 
             let similar = similar
                 .into_iter()
@@ -87,6 +112,12 @@ impl Binary {
         }
     }
 
+    /// Evaluates an expression like `table + 8`, where a name can be either a label
+    /// or a constant. A name that doesn't exist gives back [`compiler::Error::UnresolvedConstant`].
+    pub fn eval_expression(&self, expr: &MpConstValueLoc) -> Result<i64, compiler::Error> {
+        data::eval_const_value(self, expr).map_err(|err| err.into_error())
+    }
+
     pub fn insert_label(&mut self, label: &str, addr: u32) {
         self.labels.insert(label.to_string(), addr);
     }
@@ -139,11 +170,25 @@ impl Binary {
 #[derive(Debug, Default)]
 pub struct CompilerOptions {
     moves: Vec<(String, String)>,
+    defines: Vec<(String, String)>,
 }
 
 impl CompilerOptions {
     pub fn new(moves: Vec<(String, String)>) -> Self {
-        Self { moves }
+        Self {
+            moves,
+            defines: vec![],
+        }
+    }
+
+    /// Symbols to define before assembling, as with `-D NAME=value`.
+    pub fn with_defines(mut self, defines: Vec<(String, String)>) -> Self {
+        self.defines = defines;
+        self
+    }
+
+    pub fn defines(&self) -> &[(String, String)] {
+        &self.defines
     }
 
     pub fn moves(&self) -> impl Iterator<Item = (&str, &str)> {
@@ -158,40 +203,157 @@ pub fn compile(
     config: &MipsyConfig,
     options: &CompilerOptions,
     iset: &InstSet,
-) -> MipsyResult<Binary> {
+) -> CompileResult<(Binary, Vec<CompilerWarning>)> {
     compile_with_kernel(program, &mut get_kernel(), options, config, iset)
 }
 
+/// Compiles a program, carrying on past any errors so that every error
+/// in the program (up to [`MipsyConfig::max_errors`]) is reported at once.
 pub fn compile_with_kernel(
     program: &mut MpProgram,
     kernel: &mut MpProgram,
     options: &CompilerOptions,
     config: &MipsyConfig,
     iset: &InstSet,
-) -> MipsyResult<Binary> {
-    let warnings = check_pre(program)?;
-    if !warnings.is_empty() {
-        // TODO: Deal with warnings here
-    }
+) -> CompileResult<(Binary, Vec<CompilerWarning>)> {
+    let mut errors = Errors::new(config);
+
+    let mut warnings = errors
+        .check(check_pre(program, kernel, iset))
+        .unwrap_or_default();
 
     let mut binary = Binary::default();
 
-    populate_labels_and_data(&mut binary, config, iset, kernel)?;
+    let mut fixups = errors
+        .check(populate_labels_and_data(&mut binary, config, iset, kernel))
+        .unwrap_or_default();
 
-    populate_labels_and_data(&mut binary, config, iset, program)?;
+    fixups.append(
+        &mut errors
+            .check(populate_labels_and_data(&mut binary, config, iset, program))
+            .unwrap_or_default(),
+    );
 
-    let warnings = check_post_data_label(program, &binary)?;
-    if !warnings.is_empty() {
-        // TODO: Deal with warnings here
-    }
+    warnings.append(
+        &mut errors
+            .check(check_post_data_label(program, &binary))
+            .unwrap_or_default(),
+    );
 
     move_labels(&mut binary, options.moves());
 
-    populate_text(&mut binary, iset, config, program)?;
+    errors.check(apply_fixups(&mut binary, fixups));
+
+    // the kernel can use whichever instructions it likes
+    let profiles = errors.check(Profiles::new(program, config));
+
+    if let Some(profiles) = profiles {
+        errors.check(populate_text(&mut binary, iset, config, &profiles, program));
+    }
 
-    populate_text(&mut binary, iset, config, kernel)?;
+    errors.check(populate_text(
+        &mut binary,
+        iset,
+        config,
+        &Profiles::default(),
+        kernel,
+    ));
 
-    Ok(binary)
+    errors.into_result()?;
+
+    Ok((binary, warnings))
+}
+
+pub fn link_kernel(
+    binary: &mut Binary,
+    kernel: &mut MpProgram,
+    entry: Option<u32>,
+    config: &MipsyConfig,
+    iset: &InstSet,
+) -> CompileResult<()> {
+    // the kernel starts the program at `main`, so it's pointed
+    // at an entry point of its own instead
+    if entry.is_some() {
+        link::retarget_label(kernel, "main", KERNEL_ENTRY);
+    }
+
+    // the binary's own labels take precedence over the kernel's,
+    // since an executable may well have its own `_start`
+    let labels = std::mem::take(&mut binary.labels);
+
+    let fixups = populate_labels_and_data(binary, config, iset, kernel)?;
+
+    binary.labels.extend(labels);
+
+    if let Some(entry) = entry {
+        binary.labels.insert(String::from(KERNEL_ENTRY), entry);
+    }
+
+    apply_fixups(binary, fixups)?;
+    populate_text(binary, iset, config, &Profiles::default(), kernel)
+}
+
+/// The errors from each stage of compiling.
+///
+/// A mistake on one line tends to cause errors in later stages too,
+/// so only the first error on each line is kept.
+struct Errors {
+    errors: Vec<MipsyError>,
+    max_errors: usize,
+}
+
+impl Errors {
+    fn new(config: &MipsyConfig) -> Self {
+        Self {
+            errors: vec![],
+            max_errors: config.max_errors.max(1),
+        }
+    }
+
+    fn check<T>(&mut self, result: CompileResult<T>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(errors) => {
+                for error in errors {
+                    let seen = error.location().is_some()
+                        && self
+                            .errors
+                            .iter()
+                            .any(|seen| seen.location() == error.location());
+
+                    if !seen && self.errors.len() < self.max_errors {
+                        self.errors.push(error);
+                    }
+                }
+
+                None
+            }
+        }
+    }
+
+    /// The errors come from different stages, so they're put back in
+    /// the order they appear in the program.
+    fn into_result(mut self) -> CompileResult<()> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            let mut files: Vec<Rc<str>> = vec![];
+            for (file_tag, _) in self.errors.iter().filter_map(MipsyError::location) {
+                if !files.contains(&file_tag) {
+                    files.push(file_tag);
+                }
+            }
+
+            self.errors.sort_by_key(|error| {
+                error.location().map(|(file_tag, line)| {
+                    let file = files.iter().position(|file| *file == file_tag);
+                    (file, line)
+                })
+            });
+
+            Err(self.errors)
+        }
+    }
 }
 
 pub fn get_kernel() -> MpProgram {
@@ -199,3 +361,57 @@ pub fn get_kernel() -> MpProgram {
     mipsy_parser::parse_mips(vec![TaggedFile::new(None, KERN_FILE)], 8)
         .expect("Kernel file should always build")
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::inst::{
+        instruction::{
+            ArgumentType, CompileSignature, InstMetadata, InstSignature, RuntimeSignature,
+        },
+        RuntimeMetadata,
+    };
+
+    #[test]
+    fn test_link_kernel() {
+        let jal = InstSignature::new(
+            String::from("jal"),
+            CompileSignature::new(vec![ArgumentType::J], false),
+            RuntimeSignature::J { opcode: 3 },
+            RuntimeMetadata::new(vec![]),
+            InstMetadata::new(None, None),
+        );
+        let iset = InstSet::new(vec![jal], vec![]);
+
+        let mut binary = Binary {
+            text: vec![Safe::Valid(0); 8],
+            ..Default::default()
+        };
+        binary.insert_label("main", TEXT_BOT);
+
+        let mut kernel =
+            mipsy_parser::parse_mips(vec![TaggedFile::new(None, ".ktext\n  jal main\n")], 8)
+                .unwrap();
+
+        link_kernel(
+            &mut binary,
+            &mut kernel,
+            Some(TEXT_BOT + 4),
+            &MipsyConfig::default(),
+            &iset,
+        )
+        .unwrap();
+
+        let jal = binary.ktext[..4]
+            .iter()
+            .map(|byte| byte.as_option().copied().unwrap())
+            .collect::<Vec<_>>();
+
+        // the kernel starts at the entry point, and `main` is left where it was
+        assert_eq!(
+            u32::from_le_bytes(jal.try_into().unwrap()),
+            (3 << 26) | ((TEXT_BOT + 4) >> 2)
+        );
+        assert_eq!(binary.labels.get("main"), Some(&TEXT_BOT));
+    }
+}
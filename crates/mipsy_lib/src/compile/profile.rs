@@ -0,0 +1,295 @@
+use std::{collections::HashMap, rc::Rc};
+
+use mipsy_parser::MpProgram;
+use mipsy_utils::{MipsyConfig, Profile};
+
+use crate::{
+    error::{compiler, CompileResult},
+    CompilerError, MipsyError,
+};
+
+/// A profile that restricts which instructions may be used,
+/// along with the name it was given in the config.
+#[derive(Debug, Clone, Copy)]
+pub struct Restriction<'a> {
+    pub name: &'a str,
+    pub profile: &'a Profile,
+}
+
+impl<'a> Restriction<'a> {
+    /// The profile that the config applies by default, if it exists.
+    pub fn configured(config: &'a MipsyConfig) -> Option<Self> {
+        let name = config.profile.as_deref()?;
+
+        config
+            .profiles
+            .get_key_value(name)
+            .map(|(name, profile)| Self { name, profile })
+    }
+}
+
+/// The profile that applies to each unit of a program,
+/// from its `#![profile(...)]` attribute, or otherwise the config.
+#[derive(Debug, Default)]
+pub(crate) struct Profiles<'a> {
+    default: Option<Restriction<'a>>,
+    units: HashMap<Option<Rc<str>>, Restriction<'a>>,
+}
+
+impl<'a> Profiles<'a> {
+    pub(crate) fn new(program: &MpProgram, config: &'a MipsyConfig) -> CompileResult<Self> {
+        let mut profiles = Self::default();
+        let mut errors = vec![];
+
+        let unknown = |name: &str, in_config| compiler::Error::UnknownProfile {
+            profile: name.to_string(),
+            profiles: config.profiles.keys().cloned().collect(),
+            in_config,
+        };
+
+        if let Some(name) = &config.profile {
+            match Restriction::configured(config) {
+                Some(restriction) => profiles.default = Some(restriction),
+                None => errors.push(MipsyError::Compiler(CompilerError::new(
+                    unknown(name, true),
+                    // there's no line to point at
+                    Rc::from(""),
+                    0,
+                    0,
+                    0,
+                ))),
+            }
+        }
+
+        for (unit, attributes) in program.units() {
+            for attribute in attributes {
+                if !attribute.key().eq_ignore_ascii_case("profile") {
+                    continue;
+                }
+
+                let name = attribute.value().unwrap_or_default().trim();
+
+                match config.profiles.get_key_value(name) {
+                    Some((name, profile)) => {
+                        profiles
+                            .units
+                            .insert(unit.map(Rc::from), Restriction { name, profile });
+                    }
+                    None => errors.push(MipsyError::Compiler(CompilerError::new(
+                        unknown(name, false),
+                        Rc::from(unit.unwrap_or_default()),
+                        attribute.line(),
+                        attribute.col(),
+                        attribute.col_end(),
+                    ))),
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(profiles)
+    }
+
+    /// The restriction on the instructions of a unit, if there is one.
+    pub(crate) fn get(&self, unit: &Option<Rc<str>>) -> Option<&Restriction<'a>> {
+        self.units.get(unit).or(self.default.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        compile::compile_with_kernel,
+        inst::instruction::{
+            ArgumentType, CompileSignature, InstMetadata, InstSignature, PseudoExpand,
+            PseudoSignature, RuntimeMetadata, RuntimeSignature,
+        },
+        CompilerOptions, InstSet,
+    };
+    use mipsy_parser::TaggedFile;
+
+    fn iset() -> InstSet {
+        let native = |name: &str, format, runtime| {
+            InstSignature::new(
+                name.to_string(),
+                CompileSignature::new(format, false),
+                runtime,
+                RuntimeMetadata::new(vec![]),
+                InstMetadata::new(None, None),
+            )
+        };
+
+        InstSet::new(
+            vec![
+                native(
+                    "add",
+                    vec![ArgumentType::Rd, ArgumentType::Rs, ArgumentType::Rt],
+                    RuntimeSignature::R {
+                        opcode: 0,
+                        funct: 0x20,
+                        shamt: Some(0),
+                        rs: None,
+                        rt: None,
+                        rd: None,
+                    },
+                ),
+                native(
+                    "mul",
+                    vec![ArgumentType::Rd, ArgumentType::Rs, ArgumentType::Rt],
+                    RuntimeSignature::R {
+                        opcode: 0x1C,
+                        funct: 0x02,
+                        shamt: Some(0),
+                        rs: None,
+                        rt: None,
+                        rd: None,
+                    },
+                ),
+                native(
+                    "addi",
+                    vec![ArgumentType::Rt, ArgumentType::Rs, ArgumentType::I16],
+                    RuntimeSignature::I {
+                        opcode: 8,
+                        rt: None,
+                    },
+                ),
+            ],
+            vec![PseudoSignature::new(
+                "inc".to_string(),
+                CompileSignature::new(vec![ArgumentType::Rt], false),
+                vec![PseudoExpand::new(
+                    "addi".to_string(),
+                    vec!["$Rt".to_string(), "$Rt".to_string(), "1".to_string()],
+                )],
+                InstMetadata::new(None, None),
+            )],
+        )
+    }
+
+    fn errors(files: &[(&str, &str)], config: &MipsyConfig) -> Vec<compiler::Error> {
+        let files = files
+            .iter()
+            .map(|(tag, source)| TaggedFile::new(Some(tag), source))
+            .collect();
+        let mut program = mipsy_parser::parse_mips(files, 8).unwrap();
+
+        let result = compile_with_kernel(
+            &mut program,
+            &mut MpProgram::new(vec![], vec![]),
+            &CompilerOptions::new(vec![]),
+            config,
+            &iset(),
+        );
+
+        match result {
+            Ok(_) => vec![],
+            Err(errors) => errors
+                .into_iter()
+                .map(|error| match error {
+                    MipsyError::Compiler(error) => error.error().clone(),
+                    _ => panic!("expected a compiler error"),
+                })
+                .collect(),
+        }
+    }
+
+    /// The instruction, profile and suggested replacement of each forbidden instruction.
+    fn forbidden(errors: Vec<compiler::Error>) -> Vec<(String, String, Vec<String>)> {
+        errors
+            .into_iter()
+            .map(|error| match error {
+                compiler::Error::ForbiddenInstruction {
+                    inst_ast,
+                    profile,
+                    instead,
+                } => (inst_ast.name().to_string(), profile, instead),
+                error => panic!("expected a forbidden instruction, not {:?}", error),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_restrictions() {
+        let mut config = MipsyConfig::default();
+        config.profiles.insert(
+            String::from("native"),
+            Profile {
+                pseudo_instructions: false,
+                ..Profile::default()
+            },
+        );
+        config.profiles.insert(
+            String::from("adds"),
+            Profile {
+                allow: vec![String::from("addi"), String::from("inc")],
+                ..Profile::default()
+            },
+        );
+
+        let source = "main:\n  inc $t0\n  add $t0, $t1, $t2\n";
+        assert_eq!(errors(&[("a.s", source)], &config), vec![]);
+
+        let restricted = format!("#![profile(native)]\n{source}");
+        assert_eq!(
+            forbidden(errors(
+                &[("a.s", &restricted), ("b.s", "f:\n  inc $t1\n")],
+                &config
+            )),
+            vec![(
+                String::from("inc"),
+                String::from("native"),
+                vec![String::from("addi $t0, $t0, 1")]
+            )]
+        );
+
+        config.profile = Some(String::from("adds"));
+        assert_eq!(
+            forbidden(errors(
+                &[("a.s", &restricted), ("b.s", "f:\n  add $t1, $t1, $t1\n")],
+                &config
+            )),
+            vec![
+                (
+                    String::from("inc"),
+                    String::from("native"),
+                    vec![String::from("addi $t0, $t0, 1")]
+                ),
+                (String::from("add"), String::from("adds"), vec![]),
+            ]
+        );
+
+        // the few native instructions with another way to write them say what it is
+        assert_eq!(
+            forbidden(errors(&[("a.s", "main:\n  mul $t0, $t1, $t2\n")], &config)),
+            vec![(
+                String::from("mul"),
+                String::from("adds"),
+                vec![String::from("mult $t1, $t2"), String::from("mflo $t0")]
+            )]
+        );
+    }
+
+    #[test]
+    fn test_unknown_profiles() {
+        let mut config = MipsyConfig::default();
+        config
+            .profiles
+            .insert(String::from("week1"), Profile::default());
+        config.profile = Some(String::from("week0"));
+
+        let unknown = |profile: &str, in_config| compiler::Error::UnknownProfile {
+            profile: profile.to_string(),
+            profiles: vec![String::from("week1")],
+            in_config,
+        };
+
+        assert_eq!(
+            errors(&[("a.s", "#![profile(week2)]\nmain:\n")], &config),
+            vec![unknown("week0", true), unknown("week2", false)]
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! `.struct` blocks, which name the offsets of a struct's fields so that
+//! they don't have to be worked out by hand.
+//!
+//! Each field is laid out (and aligned) as if its directive were in the
+//! data segment, and defines a constant `Name.field` with its offset,
+//! along with `Name.size` for the size of the whole struct.
+
+use std::rc::Rc;
+
+use mipsy_parser::{MpConstValueLoc, MpDirective, MpStruct};
+use serde::{Deserialize, Serialize};
+
+use super::{data::eval_constant_in_range, Binary};
+use crate::{error::compiler::Error, CompilerError, MipsyError, MipsyResult};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StructLayout {
+    pub name: String,
+    /// Only the named fields -- padding and unnamed fields are left out.
+    pub fields: Vec<StructField>,
+    /// Padded out to the largest alignment of any field,
+    /// so that an array of the struct keeps every field aligned.
+    pub size: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StructField {
+    pub name: String,
+    pub offset: u32,
+    pub kind: FieldKind,
+    /// The number of elements, which is the number of bytes
+    /// for a [`FieldKind::Space`] or [`FieldKind::String`].
+    pub len: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FieldKind {
+    Byte,
+    Half,
+    Word,
+    Float,
+    Double,
+    Space,
+    String,
+}
+
+impl FieldKind {
+    /// The size of a single element, which is also the alignment of the field.
+    pub fn size(self) -> u32 {
+        match self {
+            FieldKind::Byte | FieldKind::Space | FieldKind::String => 1,
+            FieldKind::Half => 2,
+            FieldKind::Word | FieldKind::Float => 4,
+            FieldKind::Double => 8,
+        }
+    }
+}
+
+impl StructLayout {
+    pub fn field(&self, name: &str) -> Option<&StructField> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+impl StructField {
+    pub fn size(&self) -> u32 {
+        self.kind.size() * self.len
+    }
+}
+
+/// The name of the constant holding the offset of a field.
+pub fn field_constant_name(struct_name: &str, field_name: &str) -> String {
+    format!("{struct_name}.{field_name}")
+}
+
+pub(super) fn layout_struct(
+    binary: &Binary,
+    mpstruct: &MpStruct,
+    file_tag: Rc<str>,
+) -> MipsyResult<StructLayout> {
+    let mut fields = vec![];
+    let mut offset = 0u32;
+    let mut struct_align = 1;
+
+    for field in mpstruct.fields() {
+        let count = |repeats: Vec<&Option<MpConstValueLoc>>| -> MipsyResult<u32> {
+            if repeats.is_empty() {
+                return Ok(1);
+            }
+
+            repeats.into_iter().try_fold(0u32, |total, repeat| {
+                let repeat = match repeat {
+                    Some(repeat) => eval_constant_in_range(
+                        repeat,
+                        u32::MIN as _,
+                        u32::MAX as _,
+                        binary,
+                        file_tag.clone(),
+                    )? as u32,
+                    None => 1,
+                };
+
+                Ok(total.saturating_add(repeat))
+            })
+        };
+
+        let (kind, len) = match field.directive() {
+            MpDirective::Byte(values) => (
+                FieldKind::Byte,
+                count(values.iter().map(|(_, n)| n).collect())?,
+            ),
+            MpDirective::Half(values) => (
+                FieldKind::Half,
+                count(values.iter().map(|(_, n)| n).collect())?,
+            ),
+            MpDirective::Word(values) => (
+                FieldKind::Word,
+                count(values.iter().map(|(_, n)| n).collect())?,
+            ),
+            MpDirective::Float(values) => (
+                FieldKind::Float,
+                count(values.iter().map(|(_, n)| n).collect())?,
+            ),
+            MpDirective::Double(values) => (
+                FieldKind::Double,
+                count(values.iter().map(|(_, n)| n).collect())?,
+            ),
+            MpDirective::Ascii(string) => (FieldKind::String, string.chars().count() as u32),
+            MpDirective::Asciiz(string) => (FieldKind::String, string.chars().count() as u32 + 1),
+            MpDirective::Space(num) => (
+                FieldKind::Space,
+                eval_constant_in_range(num, u32::MIN as _, u32::MAX as _, binary, file_tag.clone())?
+                    as u32,
+            ),
+            MpDirective::Align(num) => {
+                let num = eval_constant_in_range(num, u32::MIN as _, 31, binary, file_tag.clone())?
+                    as u32;
+                let multiple = 2u32.pow(num);
+
+                offset = align_to(offset, multiple);
+                struct_align = struct_align.max(multiple);
+
+                (FieldKind::Space, 0)
+            }
+            directive @ (MpDirective::Text
+            | MpDirective::Data
+            | MpDirective::KText
+            | MpDirective::KData
+            | MpDirective::Globl(_)
+            | MpDirective::Set(_)) => {
+                let position = field.position();
+
+                return Err(MipsyError::Compiler(CompilerError::new(
+                    Error::DirectiveInStruct {
+                        directive_type: directive.clone(),
+                    },
+                    file_tag,
+                    position.line(),
+                    position.col(),
+                    position.col_end(),
+                )));
+            }
+        };
+
+        offset = align_to(offset, kind.size());
+        struct_align = struct_align.max(kind.size());
+
+        if let Some(name) = field.name() {
+            fields.push(StructField {
+                name: name.to_string(),
+                offset,
+                kind,
+                len,
+            });
+        }
+
+        offset = offset.saturating_add(kind.size().saturating_mul(len));
+    }
+
+    Ok(StructLayout {
+        name: mpstruct.name().to_string(),
+        fields,
+        size: align_to(offset, struct_align),
+    })
+}
+
+fn align_to(offset: u32, multiple: u32) -> u32 {
+    offset.saturating_add((multiple - offset % multiple) % multiple)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        compile::compile_with_kernel, error::compiler, CompileResult, CompilerOptions, InstSet,
+        Safe,
+    };
+    use mipsy_parser::{MpProgram, TaggedFile};
+    use mipsy_utils::MipsyConfig;
+
+    fn compile(source: &str) -> CompileResult<Binary> {
+        let mut program = mipsy_parser::parse_mips(vec![TaggedFile::new(None, source)], 8).unwrap();
+        let mut kernel = MpProgram::new(vec![], vec![]);
+
+        let (binary, _) = compile_with_kernel(
+            &mut program,
+            &mut kernel,
+            &CompilerOptions::new(vec![]),
+            &MipsyConfig::default(),
+            &InstSet::new(vec![], vec![]),
+        )?;
+
+        Ok(binary)
+    }
+
+    #[test]
+    fn test_struct_offsets() {
+        let binary = compile(
+            "\
+            .struct Node\n\
+            \x20   tag:  .byte\n\
+            \x20   next: .word\n\
+            \x20   name: .asciiz \"abc\"\n\
+            \x20   vals: .half 0:3\n\
+            .end_struct\n\
+            \n\
+            .data\n\
+            nodes: .space Node.size * 2\n\
+            sizes: .word Node.size, Node.vals\n\
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(binary.constants.get("Node.tag"), Some(&0));
+        assert_eq!(binary.constants.get("Node.next"), Some(&4));
+        assert_eq!(binary.constants.get("Node.name"), Some(&8));
+        assert_eq!(binary.constants.get("Node.vals"), Some(&12));
+        assert_eq!(binary.constants.get("Node.size"), Some(&20));
+
+        let layout = &binary.structs["Node"];
+        assert_eq!(layout.size, 20);
+        assert_eq!(layout.field("vals").unwrap().kind, FieldKind::Half);
+        assert_eq!(layout.field("vals").unwrap().len, 3);
+
+        assert_eq!(binary.labels.get("sizes"), Some(&(crate::DATA_BOT + 40)));
+        assert_eq!(binary.data[40..44], [20, 0, 0, 0].map(Safe::Valid));
+        assert_eq!(binary.data[44..48], [12, 0, 0, 0].map(Safe::Valid));
+    }
+
+    #[test]
+    fn test_struct_align_and_constants() {
+        let binary = compile(
+            "\
+            LEN = 5\n\
+            .struct Buf\n\
+            \x20   data: .space LEN\n\
+            \x20   .align 3\n\
+            \x20   end: .space 0\n\
+            .end_struct\n\
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(binary.constants.get("Buf.data"), Some(&0));
+        assert_eq!(binary.constants.get("Buf.end"), Some(&8));
+        assert_eq!(binary.constants.get("Buf.size"), Some(&8));
+    }
+
+    #[test]
+    fn test_struct_errors() {
+        let error = |source| match compile(source) {
+            Err(errors) => match &errors[..] {
+                [MipsyError::Compiler(error)] => error.error().clone(),
+                other => panic!("expected a compiler error, got {other:?}"),
+            },
+            Ok(_) => panic!("expected a compiler error"),
+        };
+
+        assert!(matches!(
+            error(".struct S\n  a: .text\n.end_struct"),
+            compiler::Error::DirectiveInStruct { .. }
+        ));
+        assert!(matches!(
+            error(".struct S\n  size: .word\n.end_struct"),
+            compiler::Error::RedefinedConstant { .. }
+        ));
+    }
+}
@@ -1,17 +1,65 @@
 use std::rc::Rc;
 
-use super::{bytes::ToBytes, data::Segment, Binary};
+use super::{
+    bytes::ToBytes,
+    data::Segment,
+    profile::{Profiles, Restriction},
+    Binary,
+};
 use crate::inst::instruction::InstSet;
 use crate::inst::instruction::SignatureRef;
+use crate::MpProgram;
 use crate::{
-    error::{compiler, InternalError, MipsyInternalResult, ToMipsyResult},
+    error::{compiler, CompileResult, InternalError, MipsyInternalResult, ToMipsyResult},
     Safe, KTEXT_BOT, TEXT_BOT,
 };
-use crate::{MipsyResult, MpProgram};
 use mipsy_parser::{MpInstruction, MpItem};
 use mipsy_utils::MipsyConfig;
 
+/// Finds the instruction that `inst` refers to, as long as `restriction` allows it.
 pub fn find_instruction<'a>(
+    iset: &'a InstSet,
+    restriction: Option<&Restriction<'_>>,
+    inst: &MpInstruction,
+) -> MipsyInternalResult<SignatureRef<'a>> {
+    let found = lookup_instruction(iset, inst)?;
+    let is_pseudo = matches!(found, SignatureRef::Pseudo(_));
+
+    match restriction {
+        Some(restriction) if !restriction.profile.allows(inst.name(), is_pseudo) => {
+            let instead = match found {
+                SignatureRef::Pseudo(pseudo) => pseudo.expansion_source(inst),
+                SignatureRef::Native(_) => native_instead(inst),
+            };
+
+            Err(InternalError::Compiler(
+                compiler::Error::ForbiddenInstruction {
+                    inst_ast: inst.clone(),
+                    profile: restriction.name.to_string(),
+                    instead,
+                },
+            ))
+        }
+        _ => Ok(found),
+    }
+}
+
+/// Another way to write one of the few native instructions that has one,
+/// for when a profile doesn't allow it.
+fn native_instead(inst: &MpInstruction) -> Vec<String> {
+    let args = inst
+        .arguments()
+        .iter()
+        .map(|(arg, _, _)| arg.to_string())
+        .collect::<Vec<_>>();
+
+    match (inst.name(), &args[..]) {
+        ("mul", [rd, rs, rt]) => vec![format!("mult {rs}, {rt}"), format!("mflo {rd}")],
+        _ => vec![],
+    }
+}
+
+fn lookup_instruction<'a>(
     iset: &'a InstSet,
     inst: &MpInstruction,
 ) -> MipsyInternalResult<SignatureRef<'a>> {
@@ -64,7 +112,8 @@ pub fn find_instruction<'a>(
 }
 
 pub fn instruction_length(iset: &InstSet, inst: &MpInstruction) -> MipsyInternalResult<usize> {
-    Ok(match find_instruction(iset, inst)? {
+    // the length doesn't depend on whether the instruction is allowed
+    Ok(match find_instruction(iset, None, inst)? {
         SignatureRef::Native(_) => 1,
         SignatureRef::Pseudo(pseudo) => pseudo.expansion().len(),
     })
@@ -73,18 +122,21 @@ pub fn instruction_length(iset: &InstSet, inst: &MpInstruction) -> MipsyInternal
 pub fn compile1(
     binary: &Binary,
     iset: &InstSet,
+    restriction: Option<&Restriction<'_>>,
     inst: &MpInstruction,
 ) -> MipsyInternalResult<Vec<u32>> {
-    find_instruction(iset, inst)?.compile_ops(binary, iset, inst)
+    find_instruction(iset, restriction, inst)?.compile_ops(binary, iset, inst)
 }
 
-pub fn populate_text(
+pub(crate) fn populate_text(
     binary: &mut Binary,
     iset: &InstSet,
     config: &MipsyConfig,
+    profiles: &Profiles<'_>,
     program: &MpProgram,
-) -> MipsyResult<()> {
+) -> CompileResult<()> {
     let mut segment = Segment::Text;
+    let mut errors = vec![];
 
     for attributed_item in program.items() {
         let line = attributed_item.line_number();
@@ -100,8 +152,17 @@ pub fn populate_text(
                     config,
                     file_tag.clone(),
                     &mut segment,
-                    false,
-                )?;
+                    None,
+                );
+
+                let bytes = match bytes {
+                    Ok(bytes) => bytes,
+                    Err(err) => {
+                        errors.push(err.with_expansion(attributed_item.expansion()));
+                        continue;
+                    }
+                };
+
                 match segment {
                     Segment::Text => {
                         binary.text.extend(bytes);
@@ -114,12 +175,26 @@ pub fn populate_text(
                 }
             }
             MpItem::Instruction(ref instruction) => {
-                let compiled = compile1(binary, iset, instruction).into_compiler_mipsy_result(
+                let compiled = compile1(
+                    binary,
+                    iset,
+                    profiles.get(&attributed_item.unit()),
+                    instruction,
+                )
+                .into_compiler_mipsy_result(
                     file_tag.clone(),
                     line,
                     instruction.col(),
                     instruction.col_end(),
-                )?;
+                );
+
+                let compiled = match compiled {
+                    Ok(compiled) => compiled,
+                    Err(err) => {
+                        errors.push(err.with_expansion(attributed_item.expansion()));
+                        continue;
+                    }
+                };
 
                 let text = match segment {
                     Segment::Text => {
@@ -163,8 +238,13 @@ pub fn populate_text(
             }
             MpItem::Label(_) => {}
             MpItem::Constant(_) => {}
+            MpItem::Struct(_) => {}
         }
     }
 
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
     Ok(())
 }
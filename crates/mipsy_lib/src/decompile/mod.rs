@@ -3,7 +3,8 @@ use std::{collections::HashMap, rc::Rc};
 use crate::inst::instruction::{ArgumentType, CompileSignature, InstSet, RuntimeSignature};
 use crate::inst::register::Register;
 use crate::inst::RuntimeMetadata;
-use crate::{Binary, Safe};
+use crate::{compile::source_label_name, Binary, Safe};
+use mipsy_parser::is_local_label;
 
 pub struct Decompiled<'a> {
     pub opcode: u32,
@@ -89,7 +90,7 @@ pub fn decompile_into_parts<'a>(
 
             for (label, &addr) in program.labels.iter() {
                 if addr == text_addr {
-                    labels.push(label.to_string());
+                    labels.push(source_label_name(label).to_string());
                 }
             }
 
@@ -128,7 +129,7 @@ pub fn decompile_inst_into_parts<'a>(
 
     for (label, &addr) in program.labels.iter() {
         if addr == text_addr {
-            parts.labels.push(label.to_string());
+            parts.labels.push(source_label_name(label).to_string());
         }
     }
 
@@ -256,7 +257,11 @@ pub fn decompile_inst_into_parts<'a>(
                             }
 
                             if let Some(label) = res {
-                                label.to_string()
+                                reference_name(
+                                    label,
+                                    text_addr.wrapping_add((imm as i32 * 4) as u32),
+                                    text_addr,
+                                )
                             } else {
                                 imm.to_string()
                             }
@@ -273,7 +278,7 @@ pub fn decompile_inst_into_parts<'a>(
                             }
 
                             j_label
-                                .map(|label| label.to_string())
+                                .map(|label| reference_name(label, j_addr, text_addr))
                                 .unwrap_or(format!("{:08x}", j_addr))
                         }
                         _ => unreachable!(),
@@ -286,3 +291,81 @@ pub fn decompile_inst_into_parts<'a>(
 
     parts
 }
+
+/// How an instruction at `text_addr` would refer to a label:
+/// numeric local labels are written as `1b` or `1f`.
+fn reference_name(label: &str, label_addr: u32, text_addr: u32) -> String {
+    let source_label = source_label_name(label);
+
+    if !is_local_label(source_label) {
+        source_label.to_string()
+    } else if label_addr <= text_addr {
+        format!("{}b", source_label)
+    } else {
+        format!("{}f", source_label)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        compile::compile_with_kernel,
+        inst::instruction::{InstMetadata, InstSignature},
+        CompilerOptions,
+    };
+    use mipsy_parser::{MpProgram, TaggedFile};
+    use mipsy_utils::MipsyConfig;
+
+    fn iset() -> InstSet {
+        let jump = |name: &str, opcode| {
+            InstSignature::new(
+                name.to_string(),
+                CompileSignature::new(vec![ArgumentType::J], false),
+                RuntimeSignature::J { opcode },
+                RuntimeMetadata::new(vec![]),
+                InstMetadata::new(None, None),
+            )
+        };
+
+        InstSet::new(vec![jump("j", 2), jump("jal", 3)], vec![])
+    }
+
+    #[test]
+    fn test_label_references() {
+        let files = vec![
+            TaggedFile::new(
+                Some("a.s"),
+                "main:\n  jal helper\n1:\n  j 1b\nhelper:\n  j 1f\n1:\n  j 1b\n",
+            ),
+            TaggedFile::new(Some("b.s"), "helper:\n  j helper\n"),
+        ];
+        let mut program = mipsy_parser::parse_mips(files, 8).unwrap();
+
+        let (binary, _) = compile_with_kernel(
+            &mut program,
+            &mut MpProgram::new(vec![], vec![]),
+            &CompilerOptions::new(vec![]),
+            &MipsyConfig::default(),
+            &iset(),
+        )
+        .unwrap();
+
+        let references = decompile(&binary, &iset())
+            .lines()
+            .filter(|line| line.starts_with("0x"))
+            .map(|line| {
+                line.split_whitespace()
+                    .skip(2)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>();
+
+        // private labels go by their own name, and only numeric labels are `b` or `f`
+        assert_eq!(
+            references,
+            vec!["jal helper", "j 1b", "j 1f", "j 1b", "j helper"]
+        );
+    }
+}
@@ -0,0 +1,252 @@
+//! 32-bit little-endian MIPS ELF executables, so that mipsy's output can be
+//! inspected with `readelf` and `objdump`, and so that programs linked by a
+//! MIPS GNU toolchain can be run by mipsy.
+
+use std::fmt::Display;
+
+mod read;
+mod write;
+
+pub use read::read_elf;
+pub use write::write_elf;
+
+pub const MAGIC: &[u8] = b"\x7fELF";
+
+const ELFCLASS32: u8 = 1;
+const ELFDATA2LSB: u8 = 1;
+const EV_CURRENT: u8 = 1;
+
+const ET_REL: u16 = 1;
+const ET_EXEC: u16 = 2;
+const EM_MIPS: u16 = 8;
+
+/// `EF_MIPS_ARCH_32 | EF_MIPS_ABI_O32`
+const MIPS_FLAGS: u32 = 0x5000_1000;
+
+const EHDR_SIZE: usize = 52;
+const PHDR_SIZE: usize = 32;
+const SHDR_SIZE: usize = 40;
+const SYM_SIZE: usize = 16;
+
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHF_WRITE: u32 = 1;
+const SHF_ALLOC: u32 = 2;
+const SHF_EXECINSTR: u32 = 4;
+
+const SHN_UNDEF: u16 = 0;
+const SHN_ABS: u16 = 0xfff1;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STB_WEAK: u8 = 2;
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_SECTION: u8 = 3;
+const STT_FILE: u8 = 4;
+
+/// The most memory a single segment can ask for, which stops a
+/// malformed file from making mipsy allocate gigabytes.
+const MAX_SEGMENT_SIZE: u32 = 16 << 20;
+
+/// Whether a file looks like an ELF file, rather than assembly source.
+pub fn is_elf(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ElfError {
+    Truncated,
+    NotElf,
+    NotElf32,
+    BigEndian,
+    NotMips(u16),
+    Relocatable,
+    NotExecutable(u16),
+    NoSegments,
+    SegmentOutOfRange { addr: u32, size: u32 },
+    SegmentTooLarge { addr: u32, size: u32 },
+}
+
+impl Display for ElfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ElfError::Truncated => write!(f, "the file is truncated"),
+            ElfError::NotElf => write!(f, "the file is not an ELF file"),
+            ElfError::NotElf32 => write!(f, "only 32-bit ELF files are supported"),
+            ElfError::BigEndian => write!(
+                f,
+                "only little-endian executables are supported (try a `mipsel` toolchain)"
+            ),
+            ElfError::NotMips(machine) => {
+                write!(f, "the executable is not for MIPS (machine type {machine})")
+            }
+            ElfError::Relocatable => write!(
+                f,
+                "the file is an object file that hasn't been linked into an executable yet"
+            ),
+            ElfError::NotExecutable(kind) => {
+                write!(f, "the file is not an executable (ELF type {kind})")
+            }
+            ElfError::NoSegments => write!(f, "the executable has nothing to load"),
+            ElfError::SegmentOutOfRange { addr, size } => write!(
+                f,
+                "a segment at 0x{addr:08x} ({size} bytes) is outside of the text, data, ktext and kdata segments \
+                 (try linking with `-Ttext=0x00400000 -Tdata=0x10010000`)"
+            ),
+            ElfError::SegmentTooLarge { addr, size } => write!(
+                f,
+                "a segment at 0x{addr:08x} ({size} bytes) is larger than the {} MiB that mipsy can load",
+                MAX_SEGMENT_SIZE >> 20
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{compile::source_label_name, Binary, Safe, DATA_BOT, KTEXT_BOT, TEXT_BOT};
+    use std::{collections::HashMap, rc::Rc};
+
+    fn binary() -> Binary {
+        let mut binary = Binary {
+            text: [0x20, 0x00, 0x02, 0x24, 0x0c, 0x00, 0x00, 0x00]
+                .into_iter()
+                .map(Safe::Valid)
+                .collect(),
+            data: vec![Safe::Valid(b'h'), Safe::Valid(b'i'), Safe::Uninitialised],
+            ktext: vec![Safe::Valid(0); 4],
+            constants: HashMap::from([("SIZE".to_string(), -4)]),
+            globals: vec!["main".to_string()],
+            line_numbers: HashMap::from([(TEXT_BOT, (Rc::from("prog.s"), 3))]),
+            ..Default::default()
+        };
+
+        binary.insert_label("main", TEXT_BOT);
+        binary.insert_label("end", TEXT_BOT + 8);
+        binary.insert_label("msg", DATA_BOT);
+        binary.insert_label("_start", KTEXT_BOT);
+
+        binary
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let binary = binary();
+        let (read, entry) = read_elf(&write_elf(&binary)).unwrap();
+
+        // the kernel runs first
+        assert_eq!(entry, KTEXT_BOT);
+
+        assert_eq!(read.text, binary.text);
+        assert_eq!(read.ktext, binary.ktext);
+        // uninitialised bytes are written as zeroes
+        assert_eq!(
+            read.data,
+            vec![Safe::Valid(b'h'), Safe::Valid(b'i'), Safe::Valid(0)]
+        );
+        assert!(read.kdata.is_empty());
+
+        // local symbols come before global ones, so the order can change
+        assert_eq!(
+            read.labels.into_iter().collect::<HashMap<_, _>>(),
+            binary.labels.into_iter().collect::<HashMap<_, _>>()
+        );
+        assert_eq!(read.constants, binary.constants);
+        assert_eq!(read.globals, binary.globals);
+    }
+
+    #[test]
+    fn test_renamed_labels() {
+        let mut binary = binary();
+        binary.ktext.clear();
+        binary.insert_label("1~0", TEXT_BOT);
+        binary.insert_label("1~1", TEXT_BOT + 4);
+        binary.insert_label("loop@a.s", TEXT_BOT + 4);
+        binary.insert_label("loop@b.s", TEXT_BOT + 8);
+
+        let elf = write_elf(&binary);
+        assert!(!elf.windows(2).any(|bytes| bytes == b"~0" || bytes == b"@a"));
+
+        let (read, entry) = read_elf(&elf).unwrap();
+        assert_eq!(entry, TEXT_BOT);
+
+        // each is read back under a name of its own, which shows as the source name
+        let mut labels = read
+            .labels
+            .iter()
+            .filter(|(label, _)| label.starts_with('1') || label.starts_with("loop"))
+            .map(|(label, &addr)| (source_label_name(label), addr))
+            .collect::<Vec<_>>();
+        labels.sort();
+
+        assert_eq!(
+            labels,
+            vec![
+                ("1", TEXT_BOT),
+                ("1", TEXT_BOT + 4),
+                ("loop", TEXT_BOT + 4),
+                ("loop", TEXT_BOT + 8)
+            ]
+        );
+        assert_eq!(read.globals, vec!["main".to_string()]);
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let elf = write_elf(&binary());
+
+        assert_eq!(read_elf(b"\x7fELF"), Err(ElfError::Truncated));
+        assert_eq!(read_elf(b"main:\n  jr $ra\n"), Err(ElfError::NotElf));
+
+        let mut big_endian = elf.clone();
+        big_endian[5] = 2;
+        assert_eq!(read_elf(&big_endian), Err(ElfError::BigEndian));
+
+        let mut x86 = elf.clone();
+        x86[18] = 3;
+        assert_eq!(read_elf(&x86), Err(ElfError::NotMips(3)));
+
+        let mut relocatable = elf;
+        relocatable[16] = 1;
+        assert_eq!(read_elf(&relocatable), Err(ElfError::Relocatable));
+    }
+
+    #[test]
+    fn test_malformed_headers() {
+        let elf = write_elf(&binary());
+        let set = |elf: &mut Vec<u8>, offset: usize, value: u32| {
+            elf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        };
+        let phdr = u32::from_le_bytes(elf[28..32].try_into().unwrap()) as usize;
+
+        let mut phoff = elf.clone();
+        set(&mut phoff, 28, u32::MAX);
+        assert_eq!(read_elf(&phoff), Err(ElfError::Truncated));
+
+        let mut shoff = elf.clone();
+        set(&mut shoff, 32, u32::MAX);
+        assert_eq!(read_elf(&shoff), Err(ElfError::Truncated));
+
+        let mut offset = elf.clone();
+        set(&mut offset, phdr + 4, u32::MAX);
+        assert_eq!(read_elf(&offset), Err(ElfError::Truncated));
+
+        let mut mem_size = elf;
+        set(&mut mem_size, phdr + 20, u32::MAX);
+        assert_eq!(
+            read_elf(&mem_size),
+            Err(ElfError::SegmentTooLarge {
+                addr: TEXT_BOT,
+                size: u32::MAX,
+            })
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use super::*;
+use crate::{
+    compile::{local_label_name, source_label_name},
+    util::{get_segment, Segment},
+    Binary, Safe, DATA_BOT, HEAP_BOT,
+};
+
+/// Builds a binary from an ELF executable, such as one linked by a MIPS GNU toolchain,
+/// along with the address that it starts at.
+///
+/// Each loadable segment has to sit inside one of mipsy's text, data,
+/// ktext or kdata segments. Symbols become labels (or constants, if
+/// they're absolute), but line information isn't read back in.
+pub fn read_elf(elf: &[u8]) -> Result<(Binary, u32), ElfError> {
+    if !is_elf(elf) {
+        return Err(ElfError::NotElf);
+    }
+
+    if elf.len() < EHDR_SIZE {
+        return Err(ElfError::Truncated);
+    }
+
+    if elf[4] != ELFCLASS32 {
+        return Err(ElfError::NotElf32);
+    }
+
+    if elf[5] != ELFDATA2LSB {
+        return Err(ElfError::BigEndian);
+    }
+
+    let kind = u16_at(elf, 16)?;
+    let machine = u16_at(elf, 18)?;
+
+    if machine != EM_MIPS {
+        return Err(ElfError::NotMips(machine));
+    }
+
+    match kind {
+        ET_EXEC => {}
+        ET_REL => return Err(ElfError::Relocatable),
+        _ => return Err(ElfError::NotExecutable(kind)),
+    }
+
+    let mut binary = Binary::default();
+
+    let entry = u32_at(elf, 24)?;
+    let phoff = u32_at(elf, 28)? as usize;
+    let phentsize = u16_at(elf, 42)? as usize;
+    let phnum = u16_at(elf, 44)? as usize;
+
+    let mut loaded = false;
+    for i in 0..phnum {
+        let phdr = table_entry(phoff, i, phentsize)?;
+
+        if u32_at(elf, phdr)? != PT_LOAD {
+            continue;
+        }
+
+        let offset = u32_at(elf, phdr + 4)? as usize;
+        let addr = u32_at(elf, phdr + 8)?;
+        let file_size = u32_at(elf, phdr + 16)? as usize;
+        let mem_size = u32_at(elf, phdr + 20)?;
+
+        if mem_size == 0 {
+            continue;
+        }
+
+        if mem_size > MAX_SEGMENT_SIZE {
+            return Err(ElfError::SegmentTooLarge {
+                addr,
+                size: mem_size,
+            });
+        }
+
+        let out_of_range = ElfError::SegmentOutOfRange {
+            addr,
+            size: mem_size,
+        };
+
+        let segment = get_segment(addr);
+        let end = addr.checked_add(mem_size - 1).ok_or(out_of_range.clone())?;
+        if segment != get_segment(end) {
+            return Err(out_of_range);
+        }
+
+        let (bot, bytes) = match segment {
+            Segment::Text => (segment.get_lower_bound(), &mut binary.text),
+            Segment::Data if addr >= DATA_BOT && end < HEAP_BOT => (DATA_BOT, &mut binary.data),
+            Segment::KText => (segment.get_lower_bound(), &mut binary.ktext),
+            Segment::KData => (segment.get_lower_bound(), &mut binary.kdata),
+            _ => return Err(out_of_range),
+        };
+
+        let contents = bytes_at(elf, offset, file_size)?;
+
+        let start = (addr - bot) as usize;
+        let end = start + mem_size as usize;
+        if bytes.len() < end {
+            bytes.resize(end, Safe::Uninitialised);
+        }
+
+        // anything past the file's contents is zeroed, like .bss
+        for (i, byte) in bytes[start..end].iter_mut().enumerate() {
+            *byte = Safe::Valid(contents.get(i).copied().unwrap_or(0));
+        }
+
+        loaded = true;
+    }
+
+    if !loaded {
+        return Err(ElfError::NoSegments);
+    }
+
+    read_symbols(elf, &mut binary)?;
+
+    Ok((binary, entry))
+}
+
+fn read_symbols(elf: &[u8], binary: &mut Binary) -> Result<(), ElfError> {
+    let shoff = u32_at(elf, 32)? as usize;
+    let shentsize = u16_at(elf, 46)? as usize;
+    let shnum = u16_at(elf, 48)? as usize;
+
+    let section = |index: usize| -> Result<(u32, &[u8], usize), ElfError> {
+        let shdr = table_entry(shoff, index, shentsize)?;
+
+        let kind = u32_at(elf, shdr + 4)?;
+        let offset = u32_at(elf, shdr + 16)? as usize;
+        let size = u32_at(elf, shdr + 20)? as usize;
+        let link = u32_at(elf, shdr + 24)? as usize;
+        let contents = bytes_at(elf, offset, size)?;
+
+        Ok((kind, contents, link))
+    };
+
+    for index in 0..shnum {
+        let (kind, symtab, link) = section(index)?;
+        if kind != SHT_SYMTAB {
+            continue;
+        }
+
+        let (_, strtab, _) = section(link)?;
+
+        // skip the null symbol
+        for symbol in symtab.chunks_exact(SYM_SIZE).skip(1) {
+            let name = u32_at(symbol, 0)? as usize;
+            let value = u32_at(symbol, 4)?;
+            let info = symbol[12];
+            let shndx = u16_at(symbol, 14)?;
+
+            let name = strtab.get(name..).ok_or(ElfError::Truncated)?;
+            let name = match name.iter().position(|&byte| byte == 0) {
+                Some(len) => String::from_utf8_lossy(&name[..len]).to_string(),
+                None => return Err(ElfError::Truncated),
+            };
+
+            let (bind, kind) = (info >> 4, info & 0xf);
+            if name.is_empty() || shndx == SHN_UNDEF || kind == STT_SECTION || kind == STT_FILE {
+                continue;
+            }
+
+            if shndx == SHN_ABS {
+                binary.constants.insert(name, value as i32 as i64);
+                continue;
+            }
+
+            if bind == STB_GLOBAL || bind == STB_WEAK {
+                binary.globals.push(name.clone());
+            }
+
+            // local symbols can share a name (from `1:` labels, or a private
+            // label in each file), so each one after the first gets its own
+            let name = if binary.labels.contains_key(&name) {
+                let defined = binary
+                    .labels
+                    .keys()
+                    .filter(|label| source_label_name(label) == name)
+                    .count();
+
+                local_label_name(&name, defined)
+            } else {
+                name
+            };
+
+            binary.insert_label(&name, value);
+        }
+    }
+
+    Ok(())
+}
+
+/// The offset of entry `index` of a header table, which a malformed file
+/// could put past the end of the address space.
+fn table_entry(table: usize, index: usize, entry_size: usize) -> Result<usize, ElfError> {
+    index
+        .checked_mul(entry_size)
+        .and_then(|offset| offset.checked_add(table))
+        .ok_or(ElfError::Truncated)
+}
+
+fn bytes_at(bytes: &[u8], offset: usize, len: usize) -> Result<&[u8], ElfError> {
+    let end = offset.checked_add(len).ok_or(ElfError::Truncated)?;
+
+    bytes.get(offset..end).ok_or(ElfError::Truncated)
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Result<u16, ElfError> {
+    let bytes = bytes_at(bytes, offset, 2)?;
+
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u32, ElfError> {
+    let bytes = bytes_at(bytes, offset, 4)?;
+
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
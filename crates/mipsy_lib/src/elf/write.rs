@@ -0,0 +1,399 @@
+use std::collections::BTreeMap;
+
+use super::*;
+use crate::{compile::source_label_name, Binary, DATA_BOT, KDATA_BOT, KTEXT_BOT, TEXT_BOT};
+
+const PAGE_SIZE: u32 = 0x1000;
+
+struct Section {
+    name: &'static str,
+    kind: u32,
+    flags: u32,
+    addr: u32,
+    bytes: Vec<u8>,
+    link: u32,
+    info: u32,
+    align: u32,
+    entsize: u32,
+}
+
+struct Symbol {
+    name: String,
+    value: u32,
+    info: u8,
+    shndx: u16,
+}
+
+/// Writes a binary as an ELF executable, with a section for each segment,
+/// a symbol table of its labels and constants, and a `.debug_line` table
+/// of the source line that each instruction came from.
+///
+/// Uninitialised bytes are written as zeroes.
+pub fn write_elf(binary: &Binary) -> Vec<u8> {
+    let segments = [
+        (".text", TEXT_BOT, &binary.text, SHF_EXECINSTR),
+        (".data", DATA_BOT, &binary.data, SHF_WRITE),
+        (".ktext", KTEXT_BOT, &binary.ktext, SHF_EXECINSTR),
+        (".kdata", KDATA_BOT, &binary.kdata, SHF_WRITE),
+    ];
+
+    let mut sections = vec![];
+    for (name, addr, segment, flags) in segments {
+        if segment.is_empty() {
+            continue;
+        }
+
+        sections.push(Section {
+            name,
+            kind: SHT_PROGBITS,
+            flags: SHF_ALLOC | flags,
+            addr,
+            bytes: segment
+                .iter()
+                .map(|byte| byte.into_option().unwrap_or(0))
+                .collect(),
+            link: 0,
+            info: 0,
+            align: PAGE_SIZE,
+            entsize: 0,
+        });
+    }
+    let n_segments = sections.len();
+
+    let (symbols, first_global) = symbols(binary, &sections);
+    let mut strtab = vec![0];
+    let mut symtab = vec![0; SYM_SIZE];
+    for symbol in symbols {
+        put_u32(&mut symtab, strtab.len() as u32);
+        put_u32(&mut symtab, symbol.value);
+        put_u32(&mut symtab, 0);
+        symtab.push(symbol.info);
+        symtab.push(0);
+        put_u16(&mut symtab, symbol.shndx);
+
+        strtab.extend(symbol.name.bytes());
+        strtab.push(0);
+    }
+
+    // the null section comes first, then the symbol table
+    let strtab_index = sections.len() as u32 + 2;
+    sections.push(Section {
+        name: ".symtab",
+        kind: SHT_SYMTAB,
+        flags: 0,
+        addr: 0,
+        bytes: symtab,
+        link: strtab_index,
+        info: first_global,
+        align: 4,
+        entsize: SYM_SIZE as u32,
+    });
+    sections.push(Section {
+        name: ".strtab",
+        kind: SHT_STRTAB,
+        flags: 0,
+        addr: 0,
+        bytes: strtab,
+        link: 0,
+        info: 0,
+        align: 1,
+        entsize: 0,
+    });
+
+    if !binary.line_numbers.is_empty() {
+        sections.push(Section {
+            name: ".debug_line",
+            kind: SHT_PROGBITS,
+            flags: 0,
+            addr: 0,
+            bytes: debug_line(binary),
+            link: 0,
+            info: 0,
+            align: 1,
+            entsize: 0,
+        });
+    }
+
+    let mut shstrtab = vec![0];
+    let mut names = vec![];
+    for section in sections.iter() {
+        names.push(shstrtab.len() as u32);
+        shstrtab.extend(section.name.bytes());
+        shstrtab.push(0);
+    }
+    names.push(shstrtab.len() as u32);
+    shstrtab.extend(b".shstrtab\0");
+
+    let shstrndx = sections.len() as u16 + 1;
+    sections.push(Section {
+        name: ".shstrtab",
+        kind: SHT_STRTAB,
+        flags: 0,
+        addr: 0,
+        bytes: shstrtab,
+        link: 0,
+        info: 0,
+        align: 1,
+        entsize: 0,
+    });
+
+    // lay out the file: headers, then each section, then the section headers
+    let mut offset = (EHDR_SIZE + n_segments * PHDR_SIZE) as u32;
+    let mut offsets = vec![];
+    for section in sections.iter() {
+        offset = align(offset, section.align);
+        offsets.push(offset);
+        offset += section.bytes.len() as u32;
+    }
+    let shoff = align(offset, 4);
+
+    let entry = if !binary.ktext.is_empty() {
+        KTEXT_BOT
+    } else {
+        binary.labels.get("main").copied().unwrap_or(TEXT_BOT)
+    };
+
+    let mut elf = vec![];
+    elf.extend(MAGIC);
+    elf.extend([ELFCLASS32, ELFDATA2LSB, EV_CURRENT]);
+    elf.resize(16, 0);
+    put_u16(&mut elf, ET_EXEC);
+    put_u16(&mut elf, EM_MIPS);
+    put_u32(&mut elf, EV_CURRENT as u32);
+    put_u32(&mut elf, entry);
+    put_u32(&mut elf, EHDR_SIZE as u32);
+    put_u32(&mut elf, shoff);
+    put_u32(&mut elf, MIPS_FLAGS);
+    put_u16(&mut elf, EHDR_SIZE as u16);
+    put_u16(&mut elf, PHDR_SIZE as u16);
+    put_u16(&mut elf, n_segments as u16);
+    put_u16(&mut elf, SHDR_SIZE as u16);
+    put_u16(&mut elf, sections.len() as u16 + 1);
+    put_u16(&mut elf, shstrndx);
+
+    for (section, &offset) in sections.iter().zip(offsets.iter()).take(n_segments) {
+        let flags = if section.flags & SHF_EXECINSTR != 0 {
+            PF_R | PF_X
+        } else {
+            PF_R | PF_W
+        };
+
+        put_u32(&mut elf, PT_LOAD);
+        put_u32(&mut elf, offset);
+        put_u32(&mut elf, section.addr);
+        put_u32(&mut elf, section.addr);
+        put_u32(&mut elf, section.bytes.len() as u32);
+        put_u32(&mut elf, section.bytes.len() as u32);
+        put_u32(&mut elf, flags);
+        put_u32(&mut elf, PAGE_SIZE);
+    }
+
+    for (section, &offset) in sections.iter().zip(offsets.iter()) {
+        elf.resize(offset as usize, 0);
+        elf.extend(&section.bytes);
+    }
+    elf.resize(shoff as usize, 0);
+
+    elf.extend([0; SHDR_SIZE]);
+    for ((section, &offset), &name) in sections.iter().zip(offsets.iter()).zip(names.iter()) {
+        put_u32(&mut elf, name);
+        put_u32(&mut elf, section.kind);
+        put_u32(&mut elf, section.flags);
+        put_u32(&mut elf, section.addr);
+        put_u32(&mut elf, offset);
+        put_u32(&mut elf, section.bytes.len() as u32);
+        put_u32(&mut elf, section.link);
+        put_u32(&mut elf, section.info);
+        put_u32(&mut elf, section.align);
+        put_u32(&mut elf, section.entsize);
+    }
+
+    elf
+}
+
+/// The symbols for each label and constant, with the local symbols first
+/// (as ELF requires), along with the index of the first global symbol.
+fn symbols(binary: &Binary, sections: &[Section]) -> (Vec<Symbol>, u32) {
+    let mut locals = vec![];
+    let mut globals = vec![];
+
+    for (label, &addr) in binary.labels.iter() {
+        // labels at the very end of a segment still belong to it
+        let section = sections.iter().position(|section| {
+            section.addr <= addr && addr - section.addr <= section.bytes.len() as u32
+        });
+
+        let (kind, shndx) = match section {
+            Some(index) if sections[index].flags & SHF_EXECINSTR != 0 => {
+                (STT_NOTYPE, index as u16 + 1)
+            }
+            Some(index) => (STT_OBJECT, index as u16 + 1),
+            // absolute symbols are read back as constants,
+            // so the label is put in a section even though it's outside of it
+            None if !sections.is_empty() => (STT_NOTYPE, 1),
+            None => (STT_NOTYPE, SHN_ABS),
+        };
+
+        // labels renamed by the compiler (local labels, and private labels
+        // that another file also defines) are local, under their source name
+        let name = source_label_name(label);
+        let global = name == label && binary.globals.contains(label);
+        let bind = if global { STB_GLOBAL } else { STB_LOCAL };
+
+        let symbol = Symbol {
+            name: name.to_string(),
+            value: addr,
+            info: (bind << 4) | kind,
+            shndx,
+        };
+
+        if global {
+            globals.push(symbol);
+        } else {
+            locals.push(symbol);
+        }
+    }
+
+    let constants = binary.constants.iter().collect::<BTreeMap<_, _>>();
+    for (constant, &value) in constants {
+        locals.push(Symbol {
+            name: constant.to_string(),
+            value: value as u32,
+            info: (STB_LOCAL << 4) | STT_NOTYPE,
+            shndx: SHN_ABS,
+        });
+    }
+
+    // the null symbol comes first
+    let first_global = locals.len() as u32 + 1;
+    locals.append(&mut globals);
+
+    (locals, first_global)
+}
+
+/// A DWARF 2 line number program, with a sequence for each text segment.
+fn debug_line(binary: &Binary) -> Vec<u8> {
+    let mut files = binary
+        .line_numbers
+        .values()
+        .map(|(file_tag, _)| file_tag.clone())
+        .filter(|file_tag| !file_tag.is_empty())
+        .collect::<Vec<_>>();
+    files.sort();
+    files.dedup();
+
+    let mut header = vec![];
+    // minimum_instruction_length, default_is_stmt, line_base, line_range, opcode_base
+    header.extend([4, 1, -5i8 as u8, 14, 10]);
+    // standard_opcode_lengths
+    header.extend([0, 1, 1, 1, 1, 0, 0, 0, 1]);
+    // no include_directories
+    header.push(0);
+    for file in files.iter() {
+        header.extend(file.bytes());
+        // name, directory, modification time, length
+        header.extend([0, 0, 0, 0]);
+    }
+    header.push(0);
+
+    let mut program = vec![];
+    for (bot, segment) in [(TEXT_BOT, &binary.text), (KTEXT_BOT, &binary.ktext)] {
+        let end = bot + segment.len() as u32;
+
+        let mut lines = binary
+            .line_numbers
+            .iter()
+            .filter(|(&addr, (file_tag, _))| bot <= addr && addr < end && !file_tag.is_empty())
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            continue;
+        }
+        lines.sort_by_key(|(&addr, _)| addr);
+
+        let mut current_file = 1;
+        let mut current_line = 1;
+        for (&addr, (file_tag, line)) in lines {
+            set_address(&mut program, addr);
+
+            let file = files.iter().position(|file| file == file_tag).unwrap() as u32 + 1;
+            if file != current_file {
+                // DW_LNS_set_file
+                program.push(4);
+                put_uleb128(&mut program, file);
+                current_file = file;
+            }
+
+            if *line != current_line {
+                // DW_LNS_advance_line
+                program.push(3);
+                put_sleb128(&mut program, *line as i64 - current_line as i64);
+                current_line = *line;
+            }
+
+            // DW_LNS_copy
+            program.push(1);
+        }
+
+        set_address(&mut program, end);
+        // DW_LNE_end_sequence
+        program.extend([0, 1, 1]);
+    }
+
+    let mut debug_line = vec![];
+    put_u32(
+        &mut debug_line,
+        (2 + 4 + header.len() + program.len()) as u32,
+    );
+    put_u16(&mut debug_line, 2);
+    put_u32(&mut debug_line, header.len() as u32);
+    debug_line.append(&mut header);
+    debug_line.append(&mut program);
+
+    debug_line
+}
+
+fn set_address(program: &mut Vec<u8>, addr: u32) {
+    // DW_LNE_set_address
+    program.extend([0, 5, 2]);
+    put_u32(program, addr);
+}
+
+fn put_u16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.extend(value.to_le_bytes());
+}
+
+fn put_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend(value.to_le_bytes());
+}
+
+fn put_uleb128(bytes: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn put_sleb128(bytes: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            bytes.push(byte);
+            return;
+        }
+
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn align(offset: u32, align: u32) -> u32 {
+    offset.div_ceil(align) * align
+}
@@ -2,12 +2,15 @@ use serde::{Deserialize, Serialize};
 use std::{fmt::Display, path::MAIN_SEPARATOR, rc::Rc};
 
 use crate::{inst::instruction::Signature, DATA_BOT, HEAP_BOT};
-use colored::Colorize;
-use mipsy_parser::{MpDirective, MpInstruction};
+use colored::{Color, Colorize};
+use mipsy_parser::{MpDirective, MpInstruction, MpMacroInvocation};
 use mipsy_utils::MipsyConfig;
 
 use super::util::{syntax_highlight_argument, tip_header};
 
+mod warning;
+pub use warning::{CompilerWarning, Warning, ALL_WARNINGS};
+
 #[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct CompilerError {
     error: Error,
@@ -15,6 +18,7 @@ pub struct CompilerError {
     line: u32,
     col: u32,
     col_end: u32,
+    expansion: Vec<MpMacroInvocation>,
 }
 
 impl CompilerError {
@@ -25,9 +29,16 @@ impl CompilerError {
             line,
             col,
             col_end,
+            expansion: vec![],
         }
     }
 
+    /// Records the macro invocations that the erroring code was expanded from.
+    pub fn with_expansion(mut self, expansion: &[MpMacroInvocation]) -> Self {
+        self.expansion = expansion.to_vec();
+        self
+    }
+
     pub fn error(&self) -> &Error {
         &self.error
     }
@@ -48,9 +59,24 @@ impl CompilerError {
         self.col_end
     }
 
+    pub fn expansion(&self) -> &[MpMacroInvocation] {
+        &self.expansion
+    }
+
     pub fn show_error(&self, config: &MipsyConfig, file: Rc<str>) {
         if self.error().should_highlight_line() {
-            self.highlight_line(config, file);
+            print!(
+                "{}",
+                highlight_line(
+                    config,
+                    file.clone(),
+                    &self.file_tag,
+                    self.line,
+                    self.col,
+                    self.col_end,
+                    Color::BrightRed,
+                )
+            );
         }
 
         println!("{}", self.error.message());
@@ -58,87 +84,141 @@ impl CompilerError {
         for tip in self.error.tips() {
             print!("{} {}", tip_header(), tip);
         }
+
+        print!(
+            "{}",
+            expansion_notes(config, file, &self.file_tag, &self.expansion)
+        );
     }
+}
 
-    // TODO(zkol): Can't just pull tab_size from the config, since
-    // file may have #![tabsize(...)]
-    fn highlight_line(&self, config: &MipsyConfig, file: Rc<str>) {
-        let line = file
-            .lines()
-            .nth((self.line - 1) as usize)
-            .expect("invalid line position in compiler error");
-
-        let updated_line = {
-            let mut updated_line = String::new();
-
-            for char in line.chars() {
-                if char != '\t' {
-                    updated_line.push(char);
-                    continue;
-                }
+/// Points out each of the macro invocations that an error was expanded from.
+pub(super) fn expansion_notes(
+    config: &MipsyConfig,
+    file: Rc<str>,
+    file_tag: &str,
+    expansion: &[MpMacroInvocation],
+) -> String {
+    let mut notes = String::new();
+
+    for invocation in expansion.iter() {
+        let message = "in this expansion of the macro".bright_blue().bold();
+        notes.push('\n');
+
+        // we only have the contents of the file the error is in,
+        // so invocations in other (e.g. including) files just get a location
+        let invocation_tag = invocation.file_tag().unwrap_or("");
+        if invocation_tag != file_tag {
+            notes.push_str(&format!(
+                "{} `{}` {} {}:{}:{}\n",
+                message,
+                invocation.name().bold(),
+                "at".bright_blue().bold(),
+                invocation_tag,
+                invocation.line(),
+                invocation.col(),
+            ));
+
+            continue;
+        }
 
-                let spaces_to_insert =
-                    config.tab_size - (updated_line.len() as u32 % config.tab_size);
-                updated_line.push_str(&" ".repeat(spaces_to_insert as usize));
-            }
+        notes.push_str(&highlight_line(
+            config,
+            file.clone(),
+            file_tag,
+            invocation.line(),
+            invocation.col(),
+            invocation.col_end(),
+            Color::BrightBlue,
+        ));
 
-            updated_line
-        };
+        notes.push_str(&format!("{} `{}`\n", message, invocation.name().bold()));
+    }
 
-        // format of the error:
+    notes
+}
 
-        //   --> ./foo.s:1:2
-        //    |
-        // 22 | mips code here
-        //    |      ^^^^ error: some useless diagnosis
-        //
+// TODO(zkol): Can't just pull tab_size from the config, since
+// file may have #![tabsize(...)]
+fn highlight_line(
+    config: &MipsyConfig,
+    file: Rc<str>,
+    file_tag: &str,
+    line_num: u32,
+    col: u32,
+    col_end: u32,
+    colour: Color,
+) -> String {
+    let line = file
+        .lines()
+        .nth((line_num - 1) as usize)
+        .expect("invalid line position in compiler error");
+
+    let updated_line = {
+        let mut updated_line = String::new();
+
+        for char in line.chars() {
+            if char != '\t' {
+                updated_line.push(char);
+                continue;
+            }
 
-        let line_num_str = self.line.to_string();
-        let line_num_str_colored = line_num_str.bright_blue().bold();
-        let line_num_width = line_num_str.len();
-        let line_num_blank = " ".repeat(line_num_width);
-        let arrow = "-->".bright_blue().bold();
-        let file_name = {
-            if self.file_tag.is_empty() {
-                String::new()
+            let spaces_to_insert = config.tab_size - (updated_line.len() as u32 % config.tab_size);
+            updated_line.push_str(&" ".repeat(spaces_to_insert as usize));
+        }
+
+        updated_line
+    };
+
+    // format of the error:
+
+    //   --> ./foo.s:1:2
+    //    |
+    // 22 | mips code here
+    //    |      ^^^^ error: some useless diagnosis
+    //
+
+    let line_num_str = line_num.to_string();
+    let line_num_str_colored = line_num_str.bright_blue().bold();
+    let line_num_width = line_num_str.len();
+    let line_num_blank = " ".repeat(line_num_width);
+    let arrow = "-->".bright_blue().bold();
+    let file_name = {
+        if file_tag.is_empty() {
+            String::new()
+        } else {
+            let dot_slash = if !file_tag.contains(MAIN_SEPARATOR) {
+                "./"
             } else {
-                let dot_slash = if !self.file_tag.contains(MAIN_SEPARATOR) {
-                    "./"
-                } else {
-                    ""
-                };
+                ""
+            };
 
-                let line_col = format!(":{}:{}", self.line, self.col);
+            let line_col = format!(":{}:{}", line_num, col);
 
-                format!(
-                    "{}{}{}",
-                    dot_slash.bold(),
-                    self.file_tag.bold(),
-                    line_col.bold()
-                )
-            }
-        };
-        let bar = "|".bright_blue().bold();
-        let line = updated_line;
-        let pre_highlight_space = " ".repeat((self.col - 1) as usize);
-        let highlight = "^"
-            .repeat((self.col_end - self.col) as usize)
-            .bright_red()
-            .bold();
+            format!("{}{}{}", dot_slash.bold(), file_tag.bold(), line_col.bold())
+        }
+    };
+    let bar = "|".bright_blue().bold();
+    let line = updated_line;
+    let pre_highlight_space = " ".repeat((col - 1) as usize);
+    let highlight = "^".repeat((col_end - col) as usize).color(colour).bold();
 
-        // and this is where the magic happens...
+    // and this is where the magic happens...
 
-        if !file_name.is_empty() {
-            println!("{}{} {}", line_num_blank, arrow, file_name);
-        }
+    let mut highlighted = String::new();
 
-        println!("{} {}", line_num_blank, bar);
-        println!("{} {} {}", line_num_str_colored, bar, line);
-        print!(
-            "{} {} {}{} ",
-            line_num_blank, bar, pre_highlight_space, highlight
-        );
+    if !file_name.is_empty() {
+        highlighted.push_str(&format!("{}{} {}\n", line_num_blank, arrow, file_name));
     }
+
+    highlighted.push_str(&format!("{} {}\n", line_num_blank, bar));
+    highlighted.push_str(&format!("{} {} {}\n", line_num_str_colored, bar, line));
+    highlighted.push_str(&format!(
+        "{} {} {}{} ",
+        line_num_blank, bar, pre_highlight_space, highlight
+    ));
+
+    highlighted
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
@@ -165,10 +245,29 @@ pub enum Error {
         inst_ast: MpInstruction,
         similar_instns: Vec<Signature>,
     },
+    ForbiddenInstruction {
+        inst_ast: MpInstruction,
+        profile: String,
+        instead: Vec<String>,
+    },
+    UnknownProfile {
+        profile: String,
+        profiles: Vec<String>,
+        in_config: bool,
+    },
 
     RedefinedLabel {
         label: String,
     },
+    RedefinedGlobalLabel {
+        label: String,
+        first_file_tag: String,
+        first_line: u32,
+    },
+    PrivateLabel {
+        label: String,
+        file_tag: String,
+    },
     UnresolvedLabel {
         label: String,
         similar: Vec<String>,
@@ -187,11 +286,24 @@ pub enum Error {
         range_low: i64,
         range_high: i64,
     },
+    DivisionByZero {
+        expression: String,
+    },
+    ExpressionOverflow {
+        expression: String,
+    },
+    ExpressionDoesNotFit {
+        expression: String,
+        value: i64,
+    },
 
     DataInTextSegment {
         directive_type: MpDirective,
     },
     InstructionInDataSegment,
+    DirectiveInStruct {
+        directive_type: MpDirective,
+    },
 
     TooMuchData {
         data_size: u32,
@@ -254,6 +366,28 @@ impl Error {
                 format!("{} `{}` {}", message_1, inst_name, message_2)
             }
 
+            Error::ForbiddenInstruction {
+                inst_ast, profile, ..
+            } => {
+                let message_1 = "instruction".bright_red().bold();
+                let message_2 = "is not allowed by the".bright_red().bold();
+                let message_3 = "profile".bright_red().bold();
+                let inst_name = inst_ast.name().bold();
+                let profile = profile.bold();
+
+                format!(
+                    "{} `{}` {} `{}` {}",
+                    message_1, inst_name, message_2, profile, message_3
+                )
+            }
+
+            Error::UnknownProfile { profile, .. } => {
+                let message = "unknown profile".bright_red().bold();
+                let profile = profile.bold();
+
+                format!("{} `{}`", message, profile)
+            }
+
             Error::RedefinedLabel { label } => {
                 let message_1 = "the label".bright_red().bold();
                 let message_2 = "is defined multiple times".bright_red().bold();
@@ -262,6 +396,31 @@ impl Error {
                 format!("{} `{}` {}", message_1, label, message_2)
             }
 
+            Error::RedefinedGlobalLabel {
+                label,
+                first_file_tag,
+                ..
+            } => {
+                let message_1 = "the global label".bright_red().bold();
+                let message_2 = "is already defined in".bright_red().bold();
+                let label = label.bold();
+                let first_file_tag = first_file_tag.bold();
+
+                format!(
+                    "{} `{}` {} `{}`",
+                    message_1, label, message_2, first_file_tag
+                )
+            }
+
+            Error::PrivateLabel { label, file_tag } => {
+                let message_1 = "the label".bright_red().bold();
+                let message_2 = "is private to".bright_red().bold();
+                let label = label.bold();
+                let file_tag = file_tag.bold();
+
+                format!("{} `{}` {} `{}`", message_1, label, message_2, file_tag)
+            }
+
             Error::UnresolvedLabel { label, .. } => {
                 let message_1 = "cannot find label".bright_red().bold();
                 let message_2 = "in program".bright_red().bold();
@@ -304,6 +463,30 @@ impl Error {
                 )
             }
 
+            Error::DivisionByZero { expression } => {
+                let message_1 = "cannot divide by zero in".bright_red().bold();
+                let expression = expression.bold();
+
+                format!("{} `{}`", message_1, expression)
+            }
+
+            Error::ExpressionOverflow { expression } => {
+                let message_1 = "the result of".bright_red().bold();
+                let message_2 = "is too large".bright_red().bold();
+                let expression = expression.bold();
+
+                format!("{} `{}` {}", message_1, expression, message_2)
+            }
+
+            Error::ExpressionDoesNotFit { expression, value } => {
+                let message_1 = "the value of".bright_red().bold();
+                let message_2 = "does not fit in 32 bits".bright_red().bold();
+                let expression = expression.bold();
+                let value = value.to_string().bold();
+
+                format!("{} `{}` ({}) {}", message_1, expression, value, message_2)
+            }
+
             Error::DataInTextSegment { directive_type } => {
                 let message_1 = "cannot put".bright_red().bold();
                 let message_2 = directive_type.to_string().bold();
@@ -320,6 +503,14 @@ impl Error {
                 format!("{}", message_1)
             }
 
+            Error::DirectiveInStruct { directive_type } => {
+                let message_1 = "cannot put".bright_red().bold();
+                let message_2 = directive_type.to_string().bold();
+                let message_3 = "directive into a struct".bright_red().bold();
+
+                format!("{} `{}{}` {}", message_1, ".".bold(), message_2, message_3)
+            }
+
             Error::TooMuchData { .. } => {
                 let message_1 = "too much data to fit in the".bright_red().bold();
                 let message_2 = ".data".bold();
@@ -457,11 +648,89 @@ impl Error {
                 vec![tip]
             }
 
+            Error::ForbiddenInstruction {
+                inst_ast, instead, ..
+            } => {
+                if instead.is_empty() {
+                    return vec![];
+                }
+
+                let mut tip = format!("instead of `{}`, write:\n", inst_ast.name().bold());
+
+                for inst in instead {
+                    tip.push_str(&format!("    {}\n", inst.bold()));
+                }
+
+                vec![tip]
+            }
+
+            Error::UnknownProfile {
+                profiles,
+                in_config,
+                ..
+            } => {
+                let mut tips = vec![];
+
+                if *in_config {
+                    tips.push(format!(
+                        "it is the `{}` set in your mipsy config\n",
+                        "profile".bold()
+                    ));
+                }
+
+                if profiles.is_empty() {
+                    tips.push(format!(
+                        "profiles are defined under `{}` in your mipsy config\n",
+                        "profiles".bold()
+                    ));
+                } else {
+                    let profiles = profiles
+                        .iter()
+                        .map(|profile| format!("`{}`", profile.bold()))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+
+                    tips.push(format!(
+                        "the profiles in your mipsy config are {}\n",
+                        profiles
+                    ));
+                }
+
+                tips
+            }
+
             Error::RedefinedLabel { .. } => {
                 // good luck kiddo
                 vec![]
             }
 
+            Error::RedefinedGlobalLabel {
+                label,
+                first_file_tag,
+                first_line,
+            } => {
+                let location = format!("{}:{}", first_file_tag, first_line).bold();
+                let globl = format!(".globl {}", label).bold();
+
+                vec![
+                    format!("it was first defined at {}\n", location),
+                    format!(
+                        "if each file should have its own copy, remove one of the `{}` directives\n",
+                        globl
+                    ),
+                ]
+            }
+
+            Error::PrivateLabel { label, file_tag } => {
+                let globl = format!(".globl {}", label).bold();
+
+                vec![format!(
+                    "labels can only be used from other files if they are global -- try adding `{}` to `{}`\n",
+                    globl,
+                    file_tag.bold()
+                )]
+            }
+
             Error::UnresolvedLabel { label, similar } => {
                 if label == "main" {
                     let message_1 = "you are required to add a";
@@ -507,6 +776,36 @@ impl Error {
                 vec![tip]
             }
 
+            Error::DivisionByZero { .. } => {
+                let tip = format!(
+                    "the right-hand side of a `{}` or `{}` must not be zero\n",
+                    "/".bold(),
+                    "%".bold()
+                );
+
+                vec![tip]
+            }
+
+            Error::ExpressionOverflow { .. } => {
+                let tip = format!(
+                    "expressions are worked out with 64-bit integers, and can only shift by {} to {}\n",
+                    "0".bold(),
+                    "63".bold()
+                );
+
+                vec![tip]
+            }
+
+            Error::ExpressionDoesNotFit { .. } => {
+                let tip = format!(
+                    "a value used by an instruction must be between {} and {}\n",
+                    i32::MIN.to_string().bold(),
+                    u32::MAX.to_string().bold()
+                );
+
+                vec![tip]
+            }
+
             Error::DataInTextSegment { directive_type } => {
                 let data = ".data".bold();
                 let tip = format!(
@@ -529,6 +828,23 @@ impl Error {
                 vec![tip]
             }
 
+            Error::DirectiveInStruct { .. } => {
+                let tip = format!(
+                    "the fields of a struct can be `{}`, `{}`, `{}`, `{}`, `{}`, `{}`, `{}`, `{}` or `{}`\n",
+                    ".byte".bold(),
+                    ".half".bold(),
+                    ".word".bold(),
+                    ".float".bold(),
+                    ".double".bold(),
+                    ".ascii".bold(),
+                    ".asciiz".bold(),
+                    ".space".bold(),
+                    ".align".bold(),
+                );
+
+                vec![tip]
+            }
+
             Error::TooMuchData { data_size } => {
                 let tip1 = format!(
                     "you have {} bytes of data, but the max is {} bytes\n",
@@ -557,6 +873,9 @@ impl Error {
             // useful diagnostic at this point
             Self::TooMuchData { .. } => false,
 
+            // a profile set in the config isn't on any line
+            Self::UnknownProfile { in_config, .. } => !in_config,
+
             // otherwise highlight the line causing the error
             _ => true,
         }
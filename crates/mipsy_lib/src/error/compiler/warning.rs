@@ -0,0 +1,189 @@
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+
+use colored::{Color, Colorize};
+use mipsy_utils::MipsyConfig;
+
+use super::{super::util::tip_header, highlight_line};
+
+/// The lint code that silences every warning,
+/// as in `#[allow(warnings)]`.
+pub const ALL_WARNINGS: &str = "warnings";
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct CompilerWarning {
+    warning: Warning,
+    file_tag: Rc<str>,
+    line: u32,
+    col: u32,
+    col_end: u32,
+}
+
+impl CompilerWarning {
+    pub fn new(warning: Warning, file_tag: Rc<str>, line: u32, col: u32, col_end: u32) -> Self {
+        Self {
+            warning,
+            file_tag,
+            line,
+            col,
+            col_end,
+        }
+    }
+
+    pub fn warning(&self) -> &Warning {
+        &self.warning
+    }
+
+    pub fn file_tag(&self) -> Rc<str> {
+        self.file_tag.clone()
+    }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn col(&self) -> u32 {
+        self.col
+    }
+
+    pub fn col_end(&self) -> u32 {
+        self.col_end
+    }
+
+    /// Warnings are printed to stderr, so that they
+    /// don't get mixed up with the program's own output.
+    pub fn show_warning(&self, config: &MipsyConfig, file: Rc<str>) {
+        eprint!(
+            "{}",
+            highlight_line(
+                config,
+                file,
+                &self.file_tag,
+                self.line,
+                self.col,
+                self.col_end,
+                Color::Yellow,
+            )
+        );
+
+        eprintln!("{}", self.warning.message());
+
+        for tip in self.warning.tips() {
+            eprint!("{} {}", tip_header(), tip);
+        }
+
+        eprintln!(
+            "{} this warning can be silenced with `{}`\n",
+            tip_header(),
+            format!("#[allow({})]", self.warning.code()).bold(),
+        );
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub enum Warning {
+    UnusedLabel { label: String },
+    TrivialPseudoInstruction { inst_name: String, native: String },
+    InstructionNotIndented,
+    LabelIndented { label: String },
+    NoBlankLineBeforeLabel { label: String },
+    IgnoredSetOption { option: String },
+}
+
+impl Warning {
+    /// The name of the lint, as used in `#[allow(...)]`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Warning::UnusedLabel { .. } => "unused",
+            Warning::TrivialPseudoInstruction { .. } => "trivial_pseudo",
+            Warning::InstructionNotIndented | Warning::LabelIndented { .. } => "indentation",
+            Warning::NoBlankLineBeforeLabel { .. } => "label_spacing",
+            Warning::IgnoredSetOption { .. } => "ignored_option",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Warning::UnusedLabel { label } => {
+                let message_1 = "the label".yellow().bold();
+                let message_2 = "is never used".yellow().bold();
+                let label = label.bold();
+
+                format!("{} `{}` {}", message_1, label, message_2)
+            }
+
+            Warning::TrivialPseudoInstruction { inst_name, .. } => {
+                let message_1 = "this use of".yellow().bold();
+                let message_2 = "is a pseudo-instruction".yellow().bold();
+                let inst_name = inst_name.bold();
+
+                format!("{} `{}` {}", message_1, inst_name, message_2)
+            }
+
+            Warning::InstructionNotIndented => {
+                let message_1 = "instructions should be indented".yellow().bold();
+
+                format!("{}", message_1)
+            }
+
+            Warning::LabelIndented { label } => {
+                let message_1 = "the label".yellow().bold();
+                let message_2 = "should not be indented".yellow().bold();
+                let label = label.bold();
+
+                format!("{} `{}` {}", message_1, label, message_2)
+            }
+
+            Warning::NoBlankLineBeforeLabel { label } => {
+                let message_1 = "the label".yellow().bold();
+                let message_2 = "should have a blank line before it".yellow().bold();
+                let label = label.bold();
+
+                format!("{} `{}` {}", message_1, label, message_2)
+            }
+
+            Warning::IgnoredSetOption { option } => {
+                let message_1 = "the assembler option".yellow().bold();
+                let message_2 = "is ignored".yellow().bold();
+                let option = option.bold();
+
+                format!("{} `{}` {}", message_1, option, message_2)
+            }
+        }
+    }
+
+    pub fn tips(&self) -> Vec<String> {
+        match self {
+            Warning::UnusedLabel { .. } => {
+                vec![
+                    "if you meant to jump or branch here, check the spelling of the label\n".into(),
+                ]
+            }
+
+            Warning::TrivialPseudoInstruction { native, .. } => {
+                vec![format!(
+                    "it compiles to a single `{}` instruction -- try writing that out in full\n",
+                    native.bold()
+                )]
+            }
+
+            Warning::InstructionNotIndented => {
+                vec!["only labels should start at the beginning of a line\n".into()]
+            }
+
+            Warning::LabelIndented { .. } => {
+                vec!["move the label to the beginning of the line\n".into()]
+            }
+
+            Warning::NoBlankLineBeforeLabel { .. } => {
+                vec![
+                    "a blank line makes it easier to see where a new block of code starts\n".into(),
+                ]
+            }
+
+            Warning::IgnoredSetOption { .. } => {
+                vec!["mipsy doesn't have any assembler options to set, so this line can be removed\n".into()]
+            }
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use mipsy_parser::MpMacroInvocation;
 use serde::{Deserialize, Serialize};
 use std::rc::Rc;
 
@@ -7,8 +8,12 @@ pub mod runtime;
 pub mod util;
 
 pub type MipsyResult<T> = Result<T, MipsyError>;
+/// Compiling carries on past an error where it can,
+/// so that every error in a program is reported at once.
+pub type CompileResult<T> = Result<T, Vec<MipsyError>>;
 pub type ParserError = parser::ParserError;
 pub type CompilerError = compiler::CompilerError;
+pub type CompilerWarning = compiler::CompilerWarning;
 pub type RuntimeError = runtime::RuntimeError;
 
 pub type MipsyInternalResult<T> = Result<T, InternalError>;
@@ -68,6 +73,27 @@ impl<T> ToMipsyResult<T> for MipsyInternalResult<T> {
     }
 }
 
+impl MipsyError {
+    /// Records the macro invocations that the erroring code was expanded from.
+    pub fn with_expansion(self, expansion: &[MpMacroInvocation]) -> Self {
+        match self {
+            MipsyError::Compiler(error) if !expansion.is_empty() => {
+                MipsyError::Compiler(error.with_expansion(expansion))
+            }
+            other => other,
+        }
+    }
+
+    /// The file and line that the error points at, if any.
+    pub fn location(&self) -> Option<(Rc<str>, u32)> {
+        match self {
+            MipsyError::Parser(error) => Some((error.file_tag(), error.line())),
+            MipsyError::Compiler(error) => Some((error.file_tag(), error.line())),
+            MipsyError::Runtime(_) => None,
+        }
+    }
+}
+
 impl InternalError {
     pub fn into_parser_mipsy_error(self, file_tag: Rc<str>, line: u32, col: u32) -> MipsyError {
         match self {
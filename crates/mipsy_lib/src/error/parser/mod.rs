@@ -1,14 +1,18 @@
 use colored::Colorize;
+use mipsy_parser::{MpMacroInvocation, ParseErrorKind, DIRECTIVE_NAMES};
 use mipsy_utils::MipsyConfig;
 use serde::{Deserialize, Serialize};
 use std::{path::MAIN_SEPARATOR, rc::Rc};
 
+use super::{compiler::expansion_notes, util::tip_header};
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct ParserError {
     error: Error,
     file_tag: Rc<str>,
     line: u32,
     col: u32,
+    expansion: Vec<MpMacroInvocation>,
 }
 
 impl ParserError {
@@ -18,9 +22,16 @@ impl ParserError {
             file_tag,
             line,
             col,
+            expansion: vec![],
         }
     }
 
+    /// Records the macro invocations that the erroring line was expanded from.
+    pub fn with_expansion(mut self, expansion: &[MpMacroInvocation]) -> Self {
+        self.expansion = expansion.to_vec();
+        self
+    }
+
     pub fn error(&self) -> &Error {
         &self.error
     }
@@ -37,10 +48,14 @@ impl ParserError {
         self.col
     }
 
+    pub fn expansion(&self) -> &[MpMacroInvocation] {
+        &self.expansion
+    }
+
     // TODO(zkol): Can't just pull tab_size from the config, since
     // file may have #![tabsize(...)]
     pub fn show_error(&self, config: &MipsyConfig, file: Rc<str>) {
-        let message = "failed to parse".bright_red().bold();
+        let message = self.error.message();
 
         let line = {
             let target_line = (self.line - 1) as usize;
@@ -134,10 +149,510 @@ impl ParserError {
             "{} {} {}{} {}",
             line_num_blank, bar, pre_highlight_space, highlight, message
         );
+
+        for tip in self.error.tips() {
+            eprint!("{} {}", tip_header(), tip);
+        }
+
+        eprint!(
+            "{}",
+            expansion_notes(config, file, &self.file_tag, &self.expansion)
+        );
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum Error {
     ParseFailure,
+    UnterminatedString,
+    InvalidEscape {
+        escape: char,
+    },
+    MissingComma,
+    UnclosedParen,
+    InvalidNumber {
+        literal: String,
+        radix: u32,
+    },
+    UnknownDirective {
+        directive: String,
+        similar: Vec<String>,
+    },
+    InvalidMacroHeader,
+    UnterminatedMacro {
+        name: String,
+    },
+    NestedMacro {
+        outer: String,
+    },
+    UnmatchedEndMacro,
+    MacroArgCount {
+        name: String,
+        expected: Vec<usize>,
+        found: usize,
+        expanded_from: Option<MpMacroInvocation>,
+    },
+    MacroTooDeep {
+        name: String,
+        expanded_from: Option<MpMacroInvocation>,
+    },
+    ReservedSeparator,
+    SymbolRedefined {
+        name: String,
+    },
+    UndefinedSymbol {
+        name: String,
+    },
+    ConditionDivByZero,
+    UnmatchedConditional {
+        directive: String,
+    },
+    UnterminatedConditional,
+    IncludeCycle {
+        chain: Vec<String>,
+    },
+    IncludeNotFound {
+        path: String,
+    },
+}
+
+impl Error {
+    pub fn message(&self) -> String {
+        match self {
+            Error::ParseFailure => "failed to parse".bright_red().bold().to_string(),
+
+            Error::UnterminatedString => "this string is never closed"
+                .bright_red()
+                .bold()
+                .to_string(),
+
+            Error::InvalidEscape { escape } => {
+                let message = "unknown escape sequence".bright_red().bold();
+                let escape = format!("\\{escape}").bold();
+
+                format!("{} `{}`", message, escape)
+            }
+
+            Error::MissingComma => "expected a comma before this"
+                .bright_red()
+                .bold()
+                .to_string(),
+
+            Error::UnclosedParen => "this parenthesis is never closed"
+                .bright_red()
+                .bold()
+                .to_string(),
+
+            Error::InvalidNumber { literal, radix } => {
+                let message_1 = "invalid".bright_red().bold();
+                let message_2 = "number".bright_red().bold();
+                let literal = literal.bold();
+
+                format!(
+                    "{} {} {} `{}`",
+                    message_1,
+                    radix_name(*radix).bright_red().bold(),
+                    message_2,
+                    literal
+                )
+            }
+
+            Error::UnknownDirective { directive, .. } => {
+                let message = "unknown directive".bright_red().bold();
+                let directive = directive.bold();
+
+                format!("{} `{}`", message, directive)
+            }
+
+            Error::InvalidMacroHeader => "invalid macro definition".bright_red().bold().to_string(),
+
+            Error::UnterminatedMacro { name } => {
+                let message_1 = "the macro".bright_red().bold();
+                let message_2 = "is never closed".bright_red().bold();
+                let name = name.bold();
+
+                format!("{} `{}` {}", message_1, name, message_2)
+            }
+
+            Error::NestedMacro { outer } => {
+                let message = "macros can't be defined inside the macro"
+                    .bright_red()
+                    .bold();
+                let outer = outer.bold();
+
+                format!("{} `{}`", message, outer)
+            }
+
+            Error::UnmatchedEndMacro => format!(
+                "{} `{}`",
+                "there is no macro to close with".bright_red().bold(),
+                ".end_macro".bold()
+            ),
+
+            Error::MacroArgCount { name, found, .. } => {
+                let message_1 = "the macro".bright_red().bold();
+                let message_2 = "can't take".bright_red().bold();
+                let name = name.bold();
+                let plural = if *found == 1 { "" } else { "s" };
+                let message_3 = format!("{found} argument{plural}").bright_red().bold();
+
+                format!("{} `{}` {} {}", message_1, name, message_2, message_3)
+            }
+
+            Error::MacroTooDeep { name, .. } => {
+                let message_1 = "the macro".bright_red().bold();
+                let message_2 = "expands too deeply".bright_red().bold();
+                let name = name.bold();
+
+                format!("{} `{}` {}", message_1, name, message_2)
+            }
+
+            Error::ReservedSeparator => format!(
+                "{} `{}`",
+                "names can't contain".bright_red().bold(),
+                "~".bold()
+            ),
+
+            Error::SymbolRedefined { name } => {
+                let message_1 = "the symbol".bright_red().bold();
+                let message_2 = "can't be redefined".bright_red().bold();
+                let name = name.bold();
+
+                format!("{} `{}` {}", message_1, name, message_2)
+            }
+
+            Error::UndefinedSymbol { name } => {
+                let message = "undefined symbol".bright_red().bold();
+                let name = name.bold();
+
+                format!("{} `{}`", message, name)
+            }
+
+            Error::ConditionDivByZero => "this condition divides by zero"
+                .bright_red()
+                .bold()
+                .to_string(),
+
+            Error::UnmatchedConditional { directive } => format!(
+                "{} `{}` {}",
+                "there is no".bright_red().bold(),
+                ".if".bold(),
+                format!("for this `{}` to belong to", directive.bold())
+                    .bright_red()
+                    .bold()
+            ),
+
+            Error::UnterminatedConditional => format!(
+                "{} `{}` {}",
+                "this".bright_red().bold(),
+                ".if".bold(),
+                "is never closed".bright_red().bold()
+            ),
+
+            Error::IncludeCycle { chain } => {
+                let message_1 = "the file".bright_red().bold();
+                let message_2 = "ends up including itself".bright_red().bold();
+                let file = chain.last().map(String::as_str).unwrap_or_default().bold();
+
+                format!("{} `{}` {}", message_1, file, message_2)
+            }
+
+            Error::IncludeNotFound { path } => {
+                let message = "can't find the included file".bright_red().bold();
+                let path = path.bold();
+
+                format!("{} `{}`", message, path)
+            }
+        }
+    }
+
+    pub fn tips(&self) -> Vec<String> {
+        match self {
+            Error::ParseFailure => {
+                // good luck kiddo
+                vec![]
+            }
+
+            Error::UnterminatedString => {
+                let quote = "\"".bold();
+
+                vec![format!(
+                    "strings can't span multiple lines -- try adding a {} at the end of the string\n",
+                    quote,
+                )]
+            }
+
+            Error::InvalidEscape { .. } => {
+                let escapes = ["\\n", "\\t", "\\r", "\\0", "\\\\", "\\\"", "\\'"]
+                    .map(|escape| format!("`{}`", escape.bold()))
+                    .join(", ");
+
+                let backslash = "\\\\".bold();
+
+                vec![
+                    format!("the valid escape sequences are {}\n", escapes),
+                    format!("for a literal backslash, use `{}`\n", backslash),
+                ]
+            }
+
+            Error::MissingComma => {
+                vec![String::from(
+                    "each operand must be separated from the next by a comma\n",
+                )]
+            }
+
+            Error::UnclosedParen => {
+                let example = format!("{}({})", "4".green(), "$sp".yellow()).bold();
+
+                vec![format!(
+                    "try adding a `{}` -- an offset looks like `{}`\n",
+                    ")".bold(),
+                    example,
+                )]
+            }
+
+            Error::InvalidNumber { radix, .. } => {
+                let digits = match radix {
+                    2 => "`0` and `1`",
+                    8 => "`0` to `7`",
+                    16 => "`0` to `9` and `a` to `f`",
+                    _ => "`0` to `9`",
+                };
+
+                let prefixes = format!(
+                    "`{}` (hex), `{}` (octal) or `{}` (binary)",
+                    "0x".bold(),
+                    "0o".bold(),
+                    "0b".bold(),
+                );
+
+                vec![
+                    format!(
+                        "{} numbers can only contain the digits {}\n",
+                        radix_name(*radix),
+                        digits,
+                    ),
+                    format!("numbers in another base start with {}\n", prefixes),
+                ]
+            }
+
+            Error::UnknownDirective { similar, .. } => {
+                if similar.is_empty() {
+                    return vec![];
+                }
+
+                let mut tip = String::new();
+
+                tip.push_str("directive(s) with a similar name exist:\n");
+                for directive in similar {
+                    tip.push_str(&format!(" - {}\n", directive.yellow().bold()));
+                }
+
+                vec![tip]
+            }
+
+            Error::InvalidMacroHeader => {
+                let example = format!(
+                    "{} {}({}, {})",
+                    ".macro".bold(),
+                    "name".yellow().bold(),
+                    "%first".magenta(),
+                    "%second".magenta()
+                );
+
+                vec![format!("a macro definition looks like `{}`\n", example)]
+            }
+
+            Error::UnterminatedMacro { .. } => {
+                vec![format!(
+                    "try adding an `{}` after the last line of the macro\n",
+                    ".end_macro".bold()
+                )]
+            }
+
+            Error::NestedMacro { .. } => {
+                vec![format!(
+                    "try closing the outer macro with `{}` first\n",
+                    ".end_macro".bold()
+                )]
+            }
+
+            Error::UnmatchedEndMacro => vec![],
+
+            Error::MacroArgCount {
+                expected,
+                expanded_from,
+                ..
+            } => {
+                let expected = expected
+                    .iter()
+                    .map(|count| count.to_string().bold().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" or ");
+
+                let mut tips = vec![format!("it's defined to take {} argument(s)\n", expected)];
+                tips.extend(expanded_from.as_ref().map(expansion_tip));
+
+                tips
+            }
+
+            Error::MacroTooDeep { expanded_from, .. } => {
+                let mut tips = vec![String::from(
+                    "a macro that invokes itself will never stop expanding\n",
+                )];
+                tips.extend(expanded_from.as_ref().map(expansion_tip));
+
+                tips
+            }
+
+            Error::ReservedSeparator => {
+                vec![format!(
+                    "`{}` is reserved for the labels that mipsy generates inside macros\n",
+                    "~".bold()
+                )]
+            }
+
+            Error::SymbolRedefined { .. } => {
+                vec![format!(
+                    "symbols defined with `{}` are constant -- use `{}` for one that can change\n",
+                    ".eqv".bold(),
+                    ".set".bold()
+                )]
+            }
+
+            Error::UndefinedSymbol { .. } => {
+                vec![format!(
+                    "conditions can only use numbers and symbols defined earlier with `{}` or `{}` -- labels aren't known yet\n",
+                    ".eqv".bold(),
+                    ".set".bold()
+                )]
+            }
+
+            Error::ConditionDivByZero => vec![],
+
+            Error::UnmatchedConditional { directive } => {
+                if directive == ".endif" {
+                    return vec![];
+                }
+
+                vec![format!(
+                    "a block can only have one `{}`, and it must come last\n",
+                    ".else".bold()
+                )]
+            }
+
+            Error::UnterminatedConditional => {
+                vec![format!(
+                    "try adding an `{}` after the last line of the block\n",
+                    ".endif".bold()
+                )]
+            }
+
+            Error::IncludeCycle { chain } => {
+                let chain = chain
+                    .iter()
+                    .map(|file| file.bold().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+
+                vec![
+                    format!("it's included through {}\n", chain),
+                    String::from(
+                        "code that several files need can go in a file of its own, which each of them includes\n",
+                    ),
+                ]
+            }
+
+            Error::IncludeNotFound { .. } => {
+                vec![String::from(
+                    "included paths are relative to the file that includes them\n",
+                )]
+            }
+        }
+    }
+}
+
+/// Where the line with an error was expanded from.
+fn expansion_tip(invocation: &MpMacroInvocation) -> String {
+    let site = match invocation.file_tag() {
+        Some(file_tag) if !file_tag.is_empty() => format!("{}:{}", file_tag, invocation.line()),
+        _ => format!("line {}", invocation.line()),
+    };
+
+    format!(
+        "this is part of the expansion of `{}`, invoked at {}\n",
+        invocation.name().bold(),
+        site.bold()
+    )
+}
+
+impl From<ParseErrorKind> for Error {
+    fn from(kind: ParseErrorKind) -> Self {
+        match kind {
+            ParseErrorKind::Unknown => Error::ParseFailure,
+            ParseErrorKind::UnterminatedString => Error::UnterminatedString,
+            ParseErrorKind::InvalidEscape { escape } => Error::InvalidEscape { escape },
+            ParseErrorKind::MissingComma => Error::MissingComma,
+            ParseErrorKind::UnclosedParen => Error::UnclosedParen,
+            ParseErrorKind::InvalidNumber { literal, radix } => {
+                Error::InvalidNumber { literal, radix }
+            }
+            ParseErrorKind::UnknownDirective { directive } => {
+                let lower = directive.to_ascii_lowercase();
+
+                let mut similar = DIRECTIVE_NAMES
+                    .iter()
+                    .map(|name| (strsim::jaro_winkler(name, &lower), name.to_string()))
+                    .filter(|&(sim, _)| sim >= 0.85)
+                    .collect::<Vec<_>>();
+
+                similar.sort_by(|(sim1, _), (sim2, _)| sim2.partial_cmp(sim1).unwrap());
+
+                let similar = similar.into_iter().map(|(_, name)| name).collect();
+
+                Error::UnknownDirective { directive, similar }
+            }
+            ParseErrorKind::InvalidMacroHeader => Error::InvalidMacroHeader,
+            ParseErrorKind::UnterminatedMacro { name } => Error::UnterminatedMacro { name },
+            ParseErrorKind::NestedMacro { outer } => Error::NestedMacro { outer },
+            ParseErrorKind::UnmatchedEndMacro => Error::UnmatchedEndMacro,
+            ParseErrorKind::MacroArgCount {
+                name,
+                expected,
+                found,
+                expanded_from,
+            } => Error::MacroArgCount {
+                name,
+                expected,
+                found,
+                expanded_from,
+            },
+            ParseErrorKind::MacroTooDeep {
+                name,
+                expanded_from,
+            } => Error::MacroTooDeep {
+                name,
+                expanded_from,
+            },
+            ParseErrorKind::ReservedSeparator => Error::ReservedSeparator,
+            ParseErrorKind::SymbolRedefined { name } => Error::SymbolRedefined { name },
+            ParseErrorKind::UndefinedSymbol { name } => Error::UndefinedSymbol { name },
+            ParseErrorKind::ConditionDivByZero => Error::ConditionDivByZero,
+            ParseErrorKind::UnmatchedConditional { directive } => {
+                Error::UnmatchedConditional { directive }
+            }
+            ParseErrorKind::UnterminatedConditional => Error::UnterminatedConditional,
+            ParseErrorKind::IncludeCycle { chain } => Error::IncludeCycle { chain },
+            ParseErrorKind::IncludeNotFound { path } => Error::IncludeNotFound { path },
+        }
+    }
+}
+
+fn radix_name(radix: u32) -> &'static str {
+    match radix {
+        2 => "binary",
+        8 => "octal",
+        16 => "hexadecimal",
+        _ => "decimal",
+    }
 }
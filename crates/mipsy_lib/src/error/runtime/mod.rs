@@ -4,7 +4,10 @@ use super::util::{inst_parts_to_string, inst_to_string, tip_header};
 use crate::{
     decompile::{self, decompile_inst_into_parts, Decompiled},
     inst::ReadsRegisterType,
-    runtime::state::{WRITE_MARKER_HI, WRITE_MARKER_LO},
+    runtime::{
+        self,
+        state::{WRITE_MARKER_HI, WRITE_MARKER_LO},
+    },
     util::{get_segment, Segment},
     Binary, InstSet, Register, Runtime, Safe, State,
 };
@@ -100,6 +103,7 @@ pub enum AlignmentRequirement {
 pub enum InvalidSyscallReason {
     Unimplemented, // Invalid becasue we don't have an implementation for it but it does exist
     Unknown,       // Invalid because it doesn't exist to begin with
+    InvalidArgument, // Invalid because the arguments given to it make no sense
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
@@ -987,6 +991,13 @@ impl Error {
                             syscall.to_string().bold()
                         ));
                     }
+                    InvalidSyscallReason::InvalidArgument => {
+                        // $v0 is a valid value, but the argument registers are not
+                        error.push_str(&format!(
+                            "\nthe arguments given to syscall number `{}` are not valid.\n",
+                            syscall.to_string().bold()
+                        ));
+                    }
                 }
 
                 let last_mod = get_last_mod(runtime, Register::V0.to_u32());
@@ -1509,9 +1520,27 @@ impl Error {
                 }
             }
 
-            Error::InvalidSyscall { .. } => {
-                vec![]
-            }
+            &Error::InvalidSyscall { syscall, reason } => match reason {
+                InvalidSyscallReason::Unknown
+                    if runtime::is_mars_syscall(syscall) && !runtime.mars() =>
+                {
+                    vec![format!(
+                        "syscall `{}` is a MARS extension -- try running with `{}`, or setting `{}` in your mipsy config",
+                        syscall.to_string().bold(),
+                        "--mars".bold(),
+                        "mars: true".bold(),
+                    )]
+                }
+                InvalidSyscallReason::InvalidArgument
+                    if syscall == runtime::SYS42_RANDOM_INT_RANGE =>
+                {
+                    vec![format!(
+                        "the upper bound in `{}` must be greater than zero",
+                        "$a1".yellow(),
+                    )]
+                }
+                _ => vec![],
+            },
         }
     }
 }
@@ -0,0 +1,485 @@
+//! Formats source files in a consistent style, keeping their comments.
+//!
+//! Labels, constants and file attributes sit flush left, while instructions
+//! and directives are indented, with their operands and any trailing comments
+//! lined up in columns. The body of a `.macro` is formatted a line at a time,
+//! since it can't be parsed until it's expanded. Anything that can't be rewritten
+//! safely -- `.struct` blocks, or items spread over several lines -- is left as it was written.
+
+use mipsy_parser::{
+    is_preprocessor_directive, CstContent, CstItem, CstProgram, MpItem, TaggedFile, Trivia,
+};
+use mipsy_utils::{FormatConfig, MipsyConfig};
+
+use crate::CompileResult;
+
+pub fn format(file: TaggedFile<'_, '_>, config: &MipsyConfig) -> CompileResult<String> {
+    let program = mipsy_parser::parse_mips_lossless(file, config.tab_size)
+        .map_err(|errors| crate::parser_errors(errors, config))?;
+
+    Ok(format_program(&program, &config.fmt))
+}
+
+/// Formats an already-parsed file, using the tab size it was parsed with.
+pub fn format_program(program: &CstProgram, style: &FormatConfig) -> String {
+    let mut formatter = Formatter {
+        output: String::new(),
+        col: 0,
+        last_blank: false,
+        tab_size: program.tab_size().max(1),
+        style,
+    };
+
+    let mut after_label = false;
+
+    for (index, item) in program.items().iter().enumerate() {
+        let lines = split_lines(item.leading());
+
+        // the first segment of a later item's trivia is the end of the previous item's line
+        let full_lines = match index {
+            0 => &lines[..lines.len() - 1],
+            _ => &lines[1.min(lines.len() - 1)..lines.len() - 1],
+        };
+
+        let same_line = index > 0 && lines.len() == 1 && after_label && is_statement(item);
+
+        if index > 0 && !same_line {
+            formatter.newline();
+        }
+
+        for line in full_lines {
+            formatter.trivia_line(line);
+        }
+
+        formatter.item(item);
+
+        if let Some(comment) = comment(item.trailing().iter()) {
+            formatter.pad_to(style.comment_column);
+            formatter.push(comment);
+        }
+
+        after_label = matches!(
+            item.content(),
+            CstContent::Item(item) if matches!(item.item(), MpItem::Label(_))
+        );
+    }
+
+    let lines = split_lines(program.trailing());
+    let full_lines = match program.items() {
+        [] => &lines[..],
+        _ => {
+            formatter.newline();
+            &lines[1..]
+        }
+    };
+
+    for line in full_lines {
+        formatter.trivia_line(line);
+    }
+
+    let mut output = formatter.output;
+    output.truncate(output.trim_end().len());
+    if !output.is_empty() {
+        output.push('\n');
+    }
+
+    output
+}
+
+struct Formatter<'a> {
+    output: String,
+    /// The column the end of the output is at, with tabs expanded.
+    col: u32,
+    /// Whether the last line was blank, so that runs of blank lines become one.
+    last_blank: bool,
+    tab_size: u32,
+    style: &'a FormatConfig,
+}
+
+impl Formatter<'_> {
+    fn push(&mut self, text: &str) {
+        for char in text.chars() {
+            match char {
+                '\t' => self.col = self.next_tab_stop(),
+                '\n' => self.col = 0,
+                _ => self.col += 1,
+            }
+        }
+
+        self.output.push_str(text);
+        self.last_blank = false;
+    }
+
+    fn newline(&mut self) {
+        self.output.push('\n');
+        self.col = 0;
+    }
+
+    fn next_tab_stop(&self) -> u32 {
+        (self.col / self.tab_size + 1) * self.tab_size
+    }
+
+    /// Pads out to `column`, or just separates the next thing if we're already past it.
+    fn pad_to(&mut self, column: u32) {
+        if self.col >= column {
+            if self.col > 0 {
+                self.push(if self.style.use_tabs { "\t" } else { " " });
+            }
+
+            return;
+        }
+
+        if self.style.use_tabs {
+            while self.next_tab_stop() <= column {
+                self.push("\t");
+            }
+        }
+
+        while self.col < column {
+            self.push(" ");
+        }
+    }
+
+    /// A line with nothing but trivia on it -- either a comment, or a blank line.
+    fn trivia_line(&mut self, line: &[&Trivia]) {
+        match comment(line.iter().copied()) {
+            Some(comment) => {
+                // comments that were indented line up with the instructions
+                if matches!(line.first(), Some(Trivia::Whitespace(_))) {
+                    self.pad_to(self.style.indent);
+                }
+
+                self.push(comment);
+                self.newline();
+            }
+            None => {
+                if !self.output.is_empty() && !self.last_blank {
+                    self.newline();
+                    self.last_blank = true;
+                }
+            }
+        }
+    }
+
+    fn item(&mut self, item: &CstItem) {
+        let text = item.text();
+
+        let statement = match item.content() {
+            CstContent::Item(item) => match item.item() {
+                MpItem::Instruction(_) => Some(text.strip_suffix(';').unwrap_or(text).trim_end()),
+                MpItem::Directive(_) => Some(text),
+                MpItem::Label(_) | MpItem::Constant(_) | MpItem::Struct(_) => None,
+            },
+            CstContent::Preprocessor if text.starts_with(".macro") => {
+                self.macro_definition(text);
+                return;
+            }
+            CstContent::FileAttribute(_) | CstContent::Preprocessor => None,
+        };
+
+        match statement {
+            // attributes on the item, or an item over several lines, are left alone
+            Some(text) if !text.starts_with('#') && !text.contains('\n') => {
+                self.pad_to(self.style.indent);
+                self.statement(text);
+            }
+            Some(text) => {
+                self.pad_to(self.style.indent);
+                self.push(text);
+            }
+            None => self.push(text),
+        }
+    }
+
+    fn macro_definition(&mut self, text: &str) {
+        let mut last_blank = false;
+
+        for (index, line) in text.split('\n').enumerate() {
+            let blank = line.trim().is_empty();
+            if blank && last_blank {
+                continue;
+            }
+
+            if index > 0 {
+                self.newline();
+            }
+
+            self.macro_line(line);
+            last_blank = blank;
+        }
+    }
+
+    /// A line of a `.macro` definition, laid out the same way as the rest of the file.
+    fn macro_line(&mut self, line: &str) {
+        let (code, comment) = split_comment(line);
+        let code = code.trim();
+
+        let (label, statement) = split_label(code);
+        if let Some(label) = label {
+            self.push(label);
+        }
+
+        let directive = statement
+            .split(|char: char| char.is_ascii_whitespace())
+            .next();
+
+        if directive.is_some_and(is_preprocessor_directive) && label.is_none() {
+            self.push(statement);
+        } else if !statement.is_empty() {
+            self.pad_to(self.style.indent);
+            self.statement(statement);
+        }
+
+        if let Some(comment) = comment {
+            if !code.is_empty() {
+                self.pad_to(self.style.comment_column);
+            } else if line.starts_with([' ', '\t']) {
+                // comments that were indented line up with the instructions
+                self.pad_to(self.style.indent);
+            }
+
+            self.push(comment);
+        }
+    }
+
+    fn statement(&mut self, text: &str) {
+        let (name, operands) = text
+            .split_once(|char: char| char.is_ascii_whitespace())
+            .unwrap_or((text, ""));
+
+        self.push(name);
+
+        let operands = split_operands(operands.trim());
+        if !operands.is_empty() {
+            self.pad_to(self.style.operand_column);
+            self.push(&operands.join(", "));
+        }
+    }
+}
+
+fn is_statement(item: &CstItem) -> bool {
+    matches!(
+        item.content(),
+        CstContent::Item(item) if matches!(item.item(), MpItem::Instruction(_) | MpItem::Directive(_))
+    )
+}
+
+/// Splits trivia into lines, so there's always one more line than there are newlines.
+fn split_lines(trivia: &[Trivia]) -> Vec<Vec<&Trivia>> {
+    let mut lines = vec![vec![]];
+
+    for trivia in trivia {
+        match trivia {
+            Trivia::Newline => lines.push(vec![]),
+            trivia => lines
+                .last_mut()
+                .expect("there is always a line")
+                .push(trivia),
+        }
+    }
+
+    lines
+}
+
+fn comment<'a>(mut trivia: impl Iterator<Item = &'a Trivia>) -> Option<&'a str> {
+    trivia.find_map(|trivia| match trivia {
+        Trivia::Comment(comment) => Some(comment.trim_end()),
+        _ => None,
+    })
+}
+
+/// Splits a line into its code and its comment, if it has one.
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (index, char) in line.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if char == '\\' => escaped = true,
+            Some(open) if char == open => quote = None,
+            Some(_) => {}
+            None => match char {
+                '"' | '\'' => quote = Some(char),
+                // but not an attribute, like `#[...]`
+                '#' if !matches!(line[index + 1..].chars().next(), Some('[' | '!')) => {
+                    return (&line[..index], Some(line[index..].trim_end()));
+                }
+                _ => {}
+            },
+        }
+    }
+
+    (line, None)
+}
+
+/// Splits a label like `loop:` off the start of a line.
+fn split_label(code: &str) -> (Option<&str>, &str) {
+    let name_len = code
+        .find(|char: char| !(char.is_alphanumeric() || matches!(char, '_' | '.' | '%')))
+        .unwrap_or(code.len());
+
+    if name_len == 0 || !code[name_len..].starts_with(':') {
+        return (None, code);
+    }
+
+    (Some(&code[..=name_len]), code[name_len + 1..].trim_start())
+}
+
+/// Splits operands at the commas between them, leaving any in strings or parentheses.
+fn split_operands(operands: &str) -> Vec<&str> {
+    if operands.is_empty() {
+        return vec![];
+    }
+
+    let mut split = vec![];
+    let mut start = 0;
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (index, char) in operands.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if char == '\\' => escaped = true,
+            Some(open) if char == open => quote = None,
+            Some(_) => {}
+            None => match char {
+                '"' | '\'' => quote = Some(char),
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    split.push(operands[start..index].trim());
+                    start = index + 1;
+                }
+                _ => {}
+            },
+        }
+    }
+
+    split.push(operands[start..].trim());
+
+    split
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn format_source(source: &str) -> String {
+        format(TaggedFile::new(None, source), &MipsyConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn test_format() {
+        let source = "\
+# prints a number
+main:   li $v0,1   # print_int
+  li    $a0 ,  42
+        syscall
+
+
+
+    # all done
+        jr $ra;
+msg: .asciiz \"a, b\"
+nums:.word 1,2 ,3
+LEN = 5
+";
+
+        assert_eq!(
+            format_source(source),
+            "\
+# prints a number
+main:\tli\t$v0, 1\t\t\t# print_int
+\tli\t$a0, 42
+\tsyscall
+
+\t# all done
+\tjr\t$ra
+msg:\t.asciiz\t\"a, b\"
+nums:\t.word\t1, 2, 3
+LEN = 5
+"
+        );
+    }
+
+    #[test]
+    fn test_tab_size() {
+        let source = "#![tabsize(4)]\nmain: lw $t0, 4 ($sp)\n\tjr $ra # return\n";
+
+        assert_eq!(
+            format_source(source),
+            "#![tabsize(4)]\nmain:\tlw\t\t$t0, 4 ($sp)\n\t\tjr\t\t$ra\t\t\t\t\t\t# return\n"
+        );
+
+        let config = MipsyConfig {
+            fmt: FormatConfig {
+                use_tabs: false,
+                indent: 4,
+                operand_column: 12,
+                comment_column: 24,
+            },
+            ..MipsyConfig::default()
+        };
+
+        assert_eq!(
+            format(TaggedFile::new(None, source), &config).unwrap(),
+            "#![tabsize(4)]\nmain: lw    $t0, 4 ($sp)\n    jr      $ra         # return\n"
+        );
+    }
+
+    #[test]
+    fn test_macro() {
+        let source = "\
+  .macro push(%reg)   # saves a register
+  addi $sp,$sp,-4
+    sw %reg, ($sp)
+
+
+  # done
+again: bne %reg,$zero,  again
+  .if 1
+    nop
+  .endif
+  .end_macro
+";
+
+        assert_eq!(
+            format_source(source),
+            "\
+.macro push(%reg)\t\t\t# saves a register
+\taddi\t$sp, $sp, -4
+\tsw\t%reg, ($sp)
+
+\t# done
+again:\tbne\t%reg, $zero, again
+.if 1
+\tnop
+.endif
+.end_macro
+"
+        );
+
+        assert_eq!(format_source(&format_source(source)), format_source(source));
+    }
+
+    #[test]
+    fn test_left_alone() {
+        let source = "\
+.struct Node
+    next: .word
+.end_struct
+";
+
+        assert_eq!(format_source(source), source);
+    }
+
+    #[test]
+    fn test_idempotent() {
+        let source = "main:\n  la $a0,msg  # load\n\tli $v0 , 4\n  syscall\n\n.data\nmsg: .asciiz \"hi, there\\n\"\n";
+        let formatted = format_source(source);
+
+        assert_eq!(format_source(&formatted), formatted);
+    }
+}
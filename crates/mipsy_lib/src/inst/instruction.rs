@@ -2,10 +2,14 @@ use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, fmt, str::FromStr};
 
 use super::register::Register;
-use crate::{error::MipsyInternalResult, Binary, TEXT_BOT};
+use crate::{
+    compile::{eval_relocation, eval_word_expression},
+    error::MipsyInternalResult,
+    Binary, TEXT_BOT,
+};
 use mipsy_parser::{
     parse_argument, MpArgument, MpImmediate, MpImmediateBinaryOp, MpInstruction, MpNumber,
-    MpOffsetOperator, MpRegister, MpRegisterIdentifier,
+    MpOffsetOperator, MpRegister, MpRegisterIdentifier, MpRelocation,
 };
 
 #[derive(Debug, Clone)]
@@ -165,6 +169,16 @@ pub enum ReadsRegisterType {
 }
 
 impl ReadsRegisterType {
+    /// The argument that holds the register.
+    pub fn argument_type(&self) -> ArgumentType {
+        match self {
+            Self::Rs => ArgumentType::Rs,
+            Self::Rt => ArgumentType::Rt,
+            Self::OffRs => ArgumentType::OffRs,
+            Self::OffRt => ArgumentType::OffRt,
+        }
+    }
+
     pub fn eq_argument_type(&self, other: &ArgumentType) -> bool {
         matches!(
             (self, other),
@@ -210,14 +224,21 @@ pub struct PseudoSignature {
     name: String,
     compile: CompileSignature,
     expand: Vec<PseudoExpand>,
+    meta: InstMetadata,
 }
 
 impl PseudoSignature {
-    pub fn new(name: String, compile: CompileSignature, expand: Vec<PseudoExpand>) -> Self {
+    pub fn new(
+        name: String,
+        compile: CompileSignature,
+        expand: Vec<PseudoExpand>,
+        meta: InstMetadata,
+    ) -> Self {
         Self {
             name,
             compile,
             expand,
+            meta,
         }
     }
 
@@ -232,6 +253,10 @@ impl PseudoSignature {
     pub fn expansion(&self) -> &[PseudoExpand] {
         &self.expand
     }
+
+    pub fn metadata(&self) -> &InstMetadata {
+        &self.meta
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
@@ -294,6 +319,36 @@ impl InstSet {
             .iter()
             .find(|&pseudo_inst| pseudo_inst.name == name && pseudo_inst.compile.matches(inst))
     }
+
+    /// Every signature of an instruction, in the order they're tried when compiling.
+    pub fn find_signatures(&self, inst: &str) -> Vec<SignatureRef<'_>> {
+        let name = inst.to_ascii_lowercase();
+
+        self.signatures().filter(|sig| sig.name() == name).collect()
+    }
+
+    /// Every signature with a description that contains some text, ignoring case.
+    pub fn search_descriptions(&self, text: &str) -> Vec<SignatureRef<'_>> {
+        let text = text.to_ascii_lowercase();
+
+        self.signatures()
+            .filter(|sig| {
+                let meta = sig.metadata();
+
+                [meta.desc_short(), meta.desc_long()]
+                    .into_iter()
+                    .flatten()
+                    .any(|desc| desc.to_ascii_lowercase().contains(&text))
+            })
+            .collect()
+    }
+
+    fn signatures(&self) -> impl Iterator<Item = SignatureRef<'_>> {
+        self.native_set
+            .iter()
+            .map(SignatureRef::Native)
+            .chain(self.pseudo_set.iter().map(SignatureRef::Pseudo))
+    }
 }
 
 impl InstSignature {
@@ -372,6 +427,9 @@ impl InstSignature {
                                     ((addr.wrapping_sub(current_inst_addr)) / 4) & 0xFFFF
                                 }
                             }
+                            MpImmediate::Relocation(reloc, expr) => {
+                                eval_relocation(program, *reloc, expr)? as u32
+                            }
                             _ => unreachable!(),
                         },
                         &MpNumber::Char(chr) => chr as u8 as u32,
@@ -396,6 +454,9 @@ impl InstSignature {
                                     ((addr.wrapping_sub(current_inst_addr)) / 4) & 0xFFFF
                                 }
                             }
+                            MpImmediate::Relocation(reloc, expr) => {
+                                eval_relocation(program, *reloc, expr)? as u32
+                            }
                             _ => unreachable!(),
                         },
                         &MpNumber::Char(chr) => chr as u8 as u32,
@@ -421,13 +482,19 @@ impl InstSignature {
                 },
                 ArgumentType::OffRs | ArgumentType::OffRt => match arg {
                     MpArgument::Register(reg) => match reg {
-                        MpRegister::Offset(imm, reg) => match *imm {
-                            MpImmediate::I16(imm) => {
+                        MpRegister::Offset(imm, reg) => match imm {
+                            &MpImmediate::I16(imm) => {
                                 let register = reg.to_register()?.to_u32();
                                 let imm = imm as u16 as u32;
 
                                 (register << 16) | imm
                             }
+                            MpImmediate::Relocation(reloc, expr) => {
+                                let register = reg.to_register()?.to_u32();
+                                let imm = eval_relocation(program, *reloc, expr)? as u32;
+
+                                (register << 16) | imm
+                            }
                             _ => unreachable!(),
                         },
                         _ => unreachable!(),
@@ -481,6 +548,117 @@ impl CompileSignature {
 
         true
     }
+
+    /// Whether every instruction that `other` matches is matched by this signature too,
+    /// so that `other` is never used if this one is tried first.
+    pub fn covers(&self, other: &CompileSignature) -> bool {
+        if self.format.len() != other.format.len() {
+            return false;
+        }
+
+        let last = self.format.len().saturating_sub(1);
+        let samples = sample_arguments();
+
+        self.format
+            .iter()
+            .zip(other.format.iter())
+            .enumerate()
+            .all(|(i, (my_arg, their_arg))| {
+                samples.iter().all(|sample| {
+                    !their_arg.matches(sample, i == last && other.relative_label)
+                        || my_arg.matches(sample, i == last && self.relative_label)
+                })
+            })
+    }
+}
+
+/// An argument for each of the cases that [`ArgumentType::matches`] tells apart.
+fn sample_arguments() -> Vec<MpArgument> {
+    const SAMPLES: &[&str] = &[
+        "$t0",
+        "0",
+        "32",
+        "-1",
+        "0xFFFF",
+        "-32769",
+        "0x7FFFFFFF",
+        "0xFFFFFFFF",
+        "label",
+        "(label + 4)",
+        "label + 4",
+        "%lo(label)",
+        "-4($t0)",
+        "0xFFFF($t0)",
+        "-32769($t0)",
+        "0xFFFFFFFF($t0)",
+        "label($t0)",
+        "(label + 4)($t0)",
+        "%lo(label)($t0)",
+        "1 + 2($t0)",
+    ];
+
+    SAMPLES
+        .iter()
+        .map(|sample| parse_argument(sample, 8).expect("sample arguments should parse"))
+        .chain(
+            [
+                MpNumber::Char('a'),
+                MpNumber::Float32(1.5),
+                MpNumber::Float64(1.5),
+            ]
+            .map(MpArgument::Number),
+        )
+        .collect()
+}
+
+impl fmt::Display for RuntimeSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (kind, fields) = match *self {
+            Self::R {
+                opcode,
+                funct,
+                shamt,
+                rs,
+                rt,
+                rd,
+            } => (
+                "R",
+                vec![
+                    ("opcode", Some(opcode)),
+                    ("funct", Some(funct)),
+                    ("shamt", shamt),
+                    ("rs", rs),
+                    ("rt", rt),
+                    ("rd", rd),
+                ],
+            ),
+            Self::I { opcode, rt } => ("I", vec![("opcode", Some(opcode)), ("rt", rt)]),
+            Self::J { opcode } => ("J", vec![("opcode", Some(opcode))]),
+        };
+
+        write!(f, "{kind}-type")?;
+
+        // fields without a value are filled in by the arguments
+        for (name, value) in fields {
+            if let Some(value) = value {
+                write!(f, ", {name} = 0x{value:02x}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for PseudoExpand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.inst.to_ascii_lowercase())?;
+
+        if !self.data.is_empty() {
+            write!(f, " {}", self.data.join(", "))?;
+        }
+
+        Ok(())
+    }
 }
 
 impl fmt::Display for ArgumentType {
@@ -516,10 +694,16 @@ impl ArgumentType {
                         Self::OffRs | Self::OffRt | Self::Off32Rs | Self::Off32Rt
                     ),
 
+                    MpImmediate::Relocation(..) => matches!(
+                        self,
+                        Self::OffRs | Self::OffRt | Self::Off32Rs | Self::Off32Rt
+                    ),
+
                     MpImmediate::U16(_)
                     | MpImmediate::U32(_)
                     | MpImmediate::I32(_)
-                    | MpImmediate::LabelReference(_) => {
+                    | MpImmediate::LabelReference(_)
+                    | MpImmediate::Expression(_) => {
                         matches!(self, Self::Off32Rs | Self::Off32Rt)
                     }
                 },
@@ -551,6 +735,18 @@ impl ArgumentType {
                             Self::I16 => relative_label,
                             _ => false,
                         },
+                        MpImmediate::Expression(_) => {
+                            matches!(self, Self::I32 | Self::U32 | Self::Off32Rs | Self::Off32Rt)
+                        }
+                        MpImmediate::Relocation(..) => matches!(
+                            self,
+                            Self::I16
+                                | Self::U16
+                                | Self::I32
+                                | Self::U32
+                                | Self::Off32Rs
+                                | Self::Off32Rt
+                        ),
                     },
                     MpNumber::BinaryOpImmediate(_imm1, _op, _imm2) => {
                         // TODO(zkol): this is brittle and based on faulty assumptions
@@ -697,6 +893,20 @@ impl PseudoSignature {
                             ((addr & 0xFFFF) as u16, (addr >> 16) as u16)
                         }
                     }
+                    MpImmediate::Expression(expr) => {
+                        let value = eval_word_expression(program, expr)?;
+
+                        ((value & 0xFFFF) as u16, (value >> 16) as u16)
+                    }
+                    &MpImmediate::Relocation(reloc, ref expr) => {
+                        let value = eval_relocation(program, reloc, expr)?;
+
+                        match reloc {
+                            // %lo is signed, like the offset of a load or store
+                            MpRelocation::Lo => (value, (value as i16 as i32 >> 16) as u16),
+                            MpRelocation::Hi => (value, 0),
+                        }
+                    }
                 },
                 &MpNumber::Char(chr) => (chr as u16, 0_u16),
                 MpNumber::BinaryOpImmediate(imm1, op, imm2) => {
@@ -1081,6 +1291,146 @@ impl PseudoSignature {
         Ok(new_instns)
     }
 
+    /// The native instructions that `inst` expands to, written as they would be in a program.
+    ///
+    /// The halves of a 32-bit value can only be worked out if it doesn't
+    /// depend on a label, so otherwise they're just described.
+    pub fn expansion_source(&self, inst: &MpInstruction) -> Vec<String> {
+        let empty = Binary::default();
+        let mut variables: HashMap<String, String> = HashMap::new();
+
+        for (arg_type, (arg, _, _)) in self.compile.format.iter().zip(inst.arguments()) {
+            let (imm, reg) = match arg {
+                MpArgument::Register(MpRegister::Offset(imm, reg)) => {
+                    (imm.to_string(), format!("${}", reg))
+                }
+                MpArgument::Register(MpRegister::BinaryOpOffset(i1, op, i2, reg)) => {
+                    (format!("{} {} {}", i1, op, i2), format!("${}", reg))
+                }
+                _ => (arg.to_string(), String::from("$0")),
+            };
+
+            match arg_type {
+                ArgumentType::I32
+                | ArgumentType::U32
+                | ArgumentType::Off32Rs
+                | ArgumentType::Off32Rt => {
+                    let var = match arg_type {
+                        ArgumentType::I32 => PseudoVariable::I32,
+                        ArgumentType::U32 => PseudoVariable::U32,
+                        ArgumentType::Off32Rs => {
+                            variables.insert(PseudoVariable::Rs.name(), reg);
+                            PseudoVariable::Off32
+                        }
+                        _ => {
+                            variables.insert(PseudoVariable::Rt.name(), reg);
+                            PseudoVariable::Off32
+                        }
+                    };
+
+                    match self.lower_upper(&empty, arg, false) {
+                        Ok((lower, upper)) => {
+                            for (name, value) in Self::expand_32_var(&var, lower, upper) {
+                                variables.insert(name, value.to_string());
+                            }
+                        }
+                        Err(_) => {
+                            for (half, description) in [("hi", "upper"), ("lo", "lower")] {
+                                let description = format!("<{} half of {}>", description, imm);
+
+                                for sign in ["i", "u"] {
+                                    variables.insert(
+                                        format!("{}{}{}", var.name(), sign, half),
+                                        description.clone(),
+                                    );
+                                }
+                            }
+                        }
+                    }
+                }
+                ArgumentType::I16 | ArgumentType::U16 => {
+                    let var = PseudoVariable::from_arg_type(arg_type);
+
+                    if let Ok((lower, _)) = self.lower_upper(&empty, arg, false) {
+                        for (name, value) in Self::expand_16_var(&var, lower) {
+                            variables.insert(name, value.to_string());
+                        }
+                    }
+
+                    // labels are kept as they are, since native branches take them too
+                    variables.insert(var.name(), arg.to_string());
+                }
+                ArgumentType::OffRs | ArgumentType::OffRt => {
+                    let var = match arg_type {
+                        ArgumentType::OffRs => PseudoVariable::Rs,
+                        _ => PseudoVariable::Rt,
+                    };
+
+                    variables.insert(
+                        PseudoVariable::from_arg_type(arg_type).name(),
+                        arg.to_string(),
+                    );
+                    variables.insert(PseudoVariable::Off.name(), imm);
+                    variables.insert(var.name(), reg);
+                }
+                ArgumentType::F32 | ArgumentType::F64 => {}
+                _ => {
+                    variables.insert(
+                        PseudoVariable::from_arg_type(arg_type).name(),
+                        arg.to_string(),
+                    );
+                }
+            }
+        }
+
+        let substitute = |data: &str| {
+            let mut source = String::new();
+            let mut rest = data;
+
+            while let Some(start) = rest.find('$') {
+                source.push_str(&rest[..start]);
+                rest = &rest[start + 1..];
+
+                let end = rest
+                    .find(|char: char| !char.is_ascii_alphanumeric() && char != '#')
+                    .unwrap_or(rest.len());
+                let name = &rest[..end];
+
+                if Register::from_str(name).is_ok() {
+                    source.push_str(&format!("${}", name.to_ascii_lowercase()));
+                } else if let Some(value) = variables.get(&name.to_ascii_lowercase()) {
+                    source.push_str(value);
+                } else {
+                    source.push_str(&format!("${}", name));
+                }
+
+                rest = &rest[end..];
+            }
+
+            source.push_str(rest);
+            source
+        };
+
+        self.expand
+            .iter()
+            .map(|expand| {
+                let name = expand.inst.to_ascii_lowercase();
+
+                if expand.data.is_empty() {
+                    name
+                } else {
+                    let args = expand
+                        .data
+                        .iter()
+                        .map(|data| substitute(data))
+                        .collect::<Vec<_>>();
+
+                    format!("{} {}", name, args.join(", "))
+                }
+            })
+            .collect()
+    }
+
     pub fn compile(
         &self,
         iset: &InstSet,
@@ -1117,6 +1467,13 @@ impl<'a> SignatureRef<'a> {
         }
     }
 
+    pub fn metadata(&self) -> &InstMetadata {
+        match self {
+            Self::Native(sig) => &sig.meta,
+            Self::Pseudo(sig) => &sig.meta,
+        }
+    }
+
     pub fn compile_ops(
         &self,
         binary: &Binary,
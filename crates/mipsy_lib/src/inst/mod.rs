@@ -4,4 +4,5 @@ pub mod register;
 pub use instruction::{
     ArgumentType, CompileSignature, GenericSignature, InstMetadata, InstSet, InstSignature,
     PseudoExpand, PseudoSignature, ReadsRegisterType, RuntimeMetadata, RuntimeSignature, Signature,
+    SignatureRef,
 };
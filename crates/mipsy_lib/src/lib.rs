@@ -1,6 +1,9 @@
+pub mod cache;
 pub mod compile;
 pub mod decompile;
+pub mod elf;
 pub mod error;
+pub mod format;
 pub mod inst;
 pub mod runtime;
 pub mod util;
@@ -16,11 +19,12 @@ pub use compile::{
     STACK_TOP, TEXT_BOT, TEXT_TOP,
 };
 pub use error::{
-    runtime::Uninitialised, CompilerError, MipsyError, MipsyResult, ParserError, RuntimeError,
+    runtime::Uninitialised, CompileResult, CompilerError, CompilerWarning, MipsyError, MipsyResult,
+    ParserError, RuntimeError,
 };
 pub use inst::instruction::{ArgumentType, InstSet};
 pub use inst::register::Register;
-use mipsy_parser::TaggedFile;
+use mipsy_parser::{ErrorLocation, TaggedFile};
 use mipsy_utils::MipsyConfig;
 pub use runtime::{Runtime, State};
 pub use util::Safe;
@@ -30,7 +34,16 @@ pub fn compile(
     files: Vec<TaggedFile<'_, '_>>,
     options: &CompilerOptions,
     config: &MipsyConfig,
-) -> MipsyResult<Binary> {
+) -> CompileResult<Binary> {
+    compile_with_warnings(iset, files, options, config).map(|(binary, _)| binary)
+}
+
+pub fn compile_with_warnings(
+    iset: &InstSet,
+    files: Vec<TaggedFile<'_, '_>>,
+    options: &CompilerOptions,
+    config: &MipsyConfig,
+) -> CompileResult<(Binary, Vec<CompilerWarning>)> {
     compile_with_kernel(iset, files, &mut compile::get_kernel(), options, config)
 }
 
@@ -40,15 +53,10 @@ pub fn compile_with_kernel(
     kernel: &mut MpProgram,
     options: &CompilerOptions,
     config: &MipsyConfig,
-) -> MipsyResult<Binary> {
-    let mut parsed = mipsy_parser::parse_mips(files, config.tab_size).map_err(|err| {
-        error::MipsyError::Parser(ParserError::new(
-            error::parser::Error::ParseFailure,
-            err.file_name.unwrap_or_else(|| Rc::from("")),
-            err.line,
-            err.col as u32,
-        ))
-    })?;
+) -> CompileResult<(Binary, Vec<CompilerWarning>)> {
+    let mut parsed =
+        mipsy_parser::parse_mips_with_defines(files, config.tab_size, options.defines())
+            .map_err(|errors| parser_errors(errors, config))?;
 
     let compiled = compile::compile_with_kernel(&mut parsed, kernel, options, config, iset)?;
 
@@ -57,10 +65,46 @@ pub fn compile_with_kernel(
 
 pub use compile::compile1;
 
+/// Links the kernel into a binary that was loaded without one, such as from an ELF executable.
+///
+/// The kernel starts the program at `entry` if there is one, or at `main` otherwise.
+pub fn link_kernel(
+    iset: &InstSet,
+    binary: &mut Binary,
+    kernel: &mut MpProgram,
+    entry: Option<u32>,
+    config: &MipsyConfig,
+) -> CompileResult<()> {
+    compile::link_kernel(binary, kernel, entry, config, iset)
+}
+
 pub fn decompile(iset: &InstSet, binary: &Binary) -> String {
     decompile::decompile(binary, iset)
 }
 
-pub fn runtime(binary: &Binary, args: &[&str]) -> Runtime {
-    runtime::Runtime::new(binary, args)
+/// Formats a source file in the style from the config, keeping its comments.
+pub fn format(file: TaggedFile<'_, '_>, config: &MipsyConfig) -> CompileResult<String> {
+    format::format(file, config)
+}
+
+pub fn runtime(binary: &Binary, args: &[&str], config: &MipsyConfig) -> Runtime {
+    runtime::Runtime::new(binary, args, config)
+}
+
+fn parser_errors(errors: Vec<ErrorLocation>, config: &MipsyConfig) -> Vec<MipsyError> {
+    errors
+        .into_iter()
+        .take(config.max_errors.max(1))
+        .map(|err| {
+            MipsyError::Parser(
+                ParserError::new(
+                    err.kind.into(),
+                    err.file_name.unwrap_or_else(|| Rc::from("")),
+                    err.line,
+                    err.col as u32,
+                )
+                .with_expansion(&err.expansion),
+            )
+        })
+        .collect()
 }
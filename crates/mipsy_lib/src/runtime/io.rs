@@ -0,0 +1,441 @@
+//! # Input and output for the syscalls
+//!
+//! The read syscalls pull bytes from an [`Input`],
+//! and the print syscalls push bytes to an [`Output`].
+//! Frontends pick the implementation: the real stdin/stdout,
+//! a file, or an in-memory buffer for scripted tests.
+//!
+//! ## End of input
+//!
+//! When the input runs out, each read syscall has a defined result:
+//!
+//! | syscall         | result at EOF                                |
+//! |-----------------|----------------------------------------------|
+//! | `read_int`      | `$v0` = [`EOF_READ_INT`], `$v1` = `1`        |
+//! | `read_float`    | `$f0` = [`EOF_READ_FLOAT`]                   |
+//! | `read_double`   | `$f0` = [`EOF_READ_DOUBLE`]                  |
+//! | `read_string`   | the buffer holds the empty string            |
+//! | `read_character`| `$v0` = `-1`, like C's `getchar`             |
+//!
+//! As `0` is also a valid int, `read_int` sets `$v1` to `1` at EOF,
+//! and leaves it alone otherwise -- a program that wants to tell the two
+//! apart can clear `$v1` before the syscall.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use super::{Runtime, RuntimeSyscallGuard};
+
+pub const EOF_READ_INT: i32 = 0;
+pub const EOF_READ_FLOAT: f32 = 0.0;
+pub const EOF_READ_DOUBLE: f64 = 0.0;
+
+/// # A source of bytes for the read syscalls.
+pub trait Input {
+    /// Looks at the next byte without consuming it,
+    /// or `None` if the input is exhausted.
+    fn peek_byte(&mut self) -> Option<u8>;
+
+    /// Consumes the next byte,
+    /// or `None` if the input is exhausted.
+    fn next_byte(&mut self) -> Option<u8>;
+}
+
+/// # A sink for the print syscalls.
+pub trait Output {
+    fn write_bytes(&mut self, bytes: &[u8]);
+
+    fn flush(&mut self) {}
+}
+
+/// # The process' standard input.
+pub struct StdinInput;
+
+impl Input for StdinInput {
+    fn peek_byte(&mut self) -> Option<u8> {
+        io::stdin().lock().fill_buf().ok()?.first().copied()
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        let mut stdin = io::stdin().lock();
+        let byte = stdin.fill_buf().ok()?.first().copied()?;
+        stdin.consume(1);
+
+        Some(byte)
+    }
+}
+
+/// # Any buffered reader, such as a file.
+pub struct ReaderInput<R> {
+    reader: R,
+}
+
+impl<R: BufRead> ReaderInput<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+}
+
+impl ReaderInput<BufReader<File>> {
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> Input for ReaderInput<R> {
+    fn peek_byte(&mut self) -> Option<u8> {
+        self.reader.fill_buf().ok()?.first().copied()
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        let byte = self.peek_byte()?;
+        self.reader.consume(1);
+
+        Some(byte)
+    }
+}
+
+/// # A predefined, in-memory input stream.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryInput {
+    bytes: Vec<u8>,
+    position: usize,
+}
+
+impl MemoryInput {
+    pub fn new(bytes: impl Into<Vec<u8>>) -> Self {
+        Self {
+            bytes: bytes.into(),
+            position: 0,
+        }
+    }
+
+    /// The bytes that have not been read yet.
+    pub fn remaining(&self) -> &[u8] {
+        &self.bytes[self.position..]
+    }
+}
+
+impl Input for MemoryInput {
+    fn peek_byte(&mut self) -> Option<u8> {
+        self.bytes.get(self.position).copied()
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        let byte = self.peek_byte()?;
+        self.position += 1;
+
+        Some(byte)
+    }
+}
+
+/// # The process' standard output.
+///
+/// Every write is flushed immediately, so that
+/// prompts show up before the program waits for input.
+pub struct StdoutOutput;
+
+impl Output for StdoutOutput {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(bytes).unwrap();
+        stdout.flush().unwrap();
+    }
+
+    fn flush(&mut self) {
+        io::stdout().flush().unwrap();
+    }
+}
+
+/// # Any writer, such as a file.
+pub struct WriterOutput<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> WriterOutput<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl WriterOutput<BufWriter<File>> {
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> Output for WriterOutput<W> {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        // there's nowhere sensible to report a failed write to,
+        // so the output is simply lost -- same as a closed stdout
+        let _ = self.writer.write_all(bytes);
+    }
+
+    fn flush(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+/// # Captures everything that is printed, in memory.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryOutput {
+    bytes: Vec<u8>,
+}
+
+impl MemoryOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl Output for MemoryOutput {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+}
+
+impl Output for Vec<u8> {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes);
+    }
+}
+
+/// # Why a value could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadError {
+    /// The input is exhausted.
+    Eof,
+    /// The next token could not be parsed -- it has been consumed.
+    Invalid(String),
+    /// The next token was an integer, but it doesn't fit in 32 bits.
+    TooBig(i128),
+}
+
+/// Reads the next whitespace-delimited token,
+/// or `None` if there are only whitespace bytes left.
+pub fn read_token(input: &mut dyn Input) -> Option<String> {
+    while input.peek_byte()?.is_ascii_whitespace() {
+        input.next_byte();
+    }
+
+    let mut token = Vec::new();
+    while let Some(byte) = input.peek_byte() {
+        if byte.is_ascii_whitespace() {
+            break;
+        }
+
+        token.push(byte);
+        input.next_byte();
+    }
+
+    Some(String::from_utf8_lossy(&token).into_owned())
+}
+
+/// Reads the rest of the current line, including the trailing newline (if any).
+pub fn read_line(input: &mut dyn Input) -> Result<Vec<u8>, ReadError> {
+    let mut line = Vec::new();
+
+    while let Some(byte) = input.next_byte() {
+        line.push(byte);
+
+        if byte == b'\n' {
+            break;
+        }
+    }
+
+    if line.is_empty() {
+        Err(ReadError::Eof)
+    } else {
+        Ok(line)
+    }
+}
+
+pub fn read_int(input: &mut dyn Input) -> Result<i32, ReadError> {
+    let token = read_token(input).ok_or(ReadError::Eof)?;
+
+    let n = token
+        .parse::<i128>()
+        .map_err(|_| ReadError::Invalid(token))?;
+
+    i32::try_from(n).map_err(|_| ReadError::TooBig(n))
+}
+
+pub fn read_float(input: &mut dyn Input) -> Result<f32, ReadError> {
+    let token = read_token(input).ok_or(ReadError::Eof)?;
+
+    token.parse().map_err(|_| ReadError::Invalid(token))
+}
+
+pub fn read_double(input: &mut dyn Input) -> Result<f64, ReadError> {
+    let token = read_token(input).ok_or(ReadError::Eof)?;
+
+    token.parse().map_err(|_| ReadError::Invalid(token))
+}
+
+/// Reads the next non-whitespace byte.
+pub fn read_char(input: &mut dyn Input) -> Result<u8, ReadError> {
+    loop {
+        match input.next_byte() {
+            Some(byte) if byte.is_ascii_whitespace() => continue,
+            Some(byte) => return Ok(byte),
+            None => return Err(ReadError::Eof),
+        }
+    }
+}
+
+/// Reads a value with `read`, skipping over any tokens that can't be read
+/// as `name`, after telling `bad_input` about them.
+///
+/// Returns `None` once the input is exhausted.
+pub fn read_retrying<T>(
+    input: &mut dyn Input,
+    name: &str,
+    read: fn(&mut dyn Input) -> Result<T, ReadError>,
+    bad_input: &mut dyn FnMut(&str, &ReadError),
+) -> Option<T> {
+    loop {
+        match read(input) {
+            Ok(value) => return Some(value),
+            Err(ReadError::Eof) => return None,
+            Err(err) => bad_input(name, &err),
+        }
+    }
+}
+
+impl RuntimeSyscallGuard {
+    /// Performs a print or read syscall using `input` and `output`,
+    /// with the [end of input](self#end-of-input) results described above.
+    ///
+    /// Any other syscall is given back, for the frontend to handle.
+    pub fn perform_io(
+        self,
+        input: &mut dyn Input,
+        output: &mut dyn Output,
+        bad_input: &mut dyn FnMut(&str, &ReadError),
+    ) -> Result<Runtime, Self> {
+        use RuntimeSyscallGuard::*;
+
+        let runtime = match self {
+            PrintInt(args, runtime) => {
+                output.write_bytes(args.value.to_string().as_bytes());
+                runtime
+            }
+            PrintFloat(args, runtime) => {
+                output.write_bytes(args.value.to_string().as_bytes());
+                runtime
+            }
+            PrintDouble(args, runtime) => {
+                output.write_bytes(args.value.to_string().as_bytes());
+                runtime
+            }
+            PrintString(args, runtime) => {
+                output.write_bytes(&args.value);
+                runtime
+            }
+            PrintChar(args, runtime) => {
+                output.write_bytes(&[args.value]);
+                runtime
+            }
+            ReadInt(guard) => {
+                output.flush();
+                guard(read_retrying(input, "int", read_int, bad_input))
+            }
+            ReadFloat(guard) => {
+                output.flush();
+                guard(read_retrying(input, "float", read_float, bad_input))
+            }
+            ReadDouble(guard) => {
+                output.flush();
+                guard(read_retrying(input, "double", read_double, bad_input))
+            }
+            ReadString(_args, guard) => {
+                output.flush();
+                guard(read_retrying(input, "string", read_line, bad_input).unwrap_or_default())
+            }
+            ReadChar(guard) => {
+                output.flush();
+                guard(read_retrying(input, "character", read_char, bad_input))
+            }
+            other => return Err(other),
+        };
+
+        Ok(runtime)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_int() {
+        let mut input = MemoryInput::new("  12\n-7 abc\t99999999999 ");
+
+        assert_eq!(read_int(&mut input), Ok(12));
+        assert_eq!(read_int(&mut input), Ok(-7));
+        assert_eq!(read_int(&mut input), Err(ReadError::Invalid("abc".into())));
+        assert_eq!(read_int(&mut input), Err(ReadError::TooBig(99999999999)));
+        assert_eq!(read_int(&mut input), Err(ReadError::Eof));
+    }
+
+    #[test]
+    fn test_read_line() {
+        let mut input = MemoryInput::new("hello world\nbye");
+
+        assert_eq!(read_line(&mut input), Ok(b"hello world\n".to_vec()));
+        assert_eq!(read_line(&mut input), Ok(b"bye".to_vec()));
+        assert_eq!(read_line(&mut input), Err(ReadError::Eof));
+    }
+
+    #[test]
+    fn test_read_char() {
+        let mut input = MemoryInput::new("a\n b");
+
+        assert_eq!(read_char(&mut input), Ok(b'a'));
+        assert_eq!(read_char(&mut input), Ok(b'b'));
+        assert_eq!(read_char(&mut input), Err(ReadError::Eof));
+    }
+
+    #[test]
+    fn test_read_retrying() {
+        let mut input = MemoryInput::new("x 1.5 7");
+        let mut bad = vec![];
+
+        let number = read_retrying(&mut input, "int", read_int, &mut |name, err| {
+            bad.push((name.to_string(), err.clone()))
+        });
+
+        assert_eq!(number, Some(7));
+        assert_eq!(
+            bad,
+            vec![
+                (String::from("int"), ReadError::Invalid("x".into())),
+                (String::from("int"), ReadError::Invalid("1.5".into())),
+            ]
+        );
+        assert_eq!(
+            read_retrying(&mut input, "int", read_int, &mut |_, _| {}),
+            None
+        );
+    }
+
+    #[test]
+    fn test_memory_output() {
+        let mut output = MemoryOutput::new();
+        output.write_bytes(b"1");
+        output.write_bytes(b"2\n");
+
+        assert_eq!(output.bytes(), b"12\n");
+    }
+}
@@ -1,3 +1,4 @@
+pub mod io;
 pub mod state;
 
 pub use self::state::State;
@@ -11,6 +12,7 @@ use crate::{
     Binary, MipsyError, MipsyResult, Register, RuntimeError, Safe, Uninitialised, DATA_BOT,
     HEAP_BOT, KDATA_BOT, KTEXT_BOT, STACK_PTR, TEXT_BOT,
 };
+use mipsy_utils::MipsyConfig;
 use std::cmp::Ordering;
 
 use crate::util::{get_segment, Segment};
@@ -37,6 +39,33 @@ pub const SYS15_WRITE: i32 = 15;
 pub const SYS16_CLOSE: i32 = 16;
 pub const SYS17_EXIT_STATUS: i32 = 17;
 
+// MARS-compatible extended syscalls, only available with `MipsyConfig::mars`
+pub const SYS30_TIME: i32 = 30;
+pub const SYS31_MIDI_OUT: i32 = 31;
+pub const SYS32_SLEEP: i32 = 32;
+pub const SYS33_MIDI_OUT_SYNC: i32 = 33;
+pub const SYS34_PRINT_INT_HEX: i32 = 34;
+pub const SYS35_PRINT_INT_BINARY: i32 = 35;
+pub const SYS36_PRINT_INT_UNSIGNED: i32 = 36;
+pub const SYS40_SET_SEED: i32 = 40;
+pub const SYS41_RANDOM_INT: i32 = 41;
+pub const SYS42_RANDOM_INT_RANGE: i32 = 42;
+pub const SYS50_CONFIRM_DIALOG: i32 = 50;
+pub const SYS51_INPUT_DIALOG_INT: i32 = 51;
+pub const SYS52_INPUT_DIALOG_FLOAT: i32 = 52;
+pub const SYS53_INPUT_DIALOG_DOUBLE: i32 = 53;
+pub const SYS54_INPUT_DIALOG_STRING: i32 = 54;
+pub const SYS55_MESSAGE_DIALOG: i32 = 55;
+pub const SYS56_MESSAGE_DIALOG_INT: i32 = 56;
+pub const SYS57_MESSAGE_DIALOG_FLOAT: i32 = 57;
+pub const SYS58_MESSAGE_DIALOG_DOUBLE: i32 = 58;
+pub const SYS59_MESSAGE_DIALOG_STRING: i32 = 59;
+
+/// Whether the given syscall number is one of the MARS-compatible extended syscalls
+pub fn is_mars_syscall(syscall: i32) -> bool {
+    matches!(syscall, SYS30_TIME..=SYS36_PRINT_INT_UNSIGNED | SYS40_SET_SEED..=SYS42_RANDOM_INT_RANGE | SYS50_CONFIRM_DIALOG..=SYS59_MESSAGE_DIALOG_STRING)
+}
+
 pub const SPECIAL: u32 = 0b000000;
 pub const SPECIAL2: u32 = 0b011100;
 pub const SPECIAL3: u32 = 0b011111;
@@ -56,6 +85,7 @@ macro_rules! try_owned_self {
 #[derive(Default)]
 pub struct Runtime {
     timeline: Timeline,
+    mars: bool,
 }
 
 impl Runtime {
@@ -63,6 +93,11 @@ impl Runtime {
         &self.timeline
     }
 
+    /// Whether the MARS-compatible extended syscalls are enabled
+    pub fn mars(&self) -> bool {
+        self.mars
+    }
+
     pub fn timeline_mut(&mut self) -> &mut Timeline {
         &mut self.timeline
     }
@@ -173,13 +208,17 @@ impl Runtime {
 
     // remove when floating point syscalls are finished
     #[allow(unreachable_code)]
-    fn syscall(mut self) -> Result<RuntimeSyscallGuard, (Runtime, MipsyError)> {
+    fn syscall(mut self) -> Result<SteppedRuntime, (Runtime, MipsyError)> {
         let syscall = try_owned_self!(
             self,
             self.timeline.state().read_register(Register::V0.to_u32())
         );
 
-        Ok(match syscall {
+        if self.mars && is_mars_syscall(syscall) {
+            return self.mars_syscall(syscall);
+        }
+
+        Ok(Err(match syscall {
             SYS1_PRINT_INT => {
                 let value = try_owned_self!(
                     self,
@@ -230,9 +269,14 @@ impl Runtime {
                 RuntimeSyscallGuard::PrintString(PrintStringArgs { value }, self)
             }
             SYS5_READ_INT => RuntimeSyscallGuard::ReadInt(Box::new(move |value| {
-                self.timeline
-                    .state_mut()
-                    .write_register(Register::V0.to_u32(), value);
+                let state = self.timeline.state_mut();
+                state.write_register(Register::V0.to_u32(), value.unwrap_or(io::EOF_READ_INT));
+
+                // see the end of input section of `io`
+                if value.is_none() {
+                    state.write_register(Register::V1.to_u32(), 1);
+                }
+
                 self
             })),
             SYS6_READ_FLOAT => {
@@ -319,9 +363,10 @@ impl Runtime {
                 self,
             ),
             SYS12_READ_CHAR => RuntimeSyscallGuard::ReadChar(Box::new(move |value| {
+                // at EOF, read_character gives -1 (like C's getchar)
                 self.timeline
                     .state_mut()
-                    .write_register(Register::V0.to_u32(), value as _);
+                    .write_register(Register::V0.to_u32(), value.map_or(-1, |value| value as _));
                 self
             })),
             SYS13_OPEN => RuntimeSyscallGuard::Open(
@@ -445,7 +490,235 @@ impl Runtime {
                     })),
                 ));
             }
-        })
+        }))
+    }
+
+    fn mars_syscall(mut self, syscall: i32) -> Result<SteppedRuntime, (Runtime, MipsyError)> {
+        Ok(Err(match syscall {
+            SYS30_TIME => RuntimeSyscallGuard::Time(Box::new(move |millis| {
+                let state = self.timeline.state_mut();
+                state.write_register(Register::A0.to_u32(), millis as u32 as _);
+                state.write_register(Register::A1.to_u32(), (millis >> 32) as u32 as _);
+                self
+            })),
+            SYS31_MIDI_OUT | SYS33_MIDI_OUT_SYNC => {
+                // mipsy has no sound output, so MIDI is a no-op
+                return Ok(Ok(self));
+            }
+            SYS32_SLEEP => RuntimeSyscallGuard::Sleep(
+                SleepArgs {
+                    millis: try_owned_self!(
+                        self,
+                        self.timeline.state().read_register(Register::A0.to_u32())
+                    )
+                    .max(0) as _,
+                },
+                self,
+            ),
+            SYS34_PRINT_INT_HEX | SYS35_PRINT_INT_BINARY | SYS36_PRINT_INT_UNSIGNED => {
+                let value = try_owned_self!(
+                    self,
+                    self.timeline.state().read_register(Register::A0.to_u32())
+                ) as u32;
+
+                let value = match syscall {
+                    SYS34_PRINT_INT_HEX => format!("0x{:08x}", value),
+                    SYS35_PRINT_INT_BINARY => format!("{:032b}", value),
+                    _ => value.to_string(),
+                };
+
+                RuntimeSyscallGuard::PrintString(
+                    PrintStringArgs {
+                        value: value.into_bytes(),
+                    },
+                    self,
+                )
+            }
+            SYS40_SET_SEED => {
+                let id = try_owned_self!(
+                    self,
+                    self.timeline.state().read_register(Register::A0.to_u32())
+                ) as u32;
+                let seed = try_owned_self!(
+                    self,
+                    self.timeline.state().read_register(Register::A1.to_u32())
+                ) as u32;
+
+                self.timeline.state_mut().set_random_seed(id, seed as u64);
+
+                return Ok(Ok(self));
+            }
+            SYS41_RANDOM_INT => {
+                let id = try_owned_self!(
+                    self,
+                    self.timeline.state().read_register(Register::A0.to_u32())
+                ) as u32;
+
+                let state = self.timeline.state_mut();
+                let value = state.next_random(id);
+                state.write_register(Register::A0.to_u32(), value as _);
+
+                return Ok(Ok(self));
+            }
+            SYS42_RANDOM_INT_RANGE => {
+                let id = try_owned_self!(
+                    self,
+                    self.timeline.state().read_register(Register::A0.to_u32())
+                ) as u32;
+                let upper_bound = try_owned_self!(
+                    self,
+                    self.timeline.state().read_register(Register::A1.to_u32())
+                );
+
+                if upper_bound <= 0 {
+                    return Err((
+                        self,
+                        MipsyError::Runtime(RuntimeError::new(Error::InvalidSyscall {
+                            syscall,
+                            reason: InvalidSyscallReason::InvalidArgument,
+                        })),
+                    ));
+                }
+
+                let state = self.timeline.state_mut();
+                let value = (state.next_random(id) as u64 * upper_bound as u64) >> 32;
+                state.write_register(Register::A0.to_u32(), value as _);
+
+                return Ok(Ok(self));
+            }
+            SYS50_CONFIRM_DIALOG => RuntimeSyscallGuard::ConfirmDialog(
+                DialogArgs {
+                    message: try_owned_self!(self, self.read_dialog_message()),
+                },
+                Box::new(move |choice| {
+                    self.timeline
+                        .state_mut()
+                        .write_register(Register::A0.to_u32(), choice as _);
+                    self
+                }),
+            ),
+            SYS51_INPUT_DIALOG_INT => RuntimeSyscallGuard::InputDialogInt(
+                DialogArgs {
+                    message: try_owned_self!(self, self.read_dialog_message()),
+                },
+                Box::new(move |value| {
+                    let state = self.timeline.state_mut();
+
+                    match value {
+                        Some(value) => {
+                            state.write_register(Register::A0.to_u32(), value);
+                            state.write_register(Register::A1.to_u32(), DIALOG_STATUS_OK);
+                        }
+                        None => {
+                            state.write_register(Register::A1.to_u32(), DIALOG_STATUS_CANCELLED);
+                        }
+                    }
+
+                    self
+                }),
+            ),
+            SYS54_INPUT_DIALOG_STRING => {
+                let message = try_owned_self!(self, self.read_dialog_message());
+                let buf = try_owned_self!(
+                    self,
+                    self.timeline.state().read_register(Register::A1.to_u32())
+                ) as u32;
+                let len = try_owned_self!(
+                    self,
+                    self.timeline.state().read_register(Register::A2.to_u32())
+                ) as u32;
+
+                RuntimeSyscallGuard::InputDialogString(
+                    InputDialogStringArgs {
+                        message,
+                        max_len: len,
+                    },
+                    Box::new(move |string| {
+                        let status = match string {
+                            None => DIALOG_STATUS_CANCELLED,
+                            Some(string) if string.is_empty() => DIALOG_STATUS_NO_DATA,
+                            Some(mut string) => {
+                                let max_bytes = len.saturating_sub(1) as usize;
+                                let status = if string.len() > max_bytes {
+                                    string.truncate(max_bytes);
+                                    DIALOG_STATUS_TRUNCATED
+                                } else {
+                                    DIALOG_STATUS_OK
+                                };
+
+                                if len > 0 {
+                                    string.push(0);
+
+                                    for (i, byte) in string.into_iter().enumerate() {
+                                        // if there's a segmentation fault, we just don't end up writing the data
+                                        let _ = self
+                                            .timeline
+                                            .state_mut()
+                                            .write_mem_byte(buf + i as u32, byte);
+                                    }
+                                }
+
+                                status
+                            }
+                        };
+
+                        self.timeline
+                            .state_mut()
+                            .write_register(Register::A1.to_u32(), status);
+                        self
+                    }),
+                )
+            }
+            SYS55_MESSAGE_DIALOG | SYS56_MESSAGE_DIALOG_INT | SYS59_MESSAGE_DIALOG_STRING => {
+                // dialogs are mapped onto the console, so a message dialog is just a print
+                let mut value = try_owned_self!(self, self.read_dialog_message());
+
+                match syscall {
+                    SYS56_MESSAGE_DIALOG_INT => {
+                        let int = try_owned_self!(
+                            self,
+                            self.timeline.state().read_register(Register::A1.to_u32())
+                        );
+                        value.extend(int.to_string().into_bytes());
+                    }
+                    SYS59_MESSAGE_DIALOG_STRING => {
+                        let string = try_owned_self!(
+                            self,
+                            self.timeline.state().read_mem_string(try_owned_self!(
+                                self,
+                                self.timeline.state().read_register(Register::A1.to_u32())
+                            )
+                                as _)
+                        );
+                        value.extend(string);
+                    }
+                    _ => {}
+                }
+
+                value.push(b'\n');
+
+                RuntimeSyscallGuard::PrintString(PrintStringArgs { value }, self)
+            }
+            SYS52_INPUT_DIALOG_FLOAT
+            | SYS53_INPUT_DIALOG_DOUBLE
+            | SYS57_MESSAGE_DIALOG_FLOAT
+            | SYS58_MESSAGE_DIALOG_DOUBLE => {
+                return Err((
+                    self,
+                    MipsyError::Runtime(RuntimeError::new(Error::InvalidSyscall {
+                        syscall,
+                        reason: InvalidSyscallReason::Unimplemented,
+                    })),
+                ));
+            }
+            _ => unreachable!("not a MARS syscall"),
+        }))
+    }
+
+    fn read_dialog_message(&self) -> MipsyResult<Vec<u8>> {
+        let state = self.timeline.state();
+
+        state.read_mem_string(state.read_register(Register::A0.to_u32())? as _)
     }
 
     fn execute_r(
@@ -461,7 +734,7 @@ impl Runtime {
 
         match (special, funct) {
             // SYSCALL
-            (SPECIAL, 0x0C) => Ok(Err(self.syscall()?)),
+            (SPECIAL, 0x0C) => self.syscall(),
 
             // BREAK
             (SPECIAL, 0x0D) => Ok(Err(RuntimeSyscallGuard::Breakpoint(self))),
@@ -1451,25 +1724,48 @@ pub enum RuntimeSyscallGuard {
     PrintFloat(PrintFloatArgs, Runtime),
     PrintDouble(PrintDoubleArgs, Runtime),
     PrintString(PrintStringArgs, Runtime),
-    ReadInt(Box<dyn FnOnce(i32) -> Runtime>),
-    ReadFloat(Box<dyn FnOnce(f32) -> Runtime>),
-    ReadDouble(Box<dyn FnOnce(f64) -> Runtime>),
+    /// `None` if the input is exhausted
+    ReadInt(Box<dyn FnOnce(Option<i32>) -> Runtime>),
+    /// `None` if the input is exhausted
+    ReadFloat(Box<dyn FnOnce(Option<f32>) -> Runtime>),
+    /// `None` if the input is exhausted
+    ReadDouble(Box<dyn FnOnce(Option<f64>) -> Runtime>),
     ReadString(ReadStringArgs, Box<dyn FnOnce(Vec<u8>) -> Runtime>),
     Sbrk(SbrkArgs, Runtime),
     Exit(Runtime),
     PrintChar(PrintCharArgs, Runtime),
-    ReadChar(Box<dyn FnOnce(u8) -> Runtime>),
+    ReadChar(Box<dyn FnOnce(Option<u8>) -> Runtime>),
     Open(OpenArgs, Box<dyn FnOnce(i32) -> Runtime>),
     Read(ReadArgs, Box<dyn FnOnce((i32, Vec<u8>)) -> Runtime>),
     Write(WriteArgs, Box<dyn FnOnce(i32) -> Runtime>),
     Close(CloseArgs, Box<dyn FnOnce(i32) -> Runtime>),
     ExitStatus(ExitStatusArgs, Runtime),
 
+    // MARS extensions
+    Time(Box<dyn FnOnce(u64) -> Runtime>),
+    Sleep(SleepArgs, Runtime),
+    ConfirmDialog(DialogArgs, Box<dyn FnOnce(i32) -> Runtime>),
+    InputDialogInt(DialogArgs, Box<dyn FnOnce(Option<i32>) -> Runtime>),
+    InputDialogString(
+        InputDialogStringArgs,
+        Box<dyn FnOnce(Option<Vec<u8>>) -> Runtime>,
+    ),
+
     // other
     Breakpoint(Runtime),
     Trap(Runtime),
 }
 
+/// The values given to `ConfirmDialog` guards, following MARS
+pub const CONFIRM_DIALOG_YES: i32 = 0;
+pub const CONFIRM_DIALOG_NO: i32 = 1;
+pub const CONFIRM_DIALOG_CANCEL: i32 = 2;
+
+const DIALOG_STATUS_OK: i32 = 0;
+const DIALOG_STATUS_CANCELLED: i32 = -2;
+const DIALOG_STATUS_NO_DATA: i32 = -3;
+const DIALOG_STATUS_TRUNCATED: i32 = -4;
+
 pub struct PrintIntArgs {
     pub value: i32,
 }
@@ -1522,6 +1818,19 @@ pub struct ExitStatusArgs {
     pub exit_code: i32,
 }
 
+pub struct SleepArgs {
+    pub millis: u32,
+}
+
+pub struct DialogArgs {
+    pub message: Vec<u8>,
+}
+
+pub struct InputDialogStringArgs {
+    pub message: Vec<u8>,
+    pub max_len: u32,
+}
+
 pub(self) trait SafeToUninitResult {
     type Output;
 
@@ -1761,10 +2070,11 @@ impl Runtime {
 
         Self {
             timeline: Timeline::new(initial_state),
+            mars: false,
         }
     }
 
-    pub fn new(program: &Binary, args: &[&str]) -> Self {
+    pub fn new(program: &Binary, args: &[&str], config: &MipsyConfig) -> Self {
         let mut initial_state = State::default();
 
         Self::fill_all_state(TEXT_BOT, &program.text, &mut initial_state);
@@ -1781,6 +2091,7 @@ impl Runtime {
 
         Self {
             timeline: Timeline::new(initial_state),
+            mars: config.mars,
         }
     }
 
@@ -115,6 +115,7 @@ pub struct State {
     pub(super) hi: Safe<i32>,
     pub(super) lo: Safe<i32>,
     pub(super) heap_size: u32,
+    pub(super) random_generators: HashMap<u32, u64>,
 }
 
 impl State {
@@ -134,6 +135,30 @@ impl State {
         self.heap_size = heap_size;
     }
 
+    /// Seeds the pseudo-random number generator with the given id
+    /// (MARS syscall 40).
+    pub fn set_random_seed(&mut self, id: u32, seed: u64) {
+        self.random_generators.insert(id, seed);
+    }
+
+    /// Gets the next value from the pseudo-random number generator with the given id
+    /// (MARS syscalls 41 and 42).
+    ///
+    /// Generators that have never been seeded start from their id,
+    /// so that programs which never call syscall 40 are still reproducible.
+    pub fn next_random(&mut self, id: u32) -> u32 {
+        // splitmix64
+        let generator = self.random_generators.entry(id).or_insert(id as u64);
+        *generator = generator.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = *generator;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        (z >> 32) as u32
+    }
+
     pub fn write_marker(&self) -> u64 {
         self.write_marker
     }
@@ -469,6 +494,7 @@ impl Clone for State {
             hi: self.hi,
             lo: self.lo,
             heap_size: self.heap_size,
+            random_generators: self.random_generators.clone(),
         }
     }
 }
@@ -483,6 +509,7 @@ impl Default for State {
             write_marker: 0,
             hi: Default::default(),
             lo: Default::default(),
+            random_generators: HashMap::new(),
         }
     }
 }
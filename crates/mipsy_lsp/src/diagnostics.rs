@@ -0,0 +1,260 @@
+//! Compiles a document with mipsy, turning any errors and warnings into diagnostics.
+
+use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range};
+use mipsy_lib::{compile::CompilerOptions, InstSet, MipsyError};
+use mipsy_parser::TaggedFile;
+use mipsy_utils::MipsyConfig;
+
+use crate::symbols::{byte_col, utf16_col};
+
+const SOURCE: &str = "mipsy";
+
+/// Diagnoses the first of `files`, the rest being any files it `.include`s.
+pub fn diagnose(
+    files: &[(String, String)],
+    iset: &InstSet,
+    config: &MipsyConfig,
+) -> Vec<Diagnostic> {
+    let (tag, source) = &files[0];
+
+    let tab_size =
+        mipsy_parser::parse_mips_lossless(TaggedFile::new(None, source), config.tab_size)
+            .map(|program| program.tab_size())
+            .unwrap_or(config.tab_size);
+
+    let range = |line: u32, col: u32, col_end: u32| {
+        // mipsy's lines and columns start at 1, and columns count tabs as `tab_size` wide
+        let line = line.saturating_sub(1);
+        let text = source.lines().nth(line as usize).unwrap_or("");
+
+        let start = raw_col(text, col.saturating_sub(1), tab_size);
+        let end = raw_col(text, col_end.saturating_sub(1), tab_size).max(start);
+
+        Range::new(
+            Position::new(line, utf16_col(text, start)),
+            Position::new(line, utf16_col(text, end)),
+        )
+    };
+
+    let tagged_files = files
+        .iter()
+        .map(|(tag, source)| TaggedFile::new(Some(tag), source))
+        .collect();
+
+    let (errors, warnings) = match mipsy_lib::compile_with_warnings(
+        iset,
+        tagged_files,
+        &CompilerOptions::new(vec![]),
+        config,
+    ) {
+        Ok((_, warnings)) => (vec![], warnings),
+        Err(errors) => (errors, vec![]),
+    };
+
+    let mut diagnostics = vec![];
+
+    for error in errors {
+        let (file_tag, line, col, col_end, message) = match error {
+            // parse errors point at where the parser gave up, so take the rest of the line
+            MipsyError::Parser(error) => (
+                error.file_tag(),
+                error.line(),
+                error.col(),
+                u32::MAX,
+                with_tips(error.error().message(), error.error().tips()),
+            ),
+            // an error that isn't on any line gets line 0
+            MipsyError::Compiler(error) if !error.error().should_highlight_line() => (
+                error.file_tag(),
+                0,
+                0,
+                0,
+                with_tips(error.error().message(), error.error().tips()),
+            ),
+            MipsyError::Compiler(error) => (
+                error.file_tag(),
+                error.line(),
+                error.col(),
+                error.col_end(),
+                with_tips(error.error().message(), error.error().tips()),
+            ),
+            _ => continue,
+        };
+
+        let (range, message) = if *file_tag == **tag || line == 0 {
+            let range = match line {
+                0 => Range::default(),
+                _ => range(line, col, col_end),
+            };
+
+            (range, message)
+        } else {
+            // an error in an included file goes on the `.include` that brings it in
+            let include = mipsy_parser::include_lines(Some(tag), source)
+                .into_iter()
+                .find(|(_, path)| includes_file(files, path, &file_tag));
+
+            let index = match include {
+                Some((index, _)) => index,
+                None => continue,
+            };
+
+            let text = source.lines().nth(index).unwrap_or("");
+            let start = text.len() - text.trim_start().len();
+            let end = text.trim_end().len();
+            let range = Range::new(
+                Position::new(index as u32, utf16_col(text, start)),
+                Position::new(index as u32, utf16_col(text, end)),
+            );
+
+            (range, format!("{}:{}:{}: {}", file_tag, line, col, message))
+        };
+
+        diagnostics.push(Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some(SOURCE.into()),
+            message,
+            ..Diagnostic::default()
+        });
+    }
+
+    for warning in warnings {
+        if *warning.file_tag() != **tag {
+            continue;
+        }
+
+        diagnostics.push(Diagnostic {
+            range: range(warning.line(), warning.col(), warning.col_end()),
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String(warning.warning().code().into())),
+            source: Some(SOURCE.into()),
+            message: with_tips(warning.warning().message(), warning.warning().tips()),
+            ..Diagnostic::default()
+        });
+    }
+
+    diagnostics
+}
+
+/// Whether `path`, or any file it (indirectly) includes, is `target`.
+fn includes_file(files: &[(String, String)], path: &str, target: &str) -> bool {
+    let mut found = vec![path.to_string()];
+    let mut index = 0;
+
+    while index < found.len() {
+        if found[index] == target {
+            return true;
+        }
+
+        if let Some((tag, source)) = files.iter().find(|(tag, _)| *tag == found[index]) {
+            for (_, path) in mipsy_parser::include_lines(Some(tag), source) {
+                if !found.contains(&path) {
+                    found.push(path);
+                }
+            }
+        }
+
+        index += 1;
+    }
+
+    false
+}
+
+fn with_tips(mut message: String, tips: Vec<String>) -> String {
+    for tip in tips {
+        message.push_str("\n\ntip: ");
+        message.push_str(tip.trim_end());
+    }
+
+    message
+}
+
+/// The byte offset of a (zero-based) column where tabs are `tab_size` wide.
+fn raw_col(line: &str, col: u32, tab_size: u32) -> usize {
+    let mut width = 0;
+
+    for (index, char) in line.char_indices() {
+        if width >= col {
+            return index;
+        }
+
+        width = match char {
+            '\t' => (width / tab_size.max(1) + 1) * tab_size.max(1),
+            _ => width + 1,
+        };
+    }
+
+    byte_col(line, u32::MAX)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn diagnose_source(source: &str) -> Vec<Diagnostic> {
+        diagnose(
+            &[(String::from("test.s"), String::from(source))],
+            &mipsy_instructions::inst_set(),
+            &MipsyConfig::default(),
+        )
+    }
+
+    #[test]
+    fn test_errors() {
+        let diagnostics = diagnose_source("main:\n\taddd\t$t0, $t1, $t2\n\tjr $ra\n");
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(1, 1), Position::new(1, 19))
+        );
+        assert!(diagnostics[0].message.contains("addd"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let diagnostics = diagnose_source("main:\n  li $t0, 1 2\n  jr $ra\n");
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start, Position::new(1, 12));
+        assert!(diagnostics[0].message.contains("tip: "));
+    }
+
+    #[test]
+    fn test_included_errors() {
+        let files = [
+            (
+                String::from("dir/main.s"),
+                String::from("main:\n  .include \"a.s\"\n  jr $ra\n"),
+            ),
+            (String::from("dir/a.s"), String::from(".include \"b.s\"\n")),
+            (
+                String::from("dir/b.s"),
+                String::from("\n  addd $t0, $t1, $t2\n"),
+            ),
+        ];
+
+        let diagnostics = diagnose(
+            &files,
+            &mipsy_instructions::inst_set(),
+            &MipsyConfig::default(),
+        );
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(1, 2), Position::new(1, 16))
+        );
+        assert!(diagnostics[0].message.starts_with("dir/b.s:2:3: "));
+    }
+
+    #[test]
+    fn test_raw_col() {
+        assert_eq!(raw_col("\tadd", 8, 8), 1);
+        assert_eq!(raw_col("\tadd", 4, 4), 1);
+        assert_eq!(raw_col("ab\tc", 8, 8), 3);
+        assert_eq!(raw_col("ab", 10, 8), 2);
+    }
+}
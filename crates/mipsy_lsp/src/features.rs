@@ -0,0 +1,317 @@
+//! Hover, completion and signature help, which all work from
+//! the instruction set and a lexical pass over the document.
+
+use lsp_types::{
+    CompletionItem, CompletionItemKind, Hover, HoverContents, MarkupContent, MarkupKind,
+    ParameterInformation, ParameterLabel, Position, SignatureHelp, SignatureInformation,
+};
+use mipsy_lib::{inst::CompileSignature, InstSet, Register};
+
+use crate::symbols::{self, byte_col, Token, TokenKind};
+
+/// Every signature of the instruction `name`, native instructions first.
+fn signatures<'a>(iset: &'a InstSet, name: &str) -> Vec<(&'a str, &'a CompileSignature)> {
+    let name = name.to_ascii_lowercase();
+
+    let native = iset
+        .native_set()
+        .iter()
+        .filter(|inst| inst.name() == name)
+        .map(|inst| (inst.name(), inst.compile_signature()));
+
+    let pseudo = iset
+        .pseudo_set()
+        .iter()
+        .filter(|inst| inst.name() == name)
+        .map(|inst| (inst.name(), inst.compile_signature()));
+
+    native.chain(pseudo).collect()
+}
+
+/// How a signature is written, like `add $Rd, $Rs, $Rt`,
+/// along with where each of its parameters is in that text.
+fn signature_label(name: &str, signature: &CompileSignature) -> (String, Vec<[u32; 2]>) {
+    let mut label = name.to_string();
+    let mut parameters = vec![];
+
+    for (index, arg) in signature.format().iter().enumerate() {
+        label.push_str(if index == 0 { " " } else { ", " });
+
+        let arg = if signature.relative_label() && index == signature.format().len() - 1 {
+            String::from("label")
+        } else {
+            arg.to_string()
+        };
+
+        let start = label.len() as u32;
+        label.push_str(&arg);
+        parameters.push([start, label.len() as u32]);
+    }
+
+    (label, parameters)
+}
+
+pub fn hover(source: &str, position: Position, iset: &InstSet) -> Option<Hover> {
+    let tokens = symbols::tokenize(source);
+    let token = symbols::token_at(&tokens, position)?;
+
+    if token.kind != TokenKind::Mnemonic {
+        return None;
+    }
+
+    let signatures = signatures(iset, token.text);
+    if signatures.is_empty() {
+        return None;
+    }
+
+    let mut text = String::new();
+
+    let metadata = iset
+        .native_set()
+        .iter()
+        .filter(|inst| inst.name() == token.text.to_ascii_lowercase())
+        .map(|inst| inst.metadata())
+        .find(|metadata| metadata.desc_short().is_some());
+
+    if let Some(metadata) = metadata {
+        if let Some(desc_short) = metadata.desc_short() {
+            text.push_str(desc_short.trim());
+            text.push_str("\n\n");
+        }
+
+        if let Some(desc_long) = metadata.desc_long() {
+            text.push_str(desc_long.trim());
+            text.push_str("\n\n");
+        }
+    }
+
+    text.push_str("```mips\n");
+    for (name, signature) in signatures {
+        text.push_str(&signature_label(name, signature).0);
+        text.push('\n');
+    }
+    text.push_str("```");
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: text,
+        }),
+        range: Some(token.range()),
+    })
+}
+
+pub fn completion(source: &str, position: Position, iset: &InstSet) -> Vec<CompletionItem> {
+    let tokens = symbols::tokenize(source);
+    let line = source.lines().nth(position.line as usize).unwrap_or("");
+    let before = &line[..byte_col(line, position.character)];
+
+    let registers = || {
+        Register::all().into_iter().map(|register| CompletionItem {
+            label: format!("${}", register.to_lower_str()),
+            kind: Some(CompletionItemKind::VARIABLE),
+            ..CompletionItem::default()
+        })
+    };
+
+    // registers are completed after their `$`
+    if before
+        .rsplit(|char: char| !(char.is_ascii_alphanumeric() || char == '$'))
+        .next()
+        .is_some_and(|word| word.starts_with('$'))
+    {
+        return registers().collect();
+    }
+
+    let on_line = tokens
+        .iter()
+        .filter(|token| token.line == position.line)
+        .collect::<Vec<_>>();
+
+    let in_operands = on_line.iter().any(|token| {
+        token.kind == TokenKind::Mnemonic && token.range().end.character < position.character
+    });
+
+    if !in_operands {
+        let mut names = iset
+            .native_set()
+            .iter()
+            .map(|inst| (inst.name(), inst.metadata().desc_short()))
+            .chain(iset.pseudo_set().iter().map(|inst| (inst.name(), None)))
+            .collect::<Vec<_>>();
+
+        names.sort_by_key(|&(name, _)| name);
+        names.dedup_by_key(|&mut (name, _)| name);
+
+        return names
+            .into_iter()
+            .map(|(name, desc_short)| CompletionItem {
+                label: name.to_string(),
+                kind: Some(CompletionItemKind::KEYWORD),
+                detail: desc_short.map(String::from),
+                ..CompletionItem::default()
+            })
+            .collect();
+    }
+
+    let mut definitions = tokens
+        .iter()
+        .filter(|token| token.is_definition())
+        .collect::<Vec<&Token<'_>>>();
+
+    definitions.sort_by_key(|token| token.text);
+    definitions.dedup_by_key(|token| token.text);
+
+    definitions
+        .into_iter()
+        .map(|token| CompletionItem {
+            label: token.text.to_string(),
+            kind: Some(match token.kind {
+                TokenKind::Constant => CompletionItemKind::CONSTANT,
+                _ => CompletionItemKind::REFERENCE,
+            }),
+            ..CompletionItem::default()
+        })
+        .chain(registers())
+        .collect()
+}
+
+pub fn signature_help(source: &str, position: Position, iset: &InstSet) -> Option<SignatureHelp> {
+    let tokens = symbols::tokenize(source);
+    let mnemonic = tokens.iter().find(|token| {
+        token.line == position.line
+            && token.kind == TokenKind::Mnemonic
+            && token.range().end.character < position.character
+    })?;
+
+    let signatures = signatures(iset, mnemonic.text);
+    if signatures.is_empty() {
+        return None;
+    }
+
+    let line = source.lines().nth(position.line as usize).unwrap_or("");
+    let operands = &line[mnemonic.end..byte_col(line, position.character).max(mnemonic.end)];
+    let active_parameter = count_operand_commas(operands);
+
+    let signatures = signatures
+        .into_iter()
+        .map(|(name, signature)| {
+            let (label, parameters) = signature_label(name, signature);
+
+            SignatureInformation {
+                label,
+                documentation: None,
+                parameters: Some(
+                    parameters
+                        .into_iter()
+                        .map(|offsets| ParameterInformation {
+                            label: ParameterLabel::LabelOffsets(offsets),
+                            documentation: None,
+                        })
+                        .collect(),
+                ),
+                active_parameter: None,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // prefer the first signature that has enough operands for where the cursor is
+    let active_signature = signatures
+        .iter()
+        .position(|signature| {
+            signature
+                .parameters
+                .as_ref()
+                .is_some_and(|parameters| parameters.len() as u32 > active_parameter)
+        })
+        .unwrap_or(0);
+
+    Some(SignatureHelp {
+        signatures,
+        active_signature: Some(active_signature as u32),
+        active_parameter: Some(active_parameter),
+    })
+}
+
+/// The commas separating operands, leaving any in strings or parentheses.
+fn count_operand_commas(operands: &str) -> u32 {
+    let mut commas = 0;
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+
+    for char in operands.chars() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if char == '\\' => escaped = true,
+            Some(open) if char == open => quote = None,
+            Some(_) => {}
+            None => match char {
+                '"' | '\'' => quote = Some(char),
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' if depth == 0 => commas += 1,
+                '#' => break,
+                _ => {}
+            },
+        }
+    }
+
+    commas
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hover() {
+        let iset = mipsy_instructions::inst_set();
+        let hover = hover("main:\n\tsll $t0, $t1, 2", Position::new(1, 2), &iset).unwrap();
+
+        let contents = match hover.contents {
+            HoverContents::Markup(contents) => contents,
+            _ => panic!("expected markdown"),
+        };
+
+        assert!(contents.value.starts_with("Shifts the value in $Rt left"));
+        assert!(contents.value.contains("sll $Rd, $Rt, shift"));
+        assert!(super::hover("main:\n\tsll $t0", Position::new(1, 6), &iset).is_none());
+    }
+
+    #[test]
+    fn test_completion() {
+        let iset = mipsy_instructions::inst_set();
+        let source = "LEN = 4\nmain:\n\tli $t0, \n\tad";
+
+        let labels = |items: Vec<CompletionItem>| {
+            items.into_iter().map(|item| item.label).collect::<Vec<_>>()
+        };
+
+        let operands = labels(completion(source, Position::new(2, 9), &iset));
+        assert_eq!(operands[..2], ["LEN", "main"]);
+        assert!(operands.contains(&String::from("$t0")));
+
+        let instructions = labels(completion(source, Position::new(3, 3), &iset));
+        assert!(instructions.contains(&String::from("add")));
+        assert!(!instructions.contains(&String::from("main")));
+
+        let registers = labels(completion("\tli $", Position::new(0, 5), &iset));
+        assert_eq!(registers.len(), 32);
+    }
+
+    #[test]
+    fn test_signature_help() {
+        let iset = mipsy_instructions::inst_set();
+        let help = signature_help("\tlw $t0, ", Position::new(0, 9), &iset).unwrap();
+
+        assert_eq!(help.active_parameter, Some(1));
+        assert!(help
+            .signatures
+            .iter()
+            .any(|signature| signature.label == "lw $Rt, i16($Rs)"));
+
+        assert!(signature_help("\tlw", Position::new(0, 3), &iset).is_none());
+        assert_eq!(count_operand_commas("$t0, 'a', \",\" # x, y"), 2);
+    }
+}
@@ -0,0 +1,291 @@
+//! `mipsy-lsp`, a language server for MIPS assembly.
+//!
+//! Speaks LSP over stdio, giving editors mipsy's own errors and warnings
+//! as diagnostics, along with hover, go-to-definition, find-references,
+//! completion and signature help.
+
+mod diagnostics;
+mod features;
+mod symbols;
+
+use std::{collections::HashMap, error::Error, fs};
+
+use lsp_server::{Connection, ErrorCode, ExtractError, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+        Notification as NotificationTrait, PublishDiagnostics,
+    },
+    request::{
+        Completion, GotoDefinition, HoverRequest, References, Request as RequestTrait,
+        SignatureHelpRequest,
+    },
+    CompletionOptions, CompletionResponse, GotoDefinitionParams, GotoDefinitionResponse,
+    HoverProviderCapability, Location, OneOf, PublishDiagnosticsParams, ReferenceParams,
+    ServerCapabilities, SignatureHelpOptions, TextDocumentPositionParams,
+    TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use mipsy_lib::InstSet;
+use mipsy_utils::{read_config, MipsyConfig};
+
+type LspResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+fn main() -> LspResult<()> {
+    // the messages are shown as plain text, so the ansi colours would just be noise
+    colored::control::set_override(false);
+
+    let (connection, io_threads) = Connection::stdio();
+
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![String::from("$")]),
+            ..CompletionOptions::default()
+        }),
+        signature_help_provider: Some(SignatureHelpOptions {
+            trigger_characters: Some(vec![String::from(" "), String::from(",")]),
+            ..SignatureHelpOptions::default()
+        }),
+        ..ServerCapabilities::default()
+    };
+
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut server = Server {
+        connection,
+        documents: HashMap::new(),
+        iset: mipsy_instructions::inst_set(),
+        // a broken config has already been replaced by the default one
+        config: read_config().unwrap_or_default(),
+    };
+
+    server.run()?;
+    io_threads.join()?;
+
+    Ok(())
+}
+
+struct Server {
+    connection: Connection,
+    documents: HashMap<Url, String>,
+    iset: InstSet,
+    config: MipsyConfig,
+}
+
+impl Server {
+    fn run(&mut self) -> LspResult<()> {
+        let receiver = self.connection.receiver.clone();
+
+        for message in receiver {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+
+                    let response = self.handle_request(request);
+                    self.connection.sender.send(Message::Response(response))?;
+                }
+                Message::Notification(notification) => self.handle_notification(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_request(&self, request: Request) -> Response {
+        match request.method.as_str() {
+            HoverRequest::METHOD => self.respond::<HoverRequest>(request, |server, params| {
+                let source = server.document(&params.text_document_position_params)?;
+
+                features::hover(
+                    source,
+                    params.text_document_position_params.position,
+                    &server.iset,
+                )
+            }),
+            GotoDefinition::METHOD => self.respond::<GotoDefinition>(request, Self::definition),
+            References::METHOD => self.respond::<References>(request, Self::references),
+            Completion::METHOD => self.respond::<Completion>(request, |server, params| {
+                let source = server.document(&params.text_document_position)?;
+                let items = features::completion(
+                    source,
+                    params.text_document_position.position,
+                    &server.iset,
+                );
+
+                Some(CompletionResponse::Array(items))
+            }),
+            SignatureHelpRequest::METHOD => {
+                self.respond::<SignatureHelpRequest>(request, |server, params| {
+                    let source = server.document(&params.text_document_position_params)?;
+
+                    features::signature_help(
+                        source,
+                        params.text_document_position_params.position,
+                        &server.iset,
+                    )
+                })
+            }
+            _ => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unsupported request `{}`", request.method),
+            ),
+        }
+    }
+
+    fn respond<R: RequestTrait>(
+        &self,
+        request: Request,
+        handler: fn(&Self, R::Params) -> R::Result,
+    ) -> Response {
+        let id = request.id.clone();
+
+        match request.extract::<R::Params>(R::METHOD) {
+            Ok((id, params)) => Response::new_ok(id, handler(self, params)),
+            Err(ExtractError::JsonError { error, .. }) => {
+                Response::new_err(id, ErrorCode::InvalidParams as i32, error.to_string())
+            }
+            Err(ExtractError::MethodMismatch(_)) => unreachable!("method was already matched"),
+        }
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> LspResult<()> {
+        let uri = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params = notification
+                    .extract::<<DidOpenTextDocument as NotificationTrait>::Params>(
+                        DidOpenTextDocument::METHOD,
+                    )?;
+
+                self.documents
+                    .insert(params.text_document.uri.clone(), params.text_document.text);
+
+                params.text_document.uri
+            }
+            DidChangeTextDocument::METHOD => {
+                let params = notification
+                    .extract::<<DidChangeTextDocument as NotificationTrait>::Params>(
+                        DidChangeTextDocument::METHOD,
+                    )?;
+
+                // with full syncing, the last change is the whole document
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.documents
+                        .insert(params.text_document.uri.clone(), change.text);
+                }
+
+                params.text_document.uri
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = notification
+                    .extract::<<DidCloseTextDocument as NotificationTrait>::Params>(
+                        DidCloseTextDocument::METHOD,
+                    )?;
+
+                self.documents.remove(&params.text_document.uri);
+
+                return self.publish(params.text_document.uri, vec![]);
+            }
+            _ => return Ok(()),
+        };
+
+        let diagnostics = self.diagnose(&uri);
+        self.publish(uri, diagnostics)
+    }
+
+    fn publish(&self, uri: Url, diagnostics: Vec<lsp_types::Diagnostic>) -> LspResult<()> {
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+
+        self.connection
+            .sender
+            .send(Message::Notification(Notification::new(
+                PublishDiagnostics::METHOD.to_string(),
+                params,
+            )))?;
+
+        Ok(())
+    }
+
+    fn diagnose(&self, uri: &Url) -> Vec<lsp_types::Diagnostic> {
+        let source = match self.documents.get(uri) {
+            Some(source) => source.clone(),
+            None => return vec![],
+        };
+
+        let mut files = vec![(tag(uri), source)];
+
+        // any included files that aren't open come from disk,
+        // and any that can't be read will show up as errors anyway
+        let _ = mipsy_parser::load_includes(&mut files, |path| {
+            let open = self
+                .documents
+                .iter()
+                .find(|(uri, _)| tag(uri) == path)
+                .map(|(_, source)| source.clone());
+
+            match open {
+                Some(source) => Ok(source),
+                None => fs::read_to_string(path),
+            }
+        });
+
+        diagnostics::diagnose(&files, &self.iset, &self.config)
+    }
+
+    fn document(&self, params: &TextDocumentPositionParams) -> Option<&str> {
+        self.documents
+            .get(&params.text_document.uri)
+            .map(String::as_str)
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = &params.text_document_position_params;
+        let source = self.document(position)?;
+
+        let tokens = symbols::tokenize(source);
+        let token = symbols::token_at(&tokens, position.position)?;
+        let definition = symbols::definition(&tokens, token.text)?;
+
+        Some(GotoDefinitionResponse::Scalar(Location::new(
+            position.text_document.uri.clone(),
+            definition.range(),
+        )))
+    }
+
+    fn references(&self, params: ReferenceParams) -> Option<Vec<Location>> {
+        let position = &params.text_document_position;
+        let source = self.document(position)?;
+
+        let tokens = symbols::tokenize(source);
+        let token = symbols::token_at(&tokens, position.position)?;
+
+        if !matches!(
+            token.kind,
+            symbols::TokenKind::Ident | symbols::TokenKind::Label | symbols::TokenKind::Constant
+        ) {
+            return None;
+        }
+
+        let references =
+            symbols::references(&tokens, token.text, params.context.include_declaration)
+                .into_iter()
+                .map(|token| Location::new(position.text_document.uri.clone(), token.range()))
+                .collect();
+
+        Some(references)
+    }
+}
+
+/// The name mipsy knows a document by, which is its path for files on disk.
+fn tag(uri: &Url) -> String {
+    match uri.to_file_path() {
+        Ok(path) => path.to_string_lossy().to_string(),
+        Err(_) => uri.to_string(),
+    }
+}
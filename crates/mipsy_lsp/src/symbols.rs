@@ -0,0 +1,284 @@
+//! A quick lexical pass over the source, which (unlike the parser)
+//! copes with the half-written lines that are found in an editor.
+
+use lsp_types::{Position, Range};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// A label being defined, like `main` in `main:`.
+    Label,
+    /// A constant being defined, like `LEN` in `LEN = 5` or `.eqv LEN 5`.
+    Constant,
+    /// The name of an instruction or directive.
+    Mnemonic,
+    /// A name used in an operand, like a label or constant.
+    Ident,
+    Register,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub text: &'a str,
+    pub kind: TokenKind,
+    pub line: u32,
+    /// Byte offsets into the line.
+    pub start: usize,
+    pub end: usize,
+    line_text: &'a str,
+}
+
+impl Token<'_> {
+    pub fn range(&self) -> Range {
+        Range::new(
+            Position::new(self.line, utf16_col(self.line_text, self.start)),
+            Position::new(self.line, utf16_col(self.line_text, self.end)),
+        )
+    }
+
+    pub fn is_definition(&self) -> bool {
+        matches!(self.kind, TokenKind::Label | TokenKind::Constant)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lexeme {
+    Word,
+    Register,
+    Punct(u8),
+}
+
+pub fn tokenize(source: &str) -> Vec<Token<'_>> {
+    source
+        .lines()
+        .enumerate()
+        .flat_map(|(line, text)| line_tokens(text, line as u32))
+        .collect()
+}
+
+fn line_tokens(text: &str, line: u32) -> Vec<Token<'_>> {
+    let trimmed = text.trim_start();
+    if trimmed.starts_with("#[") || trimmed.starts_with("#![") {
+        return vec![];
+    }
+
+    let lexemes = lex(text);
+    let mut tokens = vec![];
+    let mut seen_mnemonic = false;
+    let mut defines_constant = false;
+
+    for (index, &(lexeme, start, end)) in lexemes.iter().enumerate() {
+        let next = lexemes.get(index + 1).map(|&(lexeme, ..)| lexeme);
+        let word = &text[start..end];
+
+        let kind = match lexeme {
+            Lexeme::Punct(_) => continue,
+            Lexeme::Register => TokenKind::Register,
+            Lexeme::Word if !seen_mnemonic && next == Some(Lexeme::Punct(b':')) => TokenKind::Label,
+            Lexeme::Word if !seen_mnemonic => {
+                seen_mnemonic = true;
+
+                if next == Some(Lexeme::Punct(b'=')) {
+                    TokenKind::Constant
+                } else {
+                    defines_constant = matches!(word, ".eqv" | ".set");
+                    TokenKind::Mnemonic
+                }
+            }
+            Lexeme::Word if defines_constant => {
+                defines_constant = false;
+                TokenKind::Constant
+            }
+            Lexeme::Word => TokenKind::Ident,
+        };
+
+        tokens.push(Token {
+            text: word,
+            kind,
+            line,
+            start,
+            end,
+            line_text: text,
+        });
+    }
+
+    tokens
+}
+
+/// Splits a line into words, registers and punctuation,
+/// skipping over numbers, strings and comments.
+fn lex(text: &str) -> Vec<(Lexeme, usize, usize)> {
+    let bytes = text.as_bytes();
+    let is_word_char = |byte: u8| byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'.';
+
+    let mut lexemes = vec![];
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let start = index;
+
+        match bytes[index] {
+            b'#' => break,
+            quote @ (b'"' | b'\'') => {
+                index += 1;
+
+                while index < bytes.len() && bytes[index] != quote {
+                    index += if bytes[index] == b'\\' { 2 } else { 1 };
+                }
+
+                index += 1;
+            }
+            b'$' => {
+                index += 1;
+
+                while index < bytes.len() && bytes[index].is_ascii_alphanumeric() {
+                    index += 1;
+                }
+
+                lexemes.push((Lexeme::Register, start, index));
+            }
+            byte if byte.is_ascii_digit() => {
+                while index < bytes.len() && is_word_char(bytes[index]) {
+                    index += 1;
+                }
+            }
+            byte if is_word_char(byte) => {
+                while index < bytes.len() && is_word_char(bytes[index]) {
+                    index += 1;
+                }
+
+                lexemes.push((Lexeme::Word, start, index));
+            }
+            byte if byte.is_ascii_whitespace() => index += 1,
+            byte => {
+                lexemes.push((Lexeme::Punct(byte), start, start + 1));
+                index += 1;
+            }
+        }
+    }
+
+    lexemes
+}
+
+/// The token under (or just before) the cursor.
+pub fn token_at<'a, 'b>(tokens: &'b [Token<'a>], position: Position) -> Option<&'b Token<'a>> {
+    tokens.iter().find(|token| {
+        let range = token.range();
+        token.line == position.line
+            && range.start.character <= position.character
+            && position.character <= range.end.character
+    })
+}
+
+/// The first place that `name` is defined as a label or constant.
+pub fn definition<'a, 'b>(tokens: &'b [Token<'a>], name: &str) -> Option<&'b Token<'a>> {
+    tokens
+        .iter()
+        .find(|token| token.is_definition() && token.text == name)
+}
+
+/// Every place that `name` is used, and optionally where it's defined.
+pub fn references<'a, 'b>(
+    tokens: &'b [Token<'a>],
+    name: &str,
+    include_definition: bool,
+) -> Vec<&'b Token<'a>> {
+    tokens
+        .iter()
+        .filter(|token| token.text == name)
+        .filter(|token| {
+            token.kind == TokenKind::Ident || (include_definition && token.is_definition())
+        })
+        .collect()
+}
+
+/// Converts a byte offset into a line to the UTF-16 column that LSP uses.
+pub fn utf16_col(line: &str, byte: usize) -> u32 {
+    line[..byte.min(line.len())].encode_utf16().count() as u32
+}
+
+/// Converts a UTF-16 column from LSP to a byte offset into the line.
+pub fn byte_col(line: &str, col: u32) -> usize {
+    let mut utf16 = 0;
+
+    for (index, char) in line.char_indices() {
+        if utf16 >= col {
+            return index;
+        }
+
+        utf16 += char.len_utf16() as u32;
+    }
+
+    line.len()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn kinds(line: &str) -> Vec<(&str, TokenKind)> {
+        line_tokens(line, 0)
+            .into_iter()
+            .map(|token| (token.text, token.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_tokens() {
+        use TokenKind::*;
+
+        assert_eq!(
+            kinds("loop: lw $t0, 4($sp)  # load: it"),
+            vec![
+                ("loop", Label),
+                ("lw", Mnemonic),
+                ("$t0", Register),
+                ("$sp", Register)
+            ]
+        );
+        assert_eq!(
+            kinds("LEN = SIZE * 4"),
+            vec![("LEN", Constant), ("SIZE", Ident)]
+        );
+        assert_eq!(
+            kinds("msg: .asciiz \"b label: here\""),
+            vec![("msg", Label), (".asciiz", Mnemonic)]
+        );
+        assert_eq!(
+            kinds(".eqv MAX 10"),
+            vec![(".eqv", Mnemonic), ("MAX", Constant)]
+        );
+        assert_eq!(
+            kinds("    bnez $t0, 1b"),
+            vec![("bnez", Mnemonic), ("$t0", Register)]
+        );
+        assert_eq!(kinds("#![tabsize(4)]"), vec![]);
+    }
+
+    #[test]
+    fn test_references() {
+        let source = "main:\n  la $a0, msg\n  j end\nmsg: .asciiz \"msg\"\nend: la $t0, msg";
+        let tokens = tokenize(source);
+
+        let lines = |tokens: Vec<&Token<'_>>| {
+            tokens
+                .into_iter()
+                .map(|token| token.line)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(definition(&tokens, "msg").map(|token| token.line), Some(3));
+        assert_eq!(lines(references(&tokens, "msg", false)), vec![1, 4]);
+        assert_eq!(lines(references(&tokens, "msg", true)), vec![1, 3, 4]);
+        assert_eq!(
+            token_at(&tokens, Position::new(1, 11)).map(|token| token.text),
+            Some("msg")
+        );
+    }
+
+    #[test]
+    fn test_columns() {
+        assert_eq!(utf16_col("é $t0", 3), 2);
+        assert_eq!(byte_col("é $t0", 2), 3);
+        assert_eq!(byte_col("ab", 10), 2);
+    }
+}
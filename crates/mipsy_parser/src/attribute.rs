@@ -5,6 +5,7 @@ use nom::{
     sequence::tuple,
     IResult,
 };
+use nom_locate::position;
 
 use crate::{misc::parse_ident, Span};
 
@@ -12,6 +13,9 @@ use crate::{misc::parse_ident, Span};
 pub struct Attribute {
     key: String,
     value: Option<String>,
+    line: u32,
+    col: u32,
+    col_end: u32,
 }
 
 impl Attribute {
@@ -22,6 +26,18 @@ impl Attribute {
     pub fn value(&self) -> Option<&str> {
         self.value.as_deref()
     }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn col(&self) -> u32 {
+        self.col
+    }
+
+    pub fn col_end(&self) -> u32 {
+        self.col_end
+    }
 }
 
 fn parse_attribute<'a>(
@@ -31,6 +47,7 @@ fn parse_attribute<'a>(
         map(
             tuple((
                 multispace0,
+                position,
                 tag(attribute_header),
                 multispace0,
                 parse_ident,
@@ -44,18 +61,19 @@ fn parse_attribute<'a>(
                 ))),
                 multispace0,
                 tag("]"),
+                position,
             )),
-            |(_, _, _, key, _, value, _, _)| {
-                if let Some((_, _, value, _, _)) = value {
-                    let text = String::from_utf8_lossy(&value.iter().copied().collect::<Vec<_>>())
-                        .to_string();
+            |(_, pos, _, _, key, _, value, _, _, pos_end)| {
+                let value = value.map(|(_, _, value, _, _)| {
+                    String::from_utf8_lossy(&value.iter().copied().collect::<Vec<_>>()).to_string()
+                });
 
-                    Attribute {
-                        key,
-                        value: Some(text),
-                    }
-                } else {
-                    Attribute { key, value: None }
+                Attribute {
+                    key,
+                    value,
+                    line: pos.location_line(),
+                    col: pos.get_column() as u32,
+                    col_end: pos_end.get_column() as u32,
                 }
             },
         )(i)
@@ -11,18 +11,18 @@ use nom_locate::position;
 use serde::{Deserialize, Serialize};
 
 use crate::misc::{comment_multispace0, parse_ident};
-use crate::number::{parse_char, parse_u32};
+use crate::number::{parse_char, parse_local_labelref, parse_u32};
 use crate::parser::Position;
 use crate::Span;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct MpConst {
-    label: String,
-    value: MpConstValueLoc,
-    line: u32,
-    col: u32,
-    line_end: u32,
-    col_end: u32,
+    pub(crate) label: String,
+    pub(crate) value: MpConstValueLoc,
+    pub(crate) line: u32,
+    pub(crate) col: u32,
+    pub(crate) line_end: u32,
+    pub(crate) col_end: u32,
 }
 
 impl MpConst {
@@ -34,6 +34,10 @@ impl MpConst {
         &self.value
     }
 
+    pub fn value_mut(&mut self) -> &mut MpConstValueLoc {
+        &mut self.value
+    }
+
     pub fn line(&self) -> u32 {
         self.line
     }
@@ -72,23 +76,80 @@ pub enum MpConstValue {
     Shr(Box<MpConstValueLoc>, Box<MpConstValueLoc>),
 }
 
+impl MpConstValue {
+    /// How tightly the operator binds, from `|` up to a single value.
+    fn precedence(&self) -> u8 {
+        match self {
+            MpConstValue::Or(..) => 0,
+            MpConstValue::Xor(..) => 1,
+            MpConstValue::And(..) => 2,
+            MpConstValue::Shl(..) | MpConstValue::Shr(..) => 3,
+            MpConstValue::Sum(..) | MpConstValue::Sub(..) => 4,
+            MpConstValue::Mult(..) | MpConstValue::Div(..) | MpConstValue::Mod(..) => 5,
+            MpConstValue::Minus(_) | MpConstValue::Neg(_) => 6,
+            MpConstValue::Value(_) | MpConstValue::Const(_) => 7,
+        }
+    }
+
+    fn fmt_binary(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        x: &MpConstValueLoc,
+        op: &str,
+        y: &MpConstValueLoc,
+    ) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            Operand::left(x, self),
+            op,
+            Operand::right(y, self)
+        )
+    }
+}
+
 impl Display for MpConstValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             MpConstValue::Value(x) => write!(f, "{}", x),
             MpConstValue::Const(x) => write!(f, "{}", x),
-            MpConstValue::Minus(x) => write!(f, "-{}", x.0),
-            MpConstValue::Mult(x, y) => write!(f, "{} * {}", x.0, y.0),
-            MpConstValue::Sum(x, y) => write!(f, "{} + {}", x.0, y.0),
-            MpConstValue::Sub(x, y) => write!(f, "{} - {}", x.0, y.0),
-            MpConstValue::Div(x, y) => write!(f, "{} / {}", x.0, y.0),
-            MpConstValue::Mod(x, y) => write!(f, "{} % {}", x.0, y.0),
-            MpConstValue::And(x, y) => write!(f, "{} & {}", x.0, y.0),
-            MpConstValue::Or(x, y) => write!(f, "{} | {}", x.0, y.0),
-            MpConstValue::Xor(x, y) => write!(f, "{} ^ {}", x.0, y.0),
-            MpConstValue::Neg(x) => write!(f, "~{}", x.0),
-            MpConstValue::Shl(x, y) => write!(f, "{} << {}", x.0, y.0),
-            MpConstValue::Shr(x, y) => write!(f, "{} >> {}", x.0, y.0),
+            MpConstValue::Minus(x) => write!(f, "-{}", Operand::left(x, self)),
+            MpConstValue::Mult(x, y) => self.fmt_binary(f, x, "*", y),
+            MpConstValue::Sum(x, y) => self.fmt_binary(f, x, "+", y),
+            MpConstValue::Sub(x, y) => self.fmt_binary(f, x, "-", y),
+            MpConstValue::Div(x, y) => self.fmt_binary(f, x, "/", y),
+            MpConstValue::Mod(x, y) => self.fmt_binary(f, x, "%", y),
+            MpConstValue::And(x, y) => self.fmt_binary(f, x, "&", y),
+            MpConstValue::Or(x, y) => self.fmt_binary(f, x, "|", y),
+            MpConstValue::Xor(x, y) => self.fmt_binary(f, x, "^", y),
+            MpConstValue::Neg(x) => write!(f, "~{}", Operand::left(x, self)),
+            MpConstValue::Shl(x, y) => self.fmt_binary(f, x, "<<", y),
+            MpConstValue::Shr(x, y) => self.fmt_binary(f, x, ">>", y),
+        }
+    }
+}
+
+/// Part of a larger expression, which is bracketed if it would otherwise be read differently.
+struct Operand<'a>(&'a MpConstValue, bool);
+
+impl<'a> Operand<'a> {
+    fn left(value: &'a MpConstValueLoc, parent: &MpConstValue) -> Self {
+        Self(&value.0, value.0.precedence() < parent.precedence())
+    }
+
+    /// Operators group to the left, so a right-hand side of the same precedence needs
+    /// brackets too, e.g. `4 % (1 - 1)` or `a - (b - c)`.
+    fn right(value: &'a MpConstValueLoc, parent: &MpConstValue) -> Self {
+        Self(&value.0, value.0.precedence() <= parent.precedence())
+    }
+}
+
+impl Display for Operand<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.1 {
+            write!(f, "({})", self.0)
+        } else {
+            write!(f, "{}", self.0)
         }
     }
 }
@@ -369,6 +430,15 @@ pub fn parse_neg_value(i: Span<'_>) -> IResult<Span<'_>, MpConstValueLoc> {
 
 pub fn parse_value(i: Span<'_>) -> IResult<Span<'_>, MpConstValueLoc> {
     alt((
+        map(
+            tuple((position, parse_local_labelref, position)),
+            |(pos_start, value, pos_end)| {
+                MpConstValueLoc(
+                    MpConstValue::Const(value),
+                    Position::from_positions(pos_start, pos_end),
+                )
+            },
+        ),
         map(
             tuple((position, parse_u32, position)),
             |(pos_start, value, pos_end)| {
@@ -0,0 +1,528 @@
+//! A lossless concrete syntax tree, for tools that need to rewrite
+//! source code without losing its comments and formatting.
+//!
+//! Every byte of the file ends up in exactly one place in the tree --
+//! either the text of an item, or the trivia (whitespace, newlines and comments)
+//! around it -- so [`CstProgram`]'s `Display` gives back the original file.
+
+use std::{fmt, rc::Rc};
+
+use nom::{
+    branch::alt,
+    bytes::complete::{is_a, tag, take_till},
+    character::complete::char,
+    combinator::{map, not, recognize},
+    multi::many0,
+    sequence::tuple,
+    IResult, InputTake,
+};
+
+use crate::{
+    attribute::{parse_outer_attribute, Attribute},
+    misc::ErrorLocation,
+    parser::{parse_mips_item_untrimmed, MpAttributedItem, MpProgram, TaggedFile},
+    preprocess, Span,
+};
+
+/// Preprocessor directives, which are kept as they are, since they aren't items.
+const PREPROCESSOR_DIRECTIVES: &[&str] = &[
+    ".macro",
+    ".end_macro",
+    ".include",
+    ".eqv",
+    ".set",
+    ".if",
+    ".ifdef",
+    ".ifndef",
+    ".elseif",
+    ".else",
+    ".endif",
+];
+
+/// Whether `name` (including its `.`) is one of the directives the preprocessor handles.
+pub fn is_preprocessor_directive(name: &str) -> bool {
+    PREPROCESSOR_DIRECTIVES.contains(&name)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CstProgram {
+    pub(crate) items: Vec<CstItem>,
+    /// Anything after the last item, like a final newline.
+    pub(crate) trailing: Vec<Trivia>,
+    pub(crate) tab_size: u32,
+}
+
+/// An item, along with the trivia around it.
+///
+/// The leading trivia is everything since the end of the previous line with an item,
+/// and the trailing trivia is anything left on the item's (last) line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CstItem {
+    pub(crate) leading: Vec<Trivia>,
+    pub(crate) text: String,
+    pub(crate) content: CstContent,
+    pub(crate) trailing: Vec<Trivia>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CstContent {
+    /// A file attribute, like `#![tabsize(4)]`.
+    FileAttribute(Attribute),
+    Item(MpAttributedItem),
+    /// A preprocessor directive like `.eqv`, or a whole `.macro` definition,
+    /// which is left exactly as it was written.
+    Preprocessor,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trivia {
+    /// Spaces, tabs and carriage returns.
+    Whitespace(String),
+    Newline,
+    /// A comment, including the `#`.
+    Comment(String),
+}
+
+impl CstProgram {
+    pub fn items(&self) -> &[CstItem] {
+        &self.items
+    }
+
+    pub fn items_mut(&mut self) -> &mut Vec<CstItem> {
+        &mut self.items
+    }
+
+    pub fn trailing(&self) -> &[Trivia] {
+        &self.trailing
+    }
+
+    pub fn trailing_mut(&mut self) -> &mut Vec<Trivia> {
+        &mut self.trailing
+    }
+
+    /// The tab size the file was parsed with, taking `#![tabsize(...)]` into account.
+    pub fn tab_size(&self) -> u32 {
+        self.tab_size
+    }
+
+    /// The same program that [`parse_mips`](crate::parse_mips) would give,
+    /// for a file without any preprocessor directives.
+    pub fn program(&self) -> MpProgram {
+        let mut items = vec![];
+        let mut file_attributes = vec![];
+
+        for item in self.items.iter() {
+            match &item.content {
+                CstContent::FileAttribute(attribute) => file_attributes.push(attribute.clone()),
+                CstContent::Item(item) => items.push(item.clone()),
+                CstContent::Preprocessor => {}
+            }
+        }
+
+        MpProgram::new(items, file_attributes)
+    }
+}
+
+impl CstItem {
+    pub fn leading(&self) -> &[Trivia] {
+        &self.leading
+    }
+
+    pub fn leading_mut(&mut self) -> &mut Vec<Trivia> {
+        &mut self.leading
+    }
+
+    /// Exactly how the item was written.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn set_text(&mut self, text: String) {
+        self.text = text;
+    }
+
+    pub fn content(&self) -> &CstContent {
+        &self.content
+    }
+
+    pub fn trailing(&self) -> &[Trivia] {
+        &self.trailing
+    }
+
+    pub fn trailing_mut(&mut self) -> &mut Vec<Trivia> {
+        &mut self.trailing
+    }
+}
+
+impl fmt::Display for CstProgram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for item in self.items.iter() {
+            write!(f, "{item}")?;
+        }
+
+        for trivia in self.trailing.iter() {
+            write!(f, "{trivia}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for CstItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for trivia in self.leading.iter() {
+            write!(f, "{trivia}")?;
+        }
+
+        write!(f, "{}", self.text)?;
+
+        for trivia in self.trailing.iter() {
+            write!(f, "{trivia}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Trivia {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trivia::Whitespace(text) | Trivia::Comment(text) => write!(f, "{text}"),
+            Trivia::Newline => writeln!(f),
+        }
+    }
+}
+
+/// Parses a single file into a lossless syntax tree.
+///
+/// Unlike [`parse_mips`](crate::parse_mips), files aren't preprocessed,
+/// so any `.include`s or macros are left as they are.
+pub fn parse_mips_lossless(
+    file: TaggedFile<'_, '_>,
+    default_tab_size: u32,
+) -> Result<CstProgram, Vec<ErrorLocation>> {
+    let file_name = file.tag().map(Rc::from);
+    let source = file.file_contents();
+
+    let mut input = Span::new(source.as_bytes());
+    let mut items: Vec<CstItem> = vec![];
+    let mut errors = vec![];
+    let mut tab_size = default_tab_size;
+    let mut in_attributes = true;
+
+    loop {
+        let (rest, mut leading) =
+            many0(parse_trivia)(input).expect("Trivia parser should never fail");
+
+        // anything before the first newline belongs to the previous item's line
+        if let Some(previous) = items.last_mut() {
+            let same_line = leading
+                .iter()
+                .position(|trivia| *trivia == Trivia::Newline)
+                .unwrap_or(leading.len());
+
+            previous.trailing.extend(leading.drain(..same_line));
+        }
+
+        if rest.is_empty() {
+            return if errors.is_empty() {
+                let mut program = CstProgram {
+                    items,
+                    trailing: leading,
+                    tab_size,
+                };
+
+                remap_positions(&mut program, &file_name, source);
+
+                Ok(program)
+            } else {
+                Err(errors)
+            };
+        }
+
+        let parsed = if in_attributes {
+            match parse_outer_attribute(rest) {
+                Ok((remaining, attribute)) => {
+                    if attribute.key().eq_ignore_ascii_case("tabsize") {
+                        tab_size = attribute
+                            .value()
+                            .expect("Tabsize attribute requires a value")
+                            .parse()
+                            .expect("Tabsize attribute value should be numeric");
+                    }
+
+                    Some((remaining, CstContent::FileAttribute(attribute)))
+                }
+                Err(_) => {
+                    in_attributes = false;
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let parsed = parsed
+            .or_else(|| {
+                parse_preprocessor(rest)
+                    .map(|len| (rest.take_split(len).0, CstContent::Preprocessor))
+            })
+            .or_else(|| match parse_mips_item_untrimmed(rest) {
+                Ok((remaining, (item, attributes, line_number))) => Some((
+                    remaining,
+                    CstContent::Item(MpAttributedItem {
+                        item,
+                        attributes,
+                        file_tag: file_name.clone(),
+                        unit: file_name.clone(),
+                        line_number,
+                        expansion: vec![],
+                    }),
+                )),
+                Err(_) => None,
+            });
+
+        let (remaining, content) = match parsed {
+            Some(parsed) => parsed,
+            None => {
+                errors.push(ErrorLocation::diagnosed(
+                    source.as_bytes(),
+                    file_name.clone(),
+                    rest.location_line(),
+                    rest.get_column(),
+                ));
+
+                // resynchronise at the start of the next line
+                let (remaining, _) =
+                    take_till::<_, _, nom::error::Error<_>>(|byte| byte == b'\n')(rest)
+                        .expect("Skipping to the next line should never fail");
+
+                input = remaining;
+                continue;
+            }
+        };
+
+        // the item parsers can eat up any comments and whitespace after them,
+        // which should be trivia instead
+        let consumed = &rest.fragment()[..remaining.location_offset() - rest.location_offset()];
+        let (remaining, text) = rest.take_split(content_len(consumed));
+
+        items.push(CstItem {
+            leading,
+            text: String::from_utf8_lossy(text.fragment()).to_string(),
+            content,
+            trailing: vec![],
+        });
+
+        input = remaining;
+    }
+}
+
+fn parse_trivia(i: Span<'_>) -> IResult<Span<'_>, Trivia> {
+    alt((
+        map(is_a(" \t\r"), |text: Span<'_>| {
+            Trivia::Whitespace(String::from_utf8_lossy(text.fragment()).to_string())
+        }),
+        map(char('\n'), |_| Trivia::Newline),
+        map(
+            recognize(tuple((
+                not(tag("#![")),
+                not(tag("#[")),
+                char('#'),
+                take_till(|byte| byte == b'\n'),
+            ))),
+            |text: Span<'_>| Trivia::Comment(String::from_utf8_lossy(text.fragment()).to_string()),
+        ),
+    ))(i)
+}
+
+/// The length of a preprocessor directive at the start of `i`, if there is one,
+/// which is the rest of the line -- or for a `.macro`, up to its `.end_macro`.
+fn parse_preprocessor(i: Span<'_>) -> Option<usize> {
+    let text = i.fragment();
+    let line_len = |text: &[u8]| {
+        content_len(
+            &text[..text
+                .iter()
+                .position(|&byte| byte == b'\n')
+                .unwrap_or(text.len())],
+        )
+    };
+
+    let name_len = text
+        .iter()
+        .position(|&byte| !(byte == b'.' || byte == b'_' || byte.is_ascii_alphanumeric()))
+        .unwrap_or(text.len());
+    let name = String::from_utf8_lossy(&text[..name_len]);
+
+    if !PREPROCESSOR_DIRECTIVES.contains(&&*name) {
+        return None;
+    }
+
+    if name != ".macro" {
+        return Some(line_len(text));
+    }
+
+    let mut start = 0;
+    while let Some(newline) = text[start..].iter().position(|&byte| byte == b'\n') {
+        start += newline + 1;
+
+        let line = &text[start..];
+        let indent = line
+            .iter()
+            .position(|&byte| byte != b' ' && byte != b'\t')
+            .unwrap_or(line.len());
+
+        if line[indent..].starts_with(b".end_macro") {
+            return Some(start + line_len(line));
+        }
+    }
+
+    // an unterminated macro is left to the preprocessor to complain about
+    Some(line_len(text))
+}
+
+/// The length of `text` without any trailing whitespace or comments.
+fn content_len(text: &[u8]) -> usize {
+    let mut len = 0;
+    let mut index = 0;
+
+    while index < text.len() {
+        match text[index] {
+            b'#' if !matches!(text.get(index + 1), Some(b'[' | b'!')) => {
+                while index < text.len() && text[index] != b'\n' {
+                    index += 1;
+                }
+            }
+            byte if byte.is_ascii_whitespace() => index += 1,
+            quote @ (b'"' | b'\'') => {
+                index += 1;
+
+                while index < text.len() && text[index] != quote && text[index] != b'\n' {
+                    index += if text[index] == b'\\' { 2 } else { 1 };
+                }
+
+                index = (index + 1).min(text.len());
+                len = index;
+            }
+            _ => {
+                index += 1;
+                len = index;
+            }
+        }
+    }
+
+    len
+}
+
+/// Points the items' positions at where they'd be with tabs expanded,
+/// just like [`parse_mips`](crate::parse_mips) would.
+fn remap_positions(program: &mut CstProgram, file_name: &Option<Rc<str>>, source: &str) {
+    let lines = preprocess::tab_lines(source, file_name, program.tab_size);
+
+    for item in program.items.iter_mut() {
+        if let CstContent::Item(item) = &mut item.content {
+            preprocess::remap_items(&lines, std::slice::from_mut(item));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_mips;
+
+    const SOURCE: &str = "\
+#![tabsize(4)]
+# a program
+
+\t.data
+msg:\t.asciiz \"a # not a comment\"\t# the message
+nums:  .word 1,   2 ,3
+
+.eqv LEN 3
+.macro twice(%x)
+\tadd %x, %x, %x
+.end_macro
+
+\t.text
+main:  # the start
+\tli\t$v0, 4   # print
+\tla $a0, msg
+\tsyscall
+
+\tjr $ra\r
+";
+
+    #[test]
+    fn test_lossless() {
+        let cst = parse_mips_lossless(TaggedFile::new(Some("a.s"), SOURCE), 8).unwrap();
+
+        assert_eq!(cst.to_string(), SOURCE);
+        assert_eq!(cst.tab_size(), 4);
+    }
+
+    #[test]
+    fn test_trivia() {
+        let cst = parse_mips_lossless(TaggedFile::new(None, SOURCE), 8).unwrap();
+
+        let msg = &cst.items()[3];
+        assert_eq!(msg.text(), ".asciiz \"a # not a comment\"");
+        assert_eq!(
+            msg.trailing(),
+            [
+                Trivia::Whitespace("\t".into()),
+                Trivia::Comment("# the message".into())
+            ]
+        );
+
+        let eqv = &cst.items()[6];
+        assert_eq!(eqv.text(), ".eqv LEN 3");
+        assert_eq!(eqv.content(), &CstContent::Preprocessor);
+        assert_eq!(eqv.leading(), [Trivia::Newline, Trivia::Newline]);
+
+        let twice = &cst.items()[7];
+        assert_eq!(
+            twice.text(),
+            ".macro twice(%x)\n\tadd %x, %x, %x\n.end_macro"
+        );
+
+        let main = &cst.items()[9];
+        assert_eq!(main.text(), "main:");
+        assert_eq!(
+            main.trailing(),
+            [
+                Trivia::Whitespace("  ".into()),
+                Trivia::Comment("# the start".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_program_view() {
+        let source = SOURCE
+            .lines()
+            .filter(|line| {
+                !line.starts_with(".eqv") && !line.contains("macro") && !line.contains("%x")
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let cst = parse_mips_lossless(TaggedFile::new(Some("a.s"), &source), 8).unwrap();
+        let program = parse_mips(vec![TaggedFile::new(Some("a.s"), &source)], 8).unwrap();
+
+        assert_eq!(cst.program().items(), program.items());
+    }
+
+    #[test]
+    fn test_errors() {
+        let errors = parse_mips_lossless(
+            TaggedFile::new(None, "main:\n  add $t0 $t1, $t2\n  jr $ra\n  .wrod 1\n"),
+            8,
+        )
+        .unwrap_err();
+
+        let lines = errors.iter().map(|error| error.line).collect::<Vec<_>>();
+        assert_eq!(lines, vec![2, 4]);
+    }
+}
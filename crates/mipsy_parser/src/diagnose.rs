@@ -0,0 +1,492 @@
+//! Works out *why* a line failed to parse.
+//!
+//! The parsers themselves only know that none of the alternatives matched,
+//! so once a line has failed, it's scanned again here looking for the
+//! usual suspects -- an unterminated string, a missing comma, and so on.
+
+use crate::{
+    misc::{IDENT_CONTD_CHARS, IDENT_FIRST_CHAR},
+    MpMacroInvocation,
+};
+
+/// Every directive mipsy understands, including those handled by the preprocessor.
+pub const DIRECTIVE_NAMES: &[&str] = &[
+    ".text",
+    ".data",
+    ".ktext",
+    ".kdata",
+    ".ascii",
+    ".asciiz",
+    ".byte",
+    ".half",
+    ".word",
+    ".float",
+    ".double",
+    ".space",
+    ".align",
+    ".globl",
+    ".struct",
+    ".end_struct",
+    ".macro",
+    ".end_macro",
+    ".include",
+    ".eqv",
+    ".set",
+    ".if",
+    ".ifdef",
+    ".ifndef",
+    ".elseif",
+    ".else",
+    ".endif",
+];
+
+/// Directives whose arguments are a comma-separated list.
+const LIST_DIRECTIVES: &[&str] = &[".byte", ".half", ".word", ".float", ".double"];
+
+const VALID_ESCAPES: &[u8] = b"0rnt\\\"'";
+
+/// Looks for a particular mistake in a line, starting from the given index.
+type Check = fn(&[u8], usize) -> Option<(ParseErrorKind, usize)>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// Nothing more specific could be worked out.
+    Unknown,
+    UnterminatedString,
+    InvalidEscape {
+        escape: char,
+    },
+    MissingComma,
+    UnclosedParen,
+    InvalidNumber {
+        literal: String,
+        radix: u32,
+    },
+    UnknownDirective {
+        directive: String,
+    },
+    /// A `.macro` without a name, or with a malformed parameter list.
+    InvalidMacroHeader,
+    /// A `.macro` that's never closed by an `.end_macro`.
+    UnterminatedMacro {
+        name: String,
+    },
+    /// A `.macro` inside the body of another.
+    NestedMacro {
+        outer: String,
+    },
+    /// An `.end_macro` without a `.macro`.
+    UnmatchedEndMacro,
+    /// No definition of a macro takes as many arguments as it was given.
+    MacroArgCount {
+        name: String,
+        expected: Vec<usize>,
+        found: usize,
+        /// the invocation whose expansion contains the faulty one, if any
+        expanded_from: Option<MpMacroInvocation>,
+    },
+    /// Macros expanded into each other too many times, most likely recursively.
+    MacroTooDeep {
+        name: String,
+        expanded_from: Option<MpMacroInvocation>,
+    },
+    /// A name that contains the separator reserved for generated names.
+    ReservedSeparator,
+    /// An `.eqv` symbol that's defined again.
+    SymbolRedefined {
+        name: String,
+    },
+    /// A name in the condition of an `.if` that isn't a symbol.
+    UndefinedSymbol {
+        name: String,
+    },
+    /// The condition of an `.if` divides by zero.
+    ConditionDivByZero,
+    /// An `.elseif`, `.else` or `.endif` without an `.if` to go with it.
+    UnmatchedConditional {
+        directive: String,
+    },
+    /// An `.if` that's never closed by an `.endif`.
+    UnterminatedConditional,
+    /// An `.include` of a file that's already being included,
+    /// along with each file on the way back to it.
+    IncludeCycle {
+        chain: Vec<String>,
+    },
+    /// An `.include` of a file that couldn't be found.
+    IncludeNotFound {
+        path: String,
+    },
+}
+
+/// Diagnoses the line `line` of `source`, which failed to parse at `col`.
+///
+/// Gives back the kind of error, along with the column of the culprit
+/// (or `col` itself, if nothing more specific was found).
+pub(crate) fn diagnose(source: &[u8], line: u32, col: usize) -> (ParseErrorKind, usize) {
+    let text = match source.split(|&byte| byte == b'\n').nth(line as usize - 1) {
+        Some(text) => text.strip_suffix(b"\r").unwrap_or(text),
+        None => return (ParseErrorKind::Unknown, col),
+    };
+
+    let code = match mask_literals(text) {
+        Ok(code) => code,
+        Err((kind, index)) => return (kind, index + 1),
+    };
+
+    let start = skip_labels(&code);
+
+    let checks: [Check; 4] = [
+        unknown_directive,
+        invalid_number,
+        unclosed_paren,
+        missing_comma,
+    ];
+
+    checks
+        .iter()
+        .find_map(|check| check(&code, start))
+        .map(|(kind, index)| (kind, index + 1))
+        .unwrap_or((ParseErrorKind::Unknown, col))
+}
+
+/// Blanks out the contents of any strings or characters (and drops any comment),
+/// so the other checks don't trip up on them.
+fn mask_literals(text: &[u8]) -> Result<Vec<u8>, (ParseErrorKind, usize)> {
+    let mut code = text.to_vec();
+    let mut index = 0;
+
+    while index < text.len() {
+        match text[index] {
+            b'#' => {
+                code.truncate(index);
+                break;
+            }
+            quote @ (b'"' | b'\'') => {
+                let start = index;
+                index += 1;
+
+                loop {
+                    match text.get(index) {
+                        None if quote == b'"' => {
+                            return Err((ParseErrorKind::UnterminatedString, start))
+                        }
+                        // a stray `'` is left for the parsers to complain about
+                        None => return Ok(code),
+                        Some(b'\\') => match text.get(index + 1) {
+                            Some(escape) if VALID_ESCAPES.contains(escape) => index += 2,
+                            Some(&escape) => {
+                                return Err((
+                                    ParseErrorKind::InvalidEscape {
+                                        escape: escape as char,
+                                    },
+                                    index,
+                                ))
+                            }
+                            None => index += 1,
+                        },
+                        Some(&byte) if byte == quote => break,
+                        Some(_) => index += 1,
+                    }
+                }
+
+                code[start..=index].fill(b'x');
+            }
+            _ => {}
+        }
+
+        index += 1;
+    }
+
+    Ok(code)
+}
+
+/// The index just after any labels at the start of the line.
+fn skip_labels(code: &[u8]) -> usize {
+    let mut start = skip_spaces(code, 0);
+
+    loop {
+        let end = ident_end(code, start);
+        if end == start {
+            return start;
+        }
+
+        let colon = skip_spaces(code, end);
+        if code.get(colon) != Some(&b':') {
+            return start;
+        }
+
+        start = skip_spaces(code, colon + 1);
+    }
+}
+
+fn skip_spaces(code: &[u8], mut index: usize) -> usize {
+    while code.get(index).is_some_and(u8::is_ascii_whitespace) {
+        index += 1;
+    }
+
+    index
+}
+
+fn is_ident_char(byte: u8) -> bool {
+    IDENT_CONTD_CHARS.as_bytes().contains(&byte)
+}
+
+/// The end of the identifier (or number) starting at `start`.
+fn ident_end(code: &[u8], start: usize) -> usize {
+    let mut end = start;
+    while code.get(end).is_some_and(|&byte| is_ident_char(byte)) {
+        end += 1;
+    }
+
+    end
+}
+
+/// The name of the directive at the start of the statement, if it is one.
+fn directive_name(code: &[u8], start: usize) -> Option<String> {
+    if code.get(start) != Some(&b'.') {
+        return None;
+    }
+
+    let end = ident_end(code, start + 1);
+
+    Some(String::from_utf8_lossy(&code[start..end]).to_string())
+}
+
+fn unknown_directive(code: &[u8], start: usize) -> Option<(ParseErrorKind, usize)> {
+    let directive = directive_name(code, start)?;
+
+    if DIRECTIVE_NAMES.contains(&directive.as_str()) {
+        return None;
+    }
+
+    Some((ParseErrorKind::UnknownDirective { directive }, start))
+}
+
+fn invalid_number(code: &[u8], start: usize) -> Option<(ParseErrorKind, usize)> {
+    let mut index = start;
+
+    while index < code.len() {
+        let byte = code[index];
+        let previous = index.checked_sub(1).map(|previous| code[previous]);
+
+        // only look at whole words starting with a digit -- not the `0` in `$t0`,
+        // or the fractional part of a float
+        let starts_number = byte.is_ascii_digit()
+            && !matches!(previous, Some(b'.' | b'$'))
+            && !previous.is_some_and(is_ident_char);
+
+        if !starts_number {
+            index += 1;
+            continue;
+        }
+
+        let mut end = index;
+        while code.get(end).is_some_and(u8::is_ascii_alphanumeric) {
+            end += 1;
+        }
+
+        let literal = String::from_utf8_lossy(&code[index..end]).to_string();
+        let lower = literal.to_ascii_lowercase();
+
+        let (radix, digits) = match lower.get(..2) {
+            Some("0x") => (16, &lower[2..]),
+            Some("0b") => (2, &lower[2..]),
+            Some("0o") => (8, &lower[2..]),
+            _ => (10, lower.as_str()),
+        };
+
+        let valid = if radix == 10 {
+            let suffix = digits.trim_start_matches(|char: char| char.is_ascii_digit());
+
+            // local labels like `1f`, and floats like `1e5` (or the `1e` in `1e-5`)
+            suffix.is_empty()
+                || matches!(suffix, "b" | "f")
+                || suffix
+                    .strip_prefix('e')
+                    .is_some_and(|exponent| exponent.chars().all(|char| char.is_ascii_digit()))
+        } else {
+            !digits.is_empty() && digits.chars().all(|char| char.is_digit(radix))
+        };
+
+        if !valid {
+            return Some((ParseErrorKind::InvalidNumber { literal, radix }, index));
+        }
+
+        index = end;
+    }
+
+    None
+}
+
+fn unclosed_paren(code: &[u8], start: usize) -> Option<(ParseErrorKind, usize)> {
+    let mut open = vec![];
+
+    for (index, &byte) in code.iter().enumerate().skip(start) {
+        match byte {
+            b'(' => open.push(index),
+            b')' => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+
+    open.first()
+        .map(|&index| (ParseErrorKind::UnclosedParen, index))
+}
+
+fn missing_comma(code: &[u8], start: usize) -> Option<(ParseErrorKind, usize)> {
+    let operands = match directive_name(code, start) {
+        Some(directive) if LIST_DIRECTIVES.contains(&directive.as_str()) => start + directive.len(),
+        Some(_) => return None,
+        None => {
+            if !code
+                .get(start)
+                .is_some_and(|byte| IDENT_FIRST_CHAR.as_bytes().contains(byte))
+            {
+                return None;
+            }
+
+            let end = ident_end(code, start);
+
+            // a constant definition, like `X = 1 2`
+            if code.get(skip_spaces(code, end)) == Some(&b'=') {
+                return None;
+            }
+
+            end
+        }
+    };
+
+    const ENDS_OPERATOR: &[u8] = b"+-*/%&|^~<>(=!:";
+    const STARTS_OPERATOR: &[u8] = b"+-*/%&|^<>()=!:";
+
+    let mut depth = 0;
+    let mut previous_word_end: Option<u8> = None;
+    let mut index = operands;
+
+    while index < code.len() {
+        let byte = code[index];
+
+        if byte.is_ascii_whitespace() {
+            index += 1;
+            continue;
+        }
+
+        if byte == b',' && depth == 0 {
+            previous_word_end = None;
+            index += 1;
+            continue;
+        }
+
+        if let Some(previous) = previous_word_end {
+            if !ENDS_OPERATOR.contains(&previous) && !STARTS_OPERATOR.contains(&byte) {
+                return Some((ParseErrorKind::MissingComma, index));
+            }
+        }
+
+        // find the end of this word
+        while index < code.len() && !code[index].is_ascii_whitespace() {
+            match code[index] {
+                b',' if depth == 0 => break,
+                b'(' => depth += 1,
+                b')' => depth -= 1,
+                _ => {}
+            }
+
+            previous_word_end = Some(code[index]);
+            index += 1;
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check(line: &str) -> (ParseErrorKind, usize) {
+        diagnose(line.as_bytes(), 1, 1)
+    }
+
+    #[test]
+    fn test_strings() {
+        assert_eq!(
+            check(r#"msg: .asciiz "hello"#),
+            (ParseErrorKind::UnterminatedString, 14)
+        );
+        assert_eq!(
+            check(r#"msg: .asciiz "a\qb""#),
+            (ParseErrorKind::InvalidEscape { escape: 'q' }, 16)
+        );
+        assert_eq!(check(r#"li $t0, '\n' 1"#).0, ParseErrorKind::MissingComma);
+    }
+
+    #[test]
+    fn test_directives() {
+        assert_eq!(
+            check("    .wrod 1"),
+            (
+                ParseErrorKind::UnknownDirective {
+                    directive: ".wrod".into()
+                },
+                5
+            )
+        );
+        assert_eq!(check(".word 0 1 2"), (ParseErrorKind::MissingComma, 9));
+        assert_eq!(check(".word 0 : 4"), (ParseErrorKind::Unknown, 1));
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(
+            check("li $t0, 0x12g4"),
+            (
+                ParseErrorKind::InvalidNumber {
+                    literal: "0x12g4".into(),
+                    radix: 16
+                },
+                9
+            )
+        );
+        assert_eq!(
+            check("li $t0, 0b102"),
+            (
+                ParseErrorKind::InvalidNumber {
+                    literal: "0b102".into(),
+                    radix: 2
+                },
+                9
+            )
+        );
+        assert_eq!(
+            check("li $t0, 12a"),
+            (
+                ParseErrorKind::InvalidNumber {
+                    literal: "12a".into(),
+                    radix: 10
+                },
+                9
+            )
+        );
+        assert_eq!(check("b 1f $t0").0, ParseErrorKind::MissingComma);
+        assert_eq!(check("li.s $f0, 1.5e-3 x").0, ParseErrorKind::MissingComma);
+    }
+
+    #[test]
+    fn test_operands() {
+        assert_eq!(
+            check("loop: lw $t0, 4($t1"),
+            (ParseErrorKind::UnclosedParen, 16)
+        );
+        assert_eq!(
+            check("add $t0 $t1, $t2  # a, b"),
+            (ParseErrorKind::MissingComma, 9)
+        );
+        assert_eq!(check("lw $t0, 4 ($t1) +"), (ParseErrorKind::Unknown, 1));
+        assert_eq!(check("li $t0, 1 + - 2 )"), (ParseErrorKind::Unknown, 1));
+        assert_eq!(check("X = 1 2").0, ParseErrorKind::Unknown);
+    }
+}
@@ -37,6 +37,8 @@ pub enum MpDirective {
     Align(MpConstValueLoc),
     Space(MpConstValueLoc),
     Globl(String),
+    /// An assembler option, like `.set noreorder`, which mipsy has no use for.
+    Set(String),
 }
 
 impl Display for MpDirective {
@@ -57,6 +59,7 @@ impl Display for MpDirective {
                 Align(_) => "align",
                 Space(_) => "space",
                 Globl(_) => "globl",
+                Set(_) => "set",
                 Text => "text",
                 Data => "data",
                 KText => "ktext",
@@ -85,6 +88,7 @@ pub fn parse_directive(i: Span<'_>) -> IResult<Span<'_>, MpDirectiveLoc> {
                 parse_space,
                 parse_align,
                 parse_globl,
+                parse_set,
             )),
             position,
         )),
@@ -221,6 +225,13 @@ fn parse_globl(i: Span<'_>) -> IResult<Span<'_>, MpDirective> {
     Ok((remaining_data, MpDirective::Globl(ident)))
 }
 
+fn parse_set(i: Span<'_>) -> IResult<Span<'_>, MpDirective> {
+    let (remaining_data, (_, _, option)) =
+        tuple((tag(".set"), comment_multispace1, parse_ident))(i)?;
+
+    Ok((remaining_data, MpDirective::Set(option)))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
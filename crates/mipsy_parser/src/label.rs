@@ -1,6 +1,8 @@
 use crate::{misc::parse_ident, Span};
 use nom::{
-    character::complete::{char, space0},
+    branch::alt,
+    character::complete::{char, digit1, space0},
+    combinator::map,
     sequence::tuple,
     IResult,
 };
@@ -8,9 +10,9 @@ use nom_locate::position;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct MpLabel {
-    label: String,
-    col: u32,
-    col_end: u32,
+    pub(crate) label: String,
+    pub(crate) col: u32,
+    pub(crate) col_end: u32,
 }
 
 impl MpLabel {
@@ -18,6 +20,16 @@ impl MpLabel {
         self.label.to_string()
     }
 
+    pub fn label_mut(&mut self) -> &mut String {
+        &mut self.label
+    }
+
+    /// Numeric local labels, like `1:`, can be defined many times,
+    /// and are referred to as `1b` (backwards) or `1f` (forwards).
+    pub fn is_local(&self) -> bool {
+        is_local_label(&self.label)
+    }
+
     pub fn col(&self) -> u32 {
         self.col
     }
@@ -28,8 +40,18 @@ impl MpLabel {
 }
 
 pub fn parse_label(i: Span<'_>) -> IResult<Span<'_>, MpLabel> {
-    let (remaining_data, (pos_start, label, _, _, pos_end)) =
-        tuple((position, parse_ident, space0, char(':'), position))(i)?;
+    let (remaining_data, (pos_start, label, _, _, pos_end)) = tuple((
+        position,
+        alt((
+            parse_ident,
+            map(digit1, |digits: Span<'_>| {
+                String::from_utf8_lossy(digits.fragment()).to_string()
+            }),
+        )),
+        space0,
+        char(':'),
+        position,
+    ))(i)?;
 
     let col = pos_start.get_column() as u32;
     let col_end = pos_end.get_column() as u32;
@@ -43,3 +65,7 @@ pub fn parse_label(i: Span<'_>) -> IResult<Span<'_>, MpLabel> {
         },
     ))
 }
+
+pub fn is_local_label(label: &str) -> bool {
+    !label.is_empty() && label.bytes().all(|byte| byte.is_ascii_digit())
+}
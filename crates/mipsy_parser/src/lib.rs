@@ -6,15 +6,23 @@ use nom_locate::LocatedSpan;
 
 pub type Span<'a> = LocatedSpan<&'a [u8]>;
 
+pub use attribute::Attribute;
 pub use constant::{MpConst, MpConstValue, MpConstValueLoc};
+pub use cst::{
+    is_preprocessor_directive, parse_mips_lossless, CstContent, CstItem, CstProgram, Trivia,
+};
+pub use diagnose::{ParseErrorKind, DIRECTIVE_NAMES};
 pub use directive::MpDirective;
 pub use instruction::{MpArgument, MpInstruction};
+pub use label::is_local_label;
 pub use misc::{tabs_to_spaces, ErrorLocation};
-pub use number::{MpImmediate, MpImmediateBinaryOp, MpNumber};
+pub use number::{MpImmediate, MpImmediateBinaryOp, MpNumber, MpRelocation};
 pub use parser::{MpItem, MpProgram, TaggedFile};
+pub use preprocess::{include_lines, load_includes, MpMacroInvocation};
 pub use register::{MpOffsetOperator, MpRegister, MpRegisterIdentifier};
+pub use structure::{MpStruct, MpStructField};
 
-pub use parser::parse_mips;
+pub use parser::{parse_mips, parse_mips_with_defines};
 
 pub fn parse_instruction<T>(input: T, tab_size: u32) -> Result<MpInstruction, ErrorLocation>
 where
@@ -44,10 +52,14 @@ where
 
 mod attribute;
 mod constant;
+mod cst;
+mod diagnose;
 mod directive;
 mod instruction;
 mod label;
 mod misc;
 mod number;
 pub mod parser;
+mod preprocess;
 mod register;
+mod structure;
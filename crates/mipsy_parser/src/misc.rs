@@ -1,13 +1,16 @@
 use std::rc::Rc;
 
-use crate::Span;
+use crate::{
+    diagnose::{diagnose, ParseErrorKind},
+    MpMacroInvocation, Span,
+};
 use nom::{
     branch::alt,
     bytes::complete::{is_a, tag},
-    character::complete::{anychar, char, multispace1, none_of, one_of},
-    combinator::{map, not, opt},
+    character::complete::{anychar, char, digit1, multispace1, none_of, one_of},
+    combinator::{map, not, opt, recognize, verify},
     multi::{many0, many1},
-    sequence::{preceded, tuple},
+    sequence::{pair, preceded, tuple},
     IResult,
 };
 
@@ -16,6 +19,29 @@ pub struct ErrorLocation {
     pub file_name: Option<Rc<str>>,
     pub line: u32,
     pub col: usize,
+    pub kind: ParseErrorKind,
+    /// the macro invocations that the line was expanded from, innermost first
+    pub expansion: Vec<MpMacroInvocation>,
+}
+
+impl ErrorLocation {
+    /// An error at a line that failed to parse, working out what went wrong from `source`.
+    pub(crate) fn diagnosed(
+        source: &[u8],
+        file_name: Option<Rc<str>>,
+        line: u32,
+        col: usize,
+    ) -> Self {
+        let (kind, col) = diagnose(source, line, col);
+
+        Self {
+            file_name,
+            line,
+            col,
+            kind,
+            expansion: vec![],
+        }
+    }
 }
 
 pub(crate) fn parse_result<'a, T, P>(
@@ -31,20 +57,25 @@ where
             if leftover.is_empty() {
                 Ok(t)
             } else {
-                Err(leftover_tokens_strip_multispace(leftover, file_name))
+                Err(leftover_tokens_strip_multispace(i, leftover, file_name))
             }
         }
-        Err(_) => Err(leftover_tokens_strip_multispace(i, file_name)),
+        Err(_) => Err(leftover_tokens_strip_multispace(i, i, file_name)),
     }
 }
 
-fn leftover_tokens_strip_multispace(i: Span<'_>, file_name: Option<Rc<str>>) -> ErrorLocation {
+fn leftover_tokens_strip_multispace(
+    input: Span<'_>,
+    i: Span<'_>,
+    file_name: Option<Rc<str>>,
+) -> ErrorLocation {
     match comment_multispace0(i) {
-        Ok((leftover, _)) => ErrorLocation {
+        Ok((leftover, _)) => ErrorLocation::diagnosed(
+            input.fragment(),
             file_name,
-            line: leftover.location_line(),
-            col: leftover.get_column(),
-        },
+            leftover.location_line(),
+            leftover.get_column(),
+        ),
         Err(err) => {
             eprintln!("ERROR: {}", err);
             panic!("this should never happen - please report an issue at https://github.com/insou22/mipsy")
@@ -52,8 +83,9 @@ fn leftover_tokens_strip_multispace(i: Span<'_>, file_name: Option<Rc<str>>) ->
     }
 }
 
-const IDENT_FIRST_CHAR: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz_";
-const IDENT_CONTD_CHARS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz_0123456789.";
+pub(crate) const IDENT_FIRST_CHAR: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz_";
+pub(crate) const IDENT_CONTD_CHARS: &str =
+    "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz_0123456789.";
 
 pub fn escape_char(char: char) -> String {
     match char {
@@ -83,19 +115,34 @@ pub fn parse_escaped_char(i: Span<'_>) -> IResult<Span<'_>, char> {
                 _ => unreachable!(),
             },
         ),
-        map(parse_any1, |byte| byte as char),
+        // a lone backslash is an invalid escape, and strings can't span lines
+        map(
+            verify(parse_any1, |&byte| byte != b'\\' && byte != b'\n'),
+            |byte| byte as char,
+        ),
     ))(i)
 }
 
+/// Separates a name from the number that makes it unique,
+/// in the names generated for the local labels of a macro expansion.
+/// The preprocessor doesn't let it be written in the source.
+pub(crate) const GENERATED_SEPARATOR: char = '~';
+
 pub fn parse_ident(i: Span<'_>) -> IResult<Span<'_>, String> {
-    let (remaining_data, (chr1, rem)) =
-        tuple((one_of(IDENT_FIRST_CHAR), opt(is_a(IDENT_CONTD_CHARS))))(i)?;
+    let (remaining_data, (chr1, rem, generated)) = tuple((
+        one_of(IDENT_FIRST_CHAR),
+        opt(is_a(IDENT_CONTD_CHARS)),
+        opt(recognize(pair(char(GENERATED_SEPARATOR), digit1))),
+    ))(i)?;
 
     let mut ident = String::new();
     ident.push(chr1);
     if let Some(rem) = rem {
         ident.push_str(&String::from_utf8_lossy(rem.fragment()));
     }
+    if let Some(generated) = generated {
+        ident.push_str(&String::from_utf8_lossy(generated.fragment()));
+    }
 
     Ok((remaining_data, ident))
 }
@@ -200,5 +247,10 @@ mod tests {
             unspan(parse_ident(span("abc")).unwrap()),
             ("".to_string(), "abc".into())
         );
+
+        assert_eq!(
+            unspan(parse_ident(span("loop~12:")).unwrap()),
+            (":".to_string(), "loop~12".into())
+        );
     }
 }
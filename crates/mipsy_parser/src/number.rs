@@ -1,16 +1,17 @@
 use std::fmt;
 
 use crate::{
-    misc::{escape_char, parse_escaped_char, parse_ident},
+    constant::{parse_constant_value, MpConstValueLoc},
+    misc::{comment_multispace0, escape_char, parse_escaped_char, parse_ident, IDENT_CONTD_CHARS},
     Span,
 };
 use nom::{
     branch::alt,
     bytes::complete::{is_a, tag},
-    character::complete::{char, digit1, hex_digit1, oct_digit1, one_of, space0},
-    combinator::{map, map_res, opt},
+    character::complete::{char, digit1, hex_digit1, oct_digit1, one_of, satisfy, space0},
+    combinator::{map, map_res, not, opt, peek},
     number::complete::{double, float},
-    sequence::tuple,
+    sequence::{terminated, tuple},
     IResult,
 };
 use serde::{Deserialize, Serialize};
@@ -31,6 +32,18 @@ pub enum MpImmediate {
     I32(i32),
     U32(u32),
     LabelReference(String),
+    /// An assembly-time expression that mixes labels and constants, like `end - start + 4`
+    Expression(MpConstValueLoc),
+    /// `%hi(expr)` or `%lo(expr)`
+    Relocation(MpRelocation, MpConstValueLoc),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum MpRelocation {
+    /// The upper 16 bits, adjusted so that adding the sign-extended `%lo` gives back the value
+    Hi,
+    /// The lower 16 bits
+    Lo,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
@@ -59,6 +72,17 @@ impl fmt::Display for MpImmediate {
             Self::I32(i) => write!(f, "{}", i),
             Self::U32(i) => write!(f, "{}", i),
             Self::LabelReference(label) => write!(f, "{}", label),
+            Self::Expression(expr) => write!(f, "{}", expr.0),
+            Self::Relocation(reloc, expr) => write!(f, "%{}({})", reloc, expr.0),
+        }
+    }
+}
+
+impl fmt::Display for MpRelocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Hi => write!(f, "hi"),
+            Self::Lo => write!(f, "lo"),
         }
     }
 }
@@ -73,13 +97,38 @@ impl fmt::Display for MpImmediateBinaryOp {
 }
 
 pub fn parse_number(i: Span<'_>) -> IResult<Span<'_>, MpNumber> {
-    alt((
-        parse_binary_op_immedaite,
-        map(parse_immediate, MpNumber::Immediate),
-        map(parse_f32, MpNumber::Float32),
-        map(parse_f64, MpNumber::Float64),
-        map(parse_char, MpNumber::Char),
-    ))(i)
+    or_expression(
+        alt((
+            parse_binary_op_immedaite,
+            map(parse_immediate, MpNumber::Immediate),
+            map(parse_f32, MpNumber::Float32),
+            map(parse_f64, MpNumber::Float64),
+            map(parse_char, MpNumber::Char),
+        )),
+        |expr| MpNumber::Immediate(MpImmediate::Expression(expr)),
+    )(i)
+}
+
+/// Runs `parser`, unless a whole expression (like `end - start + 4`) would
+/// cover more of the input, so that anything `parser` already understands
+/// is parsed exactly as it was before expressions were allowed.
+pub fn or_expression<'a, O>(
+    mut parser: impl FnMut(Span<'a>) -> IResult<Span<'a>, O>,
+    expression: impl Fn(MpConstValueLoc) -> O,
+) -> impl FnMut(Span<'a>) -> IResult<Span<'a>, O> {
+    move |i| {
+        let result = parser(i);
+
+        match (parse_constant_value(i), &result) {
+            (Ok((remaining_data, expr)), Ok((remaining, _)))
+                if remaining_data.len() < remaining.len() =>
+            {
+                Ok((remaining_data, expression(expr)))
+            }
+            (Ok((remaining_data, expr)), Err(_)) => Ok((remaining_data, expression(expr))),
+            _ => result,
+        }
+    }
 }
 
 pub fn parse_binary_op_immedaite(i: Span<'_>) -> IResult<Span<'_>, MpNumber> {
@@ -100,6 +149,9 @@ pub fn parse_binary_op_immedaite(i: Span<'_>) -> IResult<Span<'_>, MpNumber> {
 
 pub fn parse_immediate(i: Span<'_>) -> IResult<Span<'_>, MpImmediate> {
     alt((
+        parse_relocation,
+        // `1b` would otherwise be read as the number 1
+        map(parse_local_labelref, MpImmediate::LabelReference),
         map(parse_i16, MpImmediate::I16),
         map(parse_u16, MpImmediate::U16),
         map(parse_i32, MpImmediate::I32),
@@ -108,64 +160,84 @@ pub fn parse_immediate(i: Span<'_>) -> IResult<Span<'_>, MpImmediate> {
     ))(i)
 }
 
-pub fn parse_num<'a, O: RadixNum<O>>(i: Span<'a>) -> IResult<Span<'a>, O> {
-    map_res(
+pub fn parse_relocation(i: Span<'_>) -> IResult<Span<'_>, MpImmediate> {
+    let (remaining_data, (_, reloc, _, _, expr, _, _)) = tuple((
+        char('%'),
         alt((
-            map(
-                tuple((opt(char('-')), tag("0x"), hex_digit1)),
-                |(neg, _, digits): (Option<char>, _, Span<'a>)| {
-                    (
-                        get_sign(neg),
-                        16,
-                        String::from_utf8_lossy(digits.fragment()).to_string(),
-                    )
-                },
-            ),
-            map(
-                tuple((opt(char('-')), tag("0b"), is_a("01"))),
-                |(neg, _, digits): (Option<char>, _, Span<'a>)| {
-                    (
-                        get_sign(neg),
-                        2,
-                        String::from_utf8_lossy(digits.fragment()).to_string(),
-                    )
-                },
-            ),
-            map(
-                tuple((opt(char('-')), tag("0o"), oct_digit1)),
-                |(neg, _, digits): (Option<char>, _, Span<'a>)| {
-                    (
-                        get_sign(neg),
-                        8,
-                        String::from_utf8_lossy(digits.fragment()).to_string(),
-                    )
-                },
-            ),
-            map(
-                tuple((opt(char('-')), tag("0"), oct_digit1)),
-                |(neg, _, digits): (Option<char>, _, Span<'a>)| {
-                    (
-                        get_sign(neg),
-                        8,
-                        String::from_utf8_lossy(digits.fragment()).to_string(),
-                    )
-                },
-            ),
-            map(
-                tuple((opt(char('-')), digit1)),
-                |(neg, digits): (Option<char>, Span<'a>)| {
-                    (
-                        get_sign(neg),
-                        10,
-                        String::from_utf8_lossy(digits.fragment()).to_string(),
-                    )
-                },
-            ),
-            map(
-                tuple((tag("'"), parse_escaped_char, tag("'"))),
-                |(_, ch, _)| ("", 10, format!("{}", ch as i32)),
-            ),
+            map(tag("hi"), |_| MpRelocation::Hi),
+            map(tag("lo"), |_| MpRelocation::Lo),
         )),
+        char('('),
+        comment_multispace0,
+        parse_constant_value,
+        comment_multispace0,
+        char(')'),
+    ))(i)?;
+
+    Ok((remaining_data, MpImmediate::Relocation(reloc, expr)))
+}
+
+pub fn parse_num<'a, O: RadixNum<O>>(i: Span<'a>) -> IResult<Span<'a>, O> {
+    map_res(
+        terminated(
+            alt((
+                map(
+                    tuple((opt(char('-')), tag("0x"), hex_digit1)),
+                    |(neg, _, digits): (Option<char>, _, Span<'a>)| {
+                        (
+                            get_sign(neg),
+                            16,
+                            String::from_utf8_lossy(digits.fragment()).to_string(),
+                        )
+                    },
+                ),
+                map(
+                    tuple((opt(char('-')), tag("0b"), is_a("01"))),
+                    |(neg, _, digits): (Option<char>, _, Span<'a>)| {
+                        (
+                            get_sign(neg),
+                            2,
+                            String::from_utf8_lossy(digits.fragment()).to_string(),
+                        )
+                    },
+                ),
+                map(
+                    tuple((opt(char('-')), tag("0o"), oct_digit1)),
+                    |(neg, _, digits): (Option<char>, _, Span<'a>)| {
+                        (
+                            get_sign(neg),
+                            8,
+                            String::from_utf8_lossy(digits.fragment()).to_string(),
+                        )
+                    },
+                ),
+                map(
+                    tuple((opt(char('-')), tag("0"), oct_digit1)),
+                    |(neg, _, digits): (Option<char>, _, Span<'a>)| {
+                        (
+                            get_sign(neg),
+                            8,
+                            String::from_utf8_lossy(digits.fragment()).to_string(),
+                        )
+                    },
+                ),
+                map(
+                    tuple((opt(char('-')), digit1)),
+                    |(neg, digits): (Option<char>, Span<'a>)| {
+                        (
+                            get_sign(neg),
+                            10,
+                            String::from_utf8_lossy(digits.fragment()).to_string(),
+                        )
+                    },
+                ),
+                map(
+                    tuple((tag("'"), parse_escaped_char, tag("'"))),
+                    |(_, ch, _)| ("", 10, format!("{}", ch as i32)),
+                ),
+            )),
+            number_end,
+        ),
         |(sign, base, digits)| O::from_str_radix(&format!("{}{}", sign, digits), base),
     )(i)
 }
@@ -210,15 +282,36 @@ pub fn parse_u32(i: Span<'_>) -> IResult<Span<'_>, u32> {
 }
 
 pub fn parse_labelref(i: Span<'_>) -> IResult<Span<'_>, String> {
-    parse_ident(i)
+    alt((parse_local_labelref, parse_ident))(i)
+}
+
+/// A reference to a numeric local label, like `1b` or `2f`.
+pub fn parse_local_labelref(i: Span<'_>) -> IResult<Span<'_>, String> {
+    let (remaining_data, (digits, direction, _)) =
+        tuple((digit1, one_of("bf"), not(peek(one_of(IDENT_CONTD_CHARS)))))(i)?;
+
+    Ok((
+        remaining_data,
+        format!(
+            "{}{}",
+            String::from_utf8_lossy(digits.fragment()),
+            direction
+        ),
+    ))
 }
 
 pub fn parse_f32(i: Span<'_>) -> IResult<Span<'_>, f32> {
-    float(i)
+    terminated(float, number_end)(i)
 }
 
 pub fn parse_f64(i: Span<'_>) -> IResult<Span<'_>, f64> {
-    double(i)
+    terminated(double, number_end)(i)
+}
+
+/// Stops a number from running into a name, so that
+/// `0xZZ` fails rather than being a `0` followed by `xZZ`.
+fn number_end(i: Span<'_>) -> IResult<Span<'_>, ()> {
+    not(satisfy(|char| char.is_ascii_alphanumeric() || char == '_'))(i)
 }
 
 pub fn parse_char(i: Span<'_>) -> IResult<Span<'_>, char> {
@@ -271,6 +364,73 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn local_labelref() {
+        assert_eq!(
+            unspan(parse_immediate(span("1b, $t0")).unwrap()),
+            (
+                ", $t0".to_string(),
+                MpImmediate::LabelReference("1b".to_string())
+            )
+        );
+        assert_eq!(
+            unspan(parse_immediate(span("12f")).unwrap()),
+            (
+                "".to_string(),
+                MpImmediate::LabelReference("12f".to_string())
+            )
+        );
+
+        // binary literals, and labels that happen to start like one
+        assert_eq!(
+            unspan(parse_immediate(span("0b101")).unwrap()),
+            ("".to_string(), MpImmediate::I16(5))
+        );
+        assert!(parse_local_labelref(span("1bar")).is_err());
+    }
+
+    #[test]
+    fn expression() {
+        // anything that could already be parsed is parsed the same way
+        assert_eq!(
+            unspan(parse_number(span("table + 8, $t0")).unwrap()),
+            (
+                ", $t0".to_string(),
+                MpNumber::BinaryOpImmediate(
+                    MpImmediate::LabelReference("table".to_string()),
+                    MpImmediateBinaryOp::Plus,
+                    MpImmediate::I16(8),
+                )
+            )
+        );
+        assert_eq!(
+            unspan(parse_number(span("-4")).unwrap()),
+            ("".to_string(), MpNumber::Immediate(MpImmediate::I16(-4)))
+        );
+
+        let (remaining, number) = unspan(parse_number(span("end - start + 4, $t0")).unwrap());
+        assert_eq!(remaining, ", $t0");
+        assert!(matches!(
+            number,
+            MpNumber::Immediate(MpImmediate::Expression(_))
+        ));
+        assert_eq!(number.to_string(), "end - start + 4");
+    }
+
+    #[test]
+    fn relocation() {
+        let (remaining, imm) = unspan(parse_immediate(span("%lo(table + 4)($t0)")).unwrap());
+        assert_eq!(remaining, "($t0)");
+        assert!(matches!(imm, MpImmediate::Relocation(MpRelocation::Lo, _)));
+        assert_eq!(imm.to_string(), "%lo(table + 4)");
+
+        assert!(matches!(
+            unspan(parse_immediate(span("%hi(x)")).unwrap()).1,
+            MpImmediate::Relocation(MpRelocation::Hi, _)
+        ));
+        assert!(parse_relocation(span("%mid(x)")).is_err());
+    }
 }
 
 pub trait RadixNum<O> {
@@ -1,4 +1,4 @@
-use std::rc::Rc;
+use std::{collections::HashSet, rc::Rc};
 
 use crate::{
     attribute::{parse_inner_attribute, parse_outer_attribute, Attribute},
@@ -6,10 +6,20 @@ use crate::{
     directive::{parse_directive, MpDirective, MpDirectiveLoc},
     instruction::{parse_instruction, MpInstruction},
     label::{parse_label, MpLabel},
-    misc::{comment_multispace0, comment_multispace1, parse_result},
-    ErrorLocation, Span,
+    misc::{comment_multispace0, comment_multispace1},
+    preprocess::{self, MpMacroInvocation, Preprocessor},
+    structure::{parse_struct, MpStruct},
+    ErrorLocation, ParseErrorKind, Span,
+};
+use nom::{
+    branch::alt,
+    bytes::complete::take_till,
+    character::complete::char,
+    combinator::{map, opt},
+    multi::many0,
+    sequence::{terminated, tuple},
+    AsBytes, IResult,
 };
-use nom::{branch::alt, combinator::map, multi::many0, sequence::tuple, AsBytes, IResult};
 use nom_locate::{position, LocatedSpan};
 use serde::{Deserialize, Serialize};
 
@@ -21,16 +31,18 @@ pub struct TaggedFile<'tag, 'file> {
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Position {
-    line: u32,
-    line_end: u32,
-    col: u32,
-    col_end: u32,
+    pub(crate) line: u32,
+    pub(crate) line_end: u32,
+    pub(crate) col: u32,
+    pub(crate) col_end: u32,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct MpProgram {
     pub(crate) items: Vec<MpAttributedItem>,
     pub(crate) file_attributes: Vec<Attribute>,
+    // the file attributes of each file merged into the program, by unit
+    pub(crate) unit_attributes: Vec<(Option<Rc<str>>, Vec<Attribute>)>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -38,7 +50,9 @@ pub struct MpAttributedItem {
     pub(crate) item: MpItem,
     pub(crate) attributes: Vec<Attribute>,
     pub(crate) file_tag: Option<Rc<str>>,
+    pub(crate) unit: Option<Rc<str>>,
     pub(crate) line_number: u32,
+    pub(crate) expansion: Vec<MpMacroInvocation>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -47,6 +61,7 @@ pub enum MpItem {
     Directive(MpDirectiveLoc),
     Label(MpLabel),
     Constant(MpConst),
+    Struct(MpStruct),
 }
 
 impl<'tag, 'file> TaggedFile<'tag, 'file> {
@@ -113,8 +128,10 @@ impl MpAttributedItem {
         Self {
             item,
             attributes,
+            unit: file_tag.clone(),
             file_tag,
             line_number,
+            expansion: vec![],
         }
     }
 
@@ -137,6 +154,18 @@ impl MpAttributedItem {
     pub fn line_number(&self) -> u32 {
         self.line_number
     }
+
+    /// The file this item was given to the assembler as part of --
+    /// unlike [`Self::file_tag`], this is the including file for
+    /// an item from an `.include`d file.
+    pub fn unit(&self) -> Option<Rc<str>> {
+        self.unit.clone()
+    }
+
+    /// The macro invocations this item was expanded from, innermost first.
+    pub fn expansion(&self) -> &[MpMacroInvocation] {
+        &self.expansion
+    }
 }
 
 impl MpProgram {
@@ -144,6 +173,7 @@ impl MpProgram {
         Self {
             items,
             file_attributes,
+            unit_attributes: vec![],
         }
     }
 
@@ -151,6 +181,13 @@ impl MpProgram {
         &self.items
     }
 
+    /// Each unit of the program, along with the file attributes of the file it was parsed from.
+    pub fn units(&self) -> impl Iterator<Item = (Option<&str>, &[Attribute])> {
+        self.unit_attributes
+            .iter()
+            .map(|(tag, attributes)| (tag.as_deref(), attributes.as_slice()))
+    }
+
     pub fn items_mut(&mut self) -> &mut Vec<MpAttributedItem> {
         &mut self.items
     }
@@ -161,7 +198,9 @@ impl MpProgram {
                 item: MpItem::Directive((MpDirective::Text, Position::new(0, 0, 0, 0))),
                 attributes: vec![],
                 file_tag: None,
+                unit: None,
                 line_number: 0,
+                expansion: vec![],
             });
         }
 
@@ -170,6 +209,13 @@ impl MpProgram {
 }
 
 pub fn parse_mips_item(i: Span<'_>) -> IResult<Span<'_>, (MpItem, Vec<Attribute>, u32)> {
+    terminated(parse_mips_item_untrimmed, comment_multispace0)(i)
+}
+
+/// Like [`parse_mips_item`], but may leave any comments or whitespace after the item.
+pub(crate) fn parse_mips_item_untrimmed(
+    i: Span<'_>,
+) -> IResult<Span<'_>, (MpItem, Vec<Attribute>, u32)> {
     map(
         tuple((
             comment_multispace0,
@@ -182,12 +228,12 @@ pub fn parse_mips_item(i: Span<'_>) -> IResult<Span<'_>, (MpItem, Vec<Attribute>
             alt((
                 map(parse_constant, MpItem::Constant),
                 map(parse_label, MpItem::Label),
+                map(parse_struct, MpItem::Struct),
                 map(parse_directive, MpItem::Directive),
                 map(parse_instruction, MpItem::Instruction),
             )),
-            comment_multispace0,
         )),
-        |(_, attrs, _, pos, item, _)| (item, attrs, pos.location_line()),
+        |(_, attrs, _, pos, item)| (item, attrs, pos.location_line()),
     )(i)
 }
 
@@ -195,33 +241,93 @@ pub fn parse_mips_bytes<'a>(
     file_name: Option<Rc<str>>,
 ) -> impl FnMut(Span<'a>) -> IResult<Span<'a>, MpProgram> {
     move |i| {
-        let (remaining_input, (attrs, items)) = tuple((
-            parse_outer_attributes,
-            many0(alt((
-                map(parse_mips_item, |(item, attrs, line)| {
-                    Some(MpAttributedItem {
-                        item,
-                        attributes: attrs,
-                        file_tag: file_name.clone(),
-                        line_number: line,
-                    })
-                }),
-                map(comment_multispace1, |_| None),
-            ))),
-        ))(i)?;
-
-        let items = items.into_iter().flatten().collect();
+        let (remaining_input, (attrs, items)) =
+            tuple((parse_outer_attributes, parse_items(file_name.clone())))(i)?;
 
         Ok((
             remaining_input,
             MpProgram {
                 items,
                 file_attributes: attrs,
+                unit_attributes: vec![],
             },
         ))
     }
 }
 
+fn parse_items<'a>(
+    file_name: Option<Rc<str>>,
+) -> impl FnMut(Span<'a>) -> IResult<Span<'a>, Vec<MpAttributedItem>> {
+    move |i| {
+        let (remaining_input, items) = many0(alt((
+            map(parse_mips_item, |(item, attrs, line)| {
+                Some(MpAttributedItem {
+                    item,
+                    attributes: attrs,
+                    file_tag: file_name.clone(),
+                    unit: file_name.clone(),
+                    line_number: line,
+                    expansion: vec![],
+                })
+            }),
+            map(comment_multispace1, |_| None),
+        )))(i)?;
+
+        Ok((remaining_input, items.into_iter().flatten().collect()))
+    }
+}
+
+/// Like [`parse_mips_bytes`], but rather than stopping at the first line that
+/// fails to parse, skips to the next line and carries on, so that every
+/// error in the file can be reported at once.
+fn parse_mips_bytes_recovering(
+    i: Span<'_>,
+    file_name: Option<Rc<str>>,
+) -> (MpProgram, Vec<ErrorLocation>) {
+    let (mut remaining_input, file_attributes) =
+        parse_outer_attributes(i).expect("Outer attributes parser should never fail");
+
+    let mut items = vec![];
+    let mut errors = vec![];
+
+    loop {
+        let (leftover, mut parsed) = parse_items(file_name.clone())(remaining_input)
+            .expect("Items parser should never fail, as it can parse nothing");
+        items.append(&mut parsed);
+
+        let (leftover, _) =
+            comment_multispace0(leftover).expect("Whitespace parser should never fail");
+        if leftover.is_empty() {
+            break;
+        }
+
+        errors.push(ErrorLocation::diagnosed(
+            i.fragment(),
+            file_name.clone(),
+            leftover.location_line(),
+            leftover.get_column(),
+        ));
+
+        // resynchronise at the start of the next line
+        let (leftover, _) = tuple((
+            take_till::<_, _, nom::error::Error<_>>(|byte| byte == b'\n'),
+            opt(char('\n')),
+        ))(leftover)
+        .expect("Skipping to the next line should never fail");
+
+        remaining_input = leftover;
+    }
+
+    (
+        MpProgram {
+            items,
+            file_attributes,
+            unit_attributes: vec![],
+        },
+        errors,
+    )
+}
+
 pub fn parse_outer_attributes(i: Span<'_>) -> IResult<Span<'_>, Vec<Attribute>> {
     map(
         tuple((
@@ -235,49 +341,309 @@ pub fn parse_outer_attributes(i: Span<'_>) -> IResult<Span<'_>, Vec<Attribute>>
     )(i)
 }
 
+/// Parses each of the files into one program.
+///
+/// Files that are `.include`d by another file are parsed
+/// where they're included, rather than on their own.
 pub fn parse_mips(
     files: Vec<TaggedFile<'_, '_>>,
     default_tab_size: u32,
-) -> Result<MpProgram, ErrorLocation> {
+) -> Result<MpProgram, Vec<ErrorLocation>> {
+    parse_mips_with_defines(files, default_tab_size, &[])
+}
+
+/// Parses a program as if every `(name, value)` in `defines`
+/// had been `.set` before the first file.
+///
+/// If any of the files fail to parse, every error found in each of them is given back.
+pub fn parse_mips_with_defines(
+    files: Vec<TaggedFile<'_, '_>>,
+    default_tab_size: u32,
+    defines: &[(String, String)],
+) -> Result<MpProgram, Vec<ErrorLocation>> {
     let mut program = MpProgram {
         items: vec![],
         file_attributes: vec![],
+        unit_attributes: vec![],
     };
 
-    for file in files {
-        let file_name = file.tag;
-        let input = file.file_contents;
+    let included = files
+        .iter()
+        .flat_map(|file| preprocess::includes(file.tag, file.file_contents))
+        .collect::<HashSet<_>>();
 
-        let file_name = file_name.map(Rc::from);
+    let mut preprocessor = Preprocessor::with_defines(defines);
+    let mut include_stack = vec![];
+    let mut parsed = HashSet::new();
+    let mut errors = vec![];
 
-        let initial_file_string = crate::misc::tabs_to_spaces(input, default_tab_size);
-        let initial_span = Span::new(initial_file_string.as_bytes());
+    let is_included = |file: &TaggedFile<'_, '_>| {
+        file.tag
+            .is_some_and(|tag| included.contains(&preprocess::normalise_tag(tag)))
+    };
 
-        let (_remaining_input, outer_attrs) = parse_outer_attributes(initial_span)
-            .expect("Initial outer attributes parser should never fail");
+    // files that are only ever included from a cycle are still parsed,
+    // so that the cycle gets reported rather than silently ignored
+    let roots = files
+        .iter()
+        .filter(|file| !is_included(file))
+        .chain(files.iter().filter(|file| is_included(file)));
+
+    for file in roots {
+        let tag = preprocess::normalise_tag(file.tag.unwrap_or(""));
+        if file.tag.is_some() && parsed.contains(&tag) {
+            continue;
+        }
 
-        let mut actual_tabsize = default_tab_size;
+        let result = parse_file(
+            &mut preprocessor,
+            &files,
+            &mut include_stack,
+            &mut parsed,
+            default_tab_size,
+            file,
+        );
+
+        let mut result = match result {
+            Ok(result) => result,
+            Err(mut file_errors) => {
+                errors.append(&mut file_errors);
+                continue;
+            }
+        };
+
+        for item in result.items.iter_mut() {
+            item.unit = file.tag.map(Rc::from);
+        }
 
-        for attr in outer_attrs {
-            // TODO(zkol): Not a fan of this random hardcoding here
-            if attr.key().to_ascii_lowercase() == "tabsize" {
-                // TODO(zkol): This error handling needs to get wrapped up
-                // with the rest somehow...
-                actual_tabsize = attr
-                    .value()
-                    .expect("Tabsize attribute requires a value")
-                    .parse()
-                    .expect("Tabsize attribute value should be numeric");
+        program.unit_attributes.push((
+            file.tag.map(Rc::from),
+            std::mem::take(&mut result.file_attributes),
+        ));
+        program.merge(result);
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(program)
+}
+
+fn parse_file(
+    preprocessor: &mut Preprocessor,
+    files: &[TaggedFile<'_, '_>],
+    include_stack: &mut Vec<String>,
+    parsed: &mut HashSet<String>,
+    default_tab_size: u32,
+    file: &TaggedFile<'_, '_>,
+) -> Result<MpProgram, Vec<ErrorLocation>> {
+    let file_name = file.tag.map(Rc::from);
+    let input = file.file_contents;
+
+    let initial_file_string = crate::misc::tabs_to_spaces(input, default_tab_size);
+    let initial_span = Span::new(initial_file_string.as_bytes());
+
+    let (_remaining_input, outer_attrs) = parse_outer_attributes(initial_span)
+        .expect("Initial outer attributes parser should never fail");
+
+    let mut actual_tabsize = default_tab_size;
+
+    for attr in &outer_attrs {
+        // TODO(zkol): Not a fan of this random hardcoding here
+        if attr.key().to_ascii_lowercase() == "tabsize" {
+            // TODO(zkol): This error handling needs to get wrapped up
+            // with the rest somehow...
+            actual_tabsize = attr
+                .value()
+                .expect("Tabsize attribute requires a value")
+                .parse()
+                .expect("Tabsize attribute value should be numeric");
+        }
+    }
+
+    let tag = preprocess::normalise_tag(file.tag.unwrap_or(""));
+    parsed.insert(tag.clone());
+    include_stack.push(tag);
+
+    let mut included = vec![];
+    let mut errors = vec![];
+    let file_string = crate::misc::tabs_to_spaces(input, actual_tabsize);
+    let lines = preprocessor.preprocess(
+        &file_name,
+        &file_string,
+        &mut |preprocessor, path, line, error| {
+            let tag = preprocess::include_tag(file.tag, path);
+
+            // a file (indirectly) including itself would never end
+            if let Some(start) = include_stack.iter().position(|included| included == &tag) {
+                let mut chain = include_stack[start..].to_vec();
+                chain.push(tag);
+
+                return Err(ErrorLocation {
+                    kind: ParseErrorKind::IncludeCycle { chain },
+                    ..error
+                });
             }
+
+            let included_file = match files
+                .iter()
+                .find(|file| file.tag.map(preprocess::normalise_tag).as_ref() == Some(&tag))
+            {
+                Some(included_file) => included_file,
+                None => {
+                    return Err(ErrorLocation {
+                        kind: ParseErrorKind::IncludeNotFound { path: tag },
+                        ..error
+                    })
+                }
+            };
+
+            match parse_file(
+                preprocessor,
+                files,
+                include_stack,
+                parsed,
+                default_tab_size,
+                included_file,
+            ) {
+                Ok(program) => included.push((line, program)),
+                Err(mut include_errors) => errors.append(&mut include_errors),
+            }
+
+            Ok(())
+        },
+    );
+
+    include_stack.pop();
+
+    let lines = match lines {
+        Ok(lines) => lines,
+        Err(error) => {
+            errors.push(error);
+            return Err(errors);
         }
+    };
 
-        let file_string = crate::misc::tabs_to_spaces(input, actual_tabsize);
-        let span = Span::new(file_string.as_bytes());
+    let expanded = preprocess::expanded_text(&lines);
+    let span = Span::new(expanded.as_bytes());
 
-        let result = parse_result(span, file_name.clone(), parse_mips_bytes(file_name))?;
+    let (mut result, parse_errors) = parse_mips_bytes_recovering(span, file_name);
 
-        program.merge(result);
+    for error in parse_errors {
+        push_error(&mut errors, preprocess::remap_error(&lines, error));
     }
 
-    Ok(program)
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // included files go wherever their `.include` was
+    let splices = included
+        .into_iter()
+        .map(|(line, program)| {
+            let index = result
+                .items
+                .partition_point(|item| item.line_number <= line);
+
+            (index, program)
+        })
+        .collect::<Vec<_>>();
+
+    preprocess::remap_items(&lines, &mut result.items);
+
+    for (index, program) in splices.into_iter().rev() {
+        result.items.splice(index..index, program.items);
+    }
+
+    // these have the positions of the original file, rather than the expanded text
+    result.file_attributes = outer_attrs;
+
+    Ok(result)
+}
+
+/// Adds an error, unless the same error is already there from another
+/// expansion of the same macro, in which case the invocations are merged.
+fn push_error(errors: &mut Vec<ErrorLocation>, error: ErrorLocation) {
+    let duplicate = errors.iter_mut().find(|other| {
+        other.file_name == error.file_name
+            && other.line == error.line
+            && other.col == error.col
+            && other.kind == error.kind
+    });
+
+    match duplicate {
+        Some(duplicate) => {
+            for invocation in error.expansion {
+                if !duplicate.expansion.contains(&invocation) {
+                    duplicate.expansion.push(invocation);
+                }
+            }
+        }
+        None => errors.push(error),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_every_error() {
+        let errors = parse_mips(
+            vec![
+                TaggedFile::new(Some("a.s"), "main:\n  add $t0,\n  jr $ra\n  .word ,\n"),
+                TaggedFile::new(Some("b.s"), "  nop\n  ) nop\n"),
+            ],
+            8,
+        )
+        .unwrap_err();
+
+        let errors = errors
+            .iter()
+            .map(|err| (err.file_name.as_deref().unwrap(), err.line, err.col))
+            .collect::<Vec<_>>();
+
+        assert_eq!(errors, vec![("a.s", 2, 10), ("a.s", 4, 3), ("b.s", 2, 3)]);
+    }
+
+    #[test]
+    fn test_invalid_numbers() {
+        for (line, literal, radix) in [("li $t0, 0xZZ", "0xZZ", 16), ("li $t0, 0b12", "0b12", 2)] {
+            let errors = parse_mips(vec![TaggedFile::new(None, line)], 8).unwrap_err();
+
+            assert_eq!(
+                errors[0].kind,
+                ParseErrorKind::InvalidNumber {
+                    literal: literal.to_string(),
+                    radix,
+                }
+            );
+            assert_eq!(errors[0].col, 9);
+        }
+    }
+
+    #[test]
+    fn test_macro_body_error() {
+        let errors = parse_mips(
+            vec![TaggedFile::new(
+                Some("a.s"),
+                ".macro bad\n  add $t0,\n.end_macro\nmain:\n  bad\n  bad\n",
+            )],
+            8,
+        )
+        .unwrap_err();
+
+        // reported once, from both of its expansions
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 2);
+        assert_eq!(
+            errors[0]
+                .expansion
+                .iter()
+                .map(|invocation| (invocation.name(), invocation.line()))
+                .collect::<Vec<_>>(),
+            vec![("bad", 5), ("bad", 6)]
+        );
+    }
 }
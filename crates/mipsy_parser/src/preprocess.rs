@@ -0,0 +1,1655 @@
+//! Textual preprocessing that happens before a file is parsed:
+//! `.macro` definitions are collected, their invocations
+//! are expanded in place, `.eqv`/`.set` symbols are substituted,
+//! `.if` blocks are resolved, and `.include`d files are parsed.
+//!
+//! Every line of the expanded text remembers which line of the
+//! original file it came from, and which columns were substituted,
+//! so that parsed items can be pointed back at the original source.
+
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use nom::sequence::tuple;
+
+use crate::{
+    constant::parse_constant_value,
+    directive::MpDirectiveLoc,
+    misc::{comment_multispace0, GENERATED_SEPARATOR, IDENT_CONTD_CHARS, IDENT_FIRST_CHAR},
+    parser::{MpAttributedItem, MpItem, Position},
+    ErrorLocation, MpArgument, MpConstValue, MpConstValueLoc, MpDirective, MpImmediate, MpNumber,
+    MpRegister, ParseErrorKind, Span,
+};
+
+/// Macros can invoke other macros, but not forever.
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+/// The site of a macro invocation that an item was expanded from.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct MpMacroInvocation {
+    name: String,
+    file_tag: Option<String>,
+    line: u32,
+    col: u32,
+    col_end: u32,
+}
+
+impl MpMacroInvocation {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn file_tag(&self) -> Option<&str> {
+        self.file_tag.as_deref()
+    }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn col(&self) -> u32 {
+        self.col
+    }
+
+    pub fn col_end(&self) -> u32 {
+        self.col_end
+    }
+}
+
+struct MacroDefinition {
+    file_tag: Option<Rc<str>>,
+    params: Vec<String>,
+    body: Vec<(u32, String)>,
+    local_labels: Vec<String>,
+}
+
+/// A range of columns that was replaced while expanding a line.
+#[derive(Clone)]
+struct Substitution {
+    col: u32,
+    col_end: u32,
+    original_col: u32,
+    original_col_end: u32,
+}
+
+#[derive(Clone)]
+pub(crate) struct SourceLine {
+    text: String,
+    file_tag: Option<Rc<str>>,
+    line: u32,
+    substitutions: Vec<Substitution>,
+    expansion: Vec<MpMacroInvocation>,
+}
+
+impl SourceLine {
+    fn new(text: &str, file_tag: &Option<Rc<str>>, line: u32) -> Self {
+        Self {
+            text: text.to_string(),
+            file_tag: file_tag.clone(),
+            line,
+            substitutions: vec![],
+            expansion: vec![],
+        }
+    }
+
+    /// Maps a column of the expanded text back to the original line.
+    fn map_col(&self, col: u32) -> u32 {
+        let mut shift = 0i64;
+
+        for substitution in self.substitutions.iter() {
+            if col < substitution.col {
+                break;
+            }
+
+            if col < substitution.col_end {
+                return substitution.original_col;
+            }
+
+            shift = substitution.original_col_end as i64 - substitution.col_end as i64;
+        }
+
+        (col as i64 + shift) as u32
+    }
+
+    fn error_at(&self, col: u32, kind: ParseErrorKind) -> ErrorLocation {
+        error_at(&self.file_tag, self.line, col, kind)
+    }
+}
+
+/// Called for each `.include "path"`, with the line of the
+/// expanded text that the included file should be spliced into.
+pub(crate) type IncludeHandler<'h> =
+    dyn FnMut(&mut Preprocessor, &str, u32, ErrorLocation) -> Result<(), ErrorLocation> + 'h;
+
+/// A textual substitution from `.eqv`, `.set` or `-D`.
+struct Symbol {
+    value: String,
+    /// `.eqv` symbols can't be redefined
+    fixed: bool,
+}
+
+/// An `.if` block that hasn't seen its `.endif` yet.
+struct Conditional {
+    line: u32,
+    col: u32,
+    /// whether lines are currently being assembled
+    active: bool,
+    /// whether any branch of the block has been taken
+    taken: bool,
+    seen_else: bool,
+}
+
+/// Macros and symbols are shared by every file in a program,
+/// from the point they're defined onwards.
+#[derive(Default)]
+pub(crate) struct Preprocessor {
+    macros: HashMap<String, Vec<MacroDefinition>>,
+    symbols: HashMap<String, Symbol>,
+    expansions: usize,
+}
+
+impl Preprocessor {
+    pub(crate) fn with_defines(defines: &[(String, String)]) -> Self {
+        let mut preprocessor = Self::default();
+
+        for (name, value) in defines {
+            preprocessor.symbols.insert(
+                name.clone(),
+                Symbol {
+                    value: value.clone(),
+                    fixed: false,
+                },
+            );
+        }
+
+        preprocessor
+    }
+
+    pub(crate) fn preprocess(
+        &mut self,
+        file_tag: &Option<Rc<str>>,
+        file: &str,
+        include: &mut IncludeHandler<'_>,
+    ) -> Result<Vec<SourceLine>, ErrorLocation> {
+        let lines = file.split('\n').collect::<Vec<_>>();
+        let mut output = vec![];
+        let mut conditionals: Vec<Conditional> = vec![];
+
+        let mut index = 0;
+        while index < lines.len() {
+            let line = index as u32 + 1;
+            let text = lines[index];
+            let active = is_active(&conditionals);
+
+            if let Some((directive, col)) = conditional_directive(text) {
+                let source = SourceLine::new(text, file_tag, line);
+                self.conditional(directive, col, &source, &mut conditionals)?;
+
+                output.push(SourceLine::new("", file_tag, line));
+            } else if !active {
+                output.push(SourceLine::new("", file_tag, line));
+            } else if let Some((directive, col)) = symbol_directive(text) {
+                let (name, value) =
+                    parse_symbol(&text[col + directive.len()..]).ok_or_else(|| {
+                        error_at(file_tag, line, col as u32 + 1, ParseErrorKind::Unknown)
+                    })?;
+
+                // a symbol's value can use symbols defined before it
+                let (value, _) = substitute(&value, None, &self.symbols);
+
+                if self.symbols.get(&name).is_some_and(|symbol| symbol.fixed) {
+                    return Err(error_at(
+                        file_tag,
+                        line,
+                        col as u32 + 1,
+                        ParseErrorKind::SymbolRedefined { name },
+                    ));
+                }
+
+                self.symbols.insert(
+                    name,
+                    Symbol {
+                        value,
+                        fixed: directive == ".eqv",
+                    },
+                );
+
+                output.push(SourceLine::new("", file_tag, line));
+            } else if let Some(col) = directive_col(text, ".macro") {
+                let (name, params) =
+                    parse_macro_header(&text[col + ".macro".len()..]).ok_or_else(|| {
+                        error_at(
+                            file_tag,
+                            line,
+                            col as u32 + 1,
+                            ParseErrorKind::InvalidMacroHeader,
+                        )
+                    })?;
+
+                let mut body = vec![];
+                loop {
+                    index += 1;
+
+                    let body_text = match lines.get(index) {
+                        Some(body_text) => *body_text,
+                        None => {
+                            return Err(error_at(
+                                file_tag,
+                                line,
+                                col as u32 + 1,
+                                ParseErrorKind::UnterminatedMacro { name },
+                            ))
+                        }
+                    };
+
+                    if directive_col(body_text, ".end_macro").is_some() {
+                        break;
+                    }
+
+                    if let Some(nested_col) = directive_col(body_text, ".macro") {
+                        return Err(error_at(
+                            file_tag,
+                            index as u32 + 1,
+                            nested_col as u32 + 1,
+                            ParseErrorKind::NestedMacro { outer: name },
+                        ));
+                    }
+
+                    if let Some(separator_col) = written_separator(body_text) {
+                        return Err(error_at(
+                            file_tag,
+                            index as u32 + 1,
+                            separator_col as u32 + 1,
+                            ParseErrorKind::ReservedSeparator,
+                        ));
+                    }
+
+                    body.push((index as u32 + 1, body_text.to_string()));
+                }
+
+                // the definition itself doesn't produce anything
+                for line in line..=(index as u32 + 1) {
+                    output.push(SourceLine::new("", file_tag, line));
+                }
+
+                let local_labels = body
+                    .iter()
+                    .flat_map(|(_, body_text)| leading_labels(body_text).0)
+                    .collect();
+
+                let definitions = self.macros.entry(name).or_default();
+                definitions.retain(|definition| definition.params.len() != params.len());
+                definitions.push(MacroDefinition {
+                    file_tag: file_tag.clone(),
+                    params,
+                    body,
+                    local_labels,
+                });
+            } else if let Some(col) = directive_col(text, ".end_macro") {
+                return Err(error_at(
+                    file_tag,
+                    line,
+                    col as u32 + 1,
+                    ParseErrorKind::UnmatchedEndMacro,
+                ));
+            } else if let Some(col) = directive_col(text, ".include") {
+                let error = error_at(file_tag, line, col as u32 + 1, ParseErrorKind::Unknown);
+                let path =
+                    parse_include_path(&text[col + ".include".len()..]).ok_or_else(|| {
+                        error_at(file_tag, line, col as u32 + 1, ParseErrorKind::Unknown)
+                    })?;
+
+                output.push(SourceLine::new("", file_tag, line));
+                include(self, &path, output.len() as u32, error)?;
+            } else {
+                if let Some(separator_col) = written_separator(text) {
+                    return Err(error_at(
+                        file_tag,
+                        line,
+                        separator_col as u32 + 1,
+                        ParseErrorKind::ReservedSeparator,
+                    ));
+                }
+
+                let (text, substitutions) = substitute(text, None, &self.symbols);
+
+                let mut source = SourceLine::new(&text, file_tag, line);
+                source.substitutions = substitutions;
+
+                self.expand_line(source, &mut output, 0)?;
+            }
+
+            index += 1;
+        }
+
+        // an `.if` must be closed in the same file it was opened
+        if let Some(conditional) = conditionals.pop() {
+            return Err(error_at(
+                file_tag,
+                conditional.line,
+                conditional.col,
+                ParseErrorKind::UnterminatedConditional,
+            ));
+        }
+
+        Ok(output)
+    }
+
+    /// Handles the conditional assembly directive at `col` of a line,
+    /// opening, switching or closing the innermost block.
+    fn conditional(
+        &self,
+        directive: &str,
+        col: usize,
+        source: &SourceLine,
+        conditionals: &mut Vec<Conditional>,
+    ) -> Result<(), ErrorLocation> {
+        let active = is_active(conditionals);
+        let rest = &source.text[col + directive.len()..];
+        let directive_col = source.map_col(col as u32 + 1);
+
+        let unmatched = || {
+            source.error_at(
+                directive_col,
+                ParseErrorKind::UnmatchedConditional {
+                    directive: directive.to_string(),
+                },
+            )
+        };
+
+        match directive {
+            ".if" | ".ifdef" | ".ifndef" => {
+                let condition = active && self.condition(directive, rest, source, col)?;
+
+                conditionals.push(Conditional {
+                    line: source.line,
+                    col: directive_col,
+                    active: condition,
+                    // nothing in an inactive block can be taken
+                    taken: condition || !active,
+                    seen_else: false,
+                });
+            }
+            ".elseif" | ".else" => {
+                let parent_active =
+                    conditionals.len() < 2 || conditionals[conditionals.len() - 2].active;
+
+                let taken = match conditionals.last() {
+                    Some(conditional) if !conditional.seen_else => conditional.taken,
+                    _ => return Err(unmatched()),
+                };
+
+                let condition = !taken
+                    && parent_active
+                    && (directive == ".else" || self.condition(".if", rest, source, col)?);
+
+                if directive == ".else" && !is_blank(rest) {
+                    return Err(source.error_at(directive_col, ParseErrorKind::Unknown));
+                }
+
+                let conditional = conditionals.last_mut().unwrap();
+                conditional.active = condition;
+                conditional.taken |= condition;
+                conditional.seen_else = directive == ".else";
+            }
+            _ => {
+                if conditionals.pop().is_none() {
+                    return Err(unmatched());
+                }
+
+                if !is_blank(rest) {
+                    return Err(source.error_at(directive_col, ParseErrorKind::Unknown));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Evaluates the condition of an `.if`, `.ifdef` or `.ifndef`.
+    fn condition(
+        &self,
+        directive: &str,
+        rest: &str,
+        source: &SourceLine,
+        col: usize,
+    ) -> Result<bool, ErrorLocation> {
+        let rest_col = col + directive.len();
+
+        if directive != ".if" {
+            let name = rest.trim_start();
+            let name_len = ident_len(name);
+            if name_len == 0 || !is_blank(&name[name_len..]) {
+                return Err(
+                    source.error_at(source.map_col(rest_col as u32 + 1), ParseErrorKind::Unknown)
+                );
+            }
+
+            let defined = self.symbols.contains_key(&name[..name_len]);
+            return Ok(defined == (directive == ".ifdef"));
+        }
+
+        let (expression, substitutions) = substitute(rest, None, &self.symbols);
+        let mut expression_source = SourceLine::new(&expression, &source.file_tag, source.line);
+        expression_source.substitutions = substitutions;
+
+        let error = |expression_col: u32, kind: ParseErrorKind| {
+            let col = expression_source.map_col(expression_col) + rest_col as u32;
+            source.error_at(source.map_col(col), kind)
+        };
+
+        let span = Span::new(expression.as_bytes());
+        let (remaining, value) = match tuple((
+            comment_multispace0,
+            parse_constant_value,
+            comment_multispace0,
+        ))(span)
+        {
+            Ok((remaining, (_, value, _))) => (remaining, value),
+            Err(_) => return Err(error(1, ParseErrorKind::Unknown)),
+        };
+
+        if !remaining.fragment().is_empty() {
+            return Err(error(
+                remaining.get_column() as u32,
+                ParseErrorKind::Unknown,
+            ));
+        }
+
+        evaluate(&value).map(|value| value != 0).map_err(|value| {
+            let kind = match &value.0 {
+                MpConstValue::Const(name) => ParseErrorKind::UndefinedSymbol { name: name.clone() },
+                _ => ParseErrorKind::ConditionDivByZero,
+            };
+
+            error(value.1.col, kind)
+        })
+    }
+
+    fn expand_line(
+        &mut self,
+        source: SourceLine,
+        output: &mut Vec<SourceLine>,
+        depth: usize,
+    ) -> Result<(), ErrorLocation> {
+        let invocation = match self.find_invocation(&source.text) {
+            Some(invocation) => invocation,
+            None => {
+                output.push(source);
+                return Ok(());
+            }
+        };
+
+        let error_col = source.map_col(invocation.start as u32 + 1);
+        if depth >= MAX_EXPANSION_DEPTH {
+            return Err(source.error_at(
+                error_col,
+                ParseErrorKind::MacroTooDeep {
+                    name: invocation.name,
+                    expanded_from: source.expansion.first().cloned(),
+                },
+            ));
+        }
+
+        let definitions = &self.macros[&invocation.name];
+        let definition = match definitions
+            .iter()
+            .find(|definition| definition.params.len() == invocation.args.len())
+        {
+            Some(definition) => definition,
+            None => {
+                let mut expected = definitions
+                    .iter()
+                    .map(|definition| definition.params.len())
+                    .collect::<Vec<_>>();
+                expected.sort_unstable();
+
+                return Err(source.error_at(
+                    error_col,
+                    ParseErrorKind::MacroArgCount {
+                        name: invocation.name,
+                        expected,
+                        found: invocation.args.len(),
+                        expanded_from: source.expansion.first().cloned(),
+                    },
+                ));
+            }
+        };
+
+        // any labels in front of the invocation stay where they are
+        let mut prefix = source.clone();
+        prefix.text = source.text[..invocation.start].to_string();
+        output.push(prefix);
+
+        let mut expansion = vec![MpMacroInvocation {
+            name: invocation.name.clone(),
+            file_tag: source.file_tag.as_deref().map(str::to_string),
+            line: source.line,
+            col: error_col,
+            col_end: source.map_col(invocation.end as u32 + 1),
+        }];
+        expansion.extend(source.expansion.iter().cloned());
+
+        let file_tag = definition.file_tag.clone();
+        let suffix = format!("{GENERATED_SEPARATOR}{}", self.expansions);
+        self.expansions += 1;
+
+        let body = definition
+            .body
+            .iter()
+            .map(|(line, text)| {
+                let (text, substitutions) = substitute(
+                    text,
+                    Some((definition, &invocation.args, &suffix)),
+                    &self.symbols,
+                );
+
+                SourceLine {
+                    text,
+                    file_tag: definition.file_tag.clone(),
+                    line: *line,
+                    substitutions,
+                    expansion: expansion.clone(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // `.if`s in the body are resolved for each expansion,
+        // so that they can depend on the arguments
+        let mut conditionals = vec![];
+        for line in body {
+            if let Some((directive, col)) = conditional_directive(&line.text) {
+                self.conditional(directive, col, &line, &mut conditionals)?;
+            } else if is_active(&conditionals) {
+                self.expand_line(line, output, depth + 1)?;
+            }
+        }
+
+        if let Some(conditional) = conditionals.pop() {
+            return Err(error_at(
+                &file_tag,
+                conditional.line,
+                conditional.col,
+                ParseErrorKind::UnterminatedConditional,
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn find_invocation(&self, text: &str) -> Option<Invocation> {
+        if self.macros.is_empty() {
+            return None;
+        }
+
+        let (_, start) = leading_labels(text);
+        let rest = &text[start..];
+        let name_len = ident_len(rest);
+        if name_len == 0 || !self.macros.contains_key(&rest[..name_len]) {
+            return None;
+        }
+
+        let name = rest[..name_len].to_string();
+        let after_name = &rest[name_len..];
+        let trimmed = after_name.trim_start();
+        let args_start = start + name_len + (after_name.len() - trimmed.len());
+
+        let (args, end) = if let Some(inner) = trimmed.strip_prefix('(') {
+            let close = find_top_level(inner, b')')?;
+            let remaining = inner[close + 1..].trim_start();
+            if !remaining.is_empty() && !remaining.starts_with('#') {
+                return None;
+            }
+
+            (split_args(&inner[..close]), args_start + close + 2)
+        } else {
+            let args = match find_top_level(trimmed, b'#') {
+                Some(comment) => &trimmed[..comment],
+                None => trimmed,
+            };
+
+            if args.starts_with('=') || args.starts_with(':') {
+                return None;
+            }
+
+            (split_args(args), args_start + args.trim_end().len())
+        };
+
+        Some(Invocation {
+            name,
+            args,
+            start,
+            end: end.max(start + name_len),
+        })
+    }
+}
+
+fn error_at(
+    file_tag: &Option<Rc<str>>,
+    line: u32,
+    col: u32,
+    kind: ParseErrorKind,
+) -> ErrorLocation {
+    ErrorLocation {
+        file_name: file_tag.clone(),
+        line,
+        col: col as usize,
+        kind,
+        expansion: vec![],
+    }
+}
+
+struct Invocation {
+    name: String,
+    args: Vec<String>,
+    /// byte offsets of the invocation within its line
+    start: usize,
+    end: usize,
+}
+
+fn is_ident_first(byte: u8) -> bool {
+    IDENT_FIRST_CHAR.as_bytes().contains(&byte)
+}
+
+fn is_ident_contd(byte: u8) -> bool {
+    IDENT_CONTD_CHARS.as_bytes().contains(&byte)
+}
+
+/// The length of the name at the start of `text`,
+/// including any suffix that was generated for it.
+fn ident_len(text: &str) -> usize {
+    let len = match text.as_bytes().first() {
+        Some(&first) if is_ident_first(first) => {
+            1 + text.as_bytes()[1..]
+                .iter()
+                .take_while(|&&byte| is_ident_contd(byte))
+                .count()
+        }
+        _ => return 0,
+    };
+
+    match text[len..].strip_prefix(GENERATED_SEPARATOR) {
+        Some(rest) if rest.starts_with(|c: char| c.is_ascii_digit()) => {
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+            len + GENERATED_SEPARATOR.len_utf8() + digits
+        }
+        _ => len,
+    }
+}
+
+/// The column (0-based) of a generated name's separator written in the source,
+/// which would otherwise let a label collide with one generated for a macro.
+fn written_separator(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut quote = None;
+
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = bytes[index];
+
+        match quote {
+            Some(_) if byte == b'\\' => index += 1,
+            Some(quote_byte) if byte == quote_byte => quote = None,
+            Some(_) => {}
+            None if byte == b'#' => return None,
+            None if byte == b'"' || byte == b'\'' => quote = Some(byte),
+            None if char::from(byte) == GENERATED_SEPARATOR
+                && index > 0
+                && is_ident_contd(bytes[index - 1]) =>
+            {
+                return Some(index)
+            }
+            None => {}
+        }
+
+        index += 1;
+    }
+
+    None
+}
+
+/// The column (0-based) of a directive, if the line consists of it.
+fn directive_col(text: &str, directive: &str) -> Option<usize> {
+    let trimmed = text.trim_start();
+    let rest = trimmed.strip_prefix(directive)?;
+
+    match rest.bytes().next() {
+        None | Some(b' ' | b'\t' | b'\r' | b'#' | b'(') => Some(text.len() - trimmed.len()),
+        _ => None,
+    }
+}
+
+/// Whether lines are being assembled, given the blocks they're in.
+fn is_active(conditionals: &[Conditional]) -> bool {
+    conditionals
+        .last()
+        .is_none_or(|conditional| conditional.active)
+}
+
+fn is_blank(text: &str) -> bool {
+    let text = text.trim_start();
+    text.is_empty() || text.starts_with('#')
+}
+
+/// Which conditional assembly directive a line consists of, if any.
+fn conditional_directive(text: &str) -> Option<(&'static str, usize)> {
+    [".ifdef", ".ifndef", ".if", ".elseif", ".else", ".endif"]
+        .into_iter()
+        .find_map(|directive| Some((directive, directive_col(text, directive)?)))
+}
+
+/// Which symbol definition directive a line consists of, if any.
+///
+/// A `.set` without a value isn't one -- it sets an assembler option
+/// instead, like `.set noreorder`, and is left for the parser.
+fn symbol_directive(text: &str) -> Option<(&'static str, usize)> {
+    [".eqv", ".set"]
+        .into_iter()
+        .find_map(|directive| Some((directive, directive_col(text, directive)?)))
+        .filter(|&(directive, col)| {
+            directive == ".eqv" || parse_symbol(&text[col + directive.len()..]).is_some()
+        })
+}
+
+/// Parses `NAME value` or `NAME, value`.
+fn parse_symbol(text: &str) -> Option<(String, String)> {
+    let text = text.trim_start();
+    let name_len = ident_len(text);
+    if name_len == 0 {
+        return None;
+    }
+
+    let mut value = &text[name_len..];
+    if let Some(comment) = find_top_level(value, b'#') {
+        value = &value[..comment];
+    }
+
+    let value = value.trim();
+    let value = value.strip_prefix(',').unwrap_or(value).trim();
+    if value.is_empty() {
+        return None;
+    }
+
+    Some((text[..name_len].to_string(), value.to_string()))
+}
+
+/// Evaluates the expression of an `.if`,
+/// returning the part of it that couldn't be evaluated on failure.
+fn evaluate(value: &MpConstValueLoc) -> Result<i64, &MpConstValueLoc> {
+    let (x, y) = match &value.0 {
+        MpConstValue::Value(x) => return Ok(*x as i64),
+        // every symbol has already been substituted
+        MpConstValue::Const(_) => return Err(value),
+        MpConstValue::Minus(x) => return Ok(evaluate(x)?.wrapping_neg()),
+        MpConstValue::Neg(x) => return Ok(!evaluate(x)?),
+        MpConstValue::Mult(x, y)
+        | MpConstValue::Sum(x, y)
+        | MpConstValue::Sub(x, y)
+        | MpConstValue::Div(x, y)
+        | MpConstValue::Mod(x, y)
+        | MpConstValue::And(x, y)
+        | MpConstValue::Or(x, y)
+        | MpConstValue::Xor(x, y)
+        | MpConstValue::Shl(x, y)
+        | MpConstValue::Shr(x, y) => (x, y),
+    };
+
+    let (lhs, rhs) = (evaluate(x)?, evaluate(y)?);
+
+    Ok(match &value.0 {
+        MpConstValue::Mult(..) => lhs.wrapping_mul(rhs),
+        MpConstValue::Sum(..) => lhs.wrapping_add(rhs),
+        MpConstValue::Sub(..) => lhs.wrapping_sub(rhs),
+        MpConstValue::Div(..) | MpConstValue::Mod(..) if rhs == 0 => return Err(y),
+        MpConstValue::Div(..) => lhs.wrapping_div(rhs),
+        MpConstValue::Mod(..) => lhs.wrapping_rem(rhs),
+        MpConstValue::And(..) => lhs & rhs,
+        MpConstValue::Or(..) => lhs | rhs,
+        MpConstValue::Xor(..) => lhs ^ rhs,
+        MpConstValue::Shl(..) => lhs.wrapping_shl(rhs as u32),
+        _ => lhs.wrapping_shr(rhs as u32),
+    })
+}
+
+/// Parses `name`, `name(%a, %b)` or `name %a, %b`.
+fn parse_macro_header(text: &str) -> Option<(String, Vec<String>)> {
+    let text = text.trim_start();
+    let name_len = ident_len(text);
+    if name_len == 0 {
+        return None;
+    }
+
+    let mut rest = &text[name_len..];
+    if let Some(comment) = rest.find('#') {
+        rest = &rest[..comment];
+    }
+
+    let mut rest = rest.trim();
+    if let Some(inner) = rest.strip_prefix('(') {
+        rest = inner.strip_suffix(')')?;
+    }
+
+    let params = rest
+        .split(|char: char| char == ',' || char.is_whitespace())
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let param = param.strip_prefix('%')?;
+
+            if ident_len(param) == param.len() {
+                Some(param.to_string())
+            } else {
+                None
+            }
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some((text[..name_len].to_string(), params))
+}
+
+/// Parses the `"path"` of an `.include "path"`.
+fn parse_include_path(text: &str) -> Option<String> {
+    let text = text.trim_start().strip_prefix('"')?;
+    let close = text.find('"')?;
+
+    let rest = text[close + 1..].trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return None;
+    }
+
+    Some(text[..close].to_string())
+}
+
+/// Reads every file that `files` (indirectly) `.include`,
+/// and that isn't already in `files`, onto the end of `files`.
+pub fn load_includes<E>(
+    files: &mut Vec<(String, String)>,
+    mut read: impl FnMut(&str) -> Result<String, E>,
+) -> Result<(), (String, E)> {
+    let mut index = 0;
+
+    while index < files.len() {
+        let (tag, contents) = &files[index];
+
+        for path in includes(Some(tag), contents) {
+            let loaded = files.iter().any(|(tag, _)| normalise_tag(tag) == path);
+
+            if !loaded {
+                let contents = read(&path).map_err(|err| (path.clone(), err))?;
+                files.push((path, contents));
+            }
+        }
+
+        index += 1;
+    }
+
+    Ok(())
+}
+
+/// The files directly `.include`d by a file, relative to the file itself.
+pub(crate) fn includes(file_tag: Option<&str>, file: &str) -> Vec<String> {
+    include_lines(file_tag, file)
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect()
+}
+
+/// Like [`includes`], along with the (zero-based) index of the line each is included on.
+pub fn include_lines(file_tag: Option<&str>, file: &str) -> Vec<(usize, String)> {
+    file.lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let col = directive_col(line, ".include")?;
+            let path = parse_include_path(&line[col + ".include".len()..])?;
+
+            Some((index, include_tag(file_tag, &path)))
+        })
+        .collect()
+}
+
+/// Where an included path points, relative to the file including it.
+pub(crate) fn include_tag(file_tag: Option<&str>, path: &str) -> String {
+    let path = match file_tag.and_then(|tag| Path::new(tag).parent()) {
+        Some(dir) => dir.join(path),
+        None => PathBuf::from(path),
+    };
+
+    normalise_tag(&path.to_string_lossy())
+}
+
+/// `./foo.s` and `foo.s` are the same file.
+pub(crate) fn normalise_tag(tag: &str) -> String {
+    Path::new(tag)
+        .components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .collect::<PathBuf>()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Any labels at the start of a line, and the offset just after them.
+fn leading_labels(text: &str) -> (Vec<String>, usize) {
+    let mut labels = vec![];
+    let mut offset = text.len() - text.trim_start().len();
+
+    loop {
+        let rest = &text[offset..];
+        let len = ident_len(rest);
+        if len == 0 {
+            break;
+        }
+
+        let after = rest[len..].trim_start();
+        match after.strip_prefix(':') {
+            Some(after) => {
+                labels.push(rest[..len].to_string());
+                offset = text.len() - after.trim_start().len();
+            }
+            None => break,
+        }
+    }
+
+    (labels, offset)
+}
+
+/// Finds a byte that isn't nested in brackets or quotes.
+fn find_top_level(text: &str, target: u8) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut depth = 0;
+    let mut quote = None;
+
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = bytes[index];
+
+        match quote {
+            Some(_) if byte == b'\\' => index += 1,
+            Some(quote_byte) if byte == quote_byte => quote = None,
+            Some(_) => {}
+            None if byte == target && depth == 0 => return Some(index),
+            None if byte == b'"' || byte == b'\'' => quote = Some(byte),
+            None if byte == b'(' => depth += 1,
+            None if byte == b')' => depth -= 1,
+            None => {}
+        }
+
+        index += 1;
+    }
+
+    None
+}
+
+fn split_args(text: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut rest = text;
+
+    if rest.trim().is_empty() {
+        return args;
+    }
+
+    while let Some(comma) = find_top_level(rest, b',') {
+        args.push(rest[..comma].trim().to_string());
+        rest = &rest[comma + 1..];
+    }
+    args.push(rest.trim().to_string());
+
+    args
+}
+
+/// Replaces symbols with their values and, within a macro's body,
+/// `%param`s with their arguments. The macro's own labels are
+/// given a name unique to this expansion.
+fn substitute(
+    text: &str,
+    expansion: Option<(&MacroDefinition, &[String], &str)>,
+    symbols: &HashMap<String, Symbol>,
+) -> (String, Vec<Substitution>) {
+    let bytes = text.as_bytes();
+    let mut output = String::new();
+    let mut substitutions = vec![];
+    let mut quote = None;
+
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = bytes[index];
+
+        if let Some(quote_byte) = quote {
+            if byte == b'\\' && index + 1 < bytes.len() {
+                output.push_str(&text[index..index + 2]);
+                index += 2;
+                continue;
+            }
+
+            if byte == quote_byte {
+                quote = None;
+            }
+        } else if byte == b'"' || byte == b'\'' {
+            quote = Some(byte);
+        } else if byte == b'#' {
+            output.push_str(&text[index..]);
+            break;
+        } else {
+            let is_param = byte == b'%';
+            let ident_start = if is_param { index + 1 } else { index };
+            let starts_ident = index == 0
+                || !(is_ident_contd(bytes[index - 1])
+                    || bytes[index - 1] == b'$'
+                    || bytes[index - 1] == b'%');
+
+            let len = ident_len(&text[ident_start..]);
+            if len > 0 && (is_param || starts_ident) {
+                let ident = &text[ident_start..ident_start + len];
+                let end = ident_start + len;
+
+                let replacement = match expansion {
+                    Some((definition, args, _)) if is_param => definition
+                        .params
+                        .iter()
+                        .position(|param| param == ident)
+                        .map(|param| args[param].clone()),
+                    Some((definition, _, suffix))
+                        if definition.local_labels.iter().any(|label| label == ident) =>
+                    {
+                        Some(format!("{ident}{suffix}"))
+                    }
+                    _ if is_param => None,
+                    _ => symbols.get(ident).map(|symbol| symbol.value.clone()),
+                };
+
+                match replacement {
+                    Some(replacement) => {
+                        let col = output.len() as u32 + 1;
+                        output.push_str(&replacement);
+
+                        substitutions.push(Substitution {
+                            col,
+                            col_end: output.len() as u32 + 1,
+                            original_col: index as u32 + 1,
+                            original_col_end: end as u32 + 1,
+                        });
+                    }
+                    None => output.push_str(&text[index..end]),
+                }
+
+                index = end;
+                continue;
+            }
+        }
+
+        // multi-byte characters can only appear in strings and comments,
+        // so it's fine to copy them over a byte at a time
+        let char_len = text[index..].chars().next().map_or(1, char::len_utf8);
+        output.push_str(&text[index..index + char_len]);
+        index += char_len;
+    }
+
+    (output, substitutions)
+}
+
+/// The lines of `text` exactly as they are, where columns map back to
+/// where they would be if each tab were expanded to `tab_size` spaces.
+pub(crate) fn tab_lines(text: &str, file_tag: &Option<Rc<str>>, tab_size: u32) -> Vec<SourceLine> {
+    text.split('\n')
+        .enumerate()
+        .map(|(index, text)| {
+            let mut line = SourceLine::new(text, file_tag, index as u32 + 1);
+
+            // tab stops go by characters (like `tabs_to_spaces`), but columns by bytes
+            let mut chars = 0;
+            let mut original_col = 1;
+
+            for (col, char) in text.char_indices() {
+                if char != '\t' {
+                    chars += 1;
+                    original_col += char.len_utf8() as u32;
+                    continue;
+                }
+
+                let width = tab_size - chars % tab_size;
+
+                line.substitutions.push(Substitution {
+                    col: col as u32 + 1,
+                    col_end: col as u32 + 2,
+                    original_col,
+                    original_col_end: original_col + width,
+                });
+
+                chars += width;
+                original_col += width;
+            }
+
+            line
+        })
+        .collect()
+}
+
+pub(crate) fn expanded_text(lines: &[SourceLine]) -> String {
+    lines
+        .iter()
+        .map(|line| line.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Points an error in the expanded text back at the original file.
+pub(crate) fn remap_error(lines: &[SourceLine], mut error: ErrorLocation) -> ErrorLocation {
+    if let Some(source) = lines.get(error.line as usize - 1) {
+        error.col = source.map_col(error.col as u32) as usize;
+        error.line = source.line;
+        error.expansion = source.expansion.clone();
+    }
+
+    error
+}
+
+/// Points the positions of parsed items back at the original file.
+pub(crate) fn remap_items(lines: &[SourceLine], items: &mut [MpAttributedItem]) {
+    let source = |line: u32| &lines[line as usize - 1];
+
+    for attributed_item in items.iter_mut() {
+        let line = source(attributed_item.line_number);
+        attributed_item.line_number = line.line;
+        attributed_item.file_tag = line.file_tag.clone();
+        attributed_item.expansion = line.expansion.clone();
+
+        match &mut attributed_item.item {
+            MpItem::Instruction(instruction) => {
+                let line = source(instruction.line);
+                instruction.line = line.line;
+                instruction.col = line.map_col(instruction.col);
+                instruction.col_end = line.map_col(instruction.col_end);
+
+                for (argument, col, col_end) in instruction.arguments.iter_mut() {
+                    *col = line.map_col(*col);
+                    *col_end = line.map_col(*col_end);
+
+                    let imm = match argument {
+                        MpArgument::Number(MpNumber::Immediate(imm))
+                        | MpArgument::Register(MpRegister::Offset(imm, _)) => imm,
+                        _ => continue,
+                    };
+
+                    if let MpImmediate::Expression(expr) | MpImmediate::Relocation(_, expr) = imm {
+                        remap_value(lines, expr);
+                    }
+                }
+            }
+            MpItem::Label(label) => {
+                label.col = line.map_col(label.col);
+                label.col_end = line.map_col(label.col_end);
+            }
+            MpItem::Directive(directive) => remap_directive(lines, directive),
+            MpItem::Constant(constant) => {
+                let (start, end) = (source(constant.line), source(constant.line_end));
+                constant.col = start.map_col(constant.col);
+                constant.col_end = end.map_col(constant.col_end);
+                constant.line = start.line;
+                constant.line_end = end.line;
+
+                remap_value(lines, &mut constant.value);
+            }
+            MpItem::Struct(mpstruct) => {
+                mpstruct.col = line.map_col(mpstruct.col);
+                mpstruct.col_end = line.map_col(mpstruct.col_end);
+                mpstruct.line = line.line;
+
+                for field in mpstruct.fields.iter_mut() {
+                    remap_directive(lines, &mut field.directive);
+                }
+            }
+        }
+    }
+}
+
+fn remap_directive(lines: &[SourceLine], (directive, position): &mut MpDirectiveLoc) {
+    remap_position(lines, position);
+
+    match directive {
+        MpDirective::Byte(values) | MpDirective::Half(values) | MpDirective::Word(values) => {
+            for (value, repeat) in values.iter_mut() {
+                remap_value(lines, value);
+
+                if let Some(repeat) = repeat {
+                    remap_value(lines, repeat);
+                }
+            }
+        }
+        MpDirective::Float(values) => {
+            for (_, repeat) in values.iter_mut() {
+                if let Some(repeat) = repeat {
+                    remap_value(lines, repeat);
+                }
+            }
+        }
+        MpDirective::Double(values) => {
+            for (_, repeat) in values.iter_mut() {
+                if let Some(repeat) = repeat {
+                    remap_value(lines, repeat);
+                }
+            }
+        }
+        MpDirective::Align(value) | MpDirective::Space(value) => {
+            remap_value(lines, value);
+        }
+        MpDirective::Text
+        | MpDirective::Data
+        | MpDirective::KText
+        | MpDirective::KData
+        | MpDirective::Ascii(_)
+        | MpDirective::Asciiz(_)
+        | MpDirective::Globl(_)
+        | MpDirective::Set(_) => {}
+    }
+}
+
+fn remap_position(lines: &[SourceLine], position: &mut Position) {
+    let (start, end) = (
+        &lines[position.line as usize - 1],
+        &lines[position.line_end as usize - 1],
+    );
+
+    position.col = start.map_col(position.col);
+    position.col_end = end.map_col(position.col_end);
+    position.line = start.line;
+    position.line_end = end.line;
+}
+
+fn remap_value(lines: &[SourceLine], value: &mut MpConstValueLoc) {
+    remap_position(lines, &mut value.1);
+
+    match &mut value.0 {
+        MpConstValue::Value(_) | MpConstValue::Const(_) => {}
+        MpConstValue::Minus(x) | MpConstValue::Neg(x) => remap_value(lines, x),
+        MpConstValue::Mult(x, y)
+        | MpConstValue::Sum(x, y)
+        | MpConstValue::Sub(x, y)
+        | MpConstValue::Div(x, y)
+        | MpConstValue::Mod(x, y)
+        | MpConstValue::And(x, y)
+        | MpConstValue::Or(x, y)
+        | MpConstValue::Xor(x, y)
+        | MpConstValue::Shl(x, y)
+        | MpConstValue::Shr(x, y) => {
+            remap_value(lines, x);
+            remap_value(lines, y);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn preprocess(file: &str) -> Result<Vec<SourceLine>, ErrorLocation> {
+        Preprocessor::default().preprocess(&None, file, &mut |_, _, _, err| Err(err))
+    }
+
+    fn expand(file: &str) -> Vec<(String, u32)> {
+        preprocess(file)
+            .unwrap()
+            .into_iter()
+            .map(|line| (line.text, line.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(
+            expand(".macro inc(%r)\n  addi %r, %r, 1\n.end_macro\nmain: inc($t0)"),
+            vec![
+                ("".to_string(), 1),
+                ("".to_string(), 2),
+                ("".to_string(), 3),
+                ("main: ".to_string(), 4),
+                ("  addi $t0, $t0, 1".to_string(), 2),
+            ]
+        );
+
+        assert_eq!(
+            expand(".macro spin\nl: j l\n.end_macro\nspin\nspin")[3..],
+            [
+                ("".to_string(), 4),
+                ("l~0: j l~0".to_string(), 2),
+                ("".to_string(), 5),
+                ("l~1: j l~1".to_string(), 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_map_col() {
+        let lines =
+            preprocess(".macro m %a, %b\n  add %a, %b, $t0\n.end_macro\n  m $t1, $t22").unwrap();
+
+        let line = &lines[4];
+        assert_eq!(line.text, "  add $t1, $t22, $t0");
+        assert_eq!(line.map_col(7), 7);
+        assert_eq!(line.map_col(12), 11);
+        assert_eq!(line.map_col(16), 13);
+        assert_eq!(line.map_col(18), 15);
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |file: &str| {
+            let error = preprocess(file).err().unwrap();
+            (error.kind, error.line, error.col)
+        };
+
+        assert_eq!(
+            error(".macro m\n  nop\n"),
+            (
+                ParseErrorKind::UnterminatedMacro {
+                    name: String::from("m")
+                },
+                1,
+                1
+            )
+        );
+        assert_eq!(
+            error(".macro m\n  .macro n\n.end_macro\n"),
+            (
+                ParseErrorKind::NestedMacro {
+                    outer: String::from("m")
+                },
+                2,
+                3
+            )
+        );
+        assert_eq!(
+            error("  .end_macro\n"),
+            (ParseErrorKind::UnmatchedEndMacro, 1, 3)
+        );
+        assert_eq!(
+            error(".macro\n"),
+            (ParseErrorKind::InvalidMacroHeader, 1, 1)
+        );
+        assert_eq!(
+            error(".macro m(%a)\n  nop\n.end_macro\n  m"),
+            (
+                ParseErrorKind::MacroArgCount {
+                    name: String::from("m"),
+                    expected: vec![1],
+                    found: 0,
+                    expanded_from: None,
+                },
+                4,
+                3
+            )
+        );
+
+        // an invocation inside a macro points back at where that macro was invoked
+        let (kind, line, col) =
+            error(".macro m(%a)\n  nop\n.end_macro\n.macro n\n  m\n.end_macro\n  n");
+        assert_eq!((line, col), (5, 3));
+        match kind {
+            ParseErrorKind::MacroArgCount {
+                expanded_from: Some(invocation),
+                ..
+            } => assert_eq!((invocation.name(), invocation.line()), ("n", 7)),
+            kind => panic!("expected a macro argument count error, not {kind:?}"),
+        }
+
+        assert!(matches!(
+            error(".macro m\n  m\n.end_macro\n  m").0,
+            ParseErrorKind::MacroTooDeep { name, expanded_from: Some(_) } if name == "m"
+        ));
+
+        // names generated for macros can't be written by hand
+        assert_eq!(
+            error("l~0: j l~0"),
+            (ParseErrorKind::ReservedSeparator, 1, 2)
+        );
+        assert_eq!(
+            error(".macro m\n  j l~0\n.end_macro"),
+            (ParseErrorKind::ReservedSeparator, 2, 6)
+        );
+        assert!(preprocess("  li $t0, ~1 # l~0\n  .asciiz \"l~0\"").is_ok());
+    }
+
+    #[test]
+    fn test_symbols() {
+        assert_eq!(
+            expand(".eqv COUNTER $s0\n.set STEP, 2 # comment\n  addi COUNTER, COUNTER, STEP")[2],
+            ("  addi $s0, $s0, 2".to_string(), 3)
+        );
+
+        // strings, registers and longer identifiers are left alone
+        assert_eq!(
+            expand(".eqv s0 $t0\n  .asciiz \"s0\" # s0\n  move $s0, s0_x")[1..],
+            [
+                ("  .asciiz \"s0\" # s0".to_string(), 2),
+                ("  move $s0, s0_x".to_string(), 3)
+            ]
+        );
+
+        let lines = preprocess(".eqv REG $t0\n  add REG, REG, $t1").unwrap();
+        assert_eq!(lines[1].map_col(14), 12);
+        assert_eq!(lines[1].map_col(17), 17);
+
+        assert!(preprocess(".set A 1\n.set A 2").is_ok());
+        assert_eq!(
+            preprocess(".eqv A 1\n.eqv A 2").err().unwrap().kind,
+            ParseErrorKind::SymbolRedefined {
+                name: String::from("A")
+            }
+        );
+        assert!(preprocess(".eqv A").is_err());
+
+        // a `.set` without a value is an assembler option, which is left for the parser
+        assert_eq!(
+            expand("  .set noreorder # comment"),
+            vec![("  .set noreorder # comment".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let kept = |file: &str, defines: &[(&str, &str)]| {
+            let defines = defines
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<Vec<_>>();
+
+            Preprocessor::with_defines(&defines)
+                .preprocess(&None, file, &mut |_, _, _, err| Err(err))
+                .unwrap()
+                .into_iter()
+                .filter(|line| !line.text.is_empty())
+                .map(|line| line.text)
+                .collect::<Vec<_>>()
+        };
+
+        let file = ".if LEVEL - 1\na\n.elseif LEVEL\nb\n.else\nc\n.endif";
+        assert_eq!(kept(file, &[("LEVEL", "2")]), vec!["a"]);
+        assert_eq!(kept(file, &[("LEVEL", "1")]), vec!["b"]);
+        assert_eq!(kept(file, &[("LEVEL", "0")]), vec!["a"]);
+
+        let file = ".ifdef A\n.if B\nab\n.else\na\n.endif\n.else\n.ifndef B\nnone\n.endif\n.endif";
+        assert_eq!(kept(file, &[("A", "1"), ("B", "1")]), vec!["ab"]);
+        assert_eq!(kept(file, &[("A", "1"), ("B", "0")]), vec!["a"]);
+        assert_eq!(kept(file, &[]), vec!["none"]);
+
+        // skipped lines don't define anything
+        assert_eq!(
+            kept(".if 0\n.eqv X 1\n.endif\n.ifdef X\nx\n.endif", &[]),
+            Vec::<String>::new()
+        );
+
+        // each expansion of a macro resolves its own `.if`s
+        assert_eq!(
+            kept(
+                ".macro m(%a)\n.if %a\nyes\n.else\nno\n.endif\n.end_macro\nm(1)\nm(X)",
+                &[("X", "0")]
+            ),
+            vec!["yes", "no"]
+        );
+
+        let error = |file: &str| {
+            let error = preprocess(file).err().unwrap();
+            (error.kind, error.line, error.col)
+        };
+
+        assert_eq!(
+            error(".if 1\n"),
+            (ParseErrorKind::UnterminatedConditional, 1, 1)
+        );
+        assert_eq!(
+            error(".macro m\n  .if 1\n.end_macro\nm"),
+            (ParseErrorKind::UnterminatedConditional, 2, 3)
+        );
+        assert_eq!(
+            error("  .endif\n"),
+            (
+                ParseErrorKind::UnmatchedConditional {
+                    directive: String::from(".endif")
+                },
+                1,
+                3
+            )
+        );
+        assert_eq!(
+            error(".if 1\n.else\n.else\n.endif"),
+            (
+                ParseErrorKind::UnmatchedConditional {
+                    directive: String::from(".else")
+                },
+                3,
+                1
+            )
+        );
+        assert_eq!(
+            error(".if 1 + UNDEFINED\n.endif"),
+            (
+                ParseErrorKind::UndefinedSymbol {
+                    name: String::from("UNDEFINED")
+                },
+                1,
+                9
+            )
+        );
+        assert_eq!(
+            error(".if 1 / 0\n.endif"),
+            (ParseErrorKind::ConditionDivByZero, 1, 9)
+        );
+    }
+
+    #[test]
+    fn test_includes() {
+        assert_eq!(
+            includes(
+                Some("src/main.s"),
+                "  .include \"lib.s\"\n  .include \"../a/b.s\"\n"
+            ),
+            vec!["src/lib.s".to_string(), "src/../a/b.s".to_string()]
+        );
+        assert_eq!(
+            includes(None, ".include \"./lib.s\""),
+            vec!["lib.s".to_string()]
+        );
+        assert_eq!(
+            include_lines(None, "main:\n  .include \"lib.s\"\n"),
+            vec![(1, "lib.s".to_string())]
+        );
+
+        let mut files = vec![
+            ("main.s".to_string(), ".include \"a.s\"".to_string()),
+            ("./a.s".to_string(), ".include \"b.s\"".to_string()),
+        ];
+        load_includes(&mut files, |path| match path {
+            "b.s" => Ok(".include \"a.s\"".to_string()),
+            _ => Err(()),
+        })
+        .unwrap();
+        assert_eq!(files.len(), 3);
+        assert_eq!(files[2].0, "b.s");
+    }
+
+    #[test]
+    fn test_include() {
+        use crate::{parse_mips, TaggedFile};
+
+        let main = "main:\n  .include \"lib/lib.s\"\n  jr $ra\n";
+        let lib = "  .include \"./util.s\"\nlib:\n  nop\n";
+        let util = "util:\n  nop\n";
+
+        let program = parse_mips(
+            vec![
+                TaggedFile::new(Some("main.s"), main),
+                TaggedFile::new(Some("lib/lib.s"), lib),
+                TaggedFile::new(Some("lib/util.s"), util),
+            ],
+            8,
+        )
+        .unwrap();
+
+        let items = program
+            .items()
+            .iter()
+            .map(|item| (item.file_tag().unwrap().to_string(), item.line_number()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            items,
+            vec![
+                ("main.s".to_string(), 1),
+                ("lib/util.s".to_string(), 1),
+                ("lib/util.s".to_string(), 2),
+                ("lib/lib.s".to_string(), 2),
+                ("lib/lib.s".to_string(), 3),
+                ("main.s".to_string(), 3),
+            ]
+        );
+
+        let cycle = parse_mips(
+            vec![
+                TaggedFile::new(Some("a.s"), ".include \"b.s\""),
+                TaggedFile::new(Some("b.s"), "\n.include \"a.s\""),
+            ],
+            8,
+        )
+        .unwrap_err();
+
+        assert_eq!(cycle.len(), 1);
+        assert_eq!(cycle[0].file_name.as_deref(), Some("b.s"));
+        assert_eq!(cycle[0].line, 2);
+        assert_eq!(
+            cycle[0].kind,
+            ParseErrorKind::IncludeCycle {
+                chain: vec!["a.s".to_string(), "b.s".to_string(), "a.s".to_string()],
+            }
+        );
+
+        let missing = parse_mips(
+            vec![TaggedFile::new(Some("lib/a.s"), ".include \"b.s\"")],
+            8,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            missing[0].kind,
+            ParseErrorKind::IncludeNotFound {
+                path: "lib/b.s".to_string(),
+            }
+        );
+
+        // a file that fails is no longer being included, so including it again isn't a cycle
+        let twice = parse_mips(
+            vec![
+                TaggedFile::new(Some("a.s"), ".include \"b.s\"\n.include \"b.s\""),
+                TaggedFile::new(Some("b.s"), ".endif"),
+            ],
+            8,
+        )
+        .unwrap_err();
+
+        assert_eq!(twice.len(), 2);
+        assert!(twice
+            .iter()
+            .all(|error| matches!(error.kind, ParseErrorKind::UnmatchedConditional { .. })));
+    }
+}
@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::fmt;
 
 use crate::{
-    number::{parse_immediate, MpImmediate},
+    number::{or_expression, parse_immediate, MpImmediate},
     Span,
 };
 use nom::{
@@ -78,8 +78,8 @@ impl fmt::Display for MpOffsetOperator {
 pub fn parse_register(i: Span<'_>) -> IResult<Span<'_>, MpRegister> {
     alt((
         parse_normal_register,
-        parse_offset_register,
         parse_offset_binary_op_register,
+        parse_offset_register,
     ))(i)
 }
 
@@ -100,7 +100,7 @@ pub fn parse_normal_register(i: Span<'_>) -> IResult<Span<'_>, MpRegister> {
 
 pub fn parse_offset_register(i: Span<'_>) -> IResult<Span<'_>, MpRegister> {
     let (remaining_data, (imm, _, _, _, reg, ..)) = tuple((
-        opt(parse_immediate),
+        opt(or_expression(parse_immediate, MpImmediate::Expression)),
         space0,
         char('('),
         space0,
@@ -0,0 +1,222 @@
+use crate::{
+    directive::{parse_directive, MpDirective, MpDirectiveLoc},
+    misc::{comment_multispace0, parse_ident},
+    parser::Position,
+    Span,
+};
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{char, space0, space1},
+    combinator::{eof, map, opt, peek, value},
+    multi::many0,
+    sequence::{preceded, terminated, tuple},
+    IResult,
+};
+use nom_locate::position;
+
+/// A `.struct Name` ... `.end_struct` block, which lays out its fields
+/// like the equivalent data directives would, without emitting any data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MpStruct {
+    pub(crate) name: String,
+    pub(crate) fields: Vec<MpStructField>,
+    pub(crate) line: u32,
+    pub(crate) col: u32,
+    pub(crate) col_end: u32,
+}
+
+/// A line in a struct, like `next: .word` or `name: .space 20`.
+///
+/// Fields without a name (like an `.align`) still take up space.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MpStructField {
+    pub(crate) name: Option<String>,
+    pub(crate) directive: MpDirectiveLoc,
+}
+
+impl MpStruct {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn fields(&self) -> &[MpStructField] {
+        &self.fields
+    }
+
+    pub fn fields_mut(&mut self) -> &mut [MpStructField] {
+        &mut self.fields
+    }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn col(&self) -> u32 {
+        self.col
+    }
+
+    pub fn col_end(&self) -> u32 {
+        self.col_end
+    }
+}
+
+impl MpStructField {
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn directive(&self) -> &MpDirective {
+        &self.directive.0
+    }
+
+    pub fn directive_mut(&mut self) -> &mut MpDirective {
+        &mut self.directive.0
+    }
+
+    pub fn position(&self) -> &Position {
+        &self.directive.1
+    }
+}
+
+pub fn parse_struct(i: Span<'_>) -> IResult<Span<'_>, MpStruct> {
+    map(
+        tuple((
+            position,
+            tag(".struct"),
+            space1,
+            parse_ident,
+            position,
+            many0(preceded(comment_multispace0, parse_struct_field)),
+            comment_multispace0,
+            tag(".end_struct"),
+        )),
+        |(pos_start, _, _, name, pos_end, fields, _, _)| MpStruct {
+            name,
+            fields,
+            line: pos_start.location_line(),
+            col: pos_start.get_column() as _,
+            col_end: pos_end.get_column() as _,
+        },
+    )(i)
+}
+
+fn parse_struct_field(i: Span<'_>) -> IResult<Span<'_>, MpStructField> {
+    map(
+        tuple((
+            opt(terminated(parse_ident, tuple((space0, char(':'))))),
+            comment_multispace0,
+            alt((
+                map(
+                    tuple((position, parse_bare_field, position)),
+                    |(pos_start, directive, pos_end)| {
+                        (directive, Position::from_positions(pos_start, pos_end))
+                    },
+                ),
+                parse_directive,
+            )),
+        )),
+        |(name, _, directive)| MpStructField { name, directive },
+    )(i)
+}
+
+/// A number directive without any values, like `.word`, is a single element.
+fn parse_bare_field(i: Span<'_>) -> IResult<Span<'_>, MpDirective> {
+    map(
+        tuple((
+            alt((
+                value(MpDirective::Byte(vec![]), tag(".byte")),
+                value(MpDirective::Half(vec![]), tag(".half")),
+                value(MpDirective::Word(vec![]), tag(".word")),
+                value(MpDirective::Float(vec![]), tag(".float")),
+                value(MpDirective::Double(vec![]), tag(".double")),
+            )),
+            space0,
+            peek(alt((eof, tag("\n"), tag("\r"), tag("#")))),
+        )),
+        |(directive, ..)| directive,
+    )(i)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        misc::{span, unspan},
+        MpConstValue, MpConstValueLoc,
+    };
+
+    #[test]
+    fn test_struct() {
+        let (remaining, mpstruct) = unspan(
+            parse_struct(span(
+                ".struct Node\n\
+                 \x20   next: .word\n\
+                 \x20   # a comment\n\
+                 \x20   name: .space 20\n\
+                 \x20   .align 2\n\
+                 \x20   vals: .half 0:4\n\
+                 .end_struct",
+            ))
+            .unwrap(),
+        );
+
+        assert_eq!(remaining, "");
+        assert_eq!(mpstruct.name(), "Node");
+
+        let fields = mpstruct
+            .fields()
+            .iter()
+            .map(|field| (field.name(), field.directive().clone()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            fields,
+            vec![
+                (Some("next"), MpDirective::Word(vec![])),
+                (
+                    Some("name"),
+                    MpDirective::Space(MpConstValueLoc(
+                        MpConstValue::Value(20),
+                        Position::new(4, 4, 18, 20)
+                    ))
+                ),
+                (
+                    None,
+                    MpDirective::Align(MpConstValueLoc(
+                        MpConstValue::Value(2),
+                        Position::new(5, 5, 12, 13)
+                    ))
+                ),
+                (
+                    Some("vals"),
+                    MpDirective::Half(vec![(
+                        MpConstValueLoc(MpConstValue::Value(0), Position::new(6, 6, 17, 18)),
+                        Some(MpConstValueLoc(
+                            MpConstValue::Value(4),
+                            Position::new(6, 6, 19, 20)
+                        ))
+                    )])
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_struct_bare_fields() {
+        let (remaining, mpstruct) = unspan(
+            parse_struct(span(
+                ".struct Pair\n  a: .byte  # first\n  b: .double\n.end_struct",
+            ))
+            .unwrap(),
+        );
+
+        assert_eq!(remaining, "");
+        assert_eq!(mpstruct.fields().len(), 2);
+        assert_eq!(mpstruct.fields()[0].directive(), &MpDirective::Byte(vec![]));
+        assert_eq!(
+            mpstruct.fields()[1].directive(),
+            &MpDirective::Double(vec![])
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
     fs::{self, File},
     io::{Read, Write},
     path::PathBuf,
@@ -16,6 +17,74 @@ const CONFIG_NAME: &str = "config.yaml";
 pub struct MipsyConfig {
     pub tab_size: u32,
     pub spim: bool,
+    /// Enables the MARS-compatible extended syscalls
+    /// (system time, sleep, random numbers, dialogs, etc.)
+    #[serde(default)]
+    pub mars: bool,
+    /// The most errors to report at once when a program fails to compile
+    #[serde(default = "default_max_errors")]
+    pub max_errors: usize,
+    /// The style used by `mipsy fmt`
+    #[serde(default)]
+    pub fmt: FormatConfig,
+    /// Named sets of restrictions on which instructions may be used
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    /// The profile that applies to files without a `#![profile(...)]` attribute
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+/// # The style that source files are formatted in.
+///
+/// Columns are counted from zero, with the file's tab size.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FormatConfig {
+    /// Pad with tabs (where they fit) rather than only spaces
+    pub use_tabs: bool,
+    /// The column that instructions and directives start at
+    pub indent: u32,
+    /// The column that operands are aligned to
+    pub operand_column: u32,
+    /// The column that trailing comments are aligned to
+    pub comment_column: u32,
+}
+
+/// # A restriction on which instructions may be used.
+///
+/// Courses use these to keep students to the instructions
+/// they've been taught so far.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    /// The only instructions that may be used (any, if this is empty)
+    pub allow: Vec<String>,
+    /// Instructions that may not be used
+    pub forbid: Vec<String>,
+    /// Whether pseudo-instructions may be used at all
+    pub pseudo_instructions: bool,
+}
+
+impl Profile {
+    /// Whether an instruction may be used under this profile.
+    pub fn allows(&self, name: &str, is_pseudo: bool) -> bool {
+        let listed = |names: &[String]| names.iter().any(|n| n.eq_ignore_ascii_case(name));
+
+        (self.pseudo_instructions || !is_pseudo)
+            && (self.allow.is_empty() || listed(&self.allow))
+            && !listed(&self.forbid)
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            allow: vec![],
+            forbid: vec![],
+            pseudo_instructions: true,
+        }
+    }
 }
 
 /// # Errors arising from reading the mipsy configuration.
@@ -61,10 +130,30 @@ impl Default for MipsyConfig {
         Self {
             tab_size: 8,
             spim: false,
+            mars: false,
+            max_errors: default_max_errors(),
+            fmt: FormatConfig::default(),
+            profiles: BTreeMap::new(),
+            profile: None,
         }
     }
 }
 
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self {
+            use_tabs: true,
+            indent: 8,
+            operand_column: 16,
+            comment_column: 40,
+        }
+    }
+}
+
+fn default_max_errors() -> usize {
+    20
+}
+
 #[derive(Debug)]
 enum DeserialiseConfigError {
     NotUsingConfig,
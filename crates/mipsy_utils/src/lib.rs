@@ -4,6 +4,6 @@
 mod config;
 mod expand;
 
-pub use config::{config_path, read_config, MipsyConfig, MipsyConfigError};
+pub use config::{config_path, read_config, FormatConfig, MipsyConfig, MipsyConfigError, Profile};
 
 pub use expand::expand_tilde;
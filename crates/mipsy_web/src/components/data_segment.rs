@@ -1,4 +1,5 @@
 use crate::state::state::RunningState;
+use mipsy_lib::compile::source_label_name;
 use mipsy_lib::runtime::PAGE_SIZE;
 use mipsy_lib::util::{get_segment, Segment};
 use mipsy_lib::Register;
@@ -167,7 +168,7 @@ fn render_page(
                             .iter()
                             .find(|(_, &addr)| addr == full_page_addr as u32)
                         {
-                            title.push_str(&format!("{}:\n", label));
+                            title.push_str(&format!("{}:\n", source_label_name(label)));
                         }
 
                         title.push_str(&format!("0x{:08X}", full_page_addr));
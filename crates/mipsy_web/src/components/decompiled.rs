@@ -3,7 +3,8 @@ use crate::{
     worker::{Worker, WorkerRequest},
 };
 use derivative::Derivative;
-use yew::{classes, function_component, html, Callback, Properties, UseStateHandle};
+use mipsy_lib::compile::breakpoints::Condition;
+use yew::{classes, function_component, html, Callback, MouseEvent, Properties, UseStateHandle};
 use yew_agent::UseBridgeHandle;
 
 #[derive(Properties, Derivative)]
@@ -25,7 +26,7 @@ pub fn render_decompiled(props: &DecompiledProps) -> Html {
             <pre class="text-xs">
             <table>
             { html! {
-                for decompiled.as_str().split("\n").into_iter().map(|item| {
+                for row_addresses(decompiled).into_iter().map(|(item, addr)| {
                     if item == "" {
                         // this is &nbsp;
                         html! {
@@ -47,6 +48,65 @@ pub fn render_decompiled(props: &DecompiledProps) -> Html {
                         };
 
 
+                        let current_condition = match (&*props.state, addr) {
+                            (State::Compiled(curr), Some(addr)) => {
+                                let binary = curr.mips_state.binary.as_ref().expect("binary must exist");
+                                binary.breakpoints.get(&addr).and_then(|bp| bp.condition.as_ref()).map(|cond| cond.source().to_string())
+                            }
+                            _ => None,
+                        };
+
+                        // right-clicking the breakpoint button makes it conditional
+                        let set_condition = {
+                            let state = props.state.clone();
+                            let worker = props.worker.clone();
+                            Callback::from(move |event: MouseEvent| {
+                                event.prevent_default();
+
+                                let binary = match &*state {
+                                    State::Compiled(curr) => curr.mips_state.binary.as_ref(),
+                                    State::Error(RuntimeError(error)) => error.mips_state.binary.as_ref(),
+                                    _ => None,
+                                };
+
+                                if let (Some(binary), Some(addr)) = (binary, addr) {
+                                    let current = binary
+                                        .breakpoints
+                                        .get(&addr)
+                                        .and_then(|bp| bp.condition.as_ref())
+                                        .map(|cond| cond.source().to_string())
+                                        .unwrap_or_default();
+
+                                    let window = gloo_utils::window();
+                                    let source = window.prompt_with_message_and_default(
+                                        "Only break here when this expression is true, e.g. $t0 == 57\n(leave empty to always break)",
+                                        &current,
+                                    );
+
+                                    match source {
+                                        Ok(Some(source)) if source.trim().is_empty() => {
+                                            worker.send(WorkerRequest::SetBreakpointCondition(addr, None));
+                                        }
+                                        Ok(Some(source)) => match Condition::parse(&source, binary) {
+                                            Ok(condition) => {
+                                                worker.send(WorkerRequest::SetBreakpointCondition(addr, Some(condition)));
+                                            }
+                                            Err(err) => {
+                                                let _ = window.alert_with_message(&format!("Invalid condition: {err}"));
+                                            }
+                                        },
+                                        // cancelled
+                                        _ => {}
+                                    }
+                                }
+                            })
+                        };
+
+                        let breakpoint_title = match &current_condition {
+                            Some(condition) => format!("break if {condition} (right click to change)"),
+                            None => String::from("right click to add a condition"),
+                        };
+
                         let current_is_breakpoint = match &*props.state {
 
                             State::NoFile => unreachable!("cannot have decompiled if no file"),
@@ -59,34 +119,26 @@ pub fn render_decompiled(props: &DecompiledProps) -> Html {
                             },
                             State::Compiled(curr) => {
                                 let binary = curr.mips_state.binary.as_ref().expect("binary must exist");
-                                let addr = if source_instr.is_none() {
-                                    binary.get_label(&item.trim().replace(":", "")).ok().expect("label must exist")
-                                } else {
-                                    source_instr.expect("none case handled above")
-                                };
-                                binary.breakpoints.contains_key(&addr)
+                                addr.is_some_and(|addr| binary.breakpoints.contains_key(&addr))
                             }
 
                         };
 
                         let toggle_breakpoint = {
                             let state = props.state.clone();
-                            let item = String::from(item);
-                            let source_instr = source_instr.clone();
                             let worker = props.worker.clone();
                             Callback::from(move |_| {
+                                // a label at the very end has no instruction to break on
+                                let addr = match addr {
+                                    Some(addr) => addr,
+                                    None => return,
+                                };
 
                                 match &*state {
 
                                     State::NoFile => unreachable!(),
                                     State::Error(error_type) =>  {
-                                        if let RuntimeError(error) = error_type {
-                                            let binary = error.mips_state.binary.as_ref().expect("binary must exist");
-                                            let addr = if source_instr.is_none() {
-                                                binary.get_label(&item.trim().replace(":", "")).ok().expect("label must exist")
-                                            } else {
-                                                source_instr.expect("none case handled above")
-                                            };
+                                        if let RuntimeError(_error) = error_type {
                                             worker.send(WorkerRequest::ToggleBreakpoint(addr));
                                         } else {
                                             unreachable!("Error in decompiled not possible if not compiled");
@@ -95,13 +147,7 @@ pub fn render_decompiled(props: &DecompiledProps) -> Html {
 
                                     }
 
-                                    State::Compiled(curr) => {
-                                        let binary = curr.mips_state.binary.as_ref().expect("binary must exist");
-                                        let addr = if source_instr.is_none() {
-                                            binary.get_label(&item.trim().replace(":", "")).ok().expect("label must exist")
-                                        } else {
-                                            source_instr.expect("none case handled above")
-                                        };
+                                    State::Compiled(_curr) => {
                                         worker.send(WorkerRequest::ToggleBreakpoint(addr));
                                     },
                                 }
@@ -117,7 +163,7 @@ pub fn render_decompiled(props: &DecompiledProps) -> Html {
                                 })
                               }>
                                 <td class="group w-10 text-center" >
-                                    <button onclick={toggle_breakpoint} class={classes!("text-center", "text-xs", if !current_is_breakpoint {"group-hover:visible invisible"} else {""})}>
+                                    <button onclick={toggle_breakpoint} oncontextmenu={set_condition} title={breakpoint_title} class={classes!("text-center", "text-xs", if !current_is_breakpoint {"group-hover:visible invisible"} else {""}, if current_condition.is_some() {"text-blue-700"} else {""})}>
                                         if current_is_breakpoint {
                                             <StopIconFilled />
                                         } else {
@@ -138,6 +184,29 @@ pub fn render_decompiled(props: &DecompiledProps) -> Html {
     }
 }
 
+/// Each line of the decompiled program, along with the address a breakpoint on it goes at.
+///
+/// A label goes at the address of the instruction after it, as it's shown by its
+/// source name (`1:`), which isn't what the binary's labels are stored under.
+fn row_addresses(decompiled: &str) -> Vec<(&str, Option<u32>)> {
+    let mut rows = vec![];
+    let mut next_addr = None;
+
+    for item in decompiled.split('\n').rev() {
+        // the actual hex address lives from 2-10, 01 are 0x
+        if item.starts_with("0x") {
+            next_addr = item
+                .get(2..10)
+                .and_then(|addr| u32::from_str_radix(addr, 16).ok());
+        }
+
+        rows.push((item, next_addr));
+    }
+
+    rows.reverse();
+    rows
+}
+
 #[function_component(StopIconOutline)]
 pub(crate) fn stop_icon_outline() -> Html {
     html! {
@@ -0,0 +1,194 @@
+use crate::state::config::MipsyWebConfig;
+use bounce::use_atom;
+use mipsy_lib::{
+    inst::{ArgumentType, SignatureRef},
+    InstSet,
+};
+use std::rc::Rc;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+/// Every signature of an instruction, or the instructions whose
+/// descriptions mention some text, like `isa` in the interactive debugger.
+#[function_component(InstructionReference)]
+pub fn instruction_reference() -> Html {
+    let config = use_atom::<MipsyWebConfig>();
+    let query: UseStateHandle<String> = use_state_eq(String::new);
+    let iset: UseStateHandle<Rc<InstSet>> = use_state(|| Rc::new(mipsy_instructions::inst_set()));
+
+    // follow the instruction set the worker compiles with
+    {
+        let iset = iset.clone();
+        use_effect_with_deps(
+            move |instruction_set| {
+                let loaded = instruction_set
+                    .as_ref()
+                    .and_then(|custom| mipsy_instructions::load_inst_set(&custom.yaml).ok())
+                    .unwrap_or_else(mipsy_instructions::inst_set);
+
+                iset.set(Rc::new(loaded));
+                || {}
+            },
+            config.instruction_set.clone(),
+        );
+    }
+
+    let oninput = {
+        let query = query.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            query.set(input.value());
+        })
+    };
+
+    let text = query.trim();
+    let signatures = iset.find_signatures(text);
+
+    let results = if text.is_empty() {
+        html! {
+            <p class="text-sm">
+                {"Type the name of an instruction to see its signatures, or some text to search their descriptions."}
+            </p>
+        }
+    } else if !signatures.is_empty() {
+        html! {
+            <>{ for signatures.iter().map(render_signature) }</>
+        }
+    } else {
+        let found = iset.search_descriptions(text);
+
+        if found.is_empty() {
+            html! {
+                <p class="text-sm">{format!("No instructions mention \"{text}\"")}</p>
+            }
+        } else {
+            html! {
+                <table class="text-sm">
+                    <tbody>
+                        {
+                            for found.iter().map(|sig| {
+                                let onclick = {
+                                    let query = query.clone();
+                                    let name = sig.name().to_string();
+                                    Callback::from(move |_| query.set(name.clone()))
+                                };
+
+                                html! {
+                                    <tr class="cursor-pointer hover:bg-th-primary" {onclick}>
+                                        <td class="pr-4 whitespace-nowrap font-mono">{render_format(sig)}</td>
+                                        <td>{sig.metadata().desc_short().unwrap_or_default()}</td>
+                                    </tr>
+                                }
+                            })
+                        }
+                    </tbody>
+                </table>
+            }
+        }
+    };
+
+    html! {
+        <div class="text-sm">
+            <input
+                type="text"
+                value={(*query).clone()}
+                {oninput}
+                placeholder="instruction or description..."
+                class="w-full mb-2 px-1 bg-th-primary border-2 border-current"
+            />
+            { results }
+        </div>
+    }
+}
+
+fn render_signature(sig: &SignatureRef<'_>) -> Html {
+    let meta = sig.metadata();
+
+    let details = match sig {
+        SignatureRef::Native(native) => {
+            let reads = native
+                .runtime_metadata()
+                .reads()
+                .iter()
+                .map(|read| read.argument_type().to_string())
+                .collect::<Vec<_>>();
+
+            html! {
+                <>
+                    <p><strong>{"encoding: "}</strong>{native.runtime_signature().to_string()}</p>
+                    if !reads.is_empty() {
+                        <p><strong>{"reads: "}</strong>{reads.join(", ")}</p>
+                    }
+                </>
+            }
+        }
+        SignatureRef::Pseudo(pseudo) => html! {
+            <>
+                <p><strong>{"expands to:"}</strong></p>
+                <pre class="pl-4">
+                    {
+                        pseudo
+                            .expansion()
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    }
+                </pre>
+            </>
+        },
+    };
+
+    let kind = match sig {
+        SignatureRef::Native(_) => "native",
+        SignatureRef::Pseudo(_) => "pseudo",
+    };
+
+    html! {
+        <div class="mb-3">
+            <p class="font-mono">
+                {render_format(sig)}
+                <span class="opacity-60">{format!(" ({kind})")}</span>
+            </p>
+            <div class="pl-4">
+                <p>{meta.desc_short().unwrap_or_default()}</p>
+                <p class="whitespace-pre-wrap">{meta.desc_long().unwrap_or_default()}</p>
+                { details }
+            </div>
+        </div>
+    }
+}
+
+/// How an instruction is written, with its registers highlighted.
+fn render_format(sig: &SignatureRef<'_>) -> Html {
+    let compile = sig.compile_sig();
+    let last = compile.format().len().saturating_sub(1);
+
+    let args = compile.format().iter().enumerate().map(|(i, arg)| {
+        let separator = if i == 0 { " " } else { ", " };
+
+        // special case for relative labels
+        let (text, is_register) = if compile.relative_label() && i == last {
+            (String::from("label"), false)
+        } else {
+            (
+                arg.to_string(),
+                matches!(arg, ArgumentType::Rd | ArgumentType::Rs | ArgumentType::Rt),
+            )
+        };
+
+        html! {
+            <>
+                {separator}
+                <span class={if is_register {"font-bold"} else {""}}>{text}</span>
+            </>
+        }
+    });
+
+    html! {
+        <>
+            <strong>{sig.name().to_string()}</strong>
+            { for args }
+        </>
+    }
+}
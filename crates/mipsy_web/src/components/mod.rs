@@ -5,6 +5,7 @@ pub mod data_segment;
 pub mod decompiled;
 pub mod dropdown;
 pub mod heading;
+pub mod instruction_reference;
 pub mod navbar;
 pub mod outputarea;
 pub mod pagebackground;
@@ -315,7 +315,7 @@ pub fn render_navbar(props: &NavBarProps) -> Html {
                 </svg>
                 {"Load"}
               </label>
-              <input id="load_file" onchange={&props.load_onchange} type="file" accept=".s" class="hidden" />
+              <input id="load_file" onchange={&props.load_onchange} type="file" accept=".s,.mipsy" class="hidden" />
                 {
                     for icons.iter().map(|item| {
 
@@ -84,14 +84,14 @@ pub fn render_running_registers(props: &RegisterProps) -> Html {
                         let toggle_read = {
                             let worker = props.worker.clone();
                             Callback::from(move |_| {
-                                worker.send(WorkerRequest::ToggleWatchpoint(index as u32, TargetAction::ReadOnly))
+                                worker.send(WorkerRequest::ToggleWatchpoint(WatchpointTarget::Register(Register::from_u32(index as u32).unwrap()), TargetAction::ReadOnly))
                             })
                         };
 
                         let toggle_write = {
                             let worker = props.worker.clone();
                             Callback::from(move |_| {
-                                worker.send(WorkerRequest::ToggleWatchpoint(index as u32, TargetAction::WriteOnly))
+                                worker.send(WorkerRequest::ToggleWatchpoint(WatchpointTarget::Register(Register::from_u32(index as u32).unwrap()), TargetAction::WriteOnly))
                             })
                         };
 
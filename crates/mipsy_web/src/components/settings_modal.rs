@@ -2,10 +2,12 @@ use crate::components::{
     color_picker::ColorPicker, dropdown::Dropdown, heading::Heading, toggle::ToggleSwitch,
 };
 use crate::state::config::{
-    FontColor, HighlightColor, MipsyWebConfig, PrimaryColor, RegisterBase, SecondaryColor,
-    TertiaryColor,
+    CustomInstructionSet, FontColor, HighlightColor, MipsyWebConfig, PrimaryColor, RegisterBase,
+    SecondaryColor, TertiaryColor,
 };
 use bounce::use_atom;
+use gloo_file::callbacks::{read_as_text, FileReader};
+use gloo_file::File;
 use gloo_utils::format::JsValueSerdeExt;
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::JsValue;
@@ -34,6 +36,8 @@ pub fn render_modal(props: &ModalProps) -> Html {
             None => false,
         });
 
+    let instruction_set_task: UseStateHandle<Option<FileReader>> = use_state(|| None);
+
     html! {
         <div class={classes} id="modal1" style="left: 13%;">
             <div class="modal-dialog">
@@ -436,6 +440,71 @@ pub fn render_modal(props: &ModalProps) -> Html {
                             }
                         />
 
+                        // === Instruction Set ===
+                        <Heading
+                            title="Instruction Set"
+                            subtitle={
+                                match &config.instruction_set {
+                                    Some(instruction_set) => format!("Using the instructions from {}", instruction_set.name),
+                                    None => "Load a YAML file (in the format of mips.yaml) with a different set of instructions".to_string(),
+                                }
+                            }
+                        />
+                        <input
+                            type="file"
+                            accept=".yaml,.yml"
+                            class="m-2"
+                            onchange={
+                                let config = config.clone();
+                                let instruction_set_task = instruction_set_task.clone();
+                                Callback::from(move |e: Event| {
+                                    let input: HtmlInputElement = e.target_unchecked_into();
+
+                                    if let Some(file_blob) = input.files().and_then(|files| files.item(0)) {
+                                        let gloo_file = File::from(file_blob);
+                                        let name = gloo_file.name();
+                                        let config = config.clone();
+
+                                        instruction_set_task.set(Some(read_as_text(&gloo_file, move |res| {
+                                            if let Ok(yaml) = res {
+                                                // check it here, so that a broken file is never saved
+                                                if let Err(errors) = mipsy_instructions::load_inst_set(&yaml) {
+                                                    let message = errors
+                                                        .iter()
+                                                        .map(|error| format!("invalid instruction set `{name}`: {error}"))
+                                                        .collect::<Vec<_>>()
+                                                        .join("\n");
+
+                                                    let _ = gloo_utils::window().alert_with_message(&message);
+                                                    return;
+                                                }
+
+                                                config.set(MipsyWebConfig {
+                                                    instruction_set: Some(CustomInstructionSet { name, yaml }),
+                                                    ..(*config).clone()
+                                                });
+                                            }
+                                        })));
+                                    }
+                                })
+                            }
+                        />
+                        <button
+                            type="button"
+                            class="m-2 p-2 border bg-th-tabunselected hover:bg-th-secondary rounded"
+                            onclick={
+                                let config = config.clone();
+                                Callback::from(move |_| {
+                                    config.set(MipsyWebConfig {
+                                        instruction_set: None,
+                                        ..(*config).clone()
+                                    });
+                                })
+                            }
+                        >
+                            {"reset"}
+                        </button>
+
                         // === Analytics ===
                         // disable analytics info until implemented
                         if false {
@@ -2,8 +2,9 @@ use crate::worker::ReadSyscallInputs;
 use crate::{
     components::{
         about_modal::Modal, banner::Banner, data_segment::DataSegment, decompiled::DecompiledCode,
-        navbar::NavBar, outputarea::OutputArea, pagebackground::PageBackground,
-        registers::Registers, settings_modal::SettingsModal, sourcecode::SourceCode,
+        instruction_reference::InstructionReference, navbar::NavBar, outputarea::OutputArea,
+        pagebackground::PageBackground, registers::Registers, settings_modal::SettingsModal,
+        sourcecode::SourceCode,
     },
     state::{
         config::{MipsyWebConfig, MonacoCursor},
@@ -14,7 +15,7 @@ use crate::{
 };
 use bounce::use_atom;
 use gloo_console::log;
-use gloo_file::callbacks::{read_as_text, FileReader};
+use gloo_file::callbacks::{read_as_bytes, read_as_text, FileReader};
 use gloo_file::File;
 use log::{error, info, trace};
 use mipsy_lib::MipsyError;
@@ -190,6 +191,22 @@ pub fn render_app() -> Html {
         MipsyWebConfig::apply(&*config);
     }
 
+    // the worker compiles with whichever instruction set is configured
+    {
+        let worker = worker.clone();
+        use_effect_with_deps(
+            move |instruction_set| {
+                worker
+                    .borrow()
+                    .as_ref()
+                    .unwrap()
+                    .send(WorkerRequest::LoadInstructionSet(instruction_set.clone()));
+                move || {}
+            },
+            config.instruction_set.clone(),
+        );
+    }
+
     {
         let show_io = show_io.clone();
         let config = config.clone();
@@ -223,79 +240,90 @@ pub fn render_app() -> Html {
             if let State::Error(comp_err_state) = &*state_copy {
                 match &comp_err_state {
                     ErrorType::CompilerOrParserError(error_state) => {
-                        match &error_state.error {
-                            MipsyError::Compiler(err) => {
-                                info!("adding higlight decorations on line {}", err.line());
-
-                                if err.error().should_highlight_line() {
-                                    crate::highlight_section(err.line(), err.col(), err.col_end());
-                                }
-                                is_saved.set(true);
-                            }
-                            MipsyError::Parser(err) => {
-                                info!("adding higlights for parser err");
-
-                                let line_num = err.line();
-
-                                let line = {
-                                    let target_line = (line_num - 1) as usize;
-
-                                    let file = file.as_deref().unwrap_or("");
-                                    let line = file.lines().nth(target_line);
-
-                                    // special case: file is empty and ends with a newline, in which case the
-                                    // parser will point to char 1-1 of the final line, but .lines() won't consider
-                                    // that an actual line, as it doesn't contain any actual content.
-                                    //
-                                    // the only way this can actually occur is if the file contains no actual items,
-                                    // as otherwise it would be happy to reach the end of the file, and return the
-                                    // program. so we can just give a customised error message instead.
-                                    if line.is_none()
-                                        && file.ends_with('\n')
-                                        && target_line == file.lines().count()
-                                    {
-                                        eprintln!("file contains no MIPS contents!");
-                                        None
-                                    } else {
-                                        Some(line.expect("invalid line position in compiler error"))
+                        // every error is highlighted, so clear out the previous ones first
+                        crate::remove_highlight();
+
+                        for error in &error_state.errors {
+                            match error {
+                                MipsyError::Compiler(err) => {
+                                    info!("adding higlight decorations on line {}", err.line());
+
+                                    if err.error().should_highlight_line() {
+                                        crate::highlight_section(
+                                            err.line(),
+                                            err.col(),
+                                            err.col_end(),
+                                        );
                                     }
-                                };
+                                    is_saved.set(true);
+                                }
+                                MipsyError::Parser(err) => {
+                                    info!("adding higlights for parser err");
+
+                                    let line_num = err.line();
+
+                                    let line = {
+                                        let target_line = (line_num - 1) as usize;
+
+                                        let file = file.as_deref().unwrap_or("");
+                                        let line = file.lines().nth(target_line);
+
+                                        // special case: file is empty and ends with a newline, in which case the
+                                        // parser will point to char 1-1 of the final line, but .lines() won't consider
+                                        // that an actual line, as it doesn't contain any actual content.
+                                        //
+                                        // the only way this can actually occur is if the file contains no actual items,
+                                        // as otherwise it would be happy to reach the end of the file, and return the
+                                        // program. so we can just give a customised error message instead.
+                                        if line.is_none()
+                                            && file.ends_with('\n')
+                                            && target_line == file.lines().count()
+                                        {
+                                            eprintln!("file contains no MIPS contents!");
+                                            None
+                                        } else {
+                                            Some(line.expect(
+                                                "invalid line position in compiler error",
+                                            ))
+                                        }
+                                    };
 
-                                if let Some(line) = line {
-                                    let updated_line = {
-                                        let mut updated_line = String::new();
+                                    if let Some(line) = line {
+                                        let updated_line = {
+                                            let mut updated_line = String::new();
 
-                                        for char in line.chars() {
-                                            if char != '\t' {
-                                                updated_line.push(char);
-                                                continue;
-                                            }
+                                            for char in line.chars() {
+                                                if char != '\t' {
+                                                    updated_line.push(char);
+                                                    continue;
+                                                }
 
-                                            let spaces_to_insert =
-                                                config.deref().mipsy_config.tab_size
-                                                    - (updated_line.len() as u32
-                                                        % config.deref().mipsy_config.tab_size);
+                                                let spaces_to_insert =
+                                                    config.deref().mipsy_config.tab_size
+                                                        - (updated_line.len() as u32
+                                                            % config.deref().mipsy_config.tab_size);
 
-                                            updated_line
-                                                .push_str(&" ".repeat(spaces_to_insert as usize));
-                                        }
+                                                updated_line
+                                                    .push_str(&" ".repeat(spaces_to_insert as usize));
+                                            }
 
-                                        updated_line
-                                    };
+                                            updated_line
+                                        };
 
-                                    let last_column = updated_line.len() as u32 + 1;
+                                        let last_column = updated_line.len() as u32 + 1;
 
-                                    log!("highlighting from", err.col(), "to", last_column);
-                                    crate::highlight_section(
-                                        line_num,
-                                        err.col(),
-                                        last_column as u32,
-                                    );
+                                        log!("highlighting from", err.col(), "to", last_column);
+                                        crate::highlight_section(
+                                            line_num,
+                                            err.col(),
+                                            last_column as u32,
+                                        );
+                                    }
                                 }
+                                MipsyError::Runtime(_) => unreachable!(
+                                    "Runtime error should not be in ErrorType::CompilerOrParserError"
+                                ),
                             }
-                            MipsyError::Runtime(_) => unreachable!(
-                                "Runtime error should not be in ErrorType::CompilerOrParserError"
-                            ),
                         }
                     }
 
@@ -325,12 +353,32 @@ pub fn render_app() -> Html {
                     let gloo_file = File::from(file_blob);
 
                     let file_name = gloo_file.name();
-                    filename.set(Some(file_name.clone()));
-                    crate::set_localstorage_filename(&file_name);
                     // prep items for closure below
                     let worker = worker.clone();
 
                     let mut tasks_new = vec![];
+
+                    // already compiled, so there's no source to put in the editor
+                    if file_name.ends_with(".mipsy") {
+                        tasks_new.push(read_as_bytes(&gloo_file, move |res| {
+                            if let Ok(bytes) = res {
+                                log!("sending binary to worker");
+
+                                worker
+                                    .borrow()
+                                    .as_ref()
+                                    .unwrap()
+                                    .send(WorkerRequest::LoadBinary(bytes));
+                            }
+                        }));
+
+                        tasks.set(tasks_new);
+                        return;
+                    }
+
+                    filename.set(Some(file_name.clone()));
+                    crate::set_localstorage_filename(&file_name);
+
                     tasks_new.push(read_as_text(&gloo_file, move |res| match res {
                         Ok(ref file_contents) => {
                             // file.set(Some(file_contents.to_string()));
@@ -433,15 +481,37 @@ pub fn render_app() -> Html {
     };
 
     // REFACTOR - refactor this to use classes! macro somehow?
-    let (source_tab_classes, decompiled_tab_classes, data_tab_classes) = {
+    let (source_tab_classes, decompiled_tab_classes, data_tab_classes, instructions_tab_classes) = {
         let (tab_select, tab_unselect, tab_left_select, tab_left_unselect) = get_tab_classes();
 
         match *show_code_tab {
-            DisplayedCodeTab::Source => (tab_left_select, tab_unselect.clone(), tab_unselect),
+            DisplayedCodeTab::Source => (
+                tab_left_select,
+                tab_unselect.clone(),
+                tab_unselect.clone(),
+                tab_unselect,
+            ),
+
+            DisplayedCodeTab::Decompiled => (
+                tab_left_unselect,
+                tab_select,
+                tab_unselect.clone(),
+                tab_unselect,
+            ),
 
-            DisplayedCodeTab::Decompiled => (tab_left_unselect, tab_select, tab_unselect),
+            DisplayedCodeTab::Data => (
+                tab_left_unselect,
+                tab_unselect.clone(),
+                tab_select,
+                tab_unselect,
+            ),
 
-            DisplayedCodeTab::Data => (tab_left_unselect, tab_unselect, tab_select),
+            DisplayedCodeTab::Instructions => (
+                tab_left_unselect,
+                tab_unselect.clone(),
+                tab_unselect,
+                tab_select,
+            ),
         }
     };
 
@@ -531,6 +601,14 @@ pub fn render_app() -> Html {
                             }}>
                                 {"data"}
                             </button>
+                            <button class={instructions_tab_classes} onclick={{
+                                let show_tab = show_code_tab.clone();
+                                Callback::from(move |_| {
+                                    show_tab.set(DisplayedCodeTab::Instructions);
+                                })
+                            }}>
+                                {"instructions"}
+                            </button>
                         </div>
                         <div style="height: 96%;" class="py-2 overflow-y-auto bg-th-secondary px-2 border-2 border-current">
                                 { text_html_content }
@@ -677,6 +755,9 @@ fn render_running(
                             },
                         }
                     },
+                    DisplayedCodeTab::Instructions => html! {
+                        <InstructionReference />
+                    },
                 }
         }
         </>
@@ -34,6 +34,16 @@ pub struct MipsyWebConfig {
     pub register_base: RegisterBase,
     // TODO: should this be abstracted to hide N registers?
     pub hide_uncommon_registers: bool,
+    // a course-specific instruction set, used instead of the usual one
+    #[serde(default)]
+    pub instruction_set: Option<CustomInstructionSet>,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomInstructionSet {
+    // the name of the file it was loaded from
+    pub name: String,
+    pub yaml: String,
 }
 
 impl Observed for MipsyWebConfig {
@@ -154,6 +164,7 @@ impl Default for MipsyWebConfig {
             register_base: RegisterBase::default(),
             // This is true as most CS1521 students don't need to see them
             hide_uncommon_registers: true,
+            instruction_set: None,
         }
     }
 }
@@ -9,6 +9,7 @@ pub enum DisplayedCodeTab {
     Source,
     Decompiled,
     Data,
+    Instructions,
 }
 
 #[derive(Debug, PartialEq)]
@@ -91,7 +92,7 @@ pub enum ErrorType {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ErrorState {
-    pub error: MipsyError,
+    pub errors: Vec<MipsyError>,
     pub mipsy_stdout: Vec<String>,
 }
 
@@ -48,7 +48,7 @@ pub fn handle_response_from_worker(
                     register_values: vec![Safe::Uninitialised; 32],
                     previous_registers: vec![Safe::Uninitialised; 32],
                     current_instr: None,
-                    mipsy_stdout: Vec::new(),
+                    mipsy_stdout: response_struct.warnings,
                     memory: HashMap::new(),
                     is_stepping: true,
                     binary: Some(response_struct.binary),
@@ -99,7 +99,7 @@ pub fn handle_response_from_worker(
             log!("recieved compiler error from worker");
             log!("{}", &response_struct.message);
             let state_struct = ErrorType::CompilerOrParserError(ErrorState {
-                error: response_struct.error,
+                errors: response_struct.errors,
                 mipsy_stdout: vec![response_struct.message],
             });
 
@@ -109,6 +109,12 @@ pub fn handle_response_from_worker(
             state.set(State::Error(state_struct));
         }
 
+        WorkerResponse::LoadError(message) => {
+            error!("failed to load binary: {}", message);
+            let _ =
+                gloo_utils::window().alert_with_message(&format!("Failed to load file: {message}"));
+        }
+
         WorkerResponse::ProgramExited(mips_state) => {
             if let State::Compiled(ref curr) = *state {
                 state.set(State::Compiled(RunningState {
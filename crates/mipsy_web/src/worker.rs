@@ -1,15 +1,17 @@
-use crate::state::config::MipsyWebConfig;
+use crate::state::config::{CustomInstructionSet, MipsyWebConfig};
 use crate::{state::state::MipsState, utils::decompile, utils::generate_highlighted_line};
 use log::{error, info};
 use mipsy_lib::compile::breakpoints::{
-    get_affected_registers, Breakpoint, TargetAction, Watchpoint, WatchpointTarget,
+    find_watchpoint_hits, get_affected_registers, Breakpoint, Condition, TargetAction, TargetWatch,
+    Watchpoint, WatchpointTarget,
 };
-use mipsy_lib::compile::CompilerOptions;
+use mipsy_lib::compile::{source_label_name, CompilerOptions};
 use mipsy_lib::error::runtime::ErrorContext;
-use mipsy_lib::Register;
+use mipsy_lib::runtime::{CONFIRM_DIALOG_CANCEL, CONFIRM_DIALOG_NO, CONFIRM_DIALOG_YES};
 use mipsy_lib::{runtime::RuntimeSyscallGuard, Binary, InstSet, MipsyError, Runtime, Safe};
 use mipsy_parser::TaggedFile;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::rc::Rc;
 use yew_agent::{Agent, AgentLink, HandlerId, Public};
 
@@ -40,6 +42,9 @@ pub struct Worker {
     link: AgentLink<Self>,
     inst_set: InstSet,
     file: Option<String>,
+    // every file compiled so far, by name, so that `.include`s can be
+    // resolved against the files that have been opened in the web app
+    open_files: HashMap<String, String>,
     // the runtime may not exist if no binary
     runtime: Option<RuntimeState>,
     // the binary will not exist if we have not been sent a file
@@ -83,12 +88,19 @@ pub enum ReadSyscallInputs {
 pub enum WorkerRequest {
     // The struct that worker can obtain
     CompileCode(FileInformation),
+    // The contents of an already compiled `.mipsy` file
+    LoadBinary(Vec<u8>),
     ResetRuntime(MipsState),
     UpdateConfig(MipsyWebConfig),
+    // Use the instruction set in a YAML file, or the usual one if there isn't one
+    LoadInstructionSet(Option<CustomInstructionSet>),
     // Toggle a breakpoiint at an address
     ToggleBreakpoint(u32),
-    // Toggle watchpoints on a register
-    ToggleWatchpoint(u32, TargetAction),
+    // Set (or clear) the condition of the breakpoint at an address,
+    // inserting the breakpoint if there isn't one
+    SetBreakpointCondition(u32, Option<Condition>),
+    // Toggle watchpoints on a register or a range of memory
+    ToggleWatchpoint(WatchpointTarget, TargetAction),
     Run(MipsState, NumSteps, FileInformation),
     GiveSyscallValue(MipsState, ReadSyscallInputs),
 }
@@ -98,6 +110,8 @@ pub struct DecompiledResponse {
     pub decompiled: String,
     pub file: Option<String>,
     pub binary: Binary,
+    // the compiler's warnings, one message each
+    pub warnings: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -108,8 +122,8 @@ pub struct FileInformation {
 
 #[derive(Serialize, Deserialize)]
 pub struct ErrorResponse {
-    // error is RuntimeError, ParseError, or CompilerError
-    pub error: MipsyError,
+    // each error is a ParseError or CompilerError
+    pub errors: Vec<MipsyError>,
     // the file itself
     pub file: String,
 
@@ -127,6 +141,8 @@ pub struct RuntimeErrorResponse {
 pub enum WorkerResponse {
     DecompiledCode(DecompiledResponse),
     WorkerError(ErrorResponse),
+    // A `.mipsy` file couldn't be loaded
+    LoadError(String),
     UpdateMipsState(MipsState),
     UpdateBinary(Option<Binary>),
     InstructionOk(MipsState),
@@ -153,6 +169,7 @@ impl Agent for Worker {
             binary: None,
             config: MipsyWebConfig::default(),
             file: None,
+            open_files: HashMap::new(),
         }
     }
 
@@ -178,53 +195,90 @@ impl Agent for Worker {
         let mut watchpoints = Vec::new();
         match msg {
             Self::Input::CompileCode(FileInformation { file, filename }) => {
+                self.open_files.insert(filename.clone(), file.clone());
+                let files = self.source_files(filename, file.clone());
+
                 // TODO(shreys): this is a hack to get the file to compile
                 let config = &self.config.mipsy_config;
-                let compiled = mipsy_lib::compile(
+                let compiled = mipsy_lib::compile_with_warnings(
                     &self.inst_set,
-                    vec![TaggedFile::new(Some(&filename), file.as_str())],
+                    files
+                        .iter()
+                        .map(|(tag, contents)| {
+                            TaggedFile::new(Some(tag.as_str()), contents.as_str())
+                        })
+                        .collect(),
                     &CompilerOptions::default(),
                     config,
                 );
 
                 match compiled {
-                    Ok(binary) => {
+                    Ok((binary, warnings)) => {
                         let decompiled = decompile(&binary, &self.inst_set, Some(file.clone()));
+                        let warnings = warnings
+                            .iter()
+                            .map(|warning| {
+                                format!(
+                                    "line {}: warning: {}\n{}",
+                                    warning.line(),
+                                    warning.warning().message(),
+                                    warning.warning().tips().join("\n")
+                                )
+                            })
+                            .collect();
                         let response = Self::Output::DecompiledCode(DecompiledResponse {
                             decompiled,
                             file: Some(file.clone()),
                             binary: binary.to_owned(),
+                            warnings,
                         });
-                        let runtime = mipsy_lib::runtime(&binary, &[]);
+                        let runtime = mipsy_lib::runtime(&binary, &[], config);
                         self.binary = Some(binary);
                         self.runtime = Some(RuntimeState::Running(runtime));
                         self.file = Some(file);
                         self.link.respond(id, response)
                     }
 
-                    Err(error) => {
+                    Err(errors) => {
                         self.binary = None;
                         self.runtime = None;
-                        let error_msg = match error {
-                            MipsyError::Compiler(ref compiler_err) => {
-                                format!(
-                                    "{}\n{}\n{}",
-                                    generate_highlighted_line(file.clone(), compiler_err),
-                                    compiler_err.error().message(),
-                                    compiler_err.error().tips().join("\n")
-                                )
-                            }
-                            MipsyError::Parser(_) => String::from("failed to parse"),
-                            MipsyError::Runtime(_) => {
-                                unreachable!(
-                                    "runtime error should not be possible at compile time"
-                                );
-                            }
-                        };
+                        let error_msg = errors
+                            .iter()
+                            .map(|error| match error {
+                                MipsyError::Compiler(compiler_err) => {
+                                    // the error may be in an included file
+                                    let source = files
+                                        .iter()
+                                        .find(|(tag, _)| **tag == *compiler_err.file_tag())
+                                        .map_or(&file, |(_, contents)| contents);
+
+                                    format!(
+                                        "{}\n{}\n{}",
+                                        generate_highlighted_line(source.clone(), compiler_err),
+                                        compiler_err.error().message(),
+                                        compiler_err.error().tips().join("\n")
+                                    )
+                                }
+                                MipsyError::Parser(parser_err) => {
+                                    format!(
+                                        "line {}: {}\n{}",
+                                        parser_err.line(),
+                                        parser_err.error().message(),
+                                        parser_err.error().tips().join("\n")
+                                    )
+                                }
+                                MipsyError::Runtime(_) => {
+                                    unreachable!(
+                                        "runtime error should not be possible at compile time"
+                                    );
+                                }
+                            })
+                            .collect::<Vec<_>>()
+                            .join("\n\n");
                         self.link.respond(
                             id,
                             Self::Output::WorkerError(ErrorResponse {
-                                error,
+                                errors,
                                 file,
                                 message: error_msg,
                             }),
@@ -233,6 +287,32 @@ impl Agent for Worker {
                 }
             }
 
+            Self::Input::LoadBinary(bytes) => {
+                match mipsy_lib::cache::read_cache(&bytes, &self.inst_set) {
+                    Ok(binary) => {
+                        let decompiled = decompile(&binary, &self.inst_set, None);
+                        let response = Self::Output::DecompiledCode(DecompiledResponse {
+                            decompiled,
+                            file: None,
+                            binary: binary.to_owned(),
+                            warnings: Vec::new(),
+                        });
+                        let runtime = mipsy_lib::runtime(&binary, &[], &self.config.mipsy_config);
+                        self.binary = Some(binary);
+                        self.runtime = Some(RuntimeState::Running(runtime));
+                        self.file = None;
+                        self.link.respond(id, response)
+                    }
+
+                    Err(error) => {
+                        self.binary = None;
+                        self.runtime = None;
+                        self.link
+                            .respond(id, Self::Output::LoadError(error.to_string()))
+                    }
+                }
+            }
+
             Self::Input::ResetRuntime(mut mips_state) => {
                 if let Some(runtime_state) = &mut self.runtime {
                     match runtime_state {
@@ -256,8 +336,10 @@ impl Agent for Worker {
                                     decompiled,
                                     file: None,
                                     binary: binary.to_owned(),
+                                    warnings: Vec::new(),
                                 });
-                                let runtime = mipsy_lib::runtime(binary, &[]);
+                                let runtime =
+                                    mipsy_lib::runtime(binary, &[], &self.config.mipsy_config);
                                 self.runtime = Some(RuntimeState::Running(runtime));
                                 self.link.respond(id, response)
                             }
@@ -269,8 +351,9 @@ impl Agent for Worker {
                         decompiled,
                         file: None,
                         binary: binary.to_owned(),
+                        warnings: Vec::new(),
                     });
-                    let runtime = mipsy_lib::runtime(binary, &[]);
+                    let runtime = mipsy_lib::runtime(binary, &[], &self.config.mipsy_config);
                     self.runtime = Some(RuntimeState::Running(runtime));
                     self.link.respond(id, response)
                 }
@@ -368,10 +451,23 @@ impl Agent for Worker {
                 self.link.respond(id, response)
             }
 
-            Self::Input::ToggleWatchpoint(register, action) => {
+            Self::Input::SetBreakpointCondition(addr, condition) => {
+                if let Some(binary) = self.binary.as_mut() {
+                    let id = Binary::generate_id(&binary.breakpoints);
+                    binary
+                        .breakpoints
+                        .entry(addr)
+                        .or_insert_with(|| Breakpoint::new(id))
+                        .condition = condition;
+                }
+
+                let response = Self::Output::UpdateBinary(self.binary.clone());
+                self.link.respond(id, response)
+            }
+
+            Self::Input::ToggleWatchpoint(target, action) => {
                 let binary = self.binary.as_mut();
                 if let Some(binary) = binary {
-                    let target = WatchpointTarget::Register(Register::from_u32(register).unwrap());
                     let watchpoint = binary.watchpoints.get_mut(&target);
                     if let Some(watchpoint) = watchpoint {
                         if let Some(action) = watchpoint.action - action {
@@ -393,6 +489,29 @@ impl Agent for Worker {
 
             Self::Input::UpdateConfig(config) => self.config = config,
 
+            Self::Input::LoadInstructionSet(instruction_set) => {
+                self.inst_set = match instruction_set {
+                    Some(CustomInstructionSet { name, yaml }) => {
+                        match mipsy_instructions::load_inst_set(&yaml) {
+                            Ok(inst_set) => inst_set,
+                            Err(errors) => {
+                                let message = errors
+                                    .iter()
+                                    .map(|error| {
+                                        format!("invalid instruction set `{name}`: {error}")
+                                    })
+                                    .collect::<Vec<_>>()
+                                    .join("\n");
+
+                                self.link.respond(id, Self::Output::LoadError(message));
+                                mipsy_instructions::inst_set()
+                            }
+                        }
+                    }
+                    None => mipsy_instructions::inst_set(),
+                };
+            }
+
             Self::Input::Run(mut mips_state, step_size, FileInformation { file, filename }) => {
                 let binary = self.binary.as_ref().unwrap();
                 if let Some(RuntimeState::Running(mut runtime)) = self.runtime.take() {
@@ -418,23 +537,16 @@ impl Agent for Worker {
                                         let pc = next_runtime.timeline().state().pc();
                                         let affected_registers =
                                             get_affected_registers(&next_runtime, executed_inst);
-                                        watchpoints = affected_registers
-                                            .into_iter()
-                                            .filter(|wp| {
-                                                binary.watchpoints.get(&wp.target).map_or(
-                                                    false,
-                                                    |watch| {
-                                                        watch.action.fits(&wp.action)
-                                                            && watch.enabled
-                                                    },
-                                                )
-                                            })
-                                            .collect::<Vec<_>>();
+                                        watchpoints = describe_watchpoint_hits(
+                                            binary,
+                                            &affected_registers,
+                                            &next_runtime,
+                                        );
 
                                         runtime = next_runtime;
                                         // we want to stop at the instruction before the breakpoint
                                         // so that the line of breakpoint doesnt get executed
-                                        if binary.breakpoints.contains_key(&pc)
+                                        if breakpoint_hit(binary, &runtime, pc)
                                             && !self.config.ignore_breakpoints
                                         {
                                             breakpoint = true;
@@ -483,6 +595,11 @@ impl Agent for Worker {
                         mips_state.update_memory(&runtime);
                         let pc = runtime.timeline().state().pc();
                         let binary = self.binary.as_ref().unwrap();
+                        let condition_error = binary
+                            .breakpoints
+                            .get(&pc)
+                            .and_then(|bp| bp.condition.as_ref())
+                            .and_then(|cond| cond.holds(runtime.timeline().state()).err());
 
                         self.runtime = Some(RuntimeState::Running(runtime));
                         let response;
@@ -494,24 +611,23 @@ impl Agent for Worker {
                                 .labels
                                 .iter()
                                 .find(|(_, &addr)| addr == pc)
-                                .map(|(name, _)| name.to_string())
+                                .map(|(name, _)| source_label_name(name).to_string())
                                 .unwrap_or(format!("0x{:08x}", pc));
                             mips_state
                                 .mipsy_stdout
                                 .push(format!("BREAKPOINT - {label}"));
+                            if let Some(err) = condition_error {
+                                mips_state.mipsy_stdout.push(format!(
+                                    "failed to evaluate breakpoint condition: {err}"
+                                ));
+                            }
 
                             response = Self::Output::UpdateMipsState(mips_state);
                         } else if watchpoint {
                             for wp in watchpoints {
-                                mips_state.mipsy_stdout.push(format!(
-                                    "WATCHPOINT - {} was {} at 0x{pc:08x}",
-                                    wp.target,
-                                    match wp.action {
-                                        TargetAction::ReadOnly => "read from",
-                                        TargetAction::WriteOnly => "written to",
-                                        TargetAction::ReadWrite => "written to",
-                                    }
-                                ));
+                                mips_state
+                                    .mipsy_stdout
+                                    .push(format!("WATCHPOINT - {wp} at 0x{pc:08x}"));
                             }
 
                             response = Self::Output::UpdateMipsState(mips_state);
@@ -538,7 +654,7 @@ impl Agent for Worker {
                         if pc >= mipsy_lib::KTEXT_BOT {
                             break;
                         } else if mips_state.breakpoint_switch
-                            && binary.breakpoints.contains_key(&pc)
+                            && breakpoint_hit(binary, &runtime, pc)
                         {
                             // the previous instruction was a read syscall
                             // and indicated that the current instruction
@@ -559,19 +675,16 @@ impl Agent for Worker {
                                 let pc = next_runtime.timeline().state().pc();
                                 let affected_registers =
                                     get_affected_registers(&next_runtime, executed_inst);
-                                watchpoints = affected_registers
-                                    .into_iter()
-                                    .filter(|wp| {
-                                        binary.watchpoints.get(&wp.target).map_or(false, |watch| {
-                                            watch.action.fits(&wp.action) && watch.enabled
-                                        })
-                                    })
-                                    .collect::<Vec<_>>();
+                                watchpoints = describe_watchpoint_hits(
+                                    binary,
+                                    &affected_registers,
+                                    &next_runtime,
+                                );
 
                                 runtime = next_runtime;
                                 // we want to stop at the instruction before the breakpoint
                                 // so that the line of breakpoint doesnt get executed
-                                if binary.breakpoints.contains_key(&pc)
+                                if breakpoint_hit(binary, &runtime, pc)
                                     && !self.config.ignore_breakpoints
                                 {
                                     breakpoint = true;
@@ -597,7 +710,7 @@ impl Agent for Worker {
 
                                         // we want to stop at the instruction before the breakpoint
                                         // so that the line of breakpoint doesnt get executed
-                                        if binary.breakpoints.contains_key(&pc)
+                                        if breakpoint_hit(binary, &runtime, pc)
                                             && !self.config.ignore_breakpoints
                                         {
                                             breakpoint = true;
@@ -616,7 +729,7 @@ impl Agent for Worker {
 
                                         // we want to stop at the instruction before the breakpoint
                                         // so that the line of breakpoint doesnt get executed
-                                        if binary.breakpoints.contains_key(&pc)
+                                        if breakpoint_hit(binary, &runtime, pc)
                                             && !self.config.ignore_breakpoints
                                         {
                                             breakpoint = true;
@@ -635,7 +748,7 @@ impl Agent for Worker {
 
                                         // we want to stop at the instruction before the breakpoint
                                         // so that the line of breakpoint doesnt get executed
-                                        if binary.breakpoints.contains_key(&pc)
+                                        if breakpoint_hit(binary, &runtime, pc)
                                             && !self.config.ignore_breakpoints
                                         {
                                             breakpoint = true;
@@ -658,7 +771,7 @@ impl Agent for Worker {
 
                                         // we want to stop at the instruction before the breakpoint
                                         // so that the line of breakpoint doesnt get executed
-                                        if binary.breakpoints.contains_key(&pc)
+                                        if breakpoint_hit(binary, &runtime, pc)
                                             && !self.config.ignore_breakpoints
                                         {
                                             breakpoint = true;
@@ -681,7 +794,7 @@ impl Agent for Worker {
 
                                         // we want to stop at the instruction before the breakpoint
                                         // so that the line of breakpoint doesnt get executed
-                                        if binary.breakpoints.contains_key(&pc)
+                                        if breakpoint_hit(binary, &runtime, pc)
                                             && !self.config.ignore_breakpoints
                                         {
                                             breakpoint = true;
@@ -691,7 +804,10 @@ impl Agent for Worker {
 
                                     ReadInt(guard) => {
                                         info!("reading int");
-                                        self.runtime = Some(RuntimeState::WaitingInt(guard));
+                                        self.runtime =
+                                            Some(RuntimeState::WaitingInt(Box::new(move |int| {
+                                                guard(Some(int))
+                                            })));
 
                                         mips_state.breakpoint_switch = true;
                                         self.link.respond(id, WorkerResponse::NeedInt(mips_state));
@@ -701,7 +817,9 @@ impl Agent for Worker {
 
                                     ReadFloat(guard) => {
                                         info!("reading float");
-                                        self.runtime = Some(RuntimeState::WaitingFloat(guard));
+                                        self.runtime = Some(RuntimeState::WaitingFloat(Box::new(
+                                            move |float| guard(Some(float)),
+                                        )));
                                         mips_state.breakpoint_switch = true;
 
                                         self.link
@@ -723,7 +841,9 @@ impl Agent for Worker {
 
                                     ReadChar(guard) => {
                                         info!("Reading char");
-                                        self.runtime = Some(RuntimeState::WaitingChar(guard));
+                                        self.runtime = Some(RuntimeState::WaitingChar(Box::new(
+                                            move |char| guard(Some(char)),
+                                        )));
                                         mips_state.breakpoint_switch = true;
 
                                         self.link.respond(id, WorkerResponse::NeedChar(mips_state));
@@ -731,6 +851,76 @@ impl Agent for Worker {
                                         return;
                                     }
 
+                                    // dialogs are mapped onto the regular syscall inputs
+                                    ConfirmDialog(dialog_args, guard) => {
+                                        info!("confirm dialog");
+                                        mips_state.stdout.push(format!(
+                                            "{} [y/n] ",
+                                            String::from_utf8_lossy(&dialog_args.message)
+                                        ));
+                                        self.runtime = Some(RuntimeState::WaitingChar(Box::new(
+                                            move |char| {
+                                                guard(match char {
+                                                    b'y' | b'Y' => CONFIRM_DIALOG_YES,
+                                                    b'n' | b'N' => CONFIRM_DIALOG_NO,
+                                                    _ => CONFIRM_DIALOG_CANCEL,
+                                                })
+                                            },
+                                        )));
+                                        mips_state.breakpoint_switch = true;
+
+                                        self.link.respond(id, WorkerResponse::NeedChar(mips_state));
+
+                                        return;
+                                    }
+
+                                    InputDialogInt(dialog_args, guard) => {
+                                        info!("int input dialog");
+                                        mips_state.stdout.push(
+                                            String::from_utf8_lossy(&dialog_args.message)
+                                                .to_string(),
+                                        );
+                                        self.runtime =
+                                            Some(RuntimeState::WaitingInt(Box::new(move |int| {
+                                                guard(Some(int))
+                                            })));
+                                        mips_state.breakpoint_switch = true;
+
+                                        self.link.respond(id, WorkerResponse::NeedInt(mips_state));
+
+                                        return;
+                                    }
+
+                                    InputDialogString(dialog_args, guard) => {
+                                        info!("string input dialog");
+                                        mips_state.stdout.push(
+                                            String::from_utf8_lossy(&dialog_args.message)
+                                                .to_string(),
+                                        );
+                                        self.runtime = Some(RuntimeState::WaitingString(Box::new(
+                                            move |string| guard(Some(string)),
+                                        )));
+                                        mips_state.breakpoint_switch = true;
+
+                                        self.link
+                                            .respond(id, WorkerResponse::NeedString(mips_state));
+
+                                        return;
+                                    }
+
+                                    Time(guard) => {
+                                        info!("time");
+
+                                        runtime = guard(js_sys::Date::now() as u64);
+                                    }
+
+                                    Sleep(_sleep_args, next_runtime) => {
+                                        // sleeping would only block the worker, so it's a no-op
+                                        info!("sleep");
+
+                                        runtime = next_runtime;
+                                    }
+
                                     Sbrk(_sbrk_args, next_runtime) => {
                                         info!("sbrk");
 
@@ -821,9 +1011,13 @@ impl Agent for Worker {
 
                                 match &error {
                                     MipsyError::Runtime(runtime_error) => {
-                                        let filename: Rc<str> = Rc::from(filename);
-                                        let file: Rc<str> = Rc::from(file);
-                                        let source_code = [(filename, file)];
+                                        let source_code = self
+                                            .source_files(filename, file)
+                                            .into_iter()
+                                            .map(|(tag, contents)| {
+                                                (Rc::<str>::from(tag), Rc::<str>::from(contents))
+                                            })
+                                            .collect::<Vec<_>>();
                                         let runtime = match self.runtime {
                                             Some(RuntimeState::Running(ref runtime)) => runtime,
                                             _ => unreachable!("runtime not running"),
@@ -889,6 +1083,11 @@ impl Agent for Worker {
                     mips_state.update_memory(&runtime);
                     let pc = runtime.timeline().state().pc();
                     let binary = self.binary.as_ref().unwrap();
+                    let condition_error = binary
+                        .breakpoints
+                        .get(&pc)
+                        .and_then(|bp| bp.condition.as_ref())
+                        .and_then(|cond| cond.holds(runtime.timeline().state()).err());
                     self.runtime = Some(RuntimeState::Running(runtime));
 
                     let response;
@@ -906,24 +1105,23 @@ impl Agent for Worker {
                             .labels
                             .iter()
                             .find(|(_, &addr)| addr == pc)
-                            .map(|(name, _)| name.to_string())
+                            .map(|(name, _)| source_label_name(name).to_string())
                             .unwrap_or(format!("0x{:08x}", pc));
                         mips_state
                             .mipsy_stdout
                             .push(format!("BREAKPOINT - {label}"));
+                        if let Some(err) = condition_error {
+                            mips_state
+                                .mipsy_stdout
+                                .push(format!("failed to evaluate breakpoint condition: {err}"));
+                        }
 
                         response = Self::Output::UpdateMipsState(mips_state);
                     } else if watchpoint {
                         for wp in watchpoints {
-                            mips_state.mipsy_stdout.push(format!(
-                                "WATCHPOINT - {} was {} at 0x{pc:08x}",
-                                wp.target,
-                                match wp.action {
-                                    TargetAction::ReadOnly => "read from",
-                                    TargetAction::WriteOnly => "written to",
-                                    TargetAction::ReadWrite => "written to",
-                                }
-                            ));
+                            mips_state
+                                .mipsy_stdout
+                                .push(format!("WATCHPOINT - {wp} at 0x{pc:08x}"));
                         }
 
                         response = Self::Output::UpdateMipsState(mips_state);
@@ -943,6 +1141,20 @@ impl Agent for Worker {
 }
 
 impl Worker {
+    /// A file, along with everything it `.include`s.
+    ///
+    /// Included files can only come from the files open in the web app --
+    /// one that isn't open is reported by the compiler, at its `.include`.
+    fn source_files(&self, filename: String, file: String) -> Vec<(String, String)> {
+        let mut files = vec![(filename, file)];
+
+        let _ = mipsy_parser::load_includes(&mut files, |path| {
+            self.open_files.get(path).cloned().ok_or(())
+        });
+
+        files
+    }
+
     fn upload_syscall_value<T>(
         &mut self,
         mut mips_state: MipsState,
@@ -973,3 +1185,66 @@ impl Worker {
         }
     }
 }
+
+// a condition that can't be evaluated still triggers (with the error shown),
+// so that the user gets a chance to look at why -- the same as the cli and dap
+fn condition_holds(condition: &Option<Condition>, runtime: &Runtime) -> bool {
+    condition.as_ref().map_or(true, |cond| {
+        cond.holds(runtime.timeline().state()).unwrap_or(true)
+    })
+}
+
+fn breakpoint_hit(binary: &Binary, runtime: &Runtime, pc: u32) -> bool {
+    binary
+        .breakpoints
+        .get(&pc)
+        .map_or(false, |bp| condition_holds(&bp.condition, runtime))
+}
+
+fn describe_watchpoint_hits(
+    binary: &Binary,
+    accesses: &[TargetWatch],
+    runtime: &Runtime,
+) -> Vec<String> {
+    let format_value = |value: Option<u32>| match value {
+        Some(value) => (value as i32).to_string(),
+        None => String::from("uninitialized"),
+    };
+
+    find_watchpoint_hits(&binary.watchpoints, accesses, runtime.timeline().state())
+        .into_iter()
+        .map(|hit| {
+            let target = match (hit.target, hit.access.target) {
+                (
+                    WatchpointTarget::MemRange { start, .. },
+                    WatchpointTarget::MemRange { start: access, .. },
+                ) => {
+                    let name = binary
+                        .labels
+                        .iter()
+                        .find(|(_, &addr)| addr == start)
+                        .map(|(name, _)| source_label_name(name).to_string())
+                        .unwrap_or(format!("0x{start:08x}"));
+
+                    match hit.element_index() {
+                        Some(index) => format!("{name}[{index}] (0x{access:08x})"),
+                        None => format!("0x{access:08x}"),
+                    }
+                }
+                (_, access) => access.to_string(),
+            };
+
+            let (old, new) = hit.values(runtime);
+            match hit.access.action {
+                TargetAction::ReadOnly => {
+                    format!("{target} was read from ({})", format_value(new))
+                }
+                TargetAction::WriteOnly | TargetAction::ReadWrite => format!(
+                    "{target} was written to ({} -> {})",
+                    format_value(old),
+                    format_value(new)
+                ),
+            }
+        })
+        .collect()
+}